@@ -126,7 +126,7 @@ pub(crate) mod test {
     use locutus_runtime::{
         ContractCode, ContractContainer, Parameters, RelatedContracts, TryFromTsStd, WasmAPIVersion,
     };
-    use locutus_stdlib::client_api::ContractRequest;
+    use locutus_stdlib::client_api::{ContractRequest, ErrorKind};
     use rand::{prelude::Rng, thread_rng};
     use tokio::sync::watch::Receiver;
 
@@ -169,6 +169,12 @@ pub(crate) mod test {
             self.owned_contracts.extend(contracts);
         }
 
+        /// Generate a random event every time the node is signaled, instead of the
+        /// given events.
+        pub fn generate_random_events(&mut self) {
+            self.random = true;
+        }
+
         /// Events that the user generate.
         pub fn generate_events(
             &mut self,
@@ -177,7 +183,7 @@ pub(crate) mod test {
             self.events_to_gen.extend(events.into_iter())
         }
 
-        fn generate_deterministic_event(&mut self, id: &EventId) -> Option<ClientRequest> {
+        fn generate_deterministic_event(&mut self, id: &EventId) -> Option<ClientRequest<'static>> {
             self.events_to_gen.remove(id)
         }
 
@@ -220,9 +226,8 @@ pub(crate) mod test {
                             let contract_no = rng.gen_range(0..self.owned_contracts.len());
                             self.owned_contracts[contract_no].0.clone().key()
                         } else {
-                            // let contract_no = rng.gen_range(0..self.non_owned_contracts.len());
-                            // self.non_owned_contracts[contract_no]
-                            todo!("fixme")
+                            let contract_no = rng.gen_range(0..self.non_owned_contracts.len());
+                            self.non_owned_contracts[contract_no].clone()
                         };
                         break ContractRequest::Subscribe { key }.into();
                     }
@@ -241,37 +246,35 @@ pub(crate) mod test {
 
     impl ClientEventsProxy for MemoryEventsGen {
         fn recv(&mut self) -> BoxFuture<'_, Result<OpenRequest<'static>, ClientError>> {
-            // async move {
-            //     loop {
-            //         if self.signal.changed().await.is_ok() {
-            //             let (ev_id, pk) = *self.signal.borrow();
-            //             if pk == self.id && !self.random {
-            //                 let res = OpenRequest {
-            //                     id: ClientId(1),
-            //                     request: self
-            //                         .generate_deterministic_event(&ev_id)
-            //                         .expect("event not found"),
-            //                     notification_channel: None,
-            //                 };
-            //                 return Ok(res);
-            //             } else if pk == self.id {
-            //                 let res = OpenRequest {
-            //                     id: ClientId(1),
-            //                     request: self.generate_rand_event(),
-            //                     notification_channel: None,
-            //                 };
-            //                 return Ok(res);
-            //             }
-            //         } else {
-            //             log::debug!("sender half of user event gen dropped");
-            //             // probably the process finished, wait for a bit and then kill the thread
-            //             tokio::time::sleep(Duration::from_secs(1)).await;
-            //             panic!("finished orphan background thread");
-            //         }
-            //     }
-            // }
-            // .boxed()
-            todo!("fixme")
+            async move {
+                loop {
+                    if self.signal.changed().await.is_ok() {
+                        let (ev_id, pk) = *self.signal.borrow();
+                        if pk == self.id && !self.random {
+                            let res = OpenRequest {
+                                id: ClientId(1),
+                                request: self
+                                    .generate_deterministic_event(&ev_id)
+                                    .expect("event not found"),
+                                notification_channel: None,
+                            };
+                            return Ok(res);
+                        } else if pk == self.id {
+                            let res = OpenRequest {
+                                id: ClientId(1),
+                                request: self.generate_rand_event(),
+                                notification_channel: None,
+                            };
+                            return Ok(res);
+                        }
+                    } else {
+                        // the simulation finished, stop the node from waiting for more events
+                        tracing::debug!("sender half of user event gen dropped");
+                        return Err(ErrorKind::ChannelClosed.into());
+                    }
+                }
+            }
+            .boxed()
         }
 
         fn send(
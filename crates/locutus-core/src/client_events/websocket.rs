@@ -13,8 +13,8 @@ use tower_http::trace::TraceLayer;
 use axum::routing::get;
 use futures::{future::BoxFuture, stream::SplitSink, SinkExt, StreamExt};
 use locutus_runtime::prelude::TryFromTsStd;
-use locutus_stdlib::client_api::{ClientRequest, ContractRequest, ErrorKind, HostResponse};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use locutus_stdlib::client_api::{ClientRequest, ErrorKind, HostResponse};
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedSender};
 
 use super::{ClientError, ClientEventsProxy, ClientId, HostResult, OpenRequest};
 
@@ -186,6 +186,8 @@ async fn handle_socket(
     let client_id = ClientId(CLIENT_ID.fetch_add(1, Ordering::SeqCst));
     let (mut client_tx, mut client_rx) = socket.split();
     let (rx, mut host_responses) = channel(1);
+    // results the node sends back while handling the requests of this client
+    let (notifications, mut notifications_rx) = unbounded_channel();
     if client_handler
         .send(ClientHandling::NewClient(client_id, rx))
         .await
@@ -197,7 +199,13 @@ async fn handle_socket(
     loop {
         tokio::select! {
             result = client_rx.next() => {
-                if new_request(&request_sender, client_id, result).await.is_err() {
+                if new_request(&request_sender, client_id, result, &notifications).await.is_err() {
+                    break;
+                }
+            }
+            Some(notification) = notifications_rx.recv() => {
+                if send_reponse_to_client(&mut client_tx, notification).await.is_err() {
+                    let _ = client_handler.send(ClientHandling::ClientDisconnected(client_id)).await;
                     break;
                 }
             }
@@ -215,12 +223,13 @@ async fn new_request(
     request_sender: &Sender<StaticOpenRequest>,
     id: ClientId,
     result: Option<Result<Message, axum::Error>>,
+    notifications: &UnboundedSender<HostResult>,
 ) -> Result<(), ()> {
     let msg = match result {
         Some(Ok(msg)) => {
             let data = msg.into_data();
-            let deserialized: ClientRequest = match ContractRequest::try_decode(&data) {
-                Ok(m) => m.into(),
+            let deserialized = match ClientRequest::try_decode(&data) {
+                Ok(m) => m,
                 Err(e) => {
                     let _ = request_sender
                         .send(
@@ -261,7 +270,7 @@ async fn new_request(
             OpenRequest {
                 id,
                 request: msg,
-                notification_channel: None,
+                notification_channel: Some(notifications.clone()),
             }
             .into(),
         )
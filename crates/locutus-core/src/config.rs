@@ -8,14 +8,19 @@ use std::{
     path::{Path, PathBuf},
     pin::Pin,
     str::FromStr,
+    sync::Arc,
     time::Duration,
 };
 
 use directories::ProjectDirs;
 use libp2p::{identity, PeerId};
+use locutus_runtime::prelude::ContractKey;
+use locutus_stdlib::client_api::AdminToken;
 use once_cell::sync::Lazy;
 use tokio::runtime::Runtime;
 
+use crate::{node::AllowlistContract, util::RetryPolicy};
+
 const DEFAULT_BOOTSTRAP_PORT: u16 = 7800;
 const DEFAULT_WEBSOCKET_API_PORT: u16 = 55008;
 
@@ -37,6 +42,17 @@ pub struct Config {
     pub local_peer_keypair: Option<identity::Keypair>,
     pub log_level: tracing::log::LevelFilter,
     pub config_paths: ConfigPaths,
+    pub retry: RetryPolicies,
+    /// Autonomous system of the upstream network of this node, set through `LOCUTUS_ASN`.
+    pub asn: Option<u32>,
+    /// Ratio of transactions traced by this node, set through `LOCUTUS_TRACE_SAMPLE_RATE`.
+    pub trace_sample_rate: f64,
+    /// Contract holding the allowlist of a private network, set through
+    /// `LOCUTUS_ALLOWLIST_CONTRACT` and `LOCUTUS_ALLOWLIST_AUTHORITY_KEY_FILE`.
+    pub allowlist_contract: Option<AllowlistContract>,
+    /// Token authorizing the node administration requests, read from the file set through
+    /// `LOCUTUS_ADMIN_TOKEN_FILE`.
+    pub admin_token: Option<AdminToken>,
 
     #[cfg(feature = "websocket")]
     pub(crate) ws: WebSocketApiConfig,
@@ -76,6 +92,77 @@ impl WebSocketApiConfig {
     }
 }
 
+/// Retry policies for the different kinds of failed attempts in the node.
+///
+/// Each policy can be overriden through the `LOCUTUS_RETRY_{KIND}_{PARAM}` environment
+/// variables, e.g. `LOCUTUS_RETRY_OPERATION_MAX_ATTEMPTS`, where the params are
+/// `max_attempts`, `base_ms`, `max_delay_ms` and `jitter`.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicies {
+    /// Joining the ring through a gateway.
+    pub gateway_connection: RetryPolicy,
+    /// Retrying an operation with other peers.
+    pub operation: RetryPolicy,
+    /// Connecting to an other gateway after failing to join through one.
+    pub reconnection: RetryPolicy,
+    /// Fetching again the state of a subscribed contract after failing to sync it.
+    pub anti_entropy: RetryPolicy,
+}
+
+impl Default for RetryPolicies {
+    fn default() -> Self {
+        Self {
+            gateway_connection: RetryPolicy::new(
+                Duration::from_secs(1),
+                Duration::from_secs(120),
+                3,
+            ),
+            operation: RetryPolicy::new(Duration::from_millis(100), Duration::from_secs(5), 10),
+            reconnection: RetryPolicy::new(
+                Duration::from_secs(5),
+                Duration::from_secs(300),
+                usize::MAX,
+            ),
+            anti_entropy: RetryPolicy::new(Duration::from_secs(1), Duration::from_secs(60), 5),
+        }
+    }
+}
+
+impl RetryPolicies {
+    fn from_config(settings: &config::Config) -> Self {
+        let default = Self::default();
+        Self {
+            gateway_connection: Self::load_policy(
+                settings,
+                "gateway_connection",
+                default.gateway_connection,
+            ),
+            operation: Self::load_policy(settings, "operation", default.operation),
+            reconnection: Self::load_policy(settings, "reconnection", default.reconnection),
+            anti_entropy: Self::load_policy(settings, "anti_entropy", default.anti_entropy),
+        }
+    }
+
+    fn load_policy(settings: &config::Config, kind: &str, default: RetryPolicy) -> RetryPolicy {
+        let get_int = |param: &str| settings.get_int(&format!("retry_{kind}_{param}")).ok();
+        let millis = |param: &str| get_int(param).map(|ms| Duration::from_millis(ms as u64));
+        let mut policy = default;
+        if let Some(max_attempts) = get_int("max_attempts") {
+            policy = policy.with_max_attempts(max_attempts as usize);
+        }
+        if let Some(base) = millis("base_ms") {
+            policy = policy.with_base_delay(base);
+        }
+        if let Some(ceiling) = millis("max_delay_ms") {
+            policy = policy.with_max_delay(ceiling);
+        }
+        if let Ok(jitter) = settings.get_float(&format!("retry_{kind}_jitter")) {
+            policy = policy.with_jitter(jitter);
+        }
+        policy
+    }
+}
+
 #[derive(Debug)]
 pub struct ConfigPaths {
     // TODO: Add secrets and components dir
@@ -159,6 +246,16 @@ impl Config {
             .unwrap_or(tracing::log::LevelFilter::Info);
         let (bootstrap_ip, bootstrap_port, bootstrap_id) = Config::get_bootstrap_host(&settings)?;
         let config_paths = ConfigPaths::new()?;
+        let retry = RetryPolicies::from_config(&settings);
+        let asn = settings
+            .get_int("asn")
+            .ok()
+            .map(u32::try_from)
+            .transpose()
+            .map_err(|_err| std::io::ErrorKind::InvalidInput)?;
+        let trace_sample_rate = settings.get_float("trace_sample_rate").unwrap_or(1.0);
+        let allowlist_contract = Config::get_allowlist_contract(&settings)?;
+        let admin_token = Config::get_admin_token(&settings)?;
 
         Ok(Config {
             bootstrap_ip,
@@ -167,11 +264,47 @@ impl Config {
             local_peer_keypair,
             log_level,
             config_paths,
+            retry,
+            asn,
+            trace_sample_rate,
+            allowlist_contract,
+            admin_token,
             #[cfg(feature = "websocket")]
             ws: WebSocketApiConfig::from_config(&settings),
         })
     }
 
+    /// The allowlist contract key and the public key of the authority signing its state,
+    /// in protobuf encoding, must be both set.
+    fn get_allowlist_contract(
+        settings: &config::Config,
+    ) -> std::io::Result<Option<AllowlistContract>> {
+        let Ok(key) = settings.get_string("allowlist_contract") else {
+            return Ok(None);
+        };
+        let key = ContractKey::from_id(key).map_err(|_err| std::io::ErrorKind::InvalidInput)?;
+        let authority_key_file = settings
+            .get_string("allowlist_authority_key_file")
+            .map_err(|_err| std::io::ErrorKind::InvalidInput)?;
+        let authority = identity::PublicKey::from_protobuf_encoding(&fs::read(authority_key_file)?)
+            .map_err(|_err| std::io::ErrorKind::InvalidData)?;
+        Ok(Some(AllowlistContract { key, authority }))
+    }
+
+    /// The token is kept in a file rather than in the environment, so it isn't exposed to
+    /// other processes; surrounding whitespace is ignored.
+    fn get_admin_token(settings: &config::Config) -> std::io::Result<Option<AdminToken>> {
+        let Ok(token_file) = settings.get_string("admin_token_file") else {
+            return Ok(None);
+        };
+        let token = fs::read_to_string(token_file)?;
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+        Ok(Some(AdminToken::new(token)))
+    }
+
     fn get_bootstrap_host(
         settings: &config::Config,
     ) -> std::io::Result<(IpAddr, u16, Option<PeerId>)> {
@@ -225,6 +358,8 @@ impl GlobalExecutor {
     pub fn spawn<R: Send + 'static>(
         f: impl Future<Output = R> + Send + 'static,
     ) -> tokio::task::JoinHandle<R> {
+        #[cfg(test)]
+        let f = track_task(f);
         if let Ok(handle) = tokio::runtime::Handle::try_current() {
             handle.spawn(f)
         } else if let Some(rt) = &*ASYNC_RT {
@@ -233,6 +368,53 @@ impl GlobalExecutor {
             unreachable!("the executor must have been initialized")
         }
     }
+
+    /// Runs the future counting in `live_tasks` the tasks spawned through the executor by it,
+    /// or by any of the tasks it spawns, which did not finish yet.
+    #[cfg(test)]
+    pub async fn track_tasks<R>(
+        live_tasks: Arc<std::sync::atomic::AtomicUsize>,
+        f: impl Future<Output = R>,
+    ) -> R {
+        LIVE_TASKS.scope(live_tasks, f).await
+    }
+}
+
+#[cfg(test)]
+tokio::task_local! {
+    static LIVE_TASKS: Arc<std::sync::atomic::AtomicUsize>;
+}
+
+/// Decrements the live tasks once the task finishes or is dropped.
+#[cfg(test)]
+struct LiveTask(Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(test)]
+impl Drop for LiveTask {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+fn track_task<R>(
+    f: impl Future<Output = R> + Send + 'static,
+) -> impl Future<Output = R> + Send + 'static {
+    let live_tasks = LIVE_TASKS.try_with(Arc::clone).ok();
+    let task = live_tasks.map(|live_tasks| {
+        live_tasks.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        LiveTask(live_tasks)
+    });
+    async move {
+        match task {
+            Some(task) => {
+                let live_tasks = task.0.clone();
+                let _task = task;
+                LIVE_TASKS.scope(live_tasks, f).await
+            }
+            None => f.await,
+        }
+    }
 }
 
 impl libp2p::core::Executor for GlobalExecutor {
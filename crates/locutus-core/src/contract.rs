@@ -1,9 +1,14 @@
-use locutus_runtime::{prelude::ContractKey, ContractError as ContractRtError, Parameters};
+use locutus_runtime::{
+    prelude::ContractKey, ContractError as ContractRtError, Parameters, StateStoreError,
+    ValidateResult,
+};
 
+mod causality;
 mod handler;
 pub mod storages;
 mod test;
 
+pub(crate) use causality::CausalHistories;
 #[cfg(test)]
 pub(crate) use handler::test::{TestContractHandler, TestContractStoreError};
 pub(crate) use handler::{
@@ -19,49 +24,48 @@ pub(crate) async fn contract_handling<'a, CH, Err>(
 ) -> Result<(), ContractError<Err>>
 where
     CH: ContractHandler<Error = Err> + Send + 'static,
-    Err: std::error::Error + Send + 'static,
+    Err: std::error::Error + From<std::io::Error> + Send + 'static,
 {
     loop {
         let res = contract_handler.channel().recv_from_listener().await?;
         match res {
             (
-                _id,
+                id,
                 ContractHandlerEvent::FetchQuery {
                     key,
                     fetch_contract,
                 },
             ) => {
-                let _contract = if fetch_contract {
-                    let params = Parameters::from(vec![]); // FIXME
-                    contract_handler
-                        .contract_store()
-                        .fetch_contract(&key, &params)
-                } else {
-                    None
-                };
-                let _contract = if fetch_contract {
-                    let params = Parameters::from(vec![]); // FIXME
-                    contract_handler
-                        .contract_store()
-                        .fetch_contract(&key, &params)
-                } else {
-                    None
+                let response = match contract_handler.state_store().get(&key).await {
+                    Ok(state) => {
+                        let contract = if fetch_contract {
+                            // FIXME: the params of contracts cached without a state are unknown
+                            let params = contract_handler
+                                .state_store()
+                                .get_params(&key)
+                                .await
+                                .unwrap_or_else(|_| Parameters::from(vec![]));
+                            contract_handler
+                                .contract_store()
+                                .fetch_contract(&key, &params)
+                        } else {
+                            None
+                        };
+                        Ok(StoreResponse {
+                            state: Some(state),
+                            contract,
+                        })
+                    }
+                    Err(StateStoreError::MissingContract) => Ok(StoreResponse {
+                        state: None,
+                        contract: None,
+                    }),
+                    Err(err) => Err(state_store_error(err)),
                 };
-                todo!("get state from state store");
-                // let response = Ok(StoreResponse {
-                //     state: None,
-                //     contract: _contract,
-                // });
-                // contract_handler
-                //     .channel()
-                //     .send_to_listener(
-                //         _id,
-                //         ContractHandlerEvent::FetchResponse {
-                //             key,
-                //             response: _response,
-                //         },
-                //     )
-                //     .await?;
+                contract_handler
+                    .channel()
+                    .send_to_listener(id, ContractHandlerEvent::FetchResponse { key, response })
+                    .await?;
             }
             (id, ContractHandlerEvent::Cache(contract)) => {
                 match contract_handler.contract_store().store_contract(contract) {
@@ -80,39 +84,66 @@ where
                     }
                 }
             }
+            (id, ContractHandlerEvent::CacheState { key, params, state }) => {
+                let result = match contract_handler.validate_state(&key, &params, &state) {
+                    Ok(ValidateResult::Valid) => contract_handler
+                        .state_store()
+                        .store(key, state, Some(params))
+                        .await
+                        .map(|_| true)
+                        .map_err(Into::into),
+                    // related contracts are not available to complete the validation
+                    Ok(ValidateResult::Invalid | ValidateResult::RequestRelated(_)) => Ok(false),
+                    Err(err) => Err(ContractError::StorageError(err)),
+                };
+                contract_handler
+                    .channel()
+                    .send_to_listener(id, ContractHandlerEvent::CacheStateResult(result))
+                    .await?;
+            }
             (
-                _id,
+                id,
                 ContractHandlerEvent::PushQuery {
-                    key: _key,
-                    state: _state,
+                    key,
+                    state,
+                    causality: _,
                 },
             ) => {
-                // let _put_result = contract_handler
-                //     .handle_request(ClientRequest::Put {
-                //         contract: todo!(),
-                //         state: _state,
-                //     }.into())
-                //     .await
-                //     .map(|r| {
-                //         let _r = r.unwrap_put();
-                //         unimplemented!();
-                //     });
-                // contract_handler
-                //     .channel()
-                //     .send_to_listener(
-                //         _id,
-                //         ContractHandlerEvent::PushResponse {
-                //             new_value: put_result,
-                //         },
-                //     )
-                //     .await?;
-                todo!("perform put request");
+                let params = contract_handler
+                    .state_store()
+                    .get_params(&key)
+                    .await
+                    .unwrap_or_else(|_| Parameters::from(vec![]));
+                let new_value = match contract_handler.validate_state(&key, &params, &state) {
+                    Ok(ValidateResult::Valid) => contract_handler
+                        .state_store()
+                        .store(key, state.clone(), None)
+                        .await
+                        .map(|_| state)
+                        .map_err(state_store_error),
+                    Ok(ValidateResult::Invalid | ValidateResult::RequestRelated(_)) => {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("invalid state for contract {key}"),
+                        )
+                        .into())
+                    }
+                    Err(err) => Err(err),
+                };
+                contract_handler
+                    .channel()
+                    .send_to_listener(id, ContractHandlerEvent::PushResponse { new_value })
+                    .await?;
             }
             _ => unreachable!(),
         }
     }
 }
 
+fn state_store_error<CErr: From<std::io::Error>>(err: StateStoreError) -> CErr {
+    std::io::Error::new(std::io::ErrorKind::Other, err).into()
+}
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum ContractError<CErr> {
     #[error("handler channel dropped")]
@@ -127,4 +158,6 @@ pub(crate) enum ContractError<CErr> {
     NoEvHandlerResponse,
     #[error("failed while storing a contract")]
     StorageError(CErr),
+    #[error(transparent)]
+    StateStoreError(#[from] StateStoreError),
 }
@@ -0,0 +1,66 @@
+//! Causal histories of the contracts known to a node.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use locutus_runtime::prelude::{CausalHistory, Causality, ContractKey, UpdateData};
+
+/// Updates to each contract seen by the node. Every component tagging or applying updates uses
+/// the same histories, so an update is tagged with the same causality no matter the path it
+/// takes.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct CausalHistories(Arc<DashMap<ContractKey, CausalHistory>>);
+
+impl CausalHistories {
+    /// Causality of a new update to the contract, succeeding all the updates seen so far.
+    pub fn next(&self, key: &ContractKey, update: &UpdateData<'_>) -> Causality {
+        let update = bincode::serialize(update).expect("infallible");
+        match self.0.get(key) {
+            Some(history) => history.next(&update),
+            None => CausalHistory::default().next(&update),
+        }
+    }
+
+    /// Records an update to the contract, returns `false` if it had already been seen.
+    pub fn record(&self, key: &ContractKey, causality: &Causality) -> bool {
+        self.0.entry(key.clone()).or_default().record(causality)
+    }
+
+    /// Whether the update to the contract has already been recorded.
+    pub fn has_seen(&self, key: &ContractKey, causality: &Causality) -> bool {
+        self.0
+            .get(key)
+            .is_some_and(|history| history.contains(&causality.id()))
+    }
+
+    pub fn forget(&self, key: &ContractKey) {
+        self.0.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use locutus_runtime::prelude::{ContractCode, Parameters, State};
+
+    use super::*;
+
+    #[test]
+    fn same_update_same_causality() {
+        let key = ContractKey::from((&Parameters::from(vec![]), &ContractCode::from(vec![1])));
+        let update = UpdateData::State(State::from(vec![4, 5]));
+        let (a, b) = (CausalHistories::default(), CausalHistories::default());
+        let causality = a.next(&key, &update);
+        assert_eq!(causality, b.next(&key, &update));
+
+        assert!(!a.has_seen(&key, &causality));
+        assert!(a.record(&key, &causality));
+        assert!(a.has_seen(&key, &causality));
+        assert!(!a.record(&key, &causality));
+        assert!(b.record(&key, &causality));
+        // both peers tag the following update the same way
+        let update = UpdateData::State(State::from(vec![6]));
+        let next = a.next(&key, &update);
+        assert_eq!(next.parents(), &[causality.id()]);
+        assert_eq!(next, b.next(&key, &update));
+    }
+}
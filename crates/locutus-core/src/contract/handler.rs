@@ -6,7 +6,10 @@ use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
 use std::time::{Duration, Instant};
 
 use futures::future::BoxFuture;
-use locutus_runtime::{ContractContainer, ContractStore, Parameters, StateStorage, StateStore};
+use locutus_runtime::{
+    prelude::Causality, ContractContainer, ContractStore, Parameters, StateStorage, StateStore,
+    ValidateResult,
+};
 use locutus_stdlib::client_api::{ClientRequest, HostResponse};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
@@ -28,6 +31,14 @@ pub(crate) trait ContractHandler:
 
     fn state_store(&mut self) -> &mut StateStore<Self::Store>;
 
+    /// Verifies a state against the contract code, without storing it.
+    fn validate_state(
+        &mut self,
+        key: &ContractKey,
+        params: &Parameters<'_>,
+        state: &WrappedState,
+    ) -> Result<ValidateResult, Self::Error>;
+
     fn handle_request<'a, 's: 'a>(
         &'s mut self,
         req: ClientRequest<'a>,
@@ -163,6 +174,9 @@ pub(crate) enum ContractHandlerEvent<Err> {
     PushQuery {
         key: ContractKey,
         state: WrappedState,
+        /// Causality the update was tagged with by the peer it originated at, exposed to the
+        /// contract while applying it. Unset for values which are just being cached.
+        causality: Option<Causality>,
     },
     /// The response to a push query.
     PushResponse {
@@ -182,13 +196,21 @@ pub(crate) enum ContractHandlerEvent<Err> {
     Cache(ContractContainer),
     /// Result of a caching operation.
     CacheResult(Result<(), ContractError<Err>>),
+    /// Validate a state against its contract and store it in the local store if valid.
+    CacheState {
+        key: ContractKey,
+        params: Parameters<'static>,
+        state: WrappedState,
+    },
+    /// Result of a state caching operation, false if the state was not valid.
+    CacheStateResult(Result<bool, ContractError<Err>>),
 }
 
 #[cfg(test)]
 pub mod test {
     use std::sync::Arc;
 
-    use locutus_runtime::{ContractStore, WasmAPIVersion};
+    use locutus_runtime::{ContractRuntimeInterface, ContractStore, WasmAPIVersion};
     use locutus_stdlib::{
         client_api::{ClientRequest, HostResponse},
         prelude::ContractCode,
@@ -208,13 +230,15 @@ pub mod test {
     pub(crate) enum TestContractStoreError {
         #[error(transparent)]
         IOError(#[from] std::io::Error),
+        #[error(transparent)]
+        RuntimeError(#[from] locutus_runtime::ContractError),
     }
 
     pub(crate) struct TestContractHandler {
         channel: ContractHandlerChannel<TestContractStoreError, CHListenerHalve>,
-        kv_store: MemKVStore,
+        kv_store: StateStore<MemKVStore>,
         contract_store: ContractStore,
-        _runtime: MockRuntime,
+        runtime: MockRuntime,
     }
 
     impl TestContractHandler {
@@ -223,13 +247,13 @@ pub mod test {
         ) -> Self {
             TestContractHandler {
                 channel,
-                kv_store: MemKVStore::new(),
+                kv_store: StateStore::new(MemKVStore::new(), 10_000_000).unwrap(),
                 contract_store: ContractStore::new(
                     CONFIG.config_paths.contracts_dir.clone(),
                     MAX_MEM_CACHE,
                 )
                 .unwrap(),
-                _runtime: MockRuntime {},
+                runtime: MockRuntime {},
             }
         }
     }
@@ -282,7 +306,18 @@ pub mod test {
         }
 
         fn state_store(&mut self) -> &mut StateStore<Self::Store> {
-            todo!()
+            &mut self.kv_store
+        }
+
+        fn validate_state(
+            &mut self,
+            key: &ContractKey,
+            params: &Parameters<'_>,
+            state: &WrappedState,
+        ) -> Result<ValidateResult, Self::Error> {
+            Ok(self
+                .runtime
+                .validate_state(key, params, state, Default::default())?)
         }
     }
 
@@ -211,7 +211,12 @@ where
                         fetch_contract,
                     } => {
                         let (state, contract) = self.get_contract(&key, fetch_contract).await?;
-                        Ok(ContractResponse::GetResponse { contract, state }.into())
+                        Ok(ContractResponse::KeyedGetResponse {
+                            key,
+                            contract,
+                            state,
+                        }
+                        .into())
                     }
                     ContractRequest::Put {
                         contract,
@@ -249,6 +254,10 @@ where
                 ClientRequest::DelegateOp(_op) => unreachable!(),
                 ClientRequest::Disconnect { .. } => unreachable!(),
                 ClientRequest::GenerateRandData { bytes: _ } => unreachable!(),
+                ClientRequest::SubscribeNodeEvents => unreachable!(),
+                ClientRequest::SampleNetwork { .. } => unreachable!(),
+                ClientRequest::NodeQueries(_) => unreachable!(),
+                ClientRequest::NodeAdmin { .. } => unreachable!(),
             }
         }
         .boxed()
@@ -257,6 +266,17 @@ where
     fn state_store(&mut self) -> &mut StateStore<Self::Store> {
         &mut self.state_store
     }
+
+    fn validate_state(
+        &mut self,
+        key: &ContractKey,
+        params: &Parameters<'_>,
+        state: &WrappedState,
+    ) -> Result<ValidateResult, Self::Error> {
+        Ok(self
+            .runtime
+            .validate_state(key, params, state, Default::default())?)
+    }
 }
 
 #[cfg(test)]
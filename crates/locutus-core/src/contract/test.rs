@@ -1,7 +1,7 @@
 use dashmap::DashMap;
 use futures::future::BoxFuture;
 use locutus_runtime::{
-    ContractKey, ContractRuntimeInterface, ContractStore, StateStorage, StateStore,
+    ContractKey, ContractRuntimeInterface, ContractStore, Parameters, StateStorage, StateStore,
     UpdateModification, ValidateResult,
 };
 use locutus_stdlib::client_api::{ClientRequest, HostResponse};
@@ -13,6 +13,7 @@ pub(crate) struct MockRuntime {}
 
 #[allow(unused_variables)]
 impl ContractRuntimeInterface for MockRuntime {
+    /// Any state is valid for the simulated contracts.
     fn validate_state(
         &mut self,
         key: &ContractKey,
@@ -20,7 +21,7 @@ impl ContractRuntimeInterface for MockRuntime {
         state: &locutus_runtime::WrappedState,
         related: locutus_runtime::RelatedContracts,
     ) -> locutus_runtime::RuntimeResult<ValidateResult> {
-        todo!()
+        Ok(ValidateResult::Valid)
     }
 
     fn validate_delta(
@@ -63,7 +64,10 @@ impl ContractRuntimeInterface for MockRuntime {
 }
 
 #[derive(Default, Clone)]
-pub(crate) struct MemKVStore(DashMap<ContractKey, WrappedState>);
+pub(crate) struct MemKVStore {
+    states: DashMap<ContractKey, WrappedState>,
+    params: DashMap<ContractKey, Parameters<'static>>,
+}
 
 #[async_trait::async_trait]
 impl StateStorage for MemKVStore {
@@ -71,30 +75,32 @@ impl StateStorage for MemKVStore {
 
     async fn store(
         &mut self,
-        _key: ContractKey,
-        _state: locutus_runtime::WrappedState,
+        key: ContractKey,
+        state: locutus_runtime::WrappedState,
     ) -> Result<(), Self::Error> {
-        todo!()
+        self.states.insert(key, state);
+        Ok(())
     }
 
     async fn get(
         &self,
-        _key: &ContractKey,
+        key: &ContractKey,
     ) -> Result<Option<locutus_runtime::WrappedState>, Self::Error> {
-        todo!()
+        Ok(self.states.get(key).map(|state| state.value().clone()))
     }
 
     async fn store_params(
         &mut self,
-        _key: ContractKey,
-        _state: locutus_runtime::Parameters<'static>,
+        key: ContractKey,
+        params: locutus_runtime::Parameters<'static>,
     ) -> Result<(), Self::Error> {
-        todo!()
+        self.params.insert(key, params);
+        Ok(())
     }
 
     fn get_params<'a>(
         &'a self,
-        _key: &'a ContractKey,
+        key: &'a ContractKey,
     ) -> std::pin::Pin<
         Box<
             dyn futures::Future<
@@ -103,7 +109,7 @@ impl StateStorage for MemKVStore {
                 + 'a,
         >,
     > {
-        todo!()
+        Box::pin(async move { Ok(self.params.get(key).map(|params| params.value().clone())) })
     }
 }
 
@@ -120,7 +126,7 @@ where
     channel: ContractHandlerChannel<SimStoreError, CHListenerHalve>,
     kv_store: StateStore<KVStore>,
     contract_store: ContractStore,
-    _runtime: MockRuntime,
+    runtime: MockRuntime,
 }
 
 impl<KVStore> MemoryContractHandler<KVStore>
@@ -142,7 +148,7 @@ where
                 Self::MAX_MEM_CACHE,
             )
             .unwrap(),
-            _runtime: MockRuntime {},
+            runtime: MockRuntime {},
         }
     }
 }
@@ -195,6 +201,17 @@ impl ContractHandler for MemoryContractHandler {
     fn state_store(&mut self) -> &mut locutus_runtime::StateStore<Self::Store> {
         &mut self.kv_store
     }
+
+    fn validate_state(
+        &mut self,
+        key: &ContractKey,
+        params: &Parameters<'_>,
+        state: &WrappedState,
+    ) -> Result<ValidateResult, Self::Error> {
+        self.runtime
+            .validate_state(key, params, state, Default::default())
+            .map_err(|err| SimStoreError(format!("{err}")))
+    }
 }
 
 #[derive(Debug)]
@@ -11,8 +11,9 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use crate::{
     client_events::{ContractError as CoreContractError, DelegateError as CoreDelegateError},
+    contract::CausalHistories,
     either::Either,
-    ClientId, DynError, HostResult, RequestError, Storage,
+    ClientId, DynError, HostResult, OpenRequest, RequestError, Storage,
 };
 
 type Response = Result<HostResponse, Either<RequestError, DynError>>;
@@ -36,6 +37,12 @@ pub struct Executor {
     contract_state: StateStore<Storage>,
     update_notifications: HashMap<ContractKey, Vec<(ClientId, UnboundedSender<HostResult>)>>,
     subscriber_summaries: HashMap<ContractKey, HashMap<ClientId, StateSummary<'static>>>,
+    /// Pending requests, by the contract they target (if any).
+    pending_requests: FairScheduler<Option<ContractInstanceId>, OpenRequest<'static>>,
+    /// Updates applied to each contract, to tag new ones with their causality.
+    causal_histories: CausalHistories,
+    /// Where to route the updates for contracts not held by this node, if anywhere.
+    remote_updates: Option<UnboundedSender<ContractRequest<'static>>>,
 }
 
 impl Executor {
@@ -59,9 +66,61 @@ impl Executor {
             contract_state,
             update_notifications: HashMap::default(),
             subscriber_summaries: HashMap::default(),
+            pending_requests: FairScheduler::default(),
+            causal_histories: CausalHistories::default(),
+            remote_updates: None,
         })
     }
 
+    /// Routes the messages which contracts send to contracts not held by this node as
+    /// update requests through the given channel, so they can be sent to the peers holding
+    /// the target contracts. Otherwise those messages are dropped.
+    pub fn route_remote_updates(&mut self, updates: UnboundedSender<ContractRequest<'static>>) {
+        self.remote_updates = Some(updates);
+    }
+
+    /// Queues a request to be handled through [`Self::handle_next_request`].
+    ///
+    /// Requests are served fairly between contracts instead of in arrival order, so a
+    /// contract receiving a lot of requests can't starve the rest.
+    pub fn schedule_request(&mut self, req: OpenRequest<'static>) {
+        let contract = match &req.request {
+            ClientRequest::ContractOp(ContractRequest::Put { contract, .. }) => {
+                Some(*contract.key().id())
+            }
+            ClientRequest::ContractOp(
+                ContractRequest::Update { key, .. }
+                | ContractRequest::Get { key, .. }
+                | ContractRequest::Subscribe { key },
+            ) => Some(*key.id()),
+            _ => None,
+        };
+        self.pending_requests.push(contract, req);
+    }
+
+    pub fn has_pending_requests(&self) -> bool {
+        !self.pending_requests.is_empty()
+    }
+
+    /// Handles the next scheduled request, if any, returning the id of the client which sent it
+    /// alongside the response.
+    pub async fn handle_next_request(&mut self) -> Option<(ClientId, Response)> {
+        let (contract, req) = self.pending_requests.next_task()?;
+        // only the time spent executing contracts is charged, not the one spent in I/O
+        let started = self.runtime.execution_time();
+        let res = self
+            .handle_request(req.id, req.request, req.notification_channel)
+            .await;
+        let elapsed = self.runtime.execution_time() - started;
+        self.pending_requests.charge(&contract, elapsed);
+        Some((req.id, res))
+    }
+
+    /// Accumulated execution time of the given contract.
+    pub fn execution_stats(&self, key: &ContractKey) -> Option<&ExecutionStats> {
+        self.runtime.execution_stats(key)
+    }
+
     pub fn register_contract_notifier(
         &mut self,
         key: ContractKey,
@@ -140,6 +199,18 @@ impl Executor {
                 locutus_runtime::util::generate_random_bytes(&mut output);
                 Ok(HostResponse::GenerateRandData(output))
             }
+            ClientRequest::SubscribeNodeEvents => Err(Either::Right(
+                "node events not available for the executor".into(),
+            )),
+            ClientRequest::SampleNetwork { .. } => Err(Either::Right(
+                "network sampling not available for the executor".into(),
+            )),
+            ClientRequest::NodeQueries(_) => Err(Either::Right(
+                "node queries not available for the executor".into(),
+            )),
+            ClientRequest::NodeAdmin { .. } => Err(Either::Right(
+                "node administration not available for the executor".into(),
+            )),
         }
     }
 
@@ -167,12 +238,6 @@ impl Executor {
                 //        you can request to several nodes and determine which node has a fresher ver
                 let key = contract.key();
                 let params = contract.params();
-                self.runtime
-                    .contract_store
-                    .store_contract(contract.clone())
-                    .map_err(Into::into)
-                    .map_err(Either::Right)?;
-
                 tracing::debug!("executing with params: {:?}", params);
 
                 if self.mode == OperationMode::Local {
@@ -188,9 +253,10 @@ impl Executor {
                     }
                 }
 
+                // validate before storing anything so invalid puts leave no trace behind
                 let result = self
                     .runtime
-                    .validate_state(&key, &params, &state, related_contracts)
+                    .prevalidate_put(&contract, &state, related_contracts)
                     .map_err(Into::into)
                     .map_err(Either::Right)?;
                 let is_valid = match result {
@@ -217,6 +283,11 @@ impl Executor {
                         )
                     })?;
 
+                self.runtime
+                    .contract_store
+                    .store_contract(contract)
+                    .map_err(Into::into)
+                    .map_err(Either::Right)?;
                 self.contract_state
                     .store(key.clone(), state.clone(), Some(params.clone()))
                     .await
@@ -242,7 +313,7 @@ impl Executor {
                         .await
                         .map_err(|err| Either::Right(err.into()))?
                 };
-                let new_state = {
+                let (new_state, messages, causality) = {
                     let state = self
                         .contract_state
                         .get(&key)
@@ -250,26 +321,29 @@ impl Executor {
                         .map_err(Into::into)
                         .map_err(Either::Right)?
                         .clone();
-                    let update_modification = self
-                        .runtime
-                        .update_state(&key, &parameters, &state, &[data])
-                        .map_err(|err| match err {
-                            err if err.is_contract_exec_error() => Either::Left(
-                                CoreContractError::Update {
-                                    key: key.clone(),
-                                    cause: format!("{err}"),
-                                }
-                                .into(),
-                            ),
-                            other => Either::Right(other.into()),
-                        })?;
+                    let causality = self.causal_histories.next(&key, &data);
+                    self.runtime.set_causality(Some(&causality));
+                    let update_modification =
+                        self.runtime
+                            .update_state(&key, &parameters, &state, &[data]);
+                    self.runtime.set_causality(None);
+                    let update_modification = update_modification.map_err(|err| match err {
+                        err if err.is_contract_exec_error() => Either::Left(
+                            CoreContractError::Update {
+                                key: key.clone(),
+                                cause: format!("{err}"),
+                            }
+                            .into(),
+                        ),
+                        other => Either::Right(other.into()),
+                    })?;
                     if let Some(new_state) = update_modification.new_state {
                         let new_state = WrappedState::new(new_state.into_bytes());
                         self.contract_state
                             .store(key.clone(), new_state.clone(), None)
                             .await
                             .map_err(|err| Either::Right(err.into()))?;
-                        new_state
+                        (new_state, update_modification.messages, causality)
                     } else {
                         todo!()
                     }
@@ -280,8 +354,12 @@ impl Executor {
                     .summarize_state(&key, &parameters, &new_state)
                     .map_err(Into::into)
                     .map_err(Either::Right)?;
-                self.send_update_notification(&key, &parameters, &new_state)
+                self.causal_histories.record(&key, &causality);
+                self.send_update_notification(&key, &parameters, &new_state, &causality)
                     .await?;
+                // the update is already committed, so failing to deliver the messages it
+                // emitted doesn't fail the update
+                self.deliver_contract_messages(&key, messages).await;
                 // TODO: in network mode, wait at least for one confirmation
                 //       when a node receives a delta from updates, run the update themselves
                 //       and send back confirmation
@@ -359,11 +437,98 @@ impl Executor {
         }
     }
 
+    /// Delivers the messages emitted by a contract while updating to the target contracts,
+    /// and any further messages emitted by those in turn.
+    ///
+    /// Messages to contracts not held by this node are routed as update requests, if enabled
+    /// through [`Self::route_remote_updates`]. Messages which can't be delivered are logged
+    /// and dropped without affecting the rest.
+    async fn deliver_contract_messages(
+        &mut self,
+        from: &ContractKey,
+        messages: Vec<ContractMessage>,
+    ) {
+        let mut queue = ContractMessageQueue::default();
+        if let Err(err) = queue.enqueue(*from.id(), messages, 0) {
+            tracing::warn!("dropped messages from contract {from}: {err}");
+        }
+        while let Some(msg) = queue.next_message() {
+            let key = ContractKey::from(msg.target);
+            let from = msg.from;
+            let Ok(parameters) = self.contract_state.get_params(&key).await else {
+                self.route_remote_update(key, msg);
+                continue;
+            };
+            let state = match self.contract_state.get(&key).await {
+                Ok(state) => state,
+                Err(err) => {
+                    tracing::warn!(
+                        "failed delivering message from {from} to contract {key}: {err}"
+                    );
+                    continue;
+                }
+            };
+            let depth = msg.depth;
+            let update = msg.into_update_data();
+            let causality = self.causal_histories.next(&key, &update);
+            self.runtime.set_causality(Some(&causality));
+            let modification = self
+                .runtime
+                .update_state(&key, &parameters, &state, &[update]);
+            self.runtime.set_causality(None);
+            let modification = match modification {
+                Ok(modification) => modification,
+                Err(err) => {
+                    tracing::warn!("contract {key} failed processing message: {err}");
+                    continue;
+                }
+            };
+            if let Some(new_state) = modification.new_state {
+                let new_state = WrappedState::new(new_state.into_bytes());
+                if let Err(err) = self
+                    .contract_state
+                    .store(key.clone(), new_state.clone(), None)
+                    .await
+                {
+                    tracing::warn!("failed storing contract {key} after processing message: {err}");
+                    continue;
+                }
+                self.causal_histories.record(&key, &causality);
+                if let Err(err) = self
+                    .send_update_notification(&key, &parameters, &new_state, &causality)
+                    .await
+                {
+                    tracing::warn!("failed notifying the update of contract {key}: {err:?}");
+                }
+            }
+            if let Err(err) = queue.enqueue(*key.id(), modification.messages, depth) {
+                tracing::warn!("dropped messages from contract {key}: {err}");
+            }
+        }
+    }
+
+    /// Routes a message to a contract not held by this node as an update of the contract.
+    fn route_remote_update(&self, key: ContractKey, msg: InboundContractMessage) {
+        let from = msg.from;
+        let Some(updates) = &self.remote_updates else {
+            tracing::warn!("contract {key} not available locally, dropping message from {from}");
+            return;
+        };
+        let request = ContractRequest::Update {
+            key: key.clone(),
+            data: msg.into_update_data(),
+        };
+        if updates.send(request).is_err() {
+            tracing::warn!("failed routing message from {from} to contract {key}");
+        }
+    }
+
     async fn send_update_notification<'a>(
         &mut self,
         key: &ContractKey,
         params: &Parameters<'a>,
         new_state: &WrappedState,
+        causality: &Causality,
     ) -> Result<(), Either<RequestError, DynError>> {
         if let Some(notifiers) = self.update_notifications.get(key) {
             let summaries = self.subscriber_summaries.get_mut(key).unwrap();
@@ -386,6 +551,7 @@ impl Executor {
                     .send(Ok(ContractResponse::UpdateNotification {
                         key: key.clone(),
                         update: update.to_owned().into(),
+                        causality: causality.clone(),
                     }
                     .into()))
                     .unwrap();
@@ -421,7 +587,8 @@ impl Executor {
             got_contract = Some(contract);
         }
         match self.contract_state.get(&key).await {
-            Ok(state) => Ok(ContractResponse::GetResponse {
+            Ok(state) => Ok(ContractResponse::KeyedGetResponse {
+                key,
                 contract: got_contract,
                 state,
             }
@@ -467,4 +634,41 @@ mod test {
         assert_eq!(counter, 1);
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn route_messages_to_remote_contracts() -> Result<(), Box<dyn std::error::Error>> {
+        const MAX_SIZE: i64 = 10 * 1024 * 1024;
+        const MAX_MEM_CACHE: u32 = 10_000_000;
+        let tmp_path = std::env::temp_dir().join("locutus-test");
+        let contract_store = ContractStore::new(tmp_path.join("executor-msg-test"), MAX_SIZE)?;
+        let state_store = StateStore::new(Storage::new().await?, MAX_MEM_CACHE).unwrap();
+        let mut executor =
+            Executor::new(contract_store, state_store, || {}, OperationMode::Local).await?;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        executor.route_remote_updates(tx);
+
+        let instance_id = |seed| {
+            ContractInstanceId::from((Parameters::from(vec![]), ContractCode::from(vec![seed])))
+        };
+        let (from, target) = (instance_id(1), instance_id(2));
+        let msg = ContractMessage {
+            target,
+            payload: vec![1, 2, 3],
+        };
+        executor
+            .deliver_contract_messages(&ContractKey::from(from), vec![msg])
+            .await;
+        match rx.try_recv()? {
+            ContractRequest::Update {
+                key,
+                data: UpdateData::RelatedDelta { related_to, delta },
+            } => {
+                assert_eq!(key.id(), &target);
+                assert_eq!(related_to, from);
+                assert_eq!(delta.as_ref(), &[1, 2, 3]);
+            }
+            other => panic!("unexpected request: {other:?}"),
+        }
+        Ok(())
+    }
 }
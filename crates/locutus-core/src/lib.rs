@@ -28,5 +28,8 @@ pub use executor::{Executor, OperationMode};
 pub use libp2p;
 pub use locutus_runtime;
 pub use node::PeerKey;
-pub use node::{InitPeerNode, NodeConfig};
+pub use node::{
+    discover_gateway, AllowlistContract, AllowlistError, Capabilities, InitPeerNode, NodeConfig,
+    PeerAllowlist, PortMappingError, PortMappingProtocol,
+};
 pub use ring::Location;
@@ -5,6 +5,7 @@ use std::{
     time::{Duration, SystemTime},
 };
 
+use libp2p::Multiaddr;
 use serde::{Deserialize, Serialize};
 use uuid::{
     v1::{Context, Timestamp},
@@ -12,8 +13,12 @@ use uuid::{
 };
 
 use crate::{
+    contract::StoreResponse,
     node::{ConnectionError, PeerKey},
-    operations::{get::GetMsg, join_ring::JoinRingMsg, put::PutMsg, subscribe::SubscribeMsg},
+    operations::{
+        get::GetMsg, join_ring::JoinRingMsg, put::PutMsg, sample::SampleMsg, seed::SeedMsg,
+        subscribe::SubscribeMsg,
+    },
     ring::{Location, PeerKeyLocation},
 };
 pub(crate) use sealed_msg_type::{TransactionType, TransactionTypeId};
@@ -66,6 +71,10 @@ impl Transaction {
     pub fn tx_type(&self) -> TransactionType {
         self.ty.desc()
     }
+
+    pub fn as_u128(&self) -> u128 {
+        self.id.as_u128()
+    }
 }
 
 impl Display for Transaction {
@@ -111,6 +120,8 @@ mod sealed_msg_type {
         Put,
         Get,
         Subscribe,
+        Sample,
+        Seed,
         Canceled,
     }
 
@@ -136,7 +147,9 @@ mod sealed_msg_type {
         JoinRing -> JoinRingMsg,
         Put -> PutMsg,
         Get -> GetMsg,
-        Subscribe -> SubscribeMsg
+        Subscribe -> SubscribeMsg,
+        Sample -> SampleMsg,
+        Seed -> SeedMsg
     });
 }
 
@@ -146,12 +159,20 @@ pub(crate) enum Message {
     Put(PutMsg),
     Get(GetMsg),
     Subscribe(SubscribeMsg),
+    Sample(SampleMsg),
+    Seed(SeedMsg),
     /// Failed a transaction, informing of cancellation.
     Canceled(Transaction),
 }
 
 pub(crate) trait InnerMessage {
     fn id(&self) -> &Transaction;
+
+    /// Whether handling the message applies the changes carried by the transaction at the
+    /// receiving peer, which must happen only once even if the message is replayed.
+    fn applies_changes(&self) -> bool {
+        false
+    }
 }
 
 /// Internal node events emitted to the event loop.
@@ -168,6 +189,12 @@ pub(crate) enum NodeEvent {
     /// Error while sending a message by the connection bridge from within the ops.
     #[serde(skip)]
     Error(ConnectionError),
+    /// Start accepting connections at the given address.
+    #[serde(skip)]
+    AddListener(Multiaddr),
+    /// Stop accepting new connections at the given address, keeping the established ones.
+    #[serde(skip)]
+    RemoveListener(Multiaddr),
 }
 
 impl Display for NodeEvent {
@@ -182,6 +209,8 @@ impl Display for NodeEvent {
                 f.write_str(&format!("AcceptConnection (from {peer})"))
             }
             NodeEvent::Error(err) => f.write_str(&format!("{err}")),
+            NodeEvent::AddListener(addr) => f.write_str(&format!("AddListener (at {addr})")),
+            NodeEvent::RemoveListener(addr) => f.write_str(&format!("RemoveListener (at {addr})")),
         }
     }
 }
@@ -194,6 +223,8 @@ impl Message {
             Put(op) => op.id(),
             Get(op) => op.id(),
             Subscribe(op) => op.id(),
+            Sample(op) => op.id(),
+            Seed(op) => op.id(),
             Canceled(tx) => tx,
         }
     }
@@ -205,10 +236,22 @@ impl Message {
             Put(op) => op.target(),
             Get(op) => op.target(),
             Subscribe(op) => op.target(),
+            Sample(op) => op.target(),
+            Seed(op) => op.target(),
             Canceled(_) => None,
         }
     }
 
+    /// Whether this message could have been sent by the peer authenticated at the other end of
+    /// the connection, messages which claim to be sent by an other peer must be dropped.
+    pub fn sent_by(&self, peer: &PeerKey) -> bool {
+        match self {
+            Message::Sample(op) => op.hop_sender().map_or(true, |from| &from.peer == peer),
+            Message::Seed(op) => op.sender().map_or(true, |from| &from.peer == peer),
+            _ => true,
+        }
+    }
+
     /// Is the last expected message for this chain of messages.
     pub fn terminal(&self) -> bool {
         use Message::*;
@@ -217,9 +260,37 @@ impl Message {
             Put(op) => op.terminal(),
             Get(op) => op.terminal(),
             Subscribe(op) => op.terminal(),
+            Sample(op) => op.terminal(),
+            Seed(op) => op.terminal(),
             Canceled(_) => true,
         }
     }
+
+    /// Class of traffic of the message. Requests initiated by users are interactive even when
+    /// carrying contracts, unless they are too large for an interactive frame.
+    pub fn traffic_class(&self) -> TrafficClass {
+        use Message::*;
+        match self {
+            Put(PutMsg::PutForward { .. })
+            | Get(GetMsg::ReturnGet {
+                value: StoreResponse {
+                    contract: Some(_), ..
+                },
+                ..
+            })
+            | Seed(SeedMsg::SnapshotChunk { .. }) => TrafficClass::Bulk,
+            _ => TrafficClass::Interactive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TrafficClass {
+    /// Messages driving on-going operations, latency sensitive.
+    Interactive,
+    /// Large transfers (contract code, whole states, cache snapshots) which should yield
+    /// to interactive traffic.
+    Bulk,
 }
 
 impl Display for Message {
@@ -231,6 +302,8 @@ impl Display for Message {
             Put(msg) => msg.fmt(f)?,
             Get(msg) => msg.fmt(f)?,
             Subscribe(msg) => msg.fmt(f)?,
+            Sample(msg) => msg.fmt(f)?,
+            Seed(msg) => msg.fmt(f)?,
             Canceled(msg) => msg.fmt(f)?,
         };
         write!(f, "}}")
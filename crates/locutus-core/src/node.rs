@@ -15,7 +15,11 @@ use libp2p::{
     multiaddr::Protocol,
     Multiaddr, PeerId,
 };
-use locutus_stdlib::client_api::{ClientRequest, ContractRequest};
+use locutus_stdlib::client_api::{
+    AdminResponse, AdminToken, ClientRequest, ContractRequest, ErrorKind, HostResponse, NodeAdmin,
+    NodeQuery, QueryResponse,
+};
+use tokio::sync::broadcast::error::RecvError;
 
 #[cfg(test)]
 use self::in_memory_impl::NodeInMemory;
@@ -34,15 +38,19 @@ use crate::{
     operations::{
         get,
         join_ring::{self, JoinRingMsg, JoinRingOp},
-        put, subscribe, OpEnum, OpError,
+        put, sample, seed, subscribe, OpEnum, OpError,
     },
     ring::{Location, PeerKeyLocation},
     util::{ExponentialBackoff, IterExt},
 };
 
 use crate::operations::handle_op_request;
+pub use conn_manager::allowlist::{AllowlistContract, AllowlistError, PeerAllowlist};
+pub use conn_manager::capabilities::Capabilities;
+pub use conn_manager::port_mapping::{discover_gateway, PortMappingError, PortMappingProtocol};
 pub(crate) use conn_manager::{ConnectionBridge, ConnectionError};
 pub(crate) use op_state::OpManager;
+pub(crate) use trace_sampling::TraceSampler;
 
 mod conn_manager;
 mod event_listener;
@@ -52,6 +60,7 @@ mod op_state;
 mod p2p_impl;
 #[cfg(test)]
 pub(crate) mod test;
+mod trace_sampling;
 
 pub struct Node<CErr>(NodeP2P<CErr>);
 
@@ -79,6 +88,8 @@ where
 pub struct NodeConfig<const CLIENTS: usize> {
     /// local peer private key in
     pub(crate) local_key: identity::Keypair,
+    /// whether the key is kept across restarts, instead of generated for this run
+    pub(crate) stable_key: bool,
     // optional local info, in case this is an initial bootstrap node
     /// IP to bind to the listener
     pub(crate) local_ip: Option<IpAddr>,
@@ -93,18 +104,35 @@ pub struct NodeConfig<const CLIENTS: usize> {
     pub(crate) rnd_if_htl_above: Option<usize>,
     pub(crate) max_number_conn: Option<usize>,
     pub(crate) min_number_conn: Option<usize>,
+    /// optional features this node is willing to use with peers supporting them
+    pub(crate) capabilities: Capabilities,
+    /// try to map the listening port in the local router through UPnP or NAT-PMP
+    pub(crate) port_mapping: bool,
+    /// for how long subscriptions to contracts cached by this node last unless renewed
+    pub(crate) subscription_lease: Option<Duration>,
+    /// autonomous system of the upstream network of this node, if known
+    pub(crate) asn: Option<u32>,
+    /// ratio of transactions for which diagnostics are recorded
+    pub(crate) trace_sample_rate: f64,
+    /// if set, only peers in the allowlist are accepted as connections
+    pub(crate) peer_allowlist: Option<PeerAllowlist>,
+    /// if set, the allowlist is kept in sync with the state of this contract
+    pub(crate) allowlist_contract: Option<AllowlistContract>,
+    /// if set, clients presenting this token can administer the node
+    pub(crate) admin_token: Option<AdminToken>,
     pub(crate) clients: [BoxedClient; CLIENTS],
 }
 
 impl<const CLIENTS: usize> NodeConfig<CLIENTS> {
     pub fn new(clients: [BoxedClient; CLIENTS]) -> NodeConfig<CLIENTS> {
-        let local_key = if let Some(key) = &CONFIG.local_peer_keypair {
-            key.clone()
+        let (local_key, stable_key) = if let Some(key) = &CONFIG.local_peer_keypair {
+            (key.clone(), true)
         } else {
-            identity::Keypair::generate_ed25519()
+            (identity::Keypair::generate_ed25519(), false)
         };
         NodeConfig {
             local_key,
+            stable_key,
             remote_nodes: Vec::with_capacity(1),
             local_ip: None,
             local_port: None,
@@ -113,6 +141,14 @@ impl<const CLIENTS: usize> NodeConfig<CLIENTS> {
             rnd_if_htl_above: None,
             max_number_conn: None,
             min_number_conn: None,
+            capabilities: Capabilities::SUPPORTED,
+            port_mapping: false,
+            subscription_lease: None,
+            asn: CONFIG.asn,
+            trace_sample_rate: CONFIG.trace_sample_rate,
+            peer_allowlist: None,
+            allowlist_contract: CONFIG.allowlist_contract.clone(),
+            admin_token: CONFIG.admin_token.clone(),
             clients,
         }
     }
@@ -151,6 +187,66 @@ impl<const CLIENTS: usize> NodeConfig<CLIENTS> {
     /// If not provided it will be either obtained from the configuration or freshly generated.
     pub fn with_key(&mut self, key: identity::Keypair) -> &mut Self {
         self.local_key = key;
+        self.stable_key = true;
+        self
+    }
+
+    /// Optional features advertised to other peers while connecting.
+    pub fn with_capabilities(&mut self, capabilities: Capabilities) -> &mut Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Automatically map the listening port in the local router at startup, so this node
+    /// can accept inbound connections from behind a NAT.
+    pub fn with_port_mapping(&mut self, enabled: bool) -> &mut Self {
+        self.port_mapping = enabled;
+        self
+    }
+
+    /// Duration of the subscriptions granted to other peers, which must renew them
+    /// periodically or are dropped as subscribers.
+    pub fn with_subscription_lease(&mut self, lease: Duration) -> &mut Self {
+        self.subscription_lease = Some(lease);
+        self
+    }
+
+    /// Autonomous system number of the upstream network of this node. Announced to other
+    /// peers so they can spread the replicas of contracts over distinct networks. Read from
+    /// `LOCUTUS_ASN` by default.
+    pub fn with_asn(&mut self, asn: u32) -> &mut Self {
+        self.asn = Some(asn);
+        self
+    }
+
+    /// Ratio of transactions, between 0 and 1, which are traced, logged and dumped when going
+    /// through the wire. Transactions which fail are always traced, including their most recent
+    /// messages handled before the failure. Read from `LOCUTUS_TRACE_SAMPLE_RATE` by default,
+    /// all of them if unset.
+    pub fn with_trace_sample_rate(&mut self, rate: f64) -> &mut Self {
+        self.trace_sample_rate = rate;
+        self
+    }
+
+    /// Only accept connections with the peers in the allowlist, for private networks.
+    /// Gateways must be in the allowlist too, otherwise the node won't be able to join.
+    pub fn with_peer_allowlist(&mut self, allowlist: PeerAllowlist) -> &mut Self {
+        self.peer_allowlist = Some(allowlist);
+        self
+    }
+
+    /// Read the allowlist from the state of a contract signed by the authority of the network,
+    /// and keep it in sync with the updates to the contract. Read from
+    /// `LOCUTUS_ALLOWLIST_CONTRACT` and `LOCUTUS_ALLOWLIST_AUTHORITY_KEY_FILE` by default.
+    pub fn with_allowlist_contract(&mut self, contract: AllowlistContract) -> &mut Self {
+        self.allowlist_contract = Some(contract);
+        self
+    }
+
+    /// Token the clients must present in node administration requests, which are rejected
+    /// if no token is set. Read from the file set through `LOCUTUS_ADMIN_TOKEN_FILE` by default.
+    pub fn with_admin_token(&mut self, token: AdminToken) -> &mut Self {
+        self.admin_token = Some(token);
         self
     }
 
@@ -293,6 +389,7 @@ where
 async fn client_event_handling<ClientEv, CErr>(
     op_storage: Arc<OpManager<CErr>>,
     mut client_events: ClientEv,
+    admin_token: Option<AdminToken>,
 ) where
     ClientEv: ClientEventsProxy + Send + Sync + 'static,
     CErr: std::error::Error + Send + Sync + 'static,
@@ -300,8 +397,21 @@ async fn client_event_handling<ClientEv, CErr>(
     loop {
         // fixme: send back responses to client
         let OpenRequest {
-            id: _id, request, ..
-        } = client_events.recv().await.unwrap(); // fixme: deal with this unwrap
+            id: _id,
+            request,
+            notification_channel,
+            ..
+        } = match client_events.recv().await {
+            Ok(request) => request,
+            Err(err) if matches!(err.kind(), ErrorKind::ChannelClosed) => {
+                tracing::debug!("Client events channel closed, stop handling client requests");
+                break;
+            }
+            Err(err) => {
+                tracing::error!("{err}");
+                continue;
+            }
+        };
         if let ClientRequest::Disconnect { .. } = request {
             if let Err(err) = op_storage.notify_internal_op(NodeEvent::ShutdownNode).await {
                 tracing::error!("{}", err);
@@ -310,6 +420,7 @@ async fn client_event_handling<ClientEv, CErr>(
         }
 
         let op_storage_cp = op_storage.clone();
+        let admin_token = admin_token.clone();
         GlobalExecutor::spawn(async move {
             match request {
                 ClientRequest::ContractOp(ops) => match ops {
@@ -329,7 +440,9 @@ async fn client_event_handling<ClientEv, CErr>(
                             op_storage_cp.ring.max_hops_to_live,
                             &op_storage_cp.ring.peer_key,
                         );
-                        if let Err(err) = put::request_put(&op_storage_cp, op).await {
+                        if let Err(err) =
+                            put::request_put(&op_storage_cp, op, notification_channel).await
+                        {
                             tracing::error!("{}", err);
                         }
                         todo!("use `related_contracts`: {related_contracts:?}")
@@ -388,28 +501,145 @@ async fn client_event_handling<ClientEv, CErr>(
                 },
                 ClientRequest::DelegateOp(_op) => todo!("FIXME: component op"),
                 ClientRequest::GenerateRandData { .. } => todo!("FIXME"),
+                ClientRequest::SampleNetwork { walk_length } => {
+                    // Initialize a network sample op.
+                    tracing::debug!(
+                        "Received network sample request from user event @ {}",
+                        &op_storage_cp.ring.peer_key
+                    );
+                    let op = sample::start_op(walk_length, &op_storage_cp.ring.peer_key);
+                    if let Err(err) =
+                        sample::request_sample(&op_storage_cp, op, notification_channel).await
+                    {
+                        tracing::error!("{}", err);
+                    }
+                }
+                ClientRequest::SubscribeNodeEvents => {
+                    let Some(notification_channel) = notification_channel else {
+                        tracing::warn!("Missing notification channel for node events subscription");
+                        return;
+                    };
+                    let mut node_events = op_storage_cp.ring.subscribe_node_events();
+                    loop {
+                        match node_events.recv().await {
+                            Ok(event) => {
+                                if notification_channel
+                                    .send(Ok(HostResponse::NodeEvent(event)))
+                                    .is_err()
+                                {
+                                    // the client is gone
+                                    break;
+                                }
+                            }
+                            Err(RecvError::Lagged(skipped)) => {
+                                tracing::warn!(
+                                    "Client lagged behind, skipped {skipped} node events"
+                                );
+                            }
+                            Err(RecvError::Closed) => break,
+                        }
+                    }
+                }
+                ClientRequest::NodeQueries(query) => {
+                    let response = match query {
+                        NodeQuery::ConnectedPeers { filter, page } => {
+                            let peers = op_storage_cp.ring.connected_peers();
+                            QueryResponse::ConnectedPeers(page.paginate(&filter, peers))
+                        }
+                        NodeQuery::CachedContracts { filter, page } => {
+                            let contracts = op_storage_cp.ring.cached_contracts();
+                            QueryResponse::CachedContracts(page.paginate(&filter, contracts))
+                        }
+                    };
+                    let Some(notification_channel) = notification_channel else {
+                        tracing::warn!("Missing notification channel for node query response");
+                        return;
+                    };
+                    let _ = notification_channel.send(Ok(HostResponse::QueryResponse(response)));
+                }
+                ClientRequest::NodeAdmin { request, token } => {
+                    let authorized = admin_token
+                        .as_ref()
+                        .is_some_and(|admin_token| admin_token.matches(&token));
+                    let response = if authorized {
+                        node_admin(&op_storage_cp, request).await
+                    } else {
+                        tracing::warn!("Rejected unauthorized node administration request");
+                        AdminResponse::Rejected {
+                            cause: "unauthorized".to_owned(),
+                        }
+                    };
+                    let Some(notification_channel) = notification_channel else {
+                        tracing::warn!("Missing notification channel for node admin response");
+                        return;
+                    };
+                    let _ = notification_channel.send(Ok(HostResponse::AdminResponse(response)));
+                }
                 ClientRequest::Disconnect { .. } => unreachable!(),
             }
         });
     }
 }
 
+/// Applies an administration change to the node, the outcome is reported as a node event.
+async fn node_admin<CErr>(op_storage: &OpManager<CErr>, request: NodeAdmin) -> AdminResponse
+where
+    CErr: std::error::Error,
+{
+    let event = match request {
+        NodeAdmin::AddListener { address } => {
+            address.parse::<Multiaddr>().map(NodeEvent::AddListener)
+        }
+        NodeAdmin::RemoveListener { address } => {
+            address.parse::<Multiaddr>().map(NodeEvent::RemoveListener)
+        }
+        other => {
+            return AdminResponse::Rejected {
+                cause: format!("unsupported request: {other}"),
+            }
+        }
+    };
+    match event {
+        Ok(event) => match op_storage.notify_internal_op(event).await {
+            Ok(()) => AdminResponse::Accepted,
+            Err(_) => AdminResponse::Rejected {
+                cause: "the node is shutting down".to_owned(),
+            },
+        },
+        Err(err) => AdminResponse::Rejected {
+            cause: format!("invalid address: {err}"),
+        },
+    }
+}
+
 macro_rules! log_handling_msg {
     ($op:expr, $id:expr, $op_storage:ident) => {
-        tracing::debug!(
-            concat!("Handling ", $op, " get request @ {} (tx: {})"),
-            $op_storage.ring.peer_key,
-            $id
-        );
+        if $op_storage.trace_sampler.is_sampled(&$id) {
+            tracing::debug!(
+                concat!("Handling ", $op, " get request @ {} (tx: {})"),
+                $op_storage.ring.peer_key,
+                $id
+            );
+        }
     };
 }
 
 #[inline(always)]
-fn report_result<CErr>(op_result: Result<(), OpError<CErr>>)
-where
+fn report_result<CErr>(
+    tx: Option<Transaction>,
+    op_result: Result<(), OpError<CErr>>,
+    op_storage: &OpManager<CErr>,
+) where
     CErr: std::error::Error,
 {
     if let Err(err) = op_result {
+        // failures are always traced, as well as the messages of the failed transaction
+        // handled before and after the failure
+        if let Some(tx) = tx {
+            for msg in op_storage.trace_sampler.sample_on_error(tx) {
+                tracing::debug!("Handled before the failure of tx {}: {}", tx, msg);
+            }
+        }
         tracing::debug!("Finished tx w/ error: {}", err)
     }
 }
@@ -418,15 +648,21 @@ async fn process_message<CErr, CB>(
     msg: Result<Message, ConnectionError>,
     op_storage: Arc<OpManager<CErr>>,
     mut conn_manager: CB,
-    event_listener: Option<Box<dyn EventListener + Send + Sync>>,
+    mut event_listener: Option<Box<dyn EventListener + Send + Sync>>,
 ) where
     CB: ConnectionBridge,
     CErr: std::error::Error + Sync + Send + 'static,
 {
     match msg {
         Ok(msg) => {
-            if let Some(mut listener) = event_listener {
-                listener.event_received(EventLog::new(&msg, &op_storage));
+            let tx = *msg.id();
+            op_storage.record_message(&msg);
+            if op_storage.trace_sampler.is_sampled(&tx) {
+                if let Some(listener) = &mut event_listener {
+                    listener.event_received(EventLog::new(&msg, &op_storage));
+                }
+            } else {
+                op_storage.trace_sampler.buffer(&msg);
             }
             match msg {
                 Message::JoinRing(op) => {
@@ -437,21 +673,21 @@ async fn process_message<CErr, CB>(
                         op,
                     )
                     .await;
-                    report_result(op_result);
+                    report_result(Some(tx), op_result, &op_storage);
                 }
                 Message::Put(op) => {
                     log_handling_msg!("put", *op.id(), op_storage);
                     let op_result =
                         handle_op_request::<put::PutOp, _, _>(&op_storage, &mut conn_manager, op)
                             .await;
-                    report_result(op_result);
+                    report_result(Some(tx), op_result, &op_storage);
                 }
                 Message::Get(op) => {
                     log_handling_msg!("get", op.id(), op_storage);
                     let op_result =
                         handle_op_request::<get::GetOp, _, _>(&op_storage, &mut conn_manager, op)
                             .await;
-                    report_result(op_result);
+                    report_result(Some(tx), op_result, &op_storage);
                 }
                 Message::Subscribe(op) => {
                     log_handling_msg!("subscribe", op.id(), op_storage);
@@ -461,13 +697,30 @@ async fn process_message<CErr, CB>(
                         op,
                     )
                     .await;
-                    report_result(op_result);
+                    report_result(Some(tx), op_result, &op_storage);
+                }
+                Message::Sample(op) => {
+                    log_handling_msg!("sample", op.id(), op_storage);
+                    let op_result = handle_op_request::<sample::SampleOp, _, _>(
+                        &op_storage,
+                        &mut conn_manager,
+                        op,
+                    )
+                    .await;
+                    report_result(Some(tx), op_result, &op_storage);
+                }
+                Message::Seed(op) => {
+                    log_handling_msg!("seed", op.id(), op_storage);
+                    let op_result =
+                        handle_op_request::<seed::SeedOp, _, _>(&op_storage, &mut conn_manager, op)
+                            .await;
+                    report_result(Some(tx), op_result, &op_storage);
                 }
                 _ => {}
             }
         }
         Err(err) => {
-            report_result::<CErr>(Err(err.into()));
+            report_result(None, Err(err.into()), &op_storage);
         }
     }
 }
@@ -528,6 +781,18 @@ where
                 _ => {}
             }
         }
+        TransactionType::Sample => {
+            // a peer failed somewhere along the walk, just give up on this sample
+            op_storage.pop(&tx);
+            op_storage.notify_client(
+                &tx,
+                Err(ErrorKind::Other("the random walk failed".to_owned()).into()),
+            );
+        }
+        TransactionType::Seed => {
+            // seeding is best effort, the cache will be eventually filled passively
+            op_storage.pop(&tx);
+        }
         _ => unreachable!(),
     }
     Ok(())
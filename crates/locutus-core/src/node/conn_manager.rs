@@ -6,9 +6,14 @@ use serde::{Deserialize, Serialize};
 use super::PeerKey;
 use crate::message::Message;
 
+pub(crate) mod allowlist;
+pub(crate) mod bulk;
+pub(crate) mod capabilities;
+pub(crate) mod codec;
 #[cfg(test)]
 pub(crate) mod in_memory;
 pub(crate) mod p2p_protoc;
+pub(crate) mod port_mapping;
 
 // TODO: use this constants when we do real net i/o
 // const PING_EVERY: Duration = Duration::from_secs(30);
@@ -34,6 +39,12 @@ pub(crate) enum ConnectionError {
     #[error("error while de/serializing message")]
     #[serde(skip)]
     Serialization(#[from] Option<Box<bincode::ErrorKind>>),
+    #[error("frame of {size} bytes exceeds the max size of {max} bytes")]
+    FrameTooLarge { size: usize, max: usize },
+    #[error("unsupported message envelope version {0}")]
+    UnsupportedEnvelope(u8),
+    #[error("peer {0} is not in the allowlist")]
+    NotAllowed(PeerKey),
 
     // errors produced while handling the connection:
     #[serde(skip)]
@@ -50,6 +61,12 @@ impl Clone for ConnectionError {
             Self::LocationUnknown => Self::LocationUnknown,
             Self::Serialization(_) => Self::Serialization(None),
             Self::SendNotCompleted => Self::SendNotCompleted,
+            Self::FrameTooLarge { size, max } => Self::FrameTooLarge {
+                size: *size,
+                max: *max,
+            },
+            Self::UnsupportedEnvelope(version) => Self::UnsupportedEnvelope(*version),
+            Self::NotAllowed(peer) => Self::NotAllowed(*peer),
             Self::IOError(_) => Self::IOError(None),
             Self::NegotiationError(_) => Self::NegotiationError(None),
         }
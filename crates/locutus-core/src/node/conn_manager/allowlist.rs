@@ -0,0 +1,243 @@
+//! Allowlist of the peers a node is willing to connect to, for private or permissioned networks.
+//!
+//! When a node is configured with an allowlist, connections with any peer whose key is not
+//! in the list are dropped as soon as the handshake authenticates the key of the peer, so
+//! consortium rings can run on the same codebase as the public network without mixing with it.
+//!
+//! The allowlist can be read either from a local file, listing one peer id per line, or from
+//! a list signed by the authority of the network, which is only accepted if the signature is
+//! valid for the key of the authority. The signed list can be published as the state of an
+//! allowlist contract, in which case the node keeps its allowlist in sync with the contract
+//! (see [`AllowlistContract`]).
+
+use std::{collections::HashSet, fs, io, path::Path, str::FromStr, sync::Arc, time::Duration};
+
+use libp2p::{
+    core::PublicKey,
+    identity::{error::SigningError, Keypair},
+    PeerId,
+};
+use locutus_runtime::prelude::ContractKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    contract::{ContractHandlerEvent, StoreResponse},
+    message::NodeEvent,
+    node::{OpManager, PeerKey},
+    operations::{get, subscribe},
+    WrappedState,
+};
+
+/// How often the state of the allowlist contract is checked for changes.
+const CONTRACT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, thiserror::Error)]
+pub enum AllowlistError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid peer id at line {line}")]
+    InvalidPeer { line: usize },
+    #[error("invalid allowlist encoding: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("the allowlist signature doesn't match the authority key")]
+    InvalidSignature,
+    #[error(transparent)]
+    Signing(#[from] SigningError),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignedAllowlist {
+    peers: Vec<PeerKey>,
+    signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAllowlist {
+    peers: HashSet<PeerKey>,
+}
+
+impl PeerAllowlist {
+    pub fn new(peers: impl IntoIterator<Item = PeerKey>) -> Self {
+        Self {
+            peers: peers.into_iter().collect(),
+        }
+    }
+
+    /// Reads the allowlist from a file with one peer id per line. Empty lines and lines
+    /// starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self, AllowlistError> {
+        let contents = fs::read_to_string(path)?;
+        let mut peers = HashSet::new();
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let peer = PeerId::from_str(line)
+                .map_err(|_| AllowlistError::InvalidPeer { line: idx + 1 })?;
+            peers.insert(PeerKey::from(peer));
+        }
+        Ok(Self { peers })
+    }
+
+    /// Decodes an allowlist signed by the authority of the network, see [`Self::sign`].
+    pub fn from_signed(bytes: &[u8], authority: &PublicKey) -> Result<Self, AllowlistError> {
+        let signed: SignedAllowlist = bincode::deserialize(bytes)?;
+        let payload = bincode::serialize(&signed.peers)?;
+        if !authority.verify(&payload, &signed.signature) {
+            return Err(AllowlistError::InvalidSignature);
+        }
+        Ok(Self::new(signed.peers))
+    }
+
+    /// Encodes the allowlist signed with the key of the authority of the network.
+    pub fn sign(&self, authority: &Keypair) -> Result<Vec<u8>, AllowlistError> {
+        let mut peers: Vec<_> = self.peers.iter().copied().collect();
+        peers.sort();
+        let signature = authority.sign(&bincode::serialize(&peers)?)?;
+        Ok(bincode::serialize(&SignedAllowlist { peers, signature })?)
+    }
+
+    pub fn allows(&self, peer: &PeerKey) -> bool {
+        self.peers.contains(peer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+/// Contract holding the allowlist of a network as its state, signed by the authority of the
+/// network (see [`PeerAllowlist::sign`]).
+///
+/// Until the contract is read only the gateways are accepted, unless an initial allowlist is
+/// configured too.
+#[derive(Debug, Clone)]
+pub struct AllowlistContract {
+    pub key: ContractKey,
+    pub authority: PublicKey,
+}
+
+/// Keeps the allowlist of the node in sync with the allowlist contract of the network.
+///
+/// The contract is fetched and subscribed to, so the updates published by the authority reach
+/// the node, and every new state is only applied if signed by the authority. Connections with
+/// the peers removed from the allowlist are dropped.
+pub(crate) async fn sync_allowlist_contract<CErr>(
+    op_storage: Arc<OpManager<CErr>>,
+    contract: AllowlistContract,
+) where
+    CErr: std::error::Error,
+{
+    let key = &contract.key;
+    let peer_key = op_storage.ring.peer_key;
+    let mut applied: Option<WrappedState> = None;
+    let mut interval = tokio::time::interval(CONTRACT_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if op_storage.is_shut_down() {
+            break;
+        }
+        if !op_storage.ring.is_contract_cached(key) {
+            let op = get::start_op(key.clone(), true, &peer_key);
+            if let Err(err) = get::request_get(&op_storage, op).await {
+                tracing::warn!("Failed fetching the allowlist contract {key}: {err}");
+            }
+            continue;
+        }
+        if !op_storage.ring.is_subscribed(key) {
+            let op = subscribe::start_op(key.clone(), &peer_key);
+            if let Err(err) = subscribe::request_subscribe(&op_storage, op).await {
+                tracing::warn!("Failed subscribing to the allowlist contract {key}: {err}");
+            }
+        }
+        let state = match op_storage
+            .notify_contract_handler(ContractHandlerEvent::FetchQuery {
+                key: key.clone(),
+                fetch_contract: false,
+            })
+            .await
+        {
+            Ok(ContractHandlerEvent::FetchResponse {
+                response:
+                    Ok(StoreResponse {
+                        state: Some(state), ..
+                    }),
+                ..
+            }) => state,
+            Ok(_) => continue,
+            Err(err) => {
+                tracing::warn!("Failed reading the allowlist contract {key}: {err}");
+                continue;
+            }
+        };
+        if applied.as_ref() == Some(&state) {
+            continue;
+        }
+        match PeerAllowlist::from_signed(state.as_ref(), &contract.authority) {
+            Ok(allowlist) => {
+                tracing::info!(
+                    "Updated the peer allowlist from contract {key}, {} peers allowed",
+                    allowlist.len()
+                );
+                for peer in op_storage.ring.update_allowlist(allowlist) {
+                    tracing::info!("Peer {peer} was removed from the allowlist, disconnecting");
+                    if let Err(err) = op_storage
+                        .notify_internal_op(NodeEvent::DropConnection(peer))
+                        .await
+                    {
+                        tracing::error!("{err}");
+                        return;
+                    }
+                }
+            }
+            Err(err) => tracing::warn!("Ignoring the state of allowlist contract {key}: {err}"),
+        }
+        applied = Some(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_from_file() -> Result<(), AllowlistError> {
+        let (allowed, other) = (PeerKey::random(), PeerKey::random());
+        let path = std::env::temp_dir().join(format!("locutus-allowlist-{allowed}"));
+        fs::write(&path, format!("# consortium members\n\n{allowed}\n"))?;
+        let allowlist = PeerAllowlist::from_file(&path)?;
+        assert!(allowlist.allows(&allowed));
+        assert!(!allowlist.allows(&other));
+
+        fs::write(&path, format!("{allowed}\nnot-a-peer\n"))?;
+        assert!(matches!(
+            PeerAllowlist::from_file(&path),
+            Err(AllowlistError::InvalidPeer { line: 2 })
+        ));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn verify_signed() -> Result<(), AllowlistError> {
+        let authority = Keypair::generate_ed25519();
+        let allowlist = PeerAllowlist::new([PeerKey::random(), PeerKey::random()]);
+        let signed = allowlist.sign(&authority)?;
+        assert_eq!(
+            PeerAllowlist::from_signed(&signed, &authority.public())?,
+            allowlist
+        );
+
+        let impostor = Keypair::generate_ed25519();
+        assert!(matches!(
+            PeerAllowlist::from_signed(&signed, &impostor.public()),
+            Err(AllowlistError::InvalidSignature)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,469 @@
+//! Background channel for bulk transfers.
+//!
+//! Contract code, whole states and cache snapshots can be orders of magnitude larger than the
+//! messages driving operations forward. Sending both over the same substreams means a single
+//! large transfer can hold interactive traffic back for seconds on constrained links. Bulk
+//! messages are sent instead over a dedicated substream whose sending rate is governed by a
+//! delay-based controller inspired by LEDBAT (RFC 6817). Unlike LEDBAT, which relies on the
+//! one-way delay reported by the receiver, the delay sampled here is the time it takes to
+//! flush the substream, that is, the backpressure of the local transport: the window grows
+//! while flushes complete close to the fastest ones observed and shrinks as soon as they slow
+//! down, so bulk transfers mostly use the spare capacity of the link.
+//!
+//! Peers which refuse bulk substreams get bulk messages over interactive substreams instead,
+//! as long as they fit in an interactive frame, and opening a bulk substream is tried again
+//! after a while.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::Sink;
+
+use super::{
+    codec::{BulkCodec, InteractiveCodec, WireCodec},
+    ConnectionError,
+};
+use crate::{message::Message, util::ExponentialBackoff};
+
+/// Flush delay over the base one the controller aims for.
+const TARGET_DELAY: Duration = Duration::from_millis(100);
+/// Maximum segment size, the window grows at most by this per round trip.
+const MSS: usize = 16 * 1024;
+const GAIN: f64 = 1.0;
+const MIN_WINDOW: usize = MSS;
+const INITIAL_WINDOW: usize = 4 * MSS;
+const MAX_WINDOW: usize = 16 * 1024 * 1024;
+/// Base delays are tracked as the minimum delay observed in each bucket.
+const BASE_DELAY_BUCKET: Duration = Duration::from_secs(60);
+const BASE_DELAY_BUCKETS: usize = 10;
+/// Bulk substreams are opened again after the remote refused one at most this late, the delay
+/// doubling on every consecutive refusal.
+const REOPEN_DELAY: Duration = Duration::from_secs(10);
+const MAX_REOPEN_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Delay-based congestion controller for bulk transfers, driven by the flush latency of the
+/// bulk substream.
+pub(super) struct DelayController {
+    /// Bytes which can be in flight at any time.
+    window: usize,
+    /// Minimum delays observed, one per bucket, oldest first.
+    base_delays: VecDeque<(Instant, Duration)>,
+}
+
+impl Default for DelayController {
+    fn default() -> Self {
+        Self {
+            window: INITIAL_WINDOW,
+            base_delays: VecDeque::with_capacity(BASE_DELAY_BUCKETS),
+        }
+    }
+}
+
+impl DelayController {
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Whether a message of the given size can be sent with `in_flight` bytes not yet
+    /// acknowledged. A message is always allowed when nothing is in flight, otherwise messages
+    /// larger than the window would never be sent.
+    pub fn can_send(&self, in_flight: usize, size: usize) -> bool {
+        in_flight == 0 || in_flight + size <= self.window
+    }
+
+    /// Adjusts the window after `bytes` were flushed, with `delay` being the time it took.
+    pub fn on_delivered(&mut self, bytes: usize, delay: Duration) {
+        self.update_base_delay(Instant::now(), delay);
+        let base_delay = self.base_delay().unwrap_or(delay);
+        let queuing_delay = delay.saturating_sub(base_delay);
+        let off_target =
+            (TARGET_DELAY.as_secs_f64() - queuing_delay.as_secs_f64()) / TARGET_DELAY.as_secs_f64();
+        let change = GAIN * off_target * bytes as f64 * MSS as f64 / self.window as f64;
+        // never grow faster than slow start would
+        let change = change.min(bytes as f64);
+        let window = (self.window as f64 + change).max(0.0) as usize;
+        self.window = window.clamp(MIN_WINDOW, MAX_WINDOW);
+    }
+
+    /// Backs off after a transfer failed.
+    pub fn on_loss(&mut self) {
+        self.window = (self.window / 2).max(MIN_WINDOW);
+    }
+
+    fn base_delay(&self) -> Option<Duration> {
+        self.base_delays.iter().map(|(_, delay)| *delay).min()
+    }
+
+    fn update_base_delay(&mut self, now: Instant, delay: Duration) {
+        match self.base_delays.back_mut() {
+            Some((started, min_delay)) if now.duration_since(*started) < BASE_DELAY_BUCKET => {
+                *min_delay = (*min_delay).min(delay);
+            }
+            _ => {
+                if self.base_delays.len() == BASE_DELAY_BUCKETS {
+                    self.base_delays.pop_front();
+                }
+                self.base_delays.push_back((now, delay));
+            }
+        }
+    }
+}
+
+enum BulkStream<S> {
+    /// No substream open.
+    Closed,
+    /// Waiting for the outbound substream to be negotiated.
+    Opening,
+    Idle(S),
+    /// Waiting for the messages written to be flushed.
+    Flushing {
+        stream: S,
+        started: Instant,
+        bytes: usize,
+    },
+}
+
+pub(super) enum BulkPoll {
+    /// A new outbound substream must be requested.
+    OpenStream,
+    Error(ConnectionError),
+    Pending,
+}
+
+/// Outbound queue of bulk messages, sent over a dedicated substream.
+pub(super) struct BulkChannel<S> {
+    queue: VecDeque<(Message, usize)>,
+    controller: DelayController,
+    stream: BulkStream<S>,
+    /// The remote refused a bulk substream, until then bulk messages are sent over interactive
+    /// substreams.
+    fallback_until: Option<Instant>,
+    reopen_backoff: ExponentialBackoff,
+    /// Messages which could not be sent, to be reported.
+    errors: VecDeque<ConnectionError>,
+}
+
+impl<S> Default for BulkChannel<S> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            controller: DelayController::default(),
+            stream: BulkStream::Closed,
+            fallback_until: None,
+            reopen_backoff: ExponentialBackoff::new(REOPEN_DELAY, MAX_REOPEN_DELAY, usize::MAX),
+            errors: VecDeque::new(),
+        }
+    }
+}
+
+impl<S> BulkChannel<S>
+where
+    S: Sink<Message, Error = ConnectionError> + Unpin,
+{
+    /// Queues a message, returning it back if it must be sent over an interactive substream.
+    pub fn push(&mut self, msg: Message) -> Option<Message> {
+        self.push_at(msg, Instant::now())
+    }
+
+    fn push_at(&mut self, msg: Message, now: Instant) -> Option<Message> {
+        if matches!(self.fallback_until, Some(until) if now < until) {
+            return self.fallback(msg);
+        }
+        self.fallback_until = None;
+        match BulkCodec::encoded_len(&msg) {
+            Ok(size) => self.queue.push_back((msg, size)),
+            Err(err) => self.errors.push_back(err),
+        }
+        None
+    }
+
+    /// Returns the message to be sent over an interactive substream if it fits in an
+    /// interactive frame, otherwise it can't be sent to this peer and is reported as an error.
+    fn fallback(&mut self, msg: Message) -> Option<Message> {
+        match InteractiveCodec::encoded_len(&msg) {
+            Ok(size) if size <= InteractiveCodec::MAX_FRAME_SIZE => return Some(msg),
+            Ok(size) => self.errors.push_back(ConnectionError::FrameTooLarge {
+                size,
+                max: InteractiveCodec::MAX_FRAME_SIZE,
+            }),
+            Err(err) => self.errors.push_back(err),
+        }
+        None
+    }
+
+    /// Whether there are messages queued or being sent, or errors to report.
+    pub fn is_busy(&self) -> bool {
+        !self.queue.is_empty()
+            || !self.errors.is_empty()
+            || matches!(self.stream, BulkStream::Flushing { .. })
+    }
+
+    pub fn stream_opened(&mut self, stream: S) {
+        self.stream = BulkStream::Idle(stream);
+        self.reopen_backoff.reset();
+    }
+
+    /// The remote refused the bulk substream, returns the messages queued which fit in an
+    /// interactive frame so they can be sent over interactive substreams instead. Bulk
+    /// messages are sent the same way until opening a bulk substream is tried again.
+    pub fn open_failed(&mut self) -> Vec<Message> {
+        self.open_failed_at(Instant::now())
+    }
+
+    fn open_failed_at(&mut self, now: Instant) -> Vec<Message> {
+        let delay = self.reopen_backoff.next_delay().unwrap_or(MAX_REOPEN_DELAY);
+        self.fallback_until = Some(now + delay);
+        self.stream = BulkStream::Closed;
+        let queued: Vec<_> = self.queue.drain(..).map(|(msg, _)| msg).collect();
+        queued
+            .into_iter()
+            .filter_map(|msg| self.fallback(msg))
+            .collect()
+    }
+
+    /// Makes progress sending queued messages. New messages are only written while there is
+    /// no interactive traffic in progress.
+    pub fn poll_send(&mut self, cx: &mut Context<'_>, yield_to_interactive: bool) -> BulkPoll {
+        if let Some(err) = self.errors.pop_front() {
+            return BulkPoll::Error(err);
+        }
+        loop {
+            match std::mem::replace(&mut self.stream, BulkStream::Closed) {
+                BulkStream::Closed => {
+                    if self.queue.is_empty() {
+                        return BulkPoll::Pending;
+                    }
+                    self.stream = BulkStream::Opening;
+                    return BulkPoll::OpenStream;
+                }
+                BulkStream::Opening => {
+                    self.stream = BulkStream::Opening;
+                    return BulkPoll::Pending;
+                }
+                BulkStream::Idle(mut stream) => {
+                    if yield_to_interactive {
+                        self.stream = BulkStream::Idle(stream);
+                        return BulkPoll::Pending;
+                    }
+                    let mut bytes = 0;
+                    while let Some((_, size)) = self.queue.front() {
+                        if !self.controller.can_send(bytes, *size) {
+                            break;
+                        }
+                        match Sink::poll_ready(Pin::new(&mut stream), cx) {
+                            Poll::Ready(Ok(())) => {
+                                let (msg, size) = self.queue.pop_front().unwrap();
+                                if let Err(err) = Sink::start_send(Pin::new(&mut stream), msg) {
+                                    self.controller.on_loss();
+                                    return BulkPoll::Error(err);
+                                }
+                                bytes += size;
+                            }
+                            Poll::Pending => break,
+                            Poll::Ready(Err(err)) => {
+                                self.controller.on_loss();
+                                return BulkPoll::Error(err);
+                            }
+                        }
+                    }
+                    if bytes == 0 {
+                        self.stream = BulkStream::Idle(stream);
+                        return BulkPoll::Pending;
+                    }
+                    self.stream = BulkStream::Flushing {
+                        stream,
+                        started: Instant::now(),
+                        bytes,
+                    };
+                }
+                BulkStream::Flushing {
+                    mut stream,
+                    started,
+                    bytes,
+                } => match Sink::poll_flush(Pin::new(&mut stream), cx) {
+                    Poll::Ready(Ok(())) => {
+                        self.controller.on_delivered(bytes, started.elapsed());
+                        self.stream = BulkStream::Idle(stream);
+                    }
+                    Poll::Pending => {
+                        self.stream = BulkStream::Flushing {
+                            stream,
+                            started,
+                            bytes,
+                        };
+                        return BulkPoll::Pending;
+                    }
+                    Poll::Ready(Err(err)) => {
+                        self.controller.on_loss();
+                        return BulkPoll::Error(err);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use locutus_runtime::{
+        ContractCode, ContractContainer, Parameters, WasmAPIVersion, WrappedContract,
+    };
+
+    use super::*;
+    use crate::{
+        message::{Transaction, TxType},
+        node::PeerKey,
+        operations::put::PutMsg,
+        ring::PeerKeyLocation,
+        WrappedState,
+    };
+
+    /// Sink discarding every message.
+    struct NullSink;
+
+    impl Sink<Message> for NullSink {
+        type Error = ConnectionError;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, _: Message) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn put_request(code_size: usize) -> Message {
+        let contract = ContractContainer::Wasm(WasmAPIVersion::V1(WrappedContract::new(
+            Arc::new(ContractCode::from(vec![0; code_size])),
+            Parameters::from(vec![]),
+        )));
+        PutMsg::RequestPut {
+            id: Transaction::new(<PutMsg as TxType>::tx_type_id(), &PeerKey::random()),
+            contract,
+            value: WrappedState::new(vec![]),
+            htl: 1,
+            target: PeerKeyLocation::random(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn fall_back_within_interactive_frame_limit() {
+        let mut channel = BulkChannel::<NullSink>::default();
+        let now = Instant::now();
+        assert!(channel.push_at(put_request(1024), now).is_none());
+        assert!(channel
+            .push_at(put_request(InteractiveCodec::MAX_FRAME_SIZE), now)
+            .is_none());
+        assert_eq!(channel.open_failed_at(now).len(), 1);
+
+        assert!(channel.push_at(put_request(1024), now).is_some());
+        assert!(channel
+            .push_at(put_request(InteractiveCodec::MAX_FRAME_SIZE), now)
+            .is_none());
+        assert!(channel.is_busy());
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..2 {
+            assert!(matches!(
+                channel.poll_send(&mut cx, false),
+                BulkPoll::Error(ConnectionError::FrameTooLarge { max, .. })
+                    if max == InteractiveCodec::MAX_FRAME_SIZE
+            ));
+        }
+        assert!(matches!(
+            channel.poll_send(&mut cx, false),
+            BulkPoll::Pending
+        ));
+        assert!(!channel.is_busy());
+    }
+
+    #[test]
+    fn reopen_after_refusal() {
+        let mut channel = BulkChannel::<NullSink>::default();
+        let now = Instant::now();
+        channel.open_failed_at(now);
+        assert!(channel
+            .push_at(put_request(1024), now + REOPEN_DELAY / 2)
+            .is_some());
+        assert!(channel
+            .push_at(put_request(1024), now + REOPEN_DELAY)
+            .is_none());
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(matches!(
+            channel.poll_send(&mut cx, false),
+            BulkPoll::OpenStream
+        ));
+
+        // consecutive refusals wait longer
+        assert_eq!(channel.open_failed_at(now).len(), 1);
+        assert!(channel
+            .push_at(put_request(1024), now + REOPEN_DELAY)
+            .is_some());
+        assert!(channel
+            .push_at(put_request(1024), now + REOPEN_DELAY * 2)
+            .is_none());
+
+        channel.stream_opened(NullSink);
+        channel.open_failed_at(now);
+        assert!(channel
+            .push_at(put_request(1024), now + REOPEN_DELAY)
+            .is_none());
+    }
+
+    #[test]
+    fn grow_window_without_queuing() {
+        let mut controller = DelayController::default();
+        let base = Duration::from_millis(20);
+        for _ in 0..10 {
+            controller.on_delivered(controller.window(), base);
+        }
+        assert!(controller.window() > INITIAL_WINDOW);
+    }
+
+    #[test]
+    fn shrink_window_when_delay_builds_up() {
+        let mut controller = DelayController::default();
+        let base = Duration::from_millis(20);
+        controller.on_delivered(MSS, base);
+        let window = controller.window();
+        controller.on_delivered(MSS, base + TARGET_DELAY * 3);
+        assert!(controller.window() < window);
+
+        for _ in 0..100 {
+            controller.on_delivered(controller.window(), base + TARGET_DELAY * 3);
+        }
+        assert_eq!(controller.window(), MIN_WINDOW);
+        assert!(controller.can_send(0, MAX_WINDOW));
+        assert!(!controller.can_send(1, MIN_WINDOW));
+    }
+
+    #[test]
+    fn back_off_on_loss() {
+        let mut controller = DelayController::default();
+        controller.on_loss();
+        assert_eq!(controller.window(), INITIAL_WINDOW / 2);
+        for _ in 0..10 {
+            controller.on_loss();
+        }
+        assert_eq!(controller.window(), MIN_WINDOW);
+    }
+}
@@ -0,0 +1,96 @@
+//! Optional protocol features supported by a peer, exchanged at handshake time.
+//!
+//! Both ends of a new ring connection announce their capabilities in the join handshake
+//! messages, each end keeps the intersection of both sets and only uses features supported by
+//! both, so optional features can ship incrementally. Capabilities unknown to this node are
+//! dropped by the intersection, and peers which don't announce any are assumed to support none
+//! of them.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Messages can be compressed before being sent.
+    pub const COMPRESSION: Self = Self(1);
+    /// Large values can be streamed in chunks instead of sent as a single message.
+    pub const STREAMING: Self = Self(1 << 1);
+    /// Ranges of a contract state can be requested instead of the whole state.
+    pub const PARTIAL_GETS: Self = Self(1 << 2);
+    /// The peer is willing to relay traffic on behalf of other peers.
+    pub const RELAY: Self = Self(1 << 3);
+
+    /// Capabilities implemented by this node, enabled by default.
+    pub const SUPPORTED: Self = Self::STREAMING;
+
+    /// Registry of all the known capabilities, with their names.
+    const REGISTRY: [(Self, &'static str); 4] = [
+        (Self::COMPRESSION, "compression"),
+        (Self::STREAMING, "streaming"),
+        (Self::PARTIAL_GETS, "partial-gets"),
+        (Self::RELAY, "relay"),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = Self::REGISTRY
+            .iter()
+            .filter(|(cap, _)| self.contains(*cap))
+            .map(|(_, name)| *name)
+            .collect();
+        write!(f, "[{}]", names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_capabilities_are_dropped() {
+        // announced by a newer peer, with a capability this node doesn't know about
+        let remote = Capabilities::STREAMING.union(Capabilities(1 << 31));
+        let common = Capabilities::SUPPORTED.intersection(remote);
+        assert_eq!(common, Capabilities::STREAMING);
+        assert_eq!(remote.to_string(), "[streaming]");
+        assert_eq!(
+            Capabilities::SUPPORTED.intersection(Capabilities::default()),
+            Capabilities::empty()
+        );
+    }
+
+    #[test]
+    fn negotiate_common_capabilities() {
+        let local = Capabilities::COMPRESSION.union(Capabilities::STREAMING);
+        let remote = Capabilities::STREAMING.union(Capabilities::RELAY);
+        let common = local.intersection(remote);
+        assert!(common.contains(Capabilities::STREAMING));
+        assert!(!common.contains(Capabilities::COMPRESSION));
+        assert!(!common.contains(Capabilities::RELAY));
+        assert_eq!(common.to_string(), "[streaming]");
+    }
+}
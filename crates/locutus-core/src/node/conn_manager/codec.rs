@@ -0,0 +1,178 @@
+//! Encoding of the messages sent between peers.
+//!
+//! Every frame on the wire is an envelope starting with the version of the encoding, followed
+//! by the message serialized with bincode. Frames are limited in size depending on the class
+//! of the traffic they belong to, larger frames are rejected by the receiving peer.
+//!
+//! The same codec is used by every transport (including the in-memory one used in tests), so
+//! messages which would be rejected on the wire are caught regardless of the transport.
+
+use super::{ConnResult, ConnectionError};
+use crate::message::{Message, TrafficClass};
+
+/// The default maximum size for a varint length-delimited packet.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 16 * 1024;
+/// The maximum size for a packet sent over a bulk substream.
+pub const BULK_MAX_PACKET_SIZE: usize = 4 * 1024 * 1024;
+
+/// Version of the envelope of the frames, must be bumped on any incompatible change to the
+/// encoding of the messages.
+pub(crate) const ENVELOPE_VERSION: u8 = 1;
+
+pub(crate) trait WireCodec {
+    /// Max size of an encoded frame, envelope included.
+    const MAX_FRAME_SIZE: usize;
+
+    /// Size of the frame the message is encoded to, envelope included.
+    fn encoded_len(msg: &Message) -> ConnResult<usize> {
+        let size = bincode::serialized_size(msg)
+            .map_err(|err| ConnectionError::Serialization(Some(err)))?;
+        Ok(size as usize + 1)
+    }
+
+    fn encode(msg: &Message) -> ConnResult<Vec<u8>> {
+        let size = Self::encoded_len(msg)?;
+        if size > Self::MAX_FRAME_SIZE {
+            return Err(ConnectionError::FrameTooLarge {
+                size,
+                max: Self::MAX_FRAME_SIZE,
+            });
+        }
+        let mut frame = Vec::with_capacity(size);
+        frame.push(ENVELOPE_VERSION);
+        bincode::serialize_into(&mut frame, msg)
+            .map_err(|err| ConnectionError::Serialization(Some(err)))?;
+        Ok(frame)
+    }
+
+    fn decode(frame: &[u8]) -> ConnResult<Message> {
+        if frame.len() > Self::MAX_FRAME_SIZE {
+            return Err(ConnectionError::FrameTooLarge {
+                size: frame.len(),
+                max: Self::MAX_FRAME_SIZE,
+            });
+        }
+        match frame.split_first() {
+            Some((&ENVELOPE_VERSION, msg)) => {
+                bincode::deserialize(msg).map_err(|err| ConnectionError::Serialization(Some(err)))
+            }
+            Some((&version, _)) => Err(ConnectionError::UnsupportedEnvelope(version)),
+            None => Err(ConnectionError::Serialization(None)),
+        }
+    }
+}
+
+/// Codec for the messages driving on-going operations.
+pub(crate) struct InteractiveCodec;
+
+impl WireCodec for InteractiveCodec {
+    const MAX_FRAME_SIZE: usize = DEFAULT_MAX_PACKET_SIZE;
+}
+
+/// Codec for the large transfers sent over bulk substreams.
+pub(crate) struct BulkCodec;
+
+impl WireCodec for BulkCodec {
+    const MAX_FRAME_SIZE: usize = BULK_MAX_PACKET_SIZE;
+}
+
+/// Class of traffic the message is sent as. Messages which don't fit in an interactive frame
+/// are sent as bulk regardless of their class.
+pub(crate) fn traffic_class(msg: &Message) -> TrafficClass {
+    match msg.traffic_class() {
+        TrafficClass::Interactive => match InteractiveCodec::encoded_len(msg) {
+            Ok(size) if size > InteractiveCodec::MAX_FRAME_SIZE => TrafficClass::Bulk,
+            _ => TrafficClass::Interactive,
+        },
+        TrafficClass::Bulk => TrafficClass::Bulk,
+    }
+}
+
+/// Encodes the message with the codec of its class, as the in-memory transport does.
+#[cfg(test)]
+pub(crate) fn encode(msg: &Message, class: TrafficClass) -> ConnResult<Vec<u8>> {
+    match class {
+        TrafficClass::Interactive => InteractiveCodec::encode(msg),
+        TrafficClass::Bulk => BulkCodec::encode(msg),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn decode(frame: &[u8], class: TrafficClass) -> ConnResult<Message> {
+    match class {
+        TrafficClass::Interactive => InteractiveCodec::decode(frame),
+        TrafficClass::Bulk => BulkCodec::decode(frame),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        message::{Transaction, TxType},
+        node::PeerKey,
+        operations::put::PutMsg,
+    };
+
+    struct TinyCodec;
+
+    impl WireCodec for TinyCodec {
+        const MAX_FRAME_SIZE: usize = 8;
+    }
+
+    fn canceled() -> Message {
+        Message::Canceled(Transaction::new(
+            <PutMsg as TxType>::tx_type_id(),
+            &PeerKey::random(),
+        ))
+    }
+
+    #[test]
+    fn encode_and_decode() -> ConnResult<()> {
+        let msg = canceled();
+        let frame = encode(&msg, TrafficClass::Interactive)?;
+        assert_eq!(frame[0], ENVELOPE_VERSION);
+        let decoded = decode(&frame, TrafficClass::Interactive)?;
+        assert_eq!(decoded.id(), msg.id());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_oversized_frames() -> ConnResult<()> {
+        let msg = canceled();
+        assert!(matches!(
+            TinyCodec::encode(&msg),
+            Err(ConnectionError::FrameTooLarge { max: 8, .. })
+        ));
+        let frame = InteractiveCodec::encode(&msg)?;
+        assert!(matches!(
+            TinyCodec::decode(&frame),
+            Err(ConnectionError::FrameTooLarge { max: 8, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn encoded_len_matches_frame() -> ConnResult<()> {
+        let msg = canceled();
+        let frame = InteractiveCodec::encode(&msg)?;
+        assert_eq!(InteractiveCodec::encoded_len(&msg)?, frame.len());
+        assert_eq!(traffic_class(&msg), TrafficClass::Interactive);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_envelope() -> ConnResult<()> {
+        let mut frame = InteractiveCodec::encode(&canceled())?;
+        frame[0] = ENVELOPE_VERSION + 1;
+        assert!(matches!(
+            InteractiveCodec::decode(&frame),
+            Err(ConnectionError::UnsupportedEnvelope(v)) if v == ENVELOPE_VERSION + 1
+        ));
+        assert!(matches!(
+            InteractiveCodec::decode(&[]),
+            Err(ConnectionError::Serialization(None))
+        ));
+        Ok(())
+    }
+}
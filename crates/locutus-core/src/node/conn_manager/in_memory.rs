@@ -1,7 +1,6 @@
 //! A in-memory connection manager and transport implementation. Used for testing purposes.
 use std::{
     collections::HashMap,
-    io::Cursor,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -11,8 +10,11 @@ use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
 use rand::{prelude::StdRng, thread_rng, Rng, SeedableRng};
 
-use super::{ConnectionBridge, ConnectionError, PeerKey};
-use crate::{config::GlobalExecutor, message::Message};
+use super::{codec, ConnectionBridge, ConnectionError, PeerKey};
+use crate::{
+    config::GlobalExecutor,
+    message::{Message, TrafficClass},
+};
 
 static NETWORK_WIRES: OnceCell<(Sender<MessageOnTransit>, Receiver<MessageOnTransit>)> =
     OnceCell::new();
@@ -35,11 +37,25 @@ impl MemoryConnManager {
             loop {
                 let msg = { tr_cp.msg_stack_queue.lock().pop() };
                 if let Some(msg) = msg {
-                    let msg_data: Message =
-                        bincode::deserialize_from(Cursor::new(msg.data)).unwrap();
-                    if let Some(mut queue) = msg_queue_cp.try_lock() {
-                        queue.push(msg_data);
-                        std::mem::drop(queue);
+                    // decoded with the same codec as in the wire, so messages which would be
+                    // rejected by a real peer are dropped too
+                    match codec::decode(&msg.data, msg.class) {
+                        Ok(msg_data) if !msg_data.sent_by(&msg.origin) => tracing::warn!(
+                            "Dropping message {} spoofing its sender from {}",
+                            msg_data.id(),
+                            msg.origin
+                        ),
+                        Ok(msg_data) => {
+                            if let Some(mut queue) = msg_queue_cp.try_lock() {
+                                queue.push(msg_data);
+                                std::mem::drop(queue);
+                            }
+                        }
+                        Err(err) => tracing::error!(
+                            "Dropping message from {} at {}: {err}",
+                            msg.origin,
+                            msg.target
+                        ),
                     }
                 }
                 tokio::time::sleep(Duration::from_millis(10)).await;
@@ -80,8 +96,9 @@ impl Clone for MemoryConnManager {
 #[async_trait::async_trait]
 impl ConnectionBridge for MemoryConnManager {
     async fn send(&self, target: &PeerKey, msg: Message) -> super::ConnResult<()> {
-        let msg = bincode::serialize(&msg)?;
-        self.transport.send(*target, msg);
+        let class = codec::traffic_class(&msg);
+        let msg = codec::encode(&msg, class)?;
+        self.transport.send(*target, msg, class);
         Ok(())
     }
 
@@ -98,6 +115,7 @@ impl ConnectionBridge for MemoryConnManager {
 struct MessageOnTransit {
     origin: PeerKey,
     target: PeerKey,
+    class: TrafficClass,
     data: Vec<u8>,
 }
 
@@ -174,10 +192,11 @@ impl InMemoryTransport {
         }
     }
 
-    fn send(&self, peer: PeerKey, message: Vec<u8>) {
+    fn send(&self, peer: PeerKey, message: Vec<u8>, class: TrafficClass) {
         let send_res = self.network.send(MessageOnTransit {
             origin: self.interface_peer,
             target: peer,
+            class,
             data: message,
         });
         if let Err(channel::SendError(_)) = send_res {
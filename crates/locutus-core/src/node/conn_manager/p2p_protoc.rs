@@ -1,11 +1,11 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     io,
-    net::IpAddr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     pin::Pin,
     sync::Arc,
     task::Poll,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use asynchronous_codec::{BytesMut, Framed};
@@ -17,7 +17,10 @@ use futures::{
 };
 use libp2p::{
     autonat,
-    core::{connection::ConnectionId, muxing, transport, ConnectedPoint, UpgradeInfo},
+    core::{
+        connection::ConnectionId, muxing, transport, transport::ListenerId, ConnectedPoint,
+        UpgradeInfo,
+    },
     identify::{self, IdentifyEvent, IdentifyInfo},
     identity::Keypair,
     multiaddr::Protocol,
@@ -30,28 +33,34 @@ use libp2p::{
     },
     InboundUpgrade, Multiaddr, OutboundUpgrade, PeerId, Swarm,
 };
+use locutus_stdlib::client_api::NodeNotification;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use unsigned_varint::codec::UviBytes;
 
-use super::{ConnectionBridge, ConnectionError};
+use super::{
+    bulk::{BulkChannel, BulkPoll},
+    codec::{self, BulkCodec, InteractiveCodec, WireCodec},
+    port_mapping::{self, ExternalAddrChange, PortMappingHandle},
+    ConnectionBridge, ConnectionError,
+};
 use crate::{
     config::{self, GlobalExecutor},
-    message::{Message, NodeEvent, TransactionType},
+    message::{Message, NodeEvent, TrafficClass, TransactionType},
     node::{handle_cancelled_op, join_ring_request, process_message, OpManager, PeerKey},
     operations::OpError,
     ring::PeerKeyLocation,
-    util::IterExt,
+    util::{ExponentialBackoff, IterExt},
     InitPeerNode, NodeConfig,
 };
 
-/// The default maximum size for a varint length-delimited packet.
-pub const DEFAULT_MAX_PACKET_SIZE: usize = 16 * 1024;
-
 const CURRENT_AGENT_VER: &str = "/locutus/agent/0.1.0";
-const CURRENT_PROTOC_VER: &[u8] = b"/locutus/0.1.0";
-const CURRENT_PROTOC_VER_STR: &str = "/locutus/0.1.0";
+const CURRENT_PROTOC_VER: &[u8] = b"/locutus/0.2.0";
+const CURRENT_PROTOC_VER_STR: &str = "/locutus/0.2.0";
+const BULK_PROTOC_VER: &[u8] = b"/locutus/bulk/0.2.0";
 const CURRENT_IDENTIFY_PROTOC_VER: &str = "/id/1.0.0";
 
+const PORT_MAPPING_LEASE: Duration = Duration::from_secs(60 * 60);
+
 fn config_behaviour(
     local_key: &Keypair,
     gateways: &[InitPeerNode],
@@ -112,6 +121,14 @@ fn multiaddr_from_connection(conn: (IpAddr, u16)) -> Multiaddr {
     addr
 }
 
+fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(ip.into()),
+        Protocol::Ip6(ip) => Some(ip.into()),
+        _ => None,
+    })
+}
+
 type P2pBridgeEvent = Either<(PeerKey, Box<Message>), NodeEvent>;
 
 #[derive(Clone)]
@@ -169,6 +186,18 @@ pub(in crate::node) struct P2pConnManager {
     conn_bridge_rx: Receiver<P2pBridgeEvent>,
     /// last valid observed public address
     public_addr: Option<Multiaddr>,
+    /// cooldown between attempts to join the ring through different gateways
+    reconnection_backoff: ExponentialBackoff,
+    /// when to attempt joining the ring again, after failing to join it
+    reconnect_at: Option<tokio::time::Instant>,
+    /// listening port to map in the local router, if enabled
+    port_to_map: Option<u16>,
+    /// active mapping in the local router, renewed in the background
+    port_mapping: Option<PortMappingHandle>,
+    /// active listeners, with the address requested and the addresses they listen at
+    listeners: HashMap<ListenerId, Vec<Multiaddr>>,
+    /// local address at which each inbound connection was accepted
+    accepted_at: HashMap<PeerId, Multiaddr>,
 }
 
 impl P2pConnManager {
@@ -203,25 +232,187 @@ impl P2pConnManager {
         let bridge = P2pBridge::new(tx_bridge_cmd);
 
         let gateways = config.get_gateways()?;
+        if let Some(allowlist) = &config.peer_allowlist {
+            for gw in gateways.iter().filter(|gw| !allowlist.allows(&gw.peer)) {
+                tracing::warn!("Gateway {} is not in the peer allowlist", gw.peer);
+            }
+        }
         Ok(P2pConnManager {
             swarm,
             gateways,
             bridge,
             conn_bridge_rx: rx_bridge_cmd,
             public_addr,
+            reconnection_backoff: config::CONFIG.retry.reconnection.backoff(),
+            reconnect_at: None,
+            port_to_map: config.local_port.filter(|_| config.port_mapping),
+            port_mapping: None,
+            listeners: HashMap::new(),
+            accepted_at: HashMap::new(),
         })
     }
 
     pub fn listen_on(&mut self) -> Result<(), anyhow::Error> {
-        if let Some(listening_addr) = &self.public_addr {
-            self.swarm.listen_on(listening_addr.clone())?;
+        // a node mapping its port in the router listens at it even if it is not a gateway
+        let mapped = self
+            .port_to_map
+            .map(|port| multiaddr_from_connection((Ipv4Addr::UNSPECIFIED.into(), port)));
+        if let Some(listening_addr) = self.public_addr.clone().or(mapped) {
+            let listener = self.swarm.listen_on(listening_addr.clone())?;
+            self.listeners.insert(listener, vec![listening_addr]);
         }
         Ok(())
     }
 
+    /// Starts listening at a new address while the node is running.
+    fn add_listener<CErr>(&mut self, address: Multiaddr, op_manager: &OpManager<CErr>)
+    where
+        CErr: std::error::Error,
+    {
+        if self
+            .listeners
+            .values()
+            .any(|addrs| addrs.contains(&address))
+        {
+            tracing::debug!("Already listening at {address}");
+            return;
+        }
+        match self.swarm.listen_on(address.clone()) {
+            Ok(listener) => {
+                self.listeners.insert(listener, vec![address]);
+            }
+            Err(err) => {
+                tracing::warn!("Unable to listen at {address}: {err}");
+                op_manager
+                    .ring
+                    .notify_node_event(NodeNotification::ListenerFailed {
+                        address: address.to_string(),
+                        cause: err.to_string(),
+                    });
+            }
+        }
+    }
+
+    /// Stops accepting new connections at the given address and closes the connections
+    /// accepted through it, so the peers reconnect at the remaining addresses.
+    fn remove_listener<CErr>(&mut self, address: Multiaddr, op_manager: &OpManager<CErr>)
+    where
+        CErr: std::error::Error,
+    {
+        let listener = self
+            .listeners
+            .iter()
+            .find(|(_, addrs)| addrs.contains(&address))
+            .map(|(listener, addrs)| (*listener, addrs.clone()));
+        match listener {
+            // the listener is forgotten once the swarm reports it closed
+            Some((listener, addrs)) => {
+                self.swarm.remove_listener(listener);
+                let accepted: Vec<_> = self
+                    .accepted_at
+                    .iter()
+                    .filter(|(_, local_addr)| addrs.contains(local_addr))
+                    .map(|(peer, _)| PeerKey::from(*peer))
+                    .collect();
+                for peer in accepted {
+                    self.close_connection(peer, op_manager);
+                }
+            }
+            None => {
+                op_manager
+                    .ring
+                    .notify_node_event(NodeNotification::ListenerFailed {
+                        address: address.to_string(),
+                        cause: "not listening at this address".to_owned(),
+                    });
+            }
+        }
+    }
+
+    fn close_connection<CErr>(&mut self, peer: PeerKey, op_manager: &OpManager<CErr>)
+    where
+        CErr: std::error::Error,
+    {
+        self.bridge.active_net_connections.remove(&peer);
+        self.accepted_at.remove(&peer.0);
+        op_manager.prune_connection(peer);
+        // todo: notify the handler, read `disconnect_peer_id` doc
+        let _ = self.swarm.disconnect_peer_id(peer.0);
+        tracing::debug!("Dropped connection with peer {}", peer);
+    }
+
+    /// Stops advertising the addresses of a closed listener. If the public address was one of
+    /// them, an address of the remaining listeners is used instead and the peers are informed.
+    fn forget_addresses<'a>(&mut self, closed: impl Iterator<Item = &'a Multiaddr>) {
+        let mut public_addr_closed = false;
+        for address in closed {
+            self.swarm.remove_external_address(address);
+            public_addr_closed |= self.public_addr.as_ref() == Some(address);
+        }
+        if !public_addr_closed {
+            return;
+        }
+        self.public_addr = self
+            .listeners
+            .values()
+            .find_map(|addrs| addrs.first())
+            .cloned();
+        tracing::info!("Public address changed to {:?}", self.public_addr);
+        let peers: Vec<_> = self
+            .bridge
+            .active_net_connections
+            .iter()
+            .map(|conn| conn.key().0)
+            .collect();
+        self.swarm.behaviour_mut().identify.push(peers);
+    }
+
+    /// Whether the listening port is mapped in the local router.
+    pub fn maps_port(&self) -> bool {
+        self.port_to_map.is_some()
+    }
+
+    /// Maps the listening port in the local router, if enabled, so the external address
+    /// is announced to other peers through the identify protocol. The mapping is established
+    /// and renewed in the background and removed when the event loop stops.
+    pub fn map_port(&mut self) {
+        if let Some(port) = self.port_to_map {
+            self.port_mapping = Some(port_mapping::map_in_background(port, PORT_MAPPING_LEASE));
+        }
+    }
+
+    fn update_external_addr(&mut self, change: ExternalAddrChange) {
+        let ExternalAddrChange { previous, current } = change;
+        let as_multiaddr = |addr: SocketAddr| multiaddr_from_connection((addr.ip(), addr.port()));
+        match previous {
+            Some(previous) => {
+                tracing::info!("External address changed from {previous} to {current}");
+                self.swarm.remove_external_address(&as_multiaddr(previous));
+            }
+            None => tracing::info!("Mapped port in the local router, reachable @ {current}"),
+        }
+        self.swarm
+            .add_external_address(as_multiaddr(current), AddressScore::Infinite);
+    }
+
     pub async fn run_event_listener<CErr>(
         mut self,
         op_manager: Arc<OpManager<CErr>>,
+        notification_channel: Receiver<Either<Message, NodeEvent>>,
+    ) -> Result<(), anyhow::Error>
+    where
+        CErr: std::error::Error + Send + Sync + 'static,
+    {
+        let result = self.event_loop(op_manager, notification_channel).await;
+        if let Some(mapping) = self.port_mapping.take() {
+            mapping.close().await;
+        }
+        result
+    }
+
+    async fn event_loop<CErr>(
+        &mut self,
+        op_manager: Arc<OpManager<CErr>>,
         mut notification_channel: Receiver<Either<Message, NodeEvent>>,
     ) -> Result<(), anyhow::Error>
     where
@@ -230,9 +421,12 @@ impl P2pConnManager {
         use ConnMngrActions::*;
 
         loop {
+            let ring = &op_manager.ring;
             let net_msg = self.swarm.select_next_some().map(|event| match event {
                 SwarmEvent::Behaviour(NetEvent::Locutus(msg)) => {
-                    tracing::debug!("Message inbound: {:?}", msg);
+                    if op_manager.trace_sampler.is_sampled(msg.id()) {
+                        tracing::debug!("Message inbound: {:?}", msg);
+                    }
                     Ok(Left(*msg))
                 }
                 SwarmEvent::ConnectionClosed { peer_id, .. } => {
@@ -240,13 +434,54 @@ impl P2pConnManager {
                         peer: PeerKey::from(peer_id),
                     }))
                 }
+                SwarmEvent::ConnectionEstablished { peer_id, .. }
+                    if !ring.is_allowed(&PeerKey::from(peer_id)) =>
+                {
+                    // the key of the peer was authenticated by the handshake
+                    tracing::warn!("Peer {} is not in the allowlist, disconnecting", peer_id);
+                    Ok(Right(ConnMngrActions::RejectPeer(peer_id)))
+                }
+                SwarmEvent::ConnectionEstablished {
+                    peer_id, endpoint, ..
+                } => {
+                    if let Some(ip) = ip_of(endpoint.get_remote_address()) {
+                        ring.record_peer_addr(PeerKey::from(peer_id), ip);
+                    }
+                    match endpoint {
+                        ConnectedPoint::Listener { local_addr, .. } => {
+                            Ok(Right(ConnMngrActions::AcceptedAt {
+                                peer: peer_id,
+                                local_addr,
+                            }))
+                        }
+                        _ => Ok(Right(ConnMngrActions::NoAction)),
+                    }
+                }
                 SwarmEvent::Dialing(peer_id) => {
                     tracing::debug!("Attempting connection to {}", peer_id);
                     Ok(Right(ConnMngrActions::NoAction))
                 }
+                SwarmEvent::NewListenAddr {
+                    listener_id,
+                    address,
+                } => Ok(Right(ConnMngrActions::ListeningAt {
+                    listener: listener_id,
+                    address,
+                })),
+                SwarmEvent::ListenerClosed {
+                    listener_id,
+                    addresses,
+                    reason,
+                } => Ok(Right(ConnMngrActions::ListenerClosed {
+                    listener: listener_id,
+                    addresses,
+                    error: reason.err().map(|err| err.to_string()),
+                })),
                 SwarmEvent::Behaviour(NetEvent::Identify(id)) => {
                     if let IdentifyEvent::Received { peer_id, info } = *id {
-                        if Self::is_compatible_peer(&info) {
+                        if !ring.is_allowed(&PeerKey::from(peer_id)) {
+                            Ok(Right(ConnMngrActions::NoAction))
+                        } else if Self::is_compatible_peer(&info) {
                             Ok(Right(ConnMngrActions::ConnectionEstablished {
                                 peer: PeerKey(peer_id),
                                 address: info.observed_addr,
@@ -266,7 +501,7 @@ impl P2pConnManager {
                         address,
                         peer,
                         ..
-                    }) => {
+                    }) if ring.is_allowed(&PeerKey::from(peer)) => {
                         tracing::debug!(
                             "Successful autonat probe, established conn with {peer} @ {address}"
                         );
@@ -308,17 +543,41 @@ impl P2pConnManager {
 
             let bridge_msg = self.conn_bridge_rx.recv().map(|msg| match msg {
                 Some(Left((peer, msg))) => {
-                    tracing::debug!("Message outbound: {:?}", msg);
+                    if op_manager.trace_sampler.is_sampled(msg.id()) {
+                        tracing::debug!("Message outbound: {:?}", msg);
+                    }
                     Ok(Right(SendMessage { peer, msg }))
                 }
                 Some(Right(action)) => Ok(Right(NodeAction(action))),
                 None => Ok(Right(ClosedChannel)),
             });
 
+            let port_mapping = self.port_mapping.as_mut();
+            let mapping_change = async move {
+                match port_mapping {
+                    Some(mapping) => match mapping.next_change().await {
+                        Some(change) => Ok(Right(ExternalAddrChanged(change))),
+                        None => future::pending().await,
+                    },
+                    None => future::pending().await,
+                }
+            };
+
+            let reconnect_at = self.reconnect_at;
+            let reconnect = async move {
+                match reconnect_at {
+                    Some(reconnect_at) => tokio::time::sleep_until(reconnect_at).await,
+                    None => future::pending().await,
+                }
+                Ok(Right(Reconnect))
+            };
+
             let msg: Result<_, ConnectionError> = tokio::select! {
                 msg = net_msg => { msg }
                 msg = notification_msg => { msg }
                 msg = bridge_msg => { msg }
+                msg = mapping_change => { msg }
+                msg = reconnect => { msg }
             };
 
             match msg {
@@ -336,20 +595,19 @@ impl P2pConnManager {
                             )
                             .await;
                             match res {
-                                Err(OpError::MaxRetriesExceeded(_, _))
+                                Err(err @ OpError::MaxRetriesExceeded(_, _))
                                     if tx_type == TransactionType::JoinRing
                                         && self.public_addr.is_none() /* FIXME: this should be not a gateway instead */ =>
                                 {
-                                    tracing::warn!("Retrying joining the ring with an other peer");
-                                    let gateway = self.gateways.iter().shuffle().next().unwrap();
-                                    join_ring_request(
-                                        None,
-                                        op_manager.ring.peer_key,
-                                        gateway,
-                                        &op_manager,
-                                        &mut self.bridge,
-                                    )
-                                    .await?
+                                    if op_manager.ring.num_connections() > 0 {
+                                        // already joined the ring, so this node is not isolated
+                                        self.reconnection_backoff.reset();
+                                    }
+                                    let Some(delay) = self.reconnection_backoff.next_delay() else {
+                                        return Err(anyhow::anyhow!(err));
+                                    };
+                                    tracing::warn!("Retrying joining the ring with an other peer in {delay:?}");
+                                    self.reconnect_at = Some(tokio::time::Instant::now() + delay);
                                 }
                                 Err(err) => return Err(anyhow::anyhow!(err)),
                                 Ok(_) => {}
@@ -367,11 +625,13 @@ impl P2pConnManager {
                     }
                 }
                 Ok(Right(SendMessage { peer, msg })) => {
-                    tracing::debug!(
-                        "Sending swarm message from {} to {}",
-                        op_manager.ring.peer_key,
-                        peer
-                    );
+                    if op_manager.trace_sampler.is_sampled(msg.id()) {
+                        tracing::debug!(
+                            "Sending swarm message from {} to {}",
+                            op_manager.ring.peer_key,
+                            peer
+                        );
+                    }
                     self.swarm
                         .behaviour_mut()
                         .locutus
@@ -398,15 +658,75 @@ impl P2pConnManager {
                 }
                 Ok(Right(ConnectionClosed { peer: peer_id }))
                 | Ok(Right(NodeAction(NodeEvent::DropConnection(peer_id)))) => {
-                    self.bridge.active_net_connections.remove(&peer_id);
-                    op_manager.prune_connection(peer_id);
-                    // todo: notify the handler, read `disconnect_peer_id` doc
-                    let _ = self.swarm.disconnect_peer_id(peer_id.0);
-                    tracing::debug!("Dropped connection with peer {}", peer_id);
+                    self.close_connection(peer_id, &op_manager);
+                }
+                Ok(Right(AcceptedAt { peer, local_addr })) => {
+                    self.accepted_at.insert(peer, local_addr);
+                }
+                Ok(Right(RejectPeer(peer_id))) => {
+                    // the peer never joined the ring, so there is no connection state to prune
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
                 }
                 Ok(Right(UpdatePublicAddr(address))) => {
                     self.public_addr = Some(address);
                 }
+                Ok(Right(NodeAction(NodeEvent::AddListener(address)))) => {
+                    self.add_listener(address, &op_manager);
+                }
+                Ok(Right(NodeAction(NodeEvent::RemoveListener(address)))) => {
+                    self.remove_listener(address, &op_manager);
+                }
+                Ok(Right(ListeningAt { listener, address })) => {
+                    tracing::info!("Listening at {address}");
+                    if let Some(addrs) = self.listeners.get_mut(&listener) {
+                        addrs.push(address.clone());
+                    }
+                    op_manager
+                        .ring
+                        .notify_node_event(NodeNotification::ListenerStarted {
+                            address: address.to_string(),
+                        });
+                }
+                Ok(Right(ListenerClosed {
+                    listener,
+                    addresses,
+                    error,
+                })) => {
+                    let requested = self.listeners.remove(&listener).unwrap_or_default();
+                    self.forget_addresses(requested.iter().chain(&addresses));
+                    let event = match error {
+                        Some(cause) => {
+                            tracing::warn!("Listener at {:?} failed: {cause}", requested.first());
+                            NodeNotification::ListenerFailed {
+                                address: requested
+                                    .first()
+                                    .map(|addr| addr.to_string())
+                                    .unwrap_or_default(),
+                                cause,
+                            }
+                        }
+                        None => {
+                            tracing::info!("Stopped listening at {addresses:?}");
+                            NodeNotification::ListenerClosed {
+                                addresses: addresses.iter().map(|addr| addr.to_string()).collect(),
+                            }
+                        }
+                    };
+                    op_manager.ring.notify_node_event(event);
+                }
+                Ok(Right(ExternalAddrChanged(change))) => self.update_external_addr(change),
+                Ok(Right(Reconnect)) => {
+                    self.reconnect_at = None;
+                    let gateway = self.gateways.iter().shuffle().next().unwrap();
+                    join_ring_request(
+                        None,
+                        op_manager.ring.peer_key,
+                        gateway,
+                        &op_manager,
+                        &mut self.bridge,
+                    )
+                    .await?
+                }
                 Ok(Right(IsPrivatePeer(_peer))) => {
                     todo!("attempt hole punching")
                 }
@@ -449,10 +769,32 @@ enum ConnMngrActions {
         peer: PeerKey,
         msg: Box<Message>,
     },
+    /// Accepted an inbound connection from the peer at one of the listening addresses
+    AcceptedAt {
+        peer: PeerId,
+        local_addr: Multiaddr,
+    },
+    /// Drop the connection with a peer which is not allowed to connect to this node
+    RejectPeer(PeerId),
     /// Update self own public address, useful when communicating for first time
     UpdatePublicAddr(Multiaddr),
     /// A peer which we attempted connection to is private, attempt hole-punching
     IsPrivatePeer(PeerId),
+    /// The external address of the port mapping in the local router changed
+    ExternalAddrChanged(ExternalAddrChange),
+    /// The cooldown after failing to join the ring elapsed, attempt joining it again
+    Reconnect,
+    /// A listener is accepting connections at the given address
+    ListeningAt {
+        listener: ListenerId,
+        address: Multiaddr,
+    },
+    /// A listener stopped accepting connections, either removed or because of an error
+    ListenerClosed {
+        listener: ListenerId,
+        addresses: Vec<Multiaddr>,
+        error: Option<String>,
+    },
     NodeAction(NodeEvent),
     ClosedChannel,
     NoAction,
@@ -503,6 +845,12 @@ impl NetworkBehaviour for LocutusBehaviour {
             HandlerEvent::Outbound(msg) => {
                 self.outbound.push_front((peer_id, msg));
             }
+            HandlerEvent::Inbound(Left(msg)) if !msg.sent_by(&PeerKey::from(peer_id)) => {
+                tracing::warn!(
+                    "Dropping message {} spoofing its sender from {peer_id}",
+                    msg.id()
+                );
+            }
             HandlerEvent::Inbound(msg) => {
                 self.inbound.push_front(msg);
             }
@@ -578,6 +926,10 @@ pub(in crate::node) struct Handler {
     uniq_conn_id: UniqConnId,
     protocol_status: ProtocolStatus,
     pending: Vec<Message>,
+    /// Large transfers, sent over a dedicated substream.
+    bulk: BulkChannel<LocutusStream<NegotiatedSubstream>>,
+    /// Bulk substreams opened by the remote, only used to receive messages.
+    bulk_inbound: Vec<LocutusStream<NegotiatedSubstream>>,
 }
 
 enum ProtocolStatus {
@@ -634,6 +986,8 @@ impl Handler {
             uniq_conn_id: 0,
             protocol_status: ProtocolStatus::Unconfirmed,
             pending: Vec::new(),
+            bulk: BulkChannel::default(),
+            bulk_inbound: Vec::new(),
         }
     }
 
@@ -663,9 +1017,48 @@ impl Handler {
             Some(msg)
         }
     }
+
+    /// Whether there is interactive traffic waiting to be sent, bulk transfers yield to it.
+    fn interactive_busy(&self) -> bool {
+        !self.pending.is_empty()
+            || self.substreams.iter().any(|s| {
+                matches!(
+                    s,
+                    SubstreamState::OutPendingOpen { .. }
+                        | SubstreamState::AwaitingFirst { .. }
+                        | SubstreamState::PendingSend { .. }
+                        | SubstreamState::PendingFlush { .. }
+                )
+            })
+    }
+
+    fn poll_bulk_inbound(&mut self, cx: &mut std::task::Context<'_>) -> Option<HandlePollingEv> {
+        for n in (0..self.bulk_inbound.len()).rev() {
+            match Stream::poll_next(Pin::new(&mut self.bulk_inbound[n]), cx) {
+                Poll::Ready(Some(Ok(msg))) => {
+                    return Some(ProtocolsHandlerEvent::Custom(HandlerEvent::Inbound(Left(
+                        msg,
+                    ))));
+                }
+                Poll::Ready(Some(Err(err))) => {
+                    self.bulk_inbound.swap_remove(n);
+                    return Some(ProtocolsHandlerEvent::Custom(HandlerEvent::Inbound(Right(
+                        NodeEvent::Error(err),
+                    ))));
+                }
+                Poll::Ready(None) => {
+                    // the remote closed the substream, it will open a new one when required
+                    self.bulk_inbound.swap_remove(n);
+                }
+                Poll::Pending => {}
+            }
+        }
+        None
+    }
 }
 
-type HandlePollingEv = ProtocolsHandlerEvent<LocutusProtocol, (), HandlerEvent, ConnectionError>;
+type HandlePollingEv =
+    ProtocolsHandlerEvent<LocutusProtocol, TrafficClass, HandlerEvent, ConnectionError>;
 
 impl ProtocolsHandler for Handler {
     /// Event received from the network by the handler
@@ -682,18 +1075,20 @@ impl ProtocolsHandler for Handler {
 
     type InboundOpenInfo = ();
 
-    type OutboundOpenInfo = ();
+    type OutboundOpenInfo = TrafficClass;
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
-        SubstreamProtocol::new(LocutusProtocol, ())
+        SubstreamProtocol::new(LocutusProtocol::inbound(), ())
     }
 
     fn inject_fully_negotiated_outbound(
         &mut self,
-        stream: <Self::OutboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output,
+        (stream, class): <Self::OutboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output,
         _info: Self::OutboundOpenInfo,
     ) {
-        if let Some(pos) = self
+        if class == TrafficClass::Bulk {
+            self.bulk.stream_opened(stream);
+        } else if let Some(pos) = self
             .substreams
             .iter()
             .position(|state| matches!(state, SubstreamState::AwaitingFirst { .. }))
@@ -713,10 +1108,12 @@ impl ProtocolsHandler for Handler {
 
     fn inject_fully_negotiated_inbound(
         &mut self,
-        stream: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
+        (stream, class): <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
         _info: Self::InboundOpenInfo,
     ) {
-        if let Some(prev_stream) = self
+        if class == TrafficClass::Bulk {
+            self.bulk_inbound.push(stream);
+        } else if let Some(prev_stream) = self
             .substreams
             .iter()
             .position(|state| matches!(state, SubstreamState::AwaitingFirst { .. }))
@@ -746,6 +1143,13 @@ impl ProtocolsHandler for Handler {
     fn inject_event(&mut self, msg: Self::InEvent) {
         match msg {
             HandlerEvent::Outbound(Left(msg)) => {
+                let msg = match codec::traffic_class(&msg) {
+                    TrafficClass::Bulk => match self.bulk.push(msg) {
+                        Some(msg) => msg,
+                        None => return,
+                    },
+                    TrafficClass::Interactive => msg,
+                };
                 if let Some(msg) = self.send_to_free_substream(msg) {
                     let conn_id = self.uniq_conn_id;
                     self.uniq_conn_id += 1;
@@ -765,9 +1169,17 @@ impl ProtocolsHandler for Handler {
 
     fn inject_dial_upgrade_error(
         &mut self,
-        _: Self::OutboundOpenInfo,
+        class: Self::OutboundOpenInfo,
         error: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgradeSend>::Error>,
     ) {
+        if class == TrafficClass::Bulk {
+            // the remote may not support bulk substreams, fall back to interactive ones
+            tracing::debug!("Failed opening bulk substream: {error}");
+            for msg in self.bulk.open_failed() {
+                self.inject_event(HandlerEvent::Outbound(Left(msg)));
+            }
+            return;
+        }
         self.protocol_status = ProtocolStatus::FailedUpgrade;
         self.substreams.push(SubstreamState::ReportError {
             error: (Box::new(error)).into(),
@@ -780,7 +1192,7 @@ impl ProtocolsHandler for Handler {
     }
 
     fn poll(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<HandlePollingEv> {
-        if self.substreams.is_empty() {
+        if self.substreams.is_empty() && !self.bulk.is_busy() && self.bulk_inbound.is_empty() {
             return Poll::Pending;
         }
 
@@ -791,13 +1203,37 @@ impl ProtocolsHandler for Handler {
             )));
         }
 
+        if let Some(event) = self.poll_bulk_inbound(cx) {
+            return Poll::Ready(event);
+        }
+
+        match self.bulk.poll_send(cx, self.interactive_busy()) {
+            BulkPoll::OpenStream => {
+                return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(
+                        LocutusProtocol::outbound(TrafficClass::Bulk),
+                        TrafficClass::Bulk,
+                    ),
+                });
+            }
+            BulkPoll::Error(err) => {
+                return Poll::Ready(ProtocolsHandlerEvent::Custom(HandlerEvent::Inbound(Right(
+                    NodeEvent::Error(err),
+                ))));
+            }
+            BulkPoll::Pending => {}
+        }
+
         for n in (0..self.substreams.len()).rev() {
             let mut stream = self.substreams.swap_remove(n);
             loop {
                 match stream {
                     SubstreamState::OutPendingOpen { msg, conn_id } => {
                         let event = ProtocolsHandlerEvent::OutboundSubstreamRequest {
-                            protocol: SubstreamProtocol::new(LocutusProtocol, ()),
+                            protocol: SubstreamProtocol::new(
+                                LocutusProtocol::outbound(TrafficClass::Interactive),
+                                TrafficClass::Interactive,
+                            ),
                         };
                         self.substreams
                             .push(SubstreamState::AwaitingFirst { conn_id });
@@ -931,7 +1367,9 @@ impl ProtocolsHandler for Handler {
             }
         }
 
-        if self.substreams.is_empty() || self.substreams.iter().all(|s| s.is_free()) {
+        if self.bulk.is_busy() {
+            self.keep_alive = KeepAlive::Yes;
+        } else if self.substreams.is_empty() || self.substreams.iter().all(|s| s.is_free()) {
             // We destroyed all substreams in this iteration or all substreams are free
             self.keep_alive = KeepAlive::Until(Instant::now() + config::PEER_TIMEOUT);
         } else {
@@ -942,14 +1380,33 @@ impl ProtocolsHandler for Handler {
     }
 }
 
-pub(crate) struct LocutusProtocol;
+#[derive(Clone, Copy)]
+pub(crate) struct LocutusProtocol {
+    /// Traffic class of the substream being opened, inbound substreams accept any.
+    class: Option<TrafficClass>,
+}
+
+impl LocutusProtocol {
+    fn inbound() -> Self {
+        Self { class: None }
+    }
+
+    fn outbound(class: TrafficClass) -> Self {
+        Self { class: Some(class) }
+    }
+}
 
 impl UpgradeInfo for LocutusProtocol {
     type Info = &'static [u8];
-    type InfoIter = std::iter::Once<Self::Info>;
+    type InfoIter = std::vec::IntoIter<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        std::iter::once(CURRENT_PROTOC_VER)
+        match self.class {
+            None => vec![CURRENT_PROTOC_VER, BULK_PROTOC_VER],
+            Some(TrafficClass::Interactive) => vec![CURRENT_PROTOC_VER],
+            Some(TrafficClass::Bulk) => vec![BULK_PROTOC_VER],
+        }
+        .into_iter()
     }
 }
 
@@ -969,12 +1426,13 @@ impl<S> InboundUpgrade<S> for LocutusProtocol
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    type Output = LocutusStream<S>;
+    type Output = (LocutusStream<S>, TrafficClass);
     type Error = ConnectionError;
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
-    fn upgrade_inbound(self, incoming: S, _: Self::Info) -> Self::Future {
-        frame_stream(incoming)
+    fn upgrade_inbound(self, incoming: S, info: Self::Info) -> Self::Future {
+        let class = traffic_class(info);
+        future::ok((frame_stream(incoming, class), class))
     }
 }
 
@@ -982,40 +1440,47 @@ impl<S> OutboundUpgrade<S> for LocutusProtocol
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    type Output = LocutusStream<S>;
+    type Output = (LocutusStream<S>, TrafficClass);
     type Error = ConnectionError;
     type Future = future::Ready<Result<Self::Output, Self::Error>>;
 
-    fn upgrade_outbound(self, incoming: S, _: Self::Info) -> Self::Future {
-        frame_stream(incoming)
+    fn upgrade_outbound(self, incoming: S, info: Self::Info) -> Self::Future {
+        let class = traffic_class(info);
+        future::ok((frame_stream(incoming, class), class))
     }
 }
 
-fn frame_stream<S>(incoming: S) -> future::Ready<Result<LocutusStream<S>, ConnectionError>>
+fn traffic_class(protocol: &[u8]) -> TrafficClass {
+    if protocol == BULK_PROTOC_VER {
+        TrafficClass::Bulk
+    } else {
+        TrafficClass::Interactive
+    }
+}
+
+fn frame_stream<S>(incoming: S, class: TrafficClass) -> LocutusStream<S>
 where
     S: AsyncRead + AsyncWrite + Unpin,
+{
+    match class {
+        TrafficClass::Interactive => framed_with::<S, InteractiveCodec>(incoming),
+        TrafficClass::Bulk => framed_with::<S, BulkCodec>(incoming),
+    }
+}
+
+fn framed_with<S, C>(incoming: S) -> LocutusStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    C: WireCodec,
 {
     let mut codec = UviBytes::default();
-    codec.set_max_len(DEFAULT_MAX_PACKET_SIZE);
-    let framed = Framed::new(incoming, codec)
+    codec.set_max_len(C::MAX_FRAME_SIZE);
+    Framed::new(incoming, codec)
         .err_into()
-        .with::<_, _, fn(_) -> _, _>(|response| match encode_msg(response) {
-            Ok(msg) => future::ready(Ok(io::Cursor::new(msg))),
-            Err(err) => future::ready(Err(err)),
+        .with::<_, _, fn(_) -> _, _>(|msg: Message| {
+            future::ready(C::encode(&msg).map(io::Cursor::new))
         })
-        .and_then::<_, fn(_) -> _>(|bytes| future::ready(decode_msg(bytes)));
-    future::ok(framed)
-}
-
-#[inline(always)]
-fn encode_msg(msg: Message) -> Result<Vec<u8>, ConnectionError> {
-    bincode::serialize(&msg).map_err(|err| ConnectionError::Serialization(Some(err)))
-}
-
-#[inline(always)]
-fn decode_msg(buf: BytesMut) -> Result<Message, ConnectionError> {
-    let cursor = std::io::Cursor::new(buf);
-    bincode::deserialize_from(cursor).map_err(|err| ConnectionError::Serialization(Some(err)))
+        .and_then::<_, fn(_) -> _>(|bytes: BytesMut| future::ready(C::decode(&bytes)))
 }
 
 /// The network behaviour implements the following capabilities:
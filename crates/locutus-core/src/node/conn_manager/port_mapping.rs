@@ -0,0 +1,735 @@
+//! Automatic port mapping in the local router so nodes behind a NAT can accept inbound connections.
+//!
+//! Both [UPnP IGD](https://openconnectivity.org/developer/specifications/upnp-resources/upnp/internet-gateway-device-igd-v-2-0/)
+//! and [NAT-PMP](https://www.rfc-editor.org/rfc/rfc6886) are supported; UPnP is attempted first
+//! and NAT-PMP is used as a fallback.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::config::GlobalExecutor;
+
+const SSDP_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), 1900);
+const SSDP_TIMEOUT: Duration = Duration::from_secs(3);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const IGD_SERVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_RETRIES: u32 = 3;
+const NAT_PMP_INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+
+const MAPPING_DESCRIPTION: &str = "locutus";
+/// Delay before retrying to map the port after a failed attempt, if the lease allows it.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Maximum time waited for the router to delete the mapping on shutdown.
+const DELETE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub type PortMappingResult<T> = Result<T, PortMappingError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PortMappingError {
+    #[error("no internet gateway device found")]
+    GatewayNotFound,
+    #[error("gateway did not respond in time")]
+    Timeout,
+    #[error("gateway rejected the request: {0}")]
+    Rejected(String),
+    #[error("unexpected response from gateway: {0}")]
+    InvalidResponse(String),
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+enum Protocol {
+    Upnp {
+        control_url: HttpUrl,
+        service_type: &'static str,
+        internal_ip: Ipv4Addr,
+    },
+    NatPmp {
+        gateway: Ipv4Addr,
+    },
+}
+
+/// A TCP port mapping in the local router, must be renewed before the lease expires.
+#[derive(Debug, Clone)]
+pub(crate) struct PortMapping {
+    protocol: Protocol,
+    local_port: u16,
+    external_addr: SocketAddr,
+    /// lease granted by the router, which may differ from the one requested
+    lease: Duration,
+}
+
+/// Change of the external address through which this node is reachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExternalAddrChange {
+    pub previous: Option<SocketAddr>,
+    pub current: SocketAddr,
+}
+
+impl PortMapping {
+    /// Map the given local port to the same external port in the router, trying UPnP first
+    /// and falling back to NAT-PMP.
+    pub async fn new(local_port: u16, lease: Duration) -> PortMappingResult<Self> {
+        let gateways = Gateways::find().await;
+        let upnp_err = match gateways.upnp {
+            Ok(protocol) => match Self::map(protocol, local_port, local_port, lease).await {
+                Ok(mapping) => return Ok(mapping),
+                Err(err) => err,
+            },
+            Err(err) => err,
+        };
+        tracing::debug!("UPnP port mapping failed ({upnp_err}), trying NAT-PMP");
+        let gateway = gateways.nat_pmp.ok_or(PortMappingError::GatewayNotFound)?;
+        Self::map(Protocol::NatPmp { gateway }, local_port, local_port, lease).await
+    }
+
+    async fn map(
+        protocol: Protocol,
+        local_port: u16,
+        external_port: u16,
+        lease: Duration,
+    ) -> PortMappingResult<Self> {
+        let (external_addr, lease) = match &protocol {
+            Protocol::Upnp {
+                control_url,
+                service_type,
+                internal_ip,
+            } => {
+                let args = upnp::MappingArgs {
+                    internal_ip: *internal_ip,
+                    local_port,
+                    external_port,
+                    lease,
+                };
+                upnp::add_port_mapping(control_url, service_type, &args).await?;
+                let ip = upnp::external_ip(control_url, service_type).await?;
+                (SocketAddr::new(ip.into(), external_port), lease)
+            }
+            Protocol::NatPmp { gateway } => {
+                let (port, granted) =
+                    nat_pmp::map_tcp(*gateway, local_port, external_port, lease).await?;
+                let ip = nat_pmp::external_ip(*gateway).await?;
+                (SocketAddr::new(ip.into(), port), granted)
+            }
+        };
+        Ok(Self {
+            protocol,
+            local_port,
+            external_addr,
+            lease,
+        })
+    }
+
+    /// Address other peers can use to reach this node.
+    pub fn external_addr(&self) -> SocketAddr {
+        self.external_addr
+    }
+
+    pub fn lease(&self) -> Duration {
+        self.lease
+    }
+
+    /// Extend the lease of this mapping, returns the (potentially changed) external address.
+    pub async fn renew(&mut self, lease: Duration) -> PortMappingResult<SocketAddr> {
+        *self = Self::map(
+            self.protocol.clone(),
+            self.local_port,
+            self.external_addr.port(),
+            lease,
+        )
+        .await?;
+        Ok(self.external_addr)
+    }
+
+    /// Removes this mapping from the router.
+    pub async fn delete(self) -> PortMappingResult<()> {
+        match &self.protocol {
+            Protocol::Upnp {
+                control_url,
+                service_type,
+                ..
+            } => {
+                upnp::delete_port_mapping(control_url, service_type, self.external_addr.port())
+                    .await
+            }
+            Protocol::NatPmp { gateway } => {
+                nat_pmp::map_tcp(*gateway, self.local_port, 0, Duration::ZERO).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Protocol through which the local router can map ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortMappingProtocol {
+    Upnp,
+    NatPmp,
+}
+
+impl std::fmt::Display for PortMappingProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortMappingProtocol::Upnp => write!(f, "UPnP"),
+            PortMappingProtocol::NatPmp => write!(f, "NAT-PMP"),
+        }
+    }
+}
+
+/// Looks for a router able to map ports, trying UPnP first and falling back to NAT-PMP,
+/// without mapping any port.
+pub async fn discover_gateway() -> PortMappingResult<PortMappingProtocol> {
+    let gateways = Gateways::find().await;
+    match gateways.upnp {
+        Ok(_) => return Ok(PortMappingProtocol::Upnp),
+        Err(err) => tracing::debug!("UPnP gateway not found ({err}), trying NAT-PMP"),
+    }
+    let gateway = gateways.nat_pmp.ok_or(PortMappingError::GatewayNotFound)?;
+    nat_pmp::external_ip(gateway).await?;
+    Ok(PortMappingProtocol::NatPmp)
+}
+
+/// Gateways found in the local network.
+struct Gateways {
+    /// internet gateway device answering the SSDP search, if it supports UPnP
+    upnp: PortMappingResult<Protocol>,
+    /// address to try NAT-PMP at: the gateway of the default route or, if it can't be read
+    /// in this platform, the device answering the SSDP search
+    nat_pmp: Option<Ipv4Addr>,
+}
+
+impl Gateways {
+    async fn find() -> Self {
+        let (upnp, responder) = match upnp::search().await {
+            Ok((responder, location)) => (upnp::describe(&location).await, Some(responder)),
+            Err(err) => (Err(err), None),
+        };
+        Self {
+            upnp,
+            nat_pmp: nat_pmp::default_gateway().or(responder),
+        }
+    }
+}
+
+/// Handle to a port mapping maintained in the background, see [`map_in_background`].
+pub(crate) struct PortMappingHandle {
+    changes: mpsc::Receiver<ExternalAddrChange>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl PortMappingHandle {
+    /// Waits for the next change of the external address, `None` if the mapping is not
+    /// maintained anymore.
+    pub async fn next_change(&mut self) -> Option<ExternalAddrChange> {
+        self.changes.recv().await
+    }
+
+    /// Stops renewing the mapping and removes it from the router.
+    pub async fn close(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+/// Maps the local port in the router and keeps renewing the mapping from a background task,
+/// so the network I/O with the router never blocks the caller. Every change of the external
+/// address is reported through the handle; the mapping is deleted once the handle is closed.
+pub(crate) fn map_in_background(local_port: u16, lease: Duration) -> PortMappingHandle {
+    let (changes, changes_rx) = mpsc::channel(1);
+    let (shutdown, mut shutdown_rx) = oneshot::channel();
+    let task = GlobalExecutor::spawn(async move {
+        let mut mapping: Option<PortMapping> = None;
+        let mut next_attempt = tokio::time::Instant::now();
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_attempt) => {}
+                _ = &mut shutdown_rx => break,
+            }
+            let previous = mapping.as_ref().map(PortMapping::external_addr);
+            let result = match mapping.as_mut() {
+                Some(current) => current.renew(lease).await,
+                None => match PortMapping::new(local_port, lease).await {
+                    Ok(new) => Ok(mapping.insert(new).external_addr()),
+                    Err(err) => Err(err),
+                },
+            };
+            match result {
+                Ok(current) => {
+                    if previous != Some(current) {
+                        let change = ExternalAddrChange { previous, current };
+                        if changes.send(change).await.is_err() {
+                            break;
+                        }
+                    }
+                    let granted = mapping.as_ref().map(PortMapping::lease).unwrap_or(lease);
+                    let renew_in = (granted / 2).max(Duration::from_secs(1));
+                    next_attempt = tokio::time::Instant::now() + renew_in;
+                }
+                Err(err) => {
+                    tracing::warn!("Unable to map port {local_port} in the local router: {err}");
+                    // retry well before the current lease, if any, expires
+                    let retry = mapping
+                        .as_ref()
+                        .map(|current| RETRY_DELAY.min(current.lease() / 4))
+                        .unwrap_or(RETRY_DELAY * 10);
+                    next_attempt = tokio::time::Instant::now() + retry;
+                }
+            }
+        }
+        if let Some(mapping) = mapping {
+            match tokio::time::timeout(DELETE_TIMEOUT, mapping.delete()).await {
+                Ok(Ok(())) => tracing::debug!("Removed port mapping from the local router"),
+                Ok(Err(err)) => tracing::warn!("Failed removing port mapping: {err}"),
+                Err(_) => tracing::warn!("Timed out removing port mapping"),
+            }
+        }
+    });
+    PortMappingHandle {
+        changes: changes_rx,
+        shutdown,
+        task,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpUrl {
+    host: SocketAddr,
+    path: String,
+}
+
+impl HttpUrl {
+    fn parse(url: &str) -> Option<Self> {
+        let rest = url.trim().strip_prefix("http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, "/"),
+        };
+        let host = match authority.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => SocketAddr::new(authority.parse::<IpAddr>().ok()?, 80),
+        };
+        Some(Self {
+            host,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Resolves a (potentially relative) url found in a document served from this url.
+    fn join(&self, url: &str) -> Option<Self> {
+        if url.starts_with("http://") {
+            Self::parse(url)
+        } else if url.starts_with('/') {
+            Some(Self {
+                host: self.host,
+                path: url.to_owned(),
+            })
+        } else {
+            Some(Self {
+                host: self.host,
+                path: format!("/{url}"),
+            })
+        }
+    }
+}
+
+mod upnp {
+    use super::*;
+
+    /// Searches an internet gateway device in the local network through SSDP, returns the
+    /// address of the device which answered and the location of its description.
+    pub(super) async fn search() -> PortMappingResult<(Ipv4Addr, HttpUrl)> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let search = "M-SEARCH * HTTP/1.1\r\n\
+            HOST: 239.255.255.250:1900\r\n\
+            ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\
+            MAN: \"ssdp:discover\"\r\n\
+            MX: 2\r\n\r\n";
+        socket.send_to(search.as_bytes(), SSDP_ADDR).await?;
+
+        let mut buf = [0u8; 2048];
+        let (read, from) = tokio::time::timeout(SSDP_TIMEOUT, socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| PortMappingError::GatewayNotFound)??;
+        let SocketAddr::V4(from) = from else {
+            return Err(PortMappingError::GatewayNotFound);
+        };
+        let response = String::from_utf8_lossy(&buf[..read]);
+        let location = header(&response, "location")
+            .and_then(HttpUrl::parse)
+            .ok_or_else(|| PortMappingError::InvalidResponse("missing location".to_owned()))?;
+        Ok((*from.ip(), location))
+    }
+
+    /// Reads the description of the device found through SSDP, looking for a WAN connection
+    /// service able to map ports.
+    pub(super) async fn describe(location: &HttpUrl) -> PortMappingResult<Protocol> {
+        let description = http_request(location, "GET", &[], "").await?;
+        let (service_type, control_url) = IGD_SERVICES
+            .iter()
+            .find_map(|service| {
+                let from = description.find(service)?;
+                let control_url = tag_value(&description[from..], "controlURL")?;
+                Some((*service, location.join(control_url)?))
+            })
+            .ok_or_else(|| {
+                PortMappingError::InvalidResponse("no WAN connection service".to_owned())
+            })?;
+
+        // the address of the interface used to reach the gateway is the one to map to
+        let internal_ip = {
+            let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+            socket.connect(control_url.host)?;
+            match socket.local_addr()?.ip() {
+                IpAddr::V4(ip) => ip,
+                IpAddr::V6(_) => return Err(PortMappingError::GatewayNotFound),
+            }
+        };
+        Ok(Protocol::Upnp {
+            control_url,
+            service_type,
+            internal_ip,
+        })
+    }
+
+    pub(super) struct MappingArgs {
+        pub internal_ip: Ipv4Addr,
+        pub local_port: u16,
+        pub external_port: u16,
+        pub lease: Duration,
+    }
+
+    impl MappingArgs {
+        pub(super) fn to_soap(&self) -> String {
+            let Self {
+                internal_ip,
+                local_port,
+                external_port,
+                lease,
+            } = self;
+            format!(
+                "<NewRemoteHost></NewRemoteHost>\
+                <NewExternalPort>{external_port}</NewExternalPort>\
+                <NewProtocol>TCP</NewProtocol>\
+                <NewInternalPort>{local_port}</NewInternalPort>\
+                <NewInternalClient>{internal_ip}</NewInternalClient>\
+                <NewEnabled>1</NewEnabled>\
+                <NewPortMappingDescription>{MAPPING_DESCRIPTION}</NewPortMappingDescription>\
+                <NewLeaseDuration>{}</NewLeaseDuration>",
+                lease.as_secs()
+            )
+        }
+    }
+
+    pub(super) async fn add_port_mapping(
+        control_url: &HttpUrl,
+        service_type: &str,
+        args: &MappingArgs,
+    ) -> PortMappingResult<()> {
+        soap_request(control_url, service_type, "AddPortMapping", &args.to_soap()).await?;
+        Ok(())
+    }
+
+    pub(super) async fn delete_port_mapping(
+        control_url: &HttpUrl,
+        service_type: &str,
+        port: u16,
+    ) -> PortMappingResult<()> {
+        let args = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+            <NewExternalPort>{port}</NewExternalPort>\
+            <NewProtocol>TCP</NewProtocol>"
+        );
+        soap_request(control_url, service_type, "DeletePortMapping", &args).await?;
+        Ok(())
+    }
+
+    pub(super) async fn external_ip(
+        control_url: &HttpUrl,
+        service_type: &str,
+    ) -> PortMappingResult<Ipv4Addr> {
+        let response = soap_request(control_url, service_type, "GetExternalIPAddress", "").await?;
+        tag_value(&response, "NewExternalIPAddress")
+            .and_then(|ip| ip.trim().parse().ok())
+            .ok_or_else(|| PortMappingError::InvalidResponse("missing external address".to_owned()))
+    }
+
+    async fn soap_request(
+        control_url: &HttpUrl,
+        service_type: &str,
+        action: &str,
+        args: &str,
+    ) -> PortMappingResult<String> {
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+            s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+            <s:Body><u:{action} xmlns:u=\"{service_type}\">{args}</u:{action}></s:Body>\
+            </s:Envelope>"
+        );
+        let soap_action = format!("SOAPAction: \"{service_type}#{action}\"");
+        let headers = [
+            "Content-Type: text/xml; charset=\"utf-8\"",
+            soap_action.as_str(),
+        ];
+        http_request(control_url, "POST", &headers, &body).await
+    }
+
+    /// Minimal HTTP/1.0 client, enough to talk with the gateway; returns the response body.
+    async fn http_request(
+        url: &HttpUrl,
+        method: &str,
+        headers: &[&str],
+        body: &str,
+    ) -> PortMappingResult<String> {
+        let request = async {
+            let mut stream = TcpStream::connect(url.host).await?;
+            let mut request = format!("{method} {} HTTP/1.0\r\nHost: {}\r\n", url.path, url.host);
+            for header in headers {
+                request.push_str(header);
+                request.push_str("\r\n");
+            }
+            request.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+            stream.write_all(request.as_bytes()).await?;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await?;
+            Ok::<_, std::io::Error>(String::from_utf8_lossy(&response).into_owned())
+        };
+        let response = tokio::time::timeout(HTTP_TIMEOUT, request)
+            .await
+            .map_err(|_| PortMappingError::Timeout)??;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| PortMappingError::InvalidResponse("malformed http response".into()))?;
+        let status = head.lines().next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            let reason = tag_value(body, "errorDescription").unwrap_or(status);
+            return Err(PortMappingError::Rejected(reason.to_owned()));
+        }
+        Ok(body.to_owned())
+    }
+
+    fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+        response.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then_some(value.trim())
+        })
+    }
+
+    /// Value of the first occurrence of the given tag, ignoring any namespace prefix.
+    pub(super) fn tag_value<'a>(doc: &'a str, tag: &str) -> Option<&'a str> {
+        let open = format!("{tag}>");
+        let start = doc.find(&open)? + open.len();
+        let end = start + doc[start..].find("</")?;
+        Some(&doc[start..end])
+    }
+}
+
+mod nat_pmp {
+    use super::*;
+
+    const OP_EXTERNAL_ADDR: u8 = 0;
+    const OP_MAP_TCP: u8 = 2;
+    const RESPONSE_FLAG: u8 = 128;
+
+    /// Default IPv4 gateway, as found in the kernel routing table. Only available on Linux,
+    /// elsewhere the device answering the SSDP search is used instead.
+    pub(super) fn default_gateway() -> Option<Ipv4Addr> {
+        let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+        parse_proc_route(&routes)
+    }
+
+    pub(super) fn parse_proc_route(routes: &str) -> Option<Ipv4Addr> {
+        routes.lines().skip(1).find_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            let destination = fields.next()?;
+            let gateway = fields.next()?;
+            if destination != "00000000" {
+                return None;
+            }
+            let gateway = u32::from_str_radix(gateway, 16).ok()?;
+            Some(Ipv4Addr::from(gateway.to_le_bytes()))
+        })
+    }
+
+    pub(super) async fn external_ip(gateway: Ipv4Addr) -> PortMappingResult<Ipv4Addr> {
+        let response = request(gateway, &[0, OP_EXTERNAL_ADDR], OP_EXTERNAL_ADDR, 12).await?;
+        Ok(Ipv4Addr::new(
+            response[8],
+            response[9],
+            response[10],
+            response[11],
+        ))
+    }
+
+    /// Request a TCP mapping, returns the external port assigned by the gateway and the
+    /// lease it granted. A zero lease (and external port) deletes the mapping.
+    pub(super) async fn map_tcp(
+        gateway: Ipv4Addr,
+        port: u16,
+        external_port: u16,
+        lease: Duration,
+    ) -> PortMappingResult<(u16, Duration)> {
+        let mut req = [0u8; 12];
+        req[1] = OP_MAP_TCP;
+        req[4..6].copy_from_slice(&port.to_be_bytes());
+        req[6..8].copy_from_slice(&external_port.to_be_bytes());
+        let lease = u32::try_from(lease.as_secs()).unwrap_or(u32::MAX);
+        req[8..12].copy_from_slice(&lease.to_be_bytes());
+        let response = request(gateway, &req, OP_MAP_TCP, 16).await?;
+        Ok(parse_mapping(&response))
+    }
+
+    /// External port and lease granted in a mapping response.
+    pub(super) fn parse_mapping(response: &[u8]) -> (u16, Duration) {
+        let port = u16::from_be_bytes([response[10], response[11]]);
+        let lease = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+        (port, Duration::from_secs(lease as u64))
+    }
+
+    async fn request(
+        gateway: Ipv4Addr,
+        req: &[u8],
+        op: u8,
+        response_len: usize,
+    ) -> PortMappingResult<Vec<u8>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        socket.connect((gateway, NAT_PMP_PORT)).await?;
+        let mut buf = [0u8; 16];
+        let mut timeout = NAT_PMP_INITIAL_TIMEOUT;
+        for _ in 0..NAT_PMP_RETRIES {
+            socket.send(req).await?;
+            match tokio::time::timeout(timeout, socket.recv(&mut buf)).await {
+                Ok(read) => {
+                    let read = read?;
+                    return parse_response(&buf[..read], op, response_len).map(|r| r.to_vec());
+                }
+                Err(_) => timeout *= 2,
+            }
+        }
+        Err(PortMappingError::Timeout)
+    }
+
+    pub(super) fn parse_response(
+        response: &[u8],
+        op: u8,
+        response_len: usize,
+    ) -> PortMappingResult<&[u8]> {
+        if response.len() < response_len || response[0] != 0 || response[1] != RESPONSE_FLAG + op {
+            return Err(PortMappingError::InvalidResponse(format!(
+                "unexpected NAT-PMP response: {response:?}"
+            )));
+        }
+        match u16::from_be_bytes([response[2], response[3]]) {
+            0 => Ok(response),
+            1 => Err(PortMappingError::Rejected("unsupported version".to_owned())),
+            2 => Err(PortMappingError::Rejected("not authorized".to_owned())),
+            3 => Err(PortMappingError::Rejected("network failure".to_owned())),
+            4 => Err(PortMappingError::Rejected("out of resources".to_owned())),
+            code => Err(PortMappingError::Rejected(format!(
+                "unsupported opcode ({code})"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_urls() {
+        let location = HttpUrl::parse("http://192.168.1.1:5000/rootDesc.xml").unwrap();
+        assert_eq!(location.host, "192.168.1.1:5000".parse().unwrap());
+        assert_eq!(location.path, "/rootDesc.xml");
+
+        let control = location.join("/ctl/IPConn").unwrap();
+        assert_eq!(control.host, location.host);
+        assert_eq!(control.path, "/ctl/IPConn");
+
+        let default_port = HttpUrl::parse("http://10.0.0.1").unwrap();
+        assert_eq!(default_port.host, "10.0.0.1:80".parse().unwrap());
+        assert_eq!(default_port.path, "/");
+    }
+
+    #[test]
+    fn find_tag_values() {
+        let response = "<s:Body><u:GetExternalIPAddressResponse>\
+            <NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>\
+            </u:GetExternalIPAddressResponse></s:Body>";
+        assert_eq!(
+            upnp::tag_value(response, "NewExternalIPAddress"),
+            Some("203.0.113.7")
+        );
+        assert_eq!(upnp::tag_value(response, "controlURL"), None);
+    }
+
+    #[test]
+    fn map_to_external_port() {
+        let args = upnp::MappingArgs {
+            internal_ip: Ipv4Addr::new(192, 168, 1, 10),
+            local_port: 7800,
+            external_port: 7801,
+            lease: Duration::from_secs(3600),
+        }
+        .to_soap();
+        assert_eq!(upnp::tag_value(&args, "NewExternalPort"), Some("7801"));
+        assert_eq!(upnp::tag_value(&args, "NewInternalPort"), Some("7800"));
+        assert_eq!(
+            upnp::tag_value(&args, "NewInternalClient"),
+            Some("192.168.1.10")
+        );
+    }
+
+    #[test]
+    fn nat_pmp_responses() {
+        let ok = [
+            0, 130, 0, 0, 0, 0, 0, 1, 0x1f, 0x90, 0x1f, 0x91, 0, 0, 0x0e, 0x10,
+        ];
+        assert!(nat_pmp::parse_response(&ok, 2, 16).is_ok());
+        assert_eq!(
+            nat_pmp::parse_mapping(&ok),
+            (0x1f91, Duration::from_secs(3600))
+        );
+
+        let refused = [0, 130, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            nat_pmp::parse_response(&refused, 2, 16),
+            Err(PortMappingError::Rejected(_))
+        ));
+
+        let wrong_op = [0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 7];
+        assert!(matches!(
+            nat_pmp::parse_response(&wrong_op, 2, 16),
+            Err(PortMappingError::InvalidResponse(_))
+        ));
+    }
+
+    #[test]
+    fn find_default_gateway() {
+        let proc_route = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+            eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\n\
+            eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\n";
+        assert_eq!(
+            nat_pmp::parse_proc_route(proc_route),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+    }
+}
@@ -1,23 +1,31 @@
 use std::{collections::HashMap, sync::Arc};
 
 use either::Either;
+use futures::future;
 use locutus_runtime::prelude::ContractKey;
 use locutus_runtime::ContractContainer;
-use tokio::sync::mpsc::{self, Receiver};
+use locutus_stdlib::client_api::AdminToken;
+use tokio::{
+    sync::mpsc::{self, Receiver},
+    time::Instant,
+};
 
 use super::{
-    client_event_handling, conn_manager::in_memory::MemoryConnManager,
-    event_listener::EventListener, handle_cancelled_op, join_ring_request, op_state::OpManager,
-    process_message, PeerKey,
+    client_event_handling,
+    conn_manager::{allowlist::sync_allowlist_contract, in_memory::MemoryConnManager},
+    event_listener::EventListener,
+    handle_cancelled_op, join_ring_request,
+    op_state::{watch_stuck_ops, CompletedOps, OpManager},
+    process_message, PeerKey, TraceSampler,
 };
 use crate::{
     client_events::ClientEventsProxy,
-    config::GlobalExecutor,
+    config::{GlobalExecutor, CONFIG},
     contract::{self, ContractError, ContractHandler, ContractHandlerEvent, SimStoreError},
     message::{Message, NodeEvent, TransactionType},
-    operations::OpError,
+    operations::{subscribe, OpError},
     ring::{PeerKeyLocation, Ring},
-    util::IterExt,
+    util::{ExponentialBackoff, IterExt},
     NodeConfig, WrappedState,
 };
 
@@ -29,6 +37,11 @@ pub(super) struct NodeInMemory<CErr = SimStoreError> {
     conn_manager: MemoryConnManager,
     event_listener: Option<Box<dyn EventListener + Send + Sync + 'static>>,
     is_gateway: bool,
+    /// cooldown between attempts to join the ring through different gateways
+    reconnection_backoff: ExponentialBackoff,
+    /// when to attempt joining the ring again, after failing to join it
+    reconnect_at: Option<Instant>,
+    admin_token: Option<AdminToken>,
 }
 
 impl<CErr> NodeInMemory<CErr>
@@ -53,10 +66,22 @@ where
         let ring = Ring::new(&config, &gateways)?;
         let (notification_tx, notification_channel) = mpsc::channel(100);
         let (ops_ch_channel, ch_channel) = contract::contract_handler_channel();
-        let op_storage = Arc::new(OpManager::new(ring, notification_tx, ops_ch_channel));
+        let op_storage = Arc::new(OpManager::new(
+            ring,
+            notification_tx,
+            ops_ch_channel,
+            CompletedOps::in_memory(),
+            TraceSampler::new(config.trace_sample_rate),
+        ));
         let contract_handler = CH::from(ch_channel);
 
         GlobalExecutor::spawn(contract::contract_handling(contract_handler));
+        GlobalExecutor::spawn(subscribe::renew_subscriptions(op_storage.clone()));
+        GlobalExecutor::spawn(subscribe::sync_subscribed_contracts(op_storage.clone()));
+        GlobalExecutor::spawn(watch_stuck_ops(op_storage.clone()));
+        if let Some(contract) = config.allowlist_contract.clone() {
+            GlobalExecutor::spawn(sync_allowlist_contract(op_storage.clone(), contract));
+        }
 
         Ok(NodeInMemory {
             peer_key,
@@ -66,6 +91,9 @@ where
             notification_channel,
             event_listener,
             is_gateway,
+            reconnection_backoff: CONFIG.retry.reconnection.backoff(),
+            reconnect_at: None,
+            admin_token: config.admin_token,
         })
     }
 
@@ -87,7 +115,11 @@ where
                 anyhow::bail!("requires at least one gateway");
             }
         }
-        GlobalExecutor::spawn(client_event_handling(self.op_storage.clone(), user_events));
+        GlobalExecutor::spawn(client_event_handling(
+            self.op_storage.clone(),
+            user_events,
+            self.admin_token.clone(),
+        ));
         self.run_event_listener().await
     }
 
@@ -105,6 +137,7 @@ where
                 .notify_contract_handler(ContractHandlerEvent::PushQuery {
                     key: key.clone(),
                     state,
+                    causality: None,
                 })
                 .await?;
             tracing::debug!(
@@ -134,12 +167,33 @@ where
     /// Starts listening to incoming events. Will attempt to join the ring if any gateways have been provided.
     async fn run_event_listener(&mut self) -> Result<(), anyhow::Error> {
         loop {
+            let reconnect_at = self.reconnect_at;
+            let reconnect = async move {
+                match reconnect_at {
+                    Some(reconnect_at) => tokio::time::sleep_until(reconnect_at).await,
+                    None => future::pending().await,
+                }
+            };
+
             let msg = tokio::select! {
                 msg = self.conn_manager.recv() => { msg.map(Either::Left) }
                 msg = self.notification_channel.recv() => if let Some(msg) = msg {
                     Ok(msg)
                 } else {
                     anyhow::bail!("notification channel shutdown, fatal error");
+                },
+                _ = reconnect => {
+                    self.reconnect_at = None;
+                    let gateway = self.gateways.iter().shuffle().next().unwrap();
+                    join_ring_request(
+                        None,
+                        self.peer_key,
+                        gateway,
+                        &self.op_storage,
+                        &mut self.conn_manager,
+                    )
+                    .await?;
+                    continue;
                 }
             };
 
@@ -154,19 +208,18 @@ where
                 )
                 .await;
                 match res {
-                    Err(OpError::MaxRetriesExceeded(_, _))
+                    Err(err @ OpError::MaxRetriesExceeded(_, _))
                         if tx_type == TransactionType::JoinRing && !self.is_gateway =>
                     {
-                        tracing::warn!("Retrying joining the ring with an other peer");
-                        let gateway = self.gateways.iter().shuffle().next().unwrap();
-                        join_ring_request(
-                            None,
-                            self.peer_key,
-                            gateway,
-                            &self.op_storage,
-                            &mut self.conn_manager,
-                        )
-                        .await?
+                        if self.op_storage.ring.num_connections() > 0 {
+                            // already joined the ring, so this node is not isolated
+                            self.reconnection_backoff.reset();
+                        }
+                        let Some(delay) = self.reconnection_backoff.next_delay() else {
+                            return Err(anyhow::anyhow!(err));
+                        };
+                        tracing::warn!("Retrying joining the ring with an other peer in {delay:?}");
+                        self.reconnect_at = Some(Instant::now() + delay);
                     }
                     Err(err) => return Err(anyhow::anyhow!(err)),
                     Ok(_) => {}
@@ -181,6 +234,8 @@ where
                     NodeEvent::ConfirmedInbound => continue,
                     NodeEvent::DropConnection(_) => continue,
                     NodeEvent::AcceptConnection(_) => continue,
+                    // the in-memory transport has no listeners
+                    NodeEvent::AddListener(_) | NodeEvent::RemoveListener(_) => continue,
                     NodeEvent::Error(err) => {
                         tracing::error!("Connection error within ops: {err}");
                         continue;
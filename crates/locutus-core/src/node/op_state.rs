@@ -1,23 +1,45 @@
-use std::{collections::BTreeMap, time::Instant};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    time::{Duration, Instant},
+};
 
 use dashmap::DashMap;
 use either::Either;
 use parking_lot::RwLock;
 use tokio::sync::{
-    mpsc::{error::SendError, Sender},
-    Mutex,
+    mpsc::{error::SendError, Sender, UnboundedSender},
+    Mutex, OwnedMutexGuard,
 };
 
 use crate::{
+    client_events::HostResult,
+    config::GlobalExecutor,
     contract::{CHSenderHalve, ContractError, ContractHandlerChannel, ContractHandlerEvent},
     message::{Message, NodeEvent, Transaction, TransactionType},
     operations::{
-        get::GetOp, join_ring::JoinRingOp, put::PutOp, subscribe::SubscribeOp, OpEnum, OpError,
+        get::GetOp,
+        join_ring::JoinRingOp,
+        put::PutOp,
+        sample::{SampleOp, SamplingRateLimiter},
+        seed::{SeedOp, SnapshotRateLimiter},
+        subscribe::SubscribeOp,
+        OpEnum, OpError,
     },
     ring::Ring,
 };
 
-use super::PeerKey;
+use super::{PeerKey, TraceSampler};
+
+mod completed;
+mod locks;
+mod watchdog;
+
+pub(crate) use completed::CompletedOps;
+use completed::Completion;
+use locks::TxLocks;
+pub(crate) use watchdog::watch_stuck_ops;
+use watchdog::{OpWatchdog, ProcessingGuard};
 
 /// Thread safe and friendly data structure to maintain state of the different operations
 /// and enable their execution.
@@ -26,6 +48,22 @@ pub(crate) struct OpManager<CErr> {
     put: DashMap<Transaction, PutOp>,
     get: DashMap<Transaction, GetOp>,
     subscribe: DashMap<Transaction, SubscribeOp>,
+    sample: DashMap<Transaction, SampleOp>,
+    seed: DashMap<Transaction, SeedOp>,
+    sampling_limiter: SamplingRateLimiter,
+    snapshot_limiter: SnapshotRateLimiter,
+    /// one message of each transaction handled at a time
+    tx_locks: TxLocks,
+    /// whether the cache of this node has already been seeded from a neighbour
+    cache_seeded: AtomicBool,
+    /// recently completed transactions, to ignore replayed or delayed messages for them
+    completed: CompletedOps,
+    /// which transactions are traced
+    pub trace_sampler: TraceSampler,
+    /// recent activity, to report stuck operations
+    watchdog: OpWatchdog,
+    /// clients awaiting the result of the transactions they started at this node
+    clients: DashMap<Transaction, UnboundedSender<HostResult>>,
     notification_channel: Sender<Either<Message, NodeEvent>>,
     contract_handler: Mutex<ContractHandlerChannel<CErr, CHSenderHalve>>,
     // FIXME: think of an optimal strategy to check for timeouts and clean up garbage
@@ -49,12 +87,24 @@ where
         ring: Ring,
         notification_channel: Sender<Either<Message, NodeEvent>>,
         contract_handler: ContractHandlerChannel<CErr, CHSenderHalve>,
+        completed: CompletedOps,
+        trace_sampler: TraceSampler,
     ) -> Self {
         Self {
             join_ring: DashMap::default(),
             put: DashMap::default(),
             get: DashMap::default(),
             subscribe: DashMap::default(),
+            sample: DashMap::default(),
+            seed: DashMap::default(),
+            sampling_limiter: SamplingRateLimiter::default(),
+            snapshot_limiter: SnapshotRateLimiter::default(),
+            tx_locks: TxLocks::default(),
+            cache_seeded: AtomicBool::new(false),
+            completed,
+            trace_sampler,
+            watchdog: OpWatchdog::default(),
+            clients: DashMap::default(),
             ring,
             notification_channel,
             contract_handler: Mutex::new(contract_handler),
@@ -81,6 +131,16 @@ where
             .map_err(|err| SendError(err.0.unwrap_left()))
     }
 
+    /// Delivers a message for an on-going operation to this node event loop once the delay
+    /// elapses, e.g. to finish an operation which is waiting on other peers at a deadline.
+    pub fn notify_op_change_after(&self, delay: Duration, msg: Message) {
+        let notification_channel = self.notification_channel.clone();
+        GlobalExecutor::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let _ = notification_channel.send(Either::Left(msg)).await;
+        });
+    }
+
     /// Send an internal message to this node event loop.
     pub async fn notify_internal_op(&self, msg: NodeEvent) -> Result<(), SendError<NodeEvent>> {
         self.notification_channel
@@ -119,7 +179,16 @@ where
                 check_id_op!(id.tx_type(), TransactionType::Subscribe);
                 self.subscribe.insert(id, tx);
             }
+            OpEnum::Sample(tx) => {
+                check_id_op!(id.tx_type(), TransactionType::Sample);
+                self.sample.insert(id, tx);
+            }
+            OpEnum::Seed(tx) => {
+                check_id_op!(id.tx_type(), TransactionType::Seed);
+                self.seed.insert(id, tx);
+            }
         }
+        self.watchdog.track(id);
         Ok(())
     }
 
@@ -137,10 +206,145 @@ where
                 .remove(id)
                 .map(|(_k, v)| v)
                 .map(OpEnum::Subscribe),
+            TransactionType::Sample => self.sample.remove(id).map(|(_k, v)| v).map(OpEnum::Sample),
+            TransactionType::Seed => self.seed.remove(id).map(|(_k, v)| v).map(OpEnum::Seed),
             TransactionType::Canceled => unreachable!(),
         }
     }
 
+    /// Whether there is state kept at this node for the transaction.
+    pub fn contains(&self, id: &Transaction) -> bool {
+        match id.tx_type() {
+            TransactionType::JoinRing => self.join_ring.contains_key(id),
+            TransactionType::Put => self.put.contains_key(id),
+            TransactionType::Get => self.get.contains_key(id),
+            TransactionType::Subscribe => self.subscribe.contains_key(id),
+            TransactionType::Sample => self.sample.contains_key(id),
+            TransactionType::Seed => self.seed.contains_key(id),
+            TransactionType::Canceled => false,
+        }
+    }
+
+    /// Operations pending at this node for too long, for how long and their state.
+    fn stuck_ops(&self) -> Vec<(Transaction, Duration, String)> {
+        let now = Instant::now();
+        self.watchdog.retain_pending(|tx| self.contains(tx));
+        let mut stuck = Vec::new();
+        macro_rules! collect_stuck {
+            ($($ops:ident),+) => {
+                $(
+                    stuck.extend(self.$ops.iter().filter_map(|op| {
+                        let pending = self.watchdog.stuck_for(op.key(), now)?;
+                        Some((*op.key(), pending, format!("{:?}", op.value())))
+                    }));
+                )+
+            };
+        }
+        collect_stuck!(join_ring, put, get, subscribe, sample, seed);
+        stuck
+    }
+
+    /// Number of operations pending at this node.
+    #[cfg(test)]
+    pub fn pending_ops(&self) -> usize {
+        self.join_ring.len()
+            + self.put.len()
+            + self.get.len()
+            + self.subscribe.len()
+            + self.sample.len()
+            + self.seed.len()
+    }
+
+    /// Number of completed transactions remembered by this node.
+    #[cfg(test)]
+    pub fn completed_ops(&self) -> usize {
+        self.completed.count()
+    }
+
+    /// Keeps track of a message handled by this node, for the diagnostics of stuck operations.
+    /// Only the last few messages of each sampled operation are kept.
+    pub fn record_message(&self, msg: &Message) {
+        if self.trace_sampler.is_sampled(msg.id()) {
+            self.watchdog.record_message(msg);
+        }
+    }
+
+    /// Marks the operation as being processed at this node until the guard is dropped, while
+    /// it's popped from the storage.
+    pub fn processing(&self, id: Transaction) -> ProcessingGuard<'_> {
+        self.watchdog.processing(id)
+    }
+
+    /// Marks the transaction as completed at this node.
+    pub fn completed(&self, id: Transaction) {
+        self.watchdog.forget(&id);
+        self.completed.insert(id, Completion::Operation);
+    }
+
+    /// Whether the transaction was already completed at this node, any further
+    /// message for it is either delayed or replayed.
+    pub fn is_completed(&self, id: &Transaction) -> bool {
+        self.completed.contains(*id, Completion::Operation)
+    }
+
+    /// Marks the changes carried by the transaction as applied at this node.
+    pub fn mark_changes_applied(&self, id: Transaction) {
+        self.completed.insert(id, Completion::Changes);
+    }
+
+    /// Whether the changes carried by the transaction were already applied at this node, any
+    /// further message carrying them is replayed.
+    pub fn changes_applied(&self, id: &Transaction) -> bool {
+        self.completed.contains(*id, Completion::Changes)
+    }
+
+    /// Registers the client which started the transaction, to send it back the result.
+    pub fn register_client(&self, id: Transaction, client: UnboundedSender<HostResult>) {
+        self.clients.insert(id, client);
+    }
+
+    /// Sends the result of the transaction back to the client which started it, if any.
+    pub fn notify_client(&self, id: &Transaction, result: HostResult) {
+        if let Some((_, client)) = self.clients.remove(id) {
+            if client.send(result).is_err() {
+                tracing::debug!("Client of transaction {id} is gone");
+            }
+        }
+    }
+
+    /// Whether a random walk from the given requester can be served, rate limiting
+    /// the walks to prevent abuse of network sampling.
+    pub fn sampling_allowed(&self, requester: &PeerKey) -> bool {
+        self.sampling_limiter.allow(requester)
+    }
+
+    /// Whether a cache snapshot can be sent to the requester, rate limiting the snapshots
+    /// served to the same peer.
+    pub fn snapshot_allowed(&self, requester: &PeerKey) -> bool {
+        self.snapshot_limiter.allow(requester)
+    }
+
+    /// Waits until no other message of the given transaction is being handled at this node.
+    pub async fn lock_tx(&self, tx: &Transaction) -> OwnedMutexGuard<()> {
+        self.tx_locks.lock(tx).await
+    }
+
+    /// Whether the event loop of the node stopped, so no more messages can be sent.
+    pub fn is_shut_down(&self) -> bool {
+        self.notification_channel.is_closed()
+    }
+
+    /// Returns true only the first time it is called, so the cache is seeded once
+    /// after joining the ring.
+    pub fn start_cache_seeding(&self) -> bool {
+        !self.cache_seeded.swap(true, SeqCst)
+    }
+
+    #[cfg(test)]
+    pub fn cache_seeding_started(&self) -> bool {
+        self.cache_seeded.load(SeqCst)
+    }
+
     pub fn prune_connection(&self, peer: PeerKey) {
         // pending ops will be cleaned up by the garbage collector on time out
         self.ring.prune_connection(peer);
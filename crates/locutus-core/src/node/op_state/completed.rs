@@ -0,0 +1,320 @@
+//! Log of the transactions recently completed by this node.
+//!
+//! Messages may arrive late, be duplicated by the network or be replayed by a malicious peer.
+//! Once an operation is completed its state is dropped, so any further message would start
+//! it again from scratch, potentially applying the same state changes and notifying clients
+//! twice. Completed transactions are remembered (and optionally persisted, so they survive a
+//! quick restart) for a bounded amount of time so those messages can be discarded.
+//!
+//! Peers which only apply the changes carried by a transaction, without keeping any state for
+//! it, remember that they applied them instead, so messages carrying them again are discarded
+//! while the rest of the operation goes on.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crossbeam::channel::{self, Sender};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::message::Transaction;
+
+/// Maximum number of completed transactions remembered.
+const MAX_COMPLETED_OPS: usize = 10_000;
+/// For how long a completed transaction is remembered.
+const COMPLETED_OP_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// What was completed of a transaction at this node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum Completion {
+    /// The operation finished at this node, any further message of it is ignored.
+    Operation,
+    /// The changes carried by the transaction were applied at this node.
+    Changes,
+}
+
+type Entry = (Transaction, Completion);
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    entry: Entry,
+    /// Seconds since the unix epoch.
+    completed_at: u64,
+}
+
+pub(crate) struct CompletedOps {
+    inner: Mutex<Inner>,
+    /// Persists the completions in the background, so callers never block on file i/o.
+    log: Option<LogWriter>,
+}
+
+#[derive(Clone, Default)]
+struct Inner {
+    entries: HashSet<Entry>,
+    /// Completed transactions, oldest first.
+    order: VecDeque<(u64, Entry)>,
+}
+
+/// Append-only file where completions are recorded, compacted once it grows too large.
+struct Log {
+    path: PathBuf,
+    file: File,
+    records: usize,
+}
+
+/// Thread owning the log, which keeps its own copy of the retained completions to compact it.
+struct LogWriter {
+    records: Option<Sender<Record>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl CompletedOps {
+    /// Completed transactions are only kept in memory and forgotten on restart.
+    pub fn in_memory() -> Self {
+        Self {
+            inner: Mutex::new(Inner::default()),
+            log: None,
+        }
+    }
+
+    /// Completed transactions are persisted at the given path, and any previously persisted
+    /// which did not expire yet are loaded.
+    pub fn persistent(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut inner = Inner::default();
+        if path.exists() {
+            let data = fs::read(&path)?;
+            let mut reader = data.as_slice();
+            // a truncated record at the end of the log (e.g. after a crash) is ignored
+            while let Ok(Record {
+                entry,
+                completed_at,
+            }) = bincode::deserialize_from(&mut reader)
+            {
+                inner.insert(entry, completed_at);
+            }
+        }
+        inner.prune(unix_now());
+        let file = Log::compact(&path, &inner.order)?;
+        let log = Log {
+            path,
+            file,
+            records: inner.order.len(),
+        };
+        let log = LogWriter::spawn(log, inner.clone())?;
+        Ok(Self {
+            inner: Mutex::new(inner),
+            log: Some(log),
+        })
+    }
+
+    /// Whether the transaction was already completed at this node.
+    pub fn contains(&self, tx: Transaction, completion: Completion) -> bool {
+        let mut inner = self.inner.lock();
+        inner.prune(unix_now());
+        inner.entries.contains(&(tx, completion))
+    }
+
+    /// Number of completed transactions remembered.
+    #[cfg(test)]
+    pub fn count(&self) -> usize {
+        let mut inner = self.inner.lock();
+        inner.prune(unix_now());
+        inner.entries.len()
+    }
+
+    /// Records the transaction as completed at this node, returns false if it already was.
+    pub fn insert(&self, tx: Transaction, completion: Completion) -> bool {
+        let now = unix_now();
+        let entry = (tx, completion);
+        let inserted = {
+            let mut inner = self.inner.lock();
+            inner.prune(now);
+            inner.insert(entry, now)
+        };
+        if let (true, Some(log)) = (inserted, &self.log) {
+            log.persist(Record {
+                entry,
+                completed_at: now,
+            });
+        }
+        inserted
+    }
+}
+
+impl Inner {
+    fn insert(&mut self, entry: Entry, completed_at: u64) -> bool {
+        if !self.entries.insert(entry) {
+            return false;
+        }
+        self.order.push_back((completed_at, entry));
+        if self.order.len() > MAX_COMPLETED_OPS {
+            if let Some((_, oldest)) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    fn prune(&mut self, now: u64) {
+        let min_time = now.saturating_sub(COMPLETED_OP_TTL.as_secs());
+        while let Some((completed_at, entry)) = self.order.front() {
+            if *completed_at >= min_time {
+                break;
+            }
+            self.entries.remove(entry);
+            self.order.pop_front();
+        }
+    }
+}
+
+impl LogWriter {
+    fn spawn(mut log: Log, mut retained: Inner) -> io::Result<Self> {
+        let (records, rx) = channel::unbounded::<Record>();
+        let writer = thread::Builder::new()
+            .name("completed-ops-log".to_owned())
+            .spawn(move || {
+                for Record {
+                    entry,
+                    completed_at,
+                } in rx
+                {
+                    retained.prune(completed_at);
+                    if !retained.insert(entry, completed_at) {
+                        continue;
+                    }
+                    if let Err(err) = log.append(entry, completed_at, &retained.order) {
+                        let tx = entry.0;
+                        tracing::warn!("failed to persist completed transaction {tx}: {err}");
+                    }
+                }
+            })?;
+        Ok(Self {
+            records: Some(records),
+            writer: Some(writer),
+        })
+    }
+
+    fn persist(&self, record: Record) {
+        if let Some(records) = &self.records {
+            let _ = records.send(record);
+        }
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        // closing the channel stops the writer once every pending record is written
+        self.records.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Log {
+    fn append(
+        &mut self,
+        entry: Entry,
+        completed_at: u64,
+        retained: &VecDeque<(u64, Entry)>,
+    ) -> io::Result<()> {
+        if self.records >= MAX_COMPLETED_OPS * 2 {
+            self.file = Self::compact(&self.path, retained)?;
+            self.records = retained.len();
+            return Ok(());
+        }
+        let record = Record {
+            entry,
+            completed_at,
+        };
+        bincode::serialize_into(&mut self.file, &record).map_err(into_io_error)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Rewrites the log with only the retained records and opens it for appending.
+    fn compact(path: &Path, retained: &VecDeque<(u64, Entry)>) -> io::Result<File> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            for (completed_at, entry) in retained {
+                let record = Record {
+                    entry: *entry,
+                    completed_at: *completed_at,
+                };
+                bincode::serialize_into(&mut writer, &record).map_err(into_io_error)?;
+            }
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        OpenOptions::new().append(true).open(path)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn into_io_error(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{message::TxType, node::PeerKey, operations::put::PutMsg};
+
+    fn new_tx() -> Transaction {
+        Transaction::new(<PutMsg as TxType>::tx_type_id(), &PeerKey::random())
+    }
+
+    #[test]
+    fn forget_expired_ops() {
+        let mut inner = Inner::default();
+        let old = (new_tx(), Completion::Operation);
+        let recent = (new_tx(), Completion::Operation);
+        let now = unix_now();
+        inner.insert(old, now - COMPLETED_OP_TTL.as_secs() - 1);
+        inner.insert(recent, now);
+        assert!(!inner.insert(recent, now));
+        inner.prune(now);
+        assert!(!inner.entries.contains(&old));
+        assert!(inner.entries.contains(&recent));
+    }
+
+    #[test]
+    fn survive_restarts() -> Result<(), anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("completed_ops_{}", new_tx()));
+        let txs: Vec<_> = (0..3).map(|_| new_tx()).collect();
+        {
+            let completed = CompletedOps::persistent(&path)?;
+            for tx in &txs {
+                completed.insert(*tx, Completion::Operation);
+            }
+            completed.insert(txs[0], Completion::Changes);
+        }
+        // simulate a crash while writing a record
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&[1, 2, 3])?;
+
+        let completed = CompletedOps::persistent(&path)?;
+        assert!(txs
+            .iter()
+            .all(|tx| completed.contains(*tx, Completion::Operation)));
+        assert!(completed.contains(txs[0], Completion::Changes));
+        assert!(!completed.contains(txs[1], Completion::Changes));
+        assert!(!completed.contains(new_tx(), Completion::Operation));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
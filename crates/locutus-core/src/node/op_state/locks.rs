@@ -0,0 +1,64 @@
+//! Serializes the handling of the messages of each transaction. Handling a message pops the op
+//! from the op storage until it's processed, so a message handled concurrently with another one
+//! of the same transaction would neither find the op nor see it completed, and be lost or
+//! applied twice.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::message::Transaction;
+
+/// Number of locks above which the ones no one is holding are dropped.
+const MAX_IDLE_LOCKS: usize = 1024;
+
+#[derive(Default)]
+pub(crate) struct TxLocks {
+    locks: DashMap<Transaction, Arc<Mutex<()>>>,
+}
+
+impl TxLocks {
+    /// Waits until no other message of the transaction is being handled.
+    pub async fn lock(&self, tx: &Transaction) -> OwnedMutexGuard<()> {
+        if self.locks.len() > MAX_IDLE_LOCKS {
+            // only the map holds the locks no one is holding or waiting for
+            self.locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        }
+        let lock = self.locks.entry(*tx).or_default().clone();
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        message::TxType,
+        node::PeerKey,
+        operations::{get::GetMsg, put::PutMsg},
+    };
+
+    #[tokio::test]
+    async fn handle_tx_messages_in_order() {
+        let locks = TxLocks::default();
+        let peer = PeerKey::random();
+        let (tx, other) = (
+            Transaction::new(<PutMsg as TxType>::tx_type_id(), &peer),
+            Transaction::new(<GetMsg as TxType>::tx_type_id(), &peer),
+        );
+        let guard = locks.lock(&tx).await;
+        let timeout = Duration::from_millis(50);
+        assert!(tokio::time::timeout(timeout, locks.lock(&tx))
+            .await
+            .is_err());
+        // other transactions are not blocked
+        assert!(tokio::time::timeout(timeout, locks.lock(&other))
+            .await
+            .is_ok());
+        drop(guard);
+        assert!(tokio::time::timeout(timeout, locks.lock(&tx)).await.is_ok());
+    }
+}
@@ -0,0 +1,236 @@
+//! Detection of operations which are stuck.
+//!
+//! Operations pending at this node for several times their expected duration, as measured by
+//! the clock of this node since it first saw them, are reported
+//! once through the node events, alongside a diagnostics bundle with the state of the
+//! operation, the last messages of the transaction handled by this node and the peers
+//! this node is connected to, so hanging operations can be investigated after the fact.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use locutus_stdlib::client_api::{NodeNotification, OpDiagnostics};
+use parking_lot::Mutex;
+
+use super::OpManager;
+use crate::message::{Message, Transaction, TransactionType};
+
+/// How often pending operations are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// An operation is stuck once pending for this many times its expected duration.
+const STUCK_MULTIPLE: u32 = 4;
+/// Maximum number of messages kept for the diagnostics of each transaction, the oldest are
+/// dropped first.
+const MAX_MESSAGES_PER_TX: usize = 8;
+/// Maximum number of transactions whose messages are kept at the same time.
+const MAX_TRACKED_TXS: usize = 1024;
+
+/// Usual duration of each kind of operation.
+fn expected_duration(ty: TransactionType) -> Duration {
+    match ty {
+        TransactionType::JoinRing | TransactionType::Seed => Duration::from_secs(30),
+        TransactionType::Put => Duration::from_secs(10),
+        TransactionType::Get
+        | TransactionType::Subscribe
+        | TransactionType::Sample
+        | TransactionType::Canceled => Duration::from_secs(5),
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct OpWatchdog {
+    /// when each pending operation was first seen at this node
+    first_seen: DashMap<Transaction, Instant>,
+    /// summary of the last messages handled of each transaction, oldest first
+    messages: DashMap<Transaction, VecDeque<String>>,
+    /// operations currently popped from the storage while a message of them is processed
+    processing: DashMap<Transaction, usize>,
+    /// stuck transactions already reported
+    reported: Mutex<HashSet<Transaction>>,
+}
+
+impl OpWatchdog {
+    /// Starts tracking for how long the operation is pending, unless already tracked.
+    pub fn track(&self, tx: Transaction) {
+        self.first_seen.entry(tx).or_insert_with(Instant::now);
+    }
+
+    pub fn forget(&self, tx: &Transaction) {
+        self.first_seen.remove(tx);
+        self.messages.remove(tx);
+    }
+
+    /// Marks the operation as being processed until the returned guard is dropped, so it's
+    /// still tracked meanwhile even if not present in the operations storage.
+    pub fn processing(&self, tx: Transaction) -> ProcessingGuard<'_> {
+        *self.processing.entry(tx).or_default() += 1;
+        ProcessingGuard { watchdog: self, tx }
+    }
+
+    /// Stops tracking the operations which are neither pending nor being processed anymore.
+    pub fn retain_pending(&self, is_pending: impl Fn(&Transaction) -> bool) {
+        let keep = |tx: &Transaction| is_pending(tx) || self.processing.contains_key(tx);
+        self.first_seen.retain(|tx, _| keep(tx));
+        self.messages.retain(|tx, _| keep(tx));
+    }
+
+    fn pending_for(&self, tx: &Transaction, now: Instant) -> Option<Duration> {
+        let first_seen = *self.first_seen.get(tx)?;
+        Some(now.saturating_duration_since(first_seen))
+    }
+
+    /// For how long the operation has been pending, if it is stuck.
+    pub fn stuck_for(&self, tx: &Transaction, now: Instant) -> Option<Duration> {
+        self.pending_for(tx, now)
+            .filter(|pending| *pending > expected_duration(tx.tx_type()) * STUCK_MULTIPLE)
+    }
+
+    pub fn record_message(&self, msg: &Message) {
+        let tx = msg.id();
+        if !self.messages.contains_key(tx) && self.messages.len() >= MAX_TRACKED_TXS {
+            return;
+        }
+        let mut messages = self.messages.entry(*tx).or_default();
+        if messages.len() >= MAX_MESSAGES_PER_TX {
+            messages.pop_front();
+        }
+        messages.push_back(msg.to_string());
+    }
+
+    fn messages_of(&self, tx: &Transaction) -> Vec<String> {
+        self.messages
+            .get(tx)
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Keeps an operation tracked while it's being processed, see [`OpWatchdog::processing`].
+pub(crate) struct ProcessingGuard<'a> {
+    watchdog: &'a OpWatchdog,
+    tx: Transaction,
+}
+
+impl Drop for ProcessingGuard<'_> {
+    fn drop(&mut self) {
+        self.watchdog
+            .processing
+            .remove_if_mut(&self.tx, |_, count| {
+                *count -= 1;
+                *count == 0
+            });
+    }
+}
+
+/// Periodically reports the operations which are stuck at this node.
+pub(crate) async fn watch_stuck_ops<CErr>(op_storage: Arc<OpManager<CErr>>)
+where
+    CErr: std::error::Error,
+{
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if op_storage.is_shut_down() {
+            break;
+        }
+        for diagnostics in stuck_ops_diagnostics(&op_storage) {
+            tracing::warn!(
+                "{} operation {} stuck for {:?}, state: {}",
+                diagnostics.operation,
+                diagnostics.transaction,
+                diagnostics.elapsed,
+                diagnostics.state
+            );
+            op_storage
+                .ring
+                .notify_node_event(NodeNotification::StuckOperation(Box::new(diagnostics)));
+        }
+    }
+}
+
+/// Diagnostics of the operations which became stuck since the last check.
+fn stuck_ops_diagnostics<CErr>(op_storage: &OpManager<CErr>) -> Vec<OpDiagnostics>
+where
+    CErr: std::error::Error,
+{
+    let watchdog = &op_storage.watchdog;
+    let stuck = op_storage.stuck_ops();
+    let mut reported = watchdog.reported.lock();
+    // forget the operations which are not stuck anymore
+    reported.retain(|tx| stuck.iter().any(|(id, ..)| id == tx));
+    let mut new_stuck = Vec::new();
+    for (tx, elapsed, state) in stuck {
+        if !reported.insert(tx) {
+            continue;
+        }
+        new_stuck.push(OpDiagnostics {
+            transaction: tx.to_string(),
+            operation: format!("{:?}", tx.tx_type()),
+            elapsed,
+            expected: expected_duration(tx.tx_type()),
+            state,
+            recent_messages: watchdog.messages_of(&tx),
+            neighbours: op_storage.ring.connected_peers(),
+        });
+    }
+    new_stuck
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        message::TxType,
+        node::PeerKey,
+        operations::{sample::SampleMsg, seed::SeedMsg},
+    };
+
+    #[test]
+    fn keep_last_messages_per_tx() {
+        let watchdog = OpWatchdog::default();
+        let peer = PeerKey::random();
+        let tx = Transaction::new(<SeedMsg as TxType>::tx_type_id(), &peer);
+        let other = Transaction::new(<SampleMsg as TxType>::tx_type_id(), &peer);
+        watchdog.record_message(&Message::Canceled(tx));
+        for _ in 0..MAX_MESSAGES_PER_TX * 2 {
+            watchdog.record_message(&Message::Canceled(other));
+        }
+        // busy transactions don't evict the messages of the rest
+        assert_eq!(watchdog.messages_of(&tx).len(), 1);
+        assert_eq!(watchdog.messages_of(&other).len(), MAX_MESSAGES_PER_TX);
+
+        watchdog.forget(&tx);
+        assert!(watchdog.messages_of(&tx).is_empty());
+    }
+
+    #[test]
+    fn detect_stuck_ops() {
+        let watchdog = OpWatchdog::default();
+        let tx = Transaction::new(<SeedMsg as TxType>::tx_type_id(), &PeerKey::random());
+        let expected = expected_duration(tx.tx_type());
+        let now = Instant::now();
+        assert_eq!(watchdog.stuck_for(&tx, now + expected * 10), None);
+
+        watchdog.track(tx);
+        assert_eq!(watchdog.stuck_for(&tx, now + expected * 2), None);
+        let later = now + expected * (STUCK_MULTIPLE + 1);
+        assert!(watchdog.stuck_for(&tx, later).is_some());
+
+        // tracking it again doesn't reset the time it has been pending
+        watchdog.track(tx);
+        assert!(watchdog.stuck_for(&tx, later).is_some());
+
+        // operations being processed are not in the storage but still pending
+        let processing = watchdog.processing(tx);
+        watchdog.retain_pending(|_| false);
+        assert!(watchdog.stuck_for(&tx, later).is_some());
+
+        drop(processing);
+        watchdog.retain_pending(|_| false);
+        assert_eq!(watchdog.stuck_for(&tx, later), None);
+    }
+}
@@ -12,19 +12,24 @@ use libp2p::{
 use tokio::sync::mpsc::{self, Receiver};
 
 use super::{
-    client_event_handling, conn_manager::p2p_protoc::P2pConnManager, join_ring_request, PeerKey,
+    client_event_handling,
+    conn_manager::{allowlist::sync_allowlist_contract, p2p_protoc::P2pConnManager},
+    join_ring_request,
+    op_state::{watch_stuck_ops, CompletedOps},
+    PeerKey,
 };
 use crate::{
     client_events::combinator::ClientEventsCombinator,
-    config::{self, GlobalExecutor},
+    config::{self, Config, GlobalExecutor},
     contract::{self, ContractHandler},
     message::{Message, NodeEvent},
+    operations::subscribe,
     ring::Ring,
     util::IterExt,
     NodeConfig,
 };
 
-use super::OpManager;
+use super::{OpManager, TraceSampler};
 
 pub(super) struct NodeP2P<CErr> {
     pub(crate) peer_key: PeerKey,
@@ -40,9 +45,11 @@ where
     CErr: std::error::Error + Send + Sync + 'static,
 {
     pub(super) async fn run_node(mut self) -> Result<(), anyhow::Error> {
-        // start listening in case this is a listening node (gateway) and join the ring
-        if self.is_gateway {
+        // start listening in case this is a listening node (gateway, or a node mapping its
+        // port in the local router) and join the ring
+        if self.is_gateway || self.conn_manager.maps_port() {
             self.conn_manager.listen_on()?;
+            self.conn_manager.map_port();
         }
 
         if !self.is_gateway {
@@ -84,12 +91,40 @@ where
         let ring = Ring::new(&config, &gateways)?;
         let (notification_tx, notification_channel) = mpsc::channel(100);
         let (ops_ch_channel, ch_channel) = contract::contract_handler_channel();
-        let op_storage = Arc::new(OpManager::new(ring, notification_tx, ops_ch_channel));
+        // transactions are only addressed to this node while it keeps its identity, so the
+        // completed ones are only worth persisting if the key survives restarts
+        let completed_ops = if config.stable_key {
+            CompletedOps::persistent(
+                Config::get_conf()
+                    .config_paths
+                    .db_dir
+                    .join(format!("completed_ops_{peer_key}")),
+            )?
+        } else {
+            CompletedOps::in_memory()
+        };
+        let op_storage = Arc::new(OpManager::new(
+            ring,
+            notification_tx,
+            ops_ch_channel,
+            completed_ops,
+            TraceSampler::new(config.trace_sample_rate),
+        ));
         let contract_handler = CH::from(ch_channel);
 
         GlobalExecutor::spawn(contract::contract_handling(contract_handler));
+        GlobalExecutor::spawn(subscribe::renew_subscriptions(op_storage.clone()));
+        GlobalExecutor::spawn(subscribe::sync_subscribed_contracts(op_storage.clone()));
+        GlobalExecutor::spawn(watch_stuck_ops(op_storage.clone()));
+        if let Some(contract) = config.allowlist_contract.clone() {
+            GlobalExecutor::spawn(sync_allowlist_contract(op_storage.clone(), contract));
+        }
         let clients = ClientEventsCombinator::new(config.clients);
-        GlobalExecutor::spawn(client_event_handling(op_storage.clone(), clients));
+        GlobalExecutor::spawn(client_event_handling(
+            op_storage.clone(),
+            clients,
+            config.admin_token.clone(),
+        ));
 
         Ok(NodeP2P {
             peer_key,
@@ -145,12 +180,14 @@ mod test {
         client_events::test::MemoryEventsGen,
         config::GlobalExecutor,
         contract::{TestContractHandler, TestContractStoreError},
-        node::{test::get_free_port, InitPeerNode},
+        message::NodeEvent,
+        node::{test::get_free_port, InitPeerNode, PeerAllowlist},
         ring::Location,
     };
 
-    use futures::StreamExt;
-    use libp2p::swarm::SwarmEvent;
+    use futures::{FutureExt, StreamExt};
+    use libp2p::{swarm::SwarmEvent, Multiaddr};
+    use locutus_stdlib::client_api::NodeNotification;
     use tokio::sync::watch::channel;
 
     /// Ping test event loop
@@ -237,4 +274,187 @@ mod test {
 
         dialer.await.map_err(|_| ())?
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn reject_peers_outside_allowlist() -> Result<(), anyhow::Error> {
+        let gw_port = get_free_port().unwrap();
+        let gw_key = Keypair::generate_ed25519();
+        let gw_id: PeerId = gw_key.public().into();
+        let gw_config = InitPeerNode::new(gw_id, Location::random())
+            .listening_ip(Ipv4Addr::LOCALHOST)
+            .listening_port(gw_port);
+
+        let stranger_key = Keypair::generate_ed25519();
+        let stranger_id: PeerId = stranger_key.public().into();
+
+        let (_, receiver1) = channel((0, PeerKey::from(gw_id)));
+        let (_, receiver2) = channel((0, PeerKey::from(stranger_id)));
+
+        // a private gateway which only accepts connections from some other peer
+        let user_events = MemoryEventsGen::new(receiver1, PeerKey::from(gw_id));
+        let mut config = NodeConfig::new([Box::new(user_events)]);
+        config
+            .with_ip(Ipv4Addr::LOCALHOST)
+            .with_port(gw_port)
+            .with_key(gw_key)
+            .with_location(Location::random())
+            .with_peer_allowlist(PeerAllowlist::new([PeerKey::random()]));
+        let gateway = NodeP2P::<TestContractStoreError>::build::<
+            TestContractHandler,
+            TestContractStoreError,
+            1,
+        >(config)?;
+        let mut gw_task = GlobalExecutor::spawn(gateway.run_node());
+
+        let user_events = MemoryEventsGen::new(receiver2, PeerKey::from(stranger_id));
+        let mut config = NodeConfig::new([Box::new(user_events)]);
+        config.add_gateway(gw_config.clone()).with_key(stranger_key);
+        let mut stranger = NodeP2P::<TestContractStoreError>::build::<
+            TestContractHandler,
+            TestContractStoreError,
+            1,
+        >(config)?;
+        // wait a bit to make sure the gateway is up and listening
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stranger.conn_manager.swarm.dial(gw_config.addr.unwrap())?;
+
+        // the gateway drops the connection once the handshake authenticates the stranger
+        let disconnected = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let SwarmEvent::ConnectionClosed { peer_id, .. } =
+                    stranger.conn_manager.swarm.select_next_some().await
+                {
+                    if peer_id == gw_id {
+                        break;
+                    }
+                }
+            }
+        })
+        .await;
+        assert!(disconnected.is_ok(), "connection was not dropped");
+
+        // and keeps running
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!((&mut gw_task).now_or_never().is_none());
+        gw_task.abort();
+        Ok(())
+    }
+
+    /// Waits for the first node event matching the predicate.
+    async fn node_event(
+        events: &mut tokio::sync::broadcast::Receiver<NodeNotification>,
+        mut matches: impl FnMut(&NodeNotification) -> bool,
+    ) -> NodeNotification {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if matches(&event) => break event,
+                    Ok(_) => {}
+                    Err(err) => panic!("node events closed: {err}"),
+                }
+            }
+        })
+        .await
+        .expect("node event not received")
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn add_and_remove_listeners() -> Result<(), anyhow::Error> {
+        let (gw_port, new_port) = (get_free_port().unwrap(), get_free_port().unwrap());
+        let gw_key = Keypair::generate_ed25519();
+        let gw_id: PeerId = gw_key.public().into();
+        let gw_config = InitPeerNode::new(gw_id, Location::random())
+            .listening_ip(Ipv4Addr::LOCALHOST)
+            .listening_port(gw_port);
+        let gw_addr = gw_config.addr.clone().unwrap();
+        let new_addr: Multiaddr = format!("/ip4/127.0.0.1/tcp/{new_port}").parse()?;
+
+        let (_, receiver) = channel((0, PeerKey::from(gw_id)));
+        let user_events = MemoryEventsGen::new(receiver, PeerKey::from(gw_id));
+        let mut config = NodeConfig::new([Box::new(user_events)]);
+        config
+            .with_ip(Ipv4Addr::LOCALHOST)
+            .with_port(gw_port)
+            .with_key(gw_key)
+            .with_location(Location::random());
+        let gateway = NodeP2P::<TestContractStoreError>::build::<
+            TestContractHandler,
+            TestContractStoreError,
+            1,
+        >(config)?;
+        let op_storage = gateway.op_storage.clone();
+        let mut events = op_storage.ring.subscribe_node_events();
+        let gw_task = GlobalExecutor::spawn(gateway.run_node());
+        node_event(&mut events, |ev| {
+            matches!(ev, NodeNotification::ListenerStarted { .. })
+        })
+        .await;
+
+        op_storage
+            .notify_internal_op(NodeEvent::AddListener(new_addr.clone()))
+            .await?;
+        let started = node_event(&mut events, |ev| {
+            matches!(ev, NodeNotification::ListenerStarted { .. })
+        })
+        .await;
+        assert_eq!(
+            started,
+            NodeNotification::ListenerStarted {
+                address: new_addr.to_string()
+            }
+        );
+
+        op_storage
+            .notify_internal_op(NodeEvent::RemoveListener(gw_addr.clone()))
+            .await?;
+        let closed = node_event(&mut events, |ev| {
+            matches!(ev, NodeNotification::ListenerClosed { .. })
+        })
+        .await;
+        assert!(
+            matches!(&closed, NodeNotification::ListenerClosed { addresses } if addresses.contains(&gw_addr.to_string()))
+        );
+
+        // removing an address the node is not listening at anymore fails
+        op_storage
+            .notify_internal_op(NodeEvent::RemoveListener(gw_addr.clone()))
+            .await?;
+        let failed = node_event(&mut events, |ev| {
+            matches!(ev, NodeNotification::ListenerFailed { .. })
+        })
+        .await;
+        assert!(
+            matches!(failed, NodeNotification::ListenerFailed { address, .. } if address == gw_addr.to_string())
+        );
+
+        // while the new listener keeps accepting connections
+        let stranger_key = Keypair::generate_ed25519();
+        let stranger_id: PeerId = stranger_key.public().into();
+        let (_, receiver) = channel((0, PeerKey::from(stranger_id)));
+        let user_events = MemoryEventsGen::new(receiver, PeerKey::from(stranger_id));
+        let mut config = NodeConfig::new([Box::new(user_events)]);
+        config.add_gateway(gw_config).with_key(stranger_key);
+        let mut stranger = NodeP2P::<TestContractStoreError>::build::<
+            TestContractHandler,
+            TestContractStoreError,
+            1,
+        >(config)?;
+        stranger.conn_manager.swarm.dial(new_addr)?;
+        let connected = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let SwarmEvent::ConnectionEstablished { peer_id, .. } =
+                    stranger.conn_manager.swarm.select_next_some().await
+                {
+                    if peer_id == gw_id {
+                        break;
+                    }
+                }
+            }
+        })
+        .await;
+        assert!(connected.is_ok(), "connection not established");
+
+        gw_task.abort();
+        Ok(())
+    }
 }
@@ -2,6 +2,7 @@ use std::{
     collections::{HashMap, HashSet},
     fmt::Write,
     net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -18,13 +19,15 @@ use crate::{
     client_events::test::MemoryEventsGen,
     config::GlobalExecutor,
     contract::{MemoryContractHandler, SimStoreError},
-    node::{event_listener::TestEventListener, InitPeerNode, NodeInMemory},
+    node::{event_listener::TestEventListener, op_state::OpManager, InitPeerNode, NodeInMemory},
     ring::{Distance, Location, PeerKeyLocation},
     NodeConfig, WrappedState,
 };
 
 use super::PeerKey;
 
+mod soak;
+
 pub fn get_free_port() -> Result<u16, ()> {
     let mut port;
     for _ in 0..100 {
@@ -52,6 +55,9 @@ pub(crate) struct SimNetwork {
     receiver_ch: Receiver<(EventId, PeerKey)>,
     gateways: Vec<(NodeInMemory<SimStoreError>, GatewayConfig)>,
     nodes: Vec<(NodeInMemory<SimStoreError>, String)>,
+    /// operations storage of the peers already running, by label
+    running: Vec<(String, Arc<OpManager<SimStoreError>>)>,
+    random_events: bool,
     ring_max_htl: usize,
     rnd_if_htl_above: usize,
     max_connections: usize,
@@ -95,6 +101,8 @@ impl SimNetwork {
             receiver_ch: _rcv_copy,
             gateways: Vec::with_capacity(gateways),
             nodes: Vec::with_capacity(nodes),
+            running: Vec::with_capacity(gateways + nodes),
+            random_events: false,
             ring_max_htl,
             rnd_if_htl_above,
             max_connections,
@@ -143,31 +151,28 @@ impl SimNetwork {
             ));
         }
 
-        // FIXME:
-        // for (mut this_node, this_config) in configs {
-        //     for GatewayConfig {
-        //         port, id, location, ..
-        //     } in configs.iter().filter_map(|(_, config)| {
-        //         if this_config.label != config.label {
-        //             Some(config)
-        //         } else {
-        //             None
-        //         }
-        //     }) {
-        //         this_node.add_gateway(
-        //             InitPeerNode::new(*id, *location)
-        //                 .listening_ip(Ipv6Addr::LOCALHOST)
-        //                 .listening_port(*port),
-        //         );
-        //     }
-
-        //     let gateway = NodeInMemory::<SimStoreError>::build::<MemoryContractHandler>(
-        //         this_node,
-        //         Some(Box::new(self.event_listener.clone())),
-        //     )
-        //     .unwrap();
-        //     self.gateways.push((gateway, this_config));
-        // }
+        let gateway_configs: Vec<_> = configs.iter().map(|(_, config)| config.clone()).collect();
+        for (mut this_node, this_config) in configs {
+            for GatewayConfig {
+                port, id, location, ..
+            } in gateway_configs
+                .iter()
+                .filter(|config| this_config.label != config.label)
+            {
+                this_node.add_gateway(
+                    InitPeerNode::new(*id, *location)
+                        .listening_ip(Ipv6Addr::LOCALHOST)
+                        .listening_port(*port),
+                );
+            }
+
+            let gateway = NodeInMemory::<SimStoreError>::build::<MemoryContractHandler>(
+                this_node,
+                Some(Box::new(self.event_listener.clone())),
+            )
+            .unwrap();
+            self.gateways.push((gateway, this_config));
+        }
     }
 
     #[instrument(skip(self))]
@@ -216,6 +221,13 @@ impl SimNetwork {
         }
     }
 
+    /// Peers generate a random request every time an event is triggered for them, instead of
+    /// the events in their specification.
+    pub fn with_random_events(&mut self) -> &mut Self {
+        self.random_events = true;
+        self
+    }
+
     pub async fn build(&mut self) {
         self.build_with_specs(HashMap::new()).await
     }
@@ -246,6 +258,10 @@ impl SimNetwork {
             user_events.request_contracts(specs.non_owned_contracts);
             user_events.generate_events(specs.events_to_generate);
         }
+        if self.random_events {
+            user_events.generate_random_events();
+        }
+        self.running.push((label.clone(), peer.op_storage.clone()));
         self.labels.insert(label, peer.peer_key);
         GlobalExecutor::spawn(async move {
             if let Some(specs) = node_specs {
@@ -273,6 +289,13 @@ impl SimNetwork {
         locations_by_node
     }
 
+    /// Operations storage of the peers already running, by label.
+    pub fn running_peers(&self) -> impl Iterator<Item = (&str, &Arc<OpManager<SimStoreError>>)> {
+        self.running
+            .iter()
+            .map(|(label, op_storage)| (label.as_str(), op_storage))
+    }
+
     pub fn connected(&self, peer: &str) -> bool {
         if let Some(key) = self.labels.get(peer) {
             self.event_listener.is_connected(key)
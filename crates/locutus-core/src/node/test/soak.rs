@@ -0,0 +1,268 @@
+//! Long running soak test of a simulated network.
+//!
+//! A mid-size network runs for hours under a steady workload of random puts, gets and
+//! subscriptions, while the resources used by the peers (memory, live tasks, operations storage
+//! and contracts cache) are periodically sampled. Once the network warms up every resource is
+//! expected to plateau, so any resource which keeps growing through the run is reported as a
+//! leak; this catches, for example, operations which never expire before they reach a release.
+//!
+//! The test is ignored by default, run it with:
+//! `LOCUTUS_SOAK_SECS=14400 cargo test -p locutus-core soak_network -- --ignored`
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use locutus_runtime::{
+    ContractCode, ContractContainer, Parameters, WasmAPIVersion, WrappedContract,
+};
+use rand::{prelude::StdRng, seq::SliceRandom, SeedableRng};
+
+use super::{check_connectivity, NodeSpecification, SimNetwork};
+use crate::{config::GlobalExecutor, WrappedState};
+
+const NUM_GW: usize = 2;
+const NUM_NODES: usize = 30;
+/// Duration of the test unless set through the `LOCUTUS_SOAK_SECS` env variable.
+const DEFAULT_DURATION: Duration = Duration::from_secs(2 * 60 * 60);
+const SAMPLE_EVERY: Duration = Duration::from_secs(30);
+/// Time between the requests issued to the network.
+const REQUEST_EVERY: Duration = Duration::from_millis(100);
+/// Fraction of the samples ignored at the beginning of the run, while the network warms up.
+const WARMUP: f64 = 0.2;
+/// A resource leaks if it grows over the run beyond this fraction of its usage after warming up.
+const MAX_RELATIVE_GROWTH: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Resource {
+    Memory,
+    LiveTasks,
+    PendingOps,
+    CompletedOps,
+    CachedContracts,
+    Subscriptions,
+}
+
+impl Resource {
+    /// Growth below this is considered noise, regardless of the relative growth.
+    fn min_growth(self) -> f64 {
+        match self {
+            Resource::Memory => 64.0 * 1024.0,
+            Resource::LiveTasks => 32.0,
+            Resource::PendingOps | Resource::CompletedOps => 64.0,
+            Resource::CachedContracts | Resource::Subscriptions => 16.0,
+        }
+    }
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::Memory => write!(f, "resident memory (kB)"),
+            Resource::LiveTasks => write!(f, "live tasks"),
+            Resource::PendingOps => write!(f, "pending operations"),
+            Resource::CompletedOps => write!(f, "completed operations"),
+            Resource::CachedContracts => write!(f, "cached contracts"),
+            Resource::Subscriptions => write!(f, "subscriptions"),
+        }
+    }
+}
+
+/// Resident memory of the process in kB, only available in linux.
+fn resident_memory() -> Option<f64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Resources used by the whole network, `live_tasks` counting the tasks spawned by it.
+fn resources_usage(sim: &SimNetwork, live_tasks: &AtomicUsize) -> Vec<(Resource, f64)> {
+    let (mut pending, mut completed, mut cached, mut subscriptions) = (0, 0, 0, 0);
+    for (_, op_storage) in sim.running_peers() {
+        pending += op_storage.pending_ops();
+        completed += op_storage.completed_ops();
+        cached += op_storage.ring.cached_contracts().len();
+        subscriptions += op_storage.ring.num_subscriptions();
+    }
+    let mut usage = vec![
+        (
+            Resource::LiveTasks,
+            live_tasks.load(Ordering::Relaxed) as f64,
+        ),
+        (Resource::PendingOps, pending as f64),
+        (Resource::CompletedOps, completed as f64),
+        (Resource::CachedContracts, cached as f64),
+        (Resource::Subscriptions, subscriptions as f64),
+    ];
+    if let Some(memory) = resident_memory() {
+        usage.push((Resource::Memory, memory));
+    }
+    usage
+}
+
+#[derive(Default)]
+struct LeakDetector {
+    /// seconds since the beginning of the run and usage, per resource
+    samples: BTreeMap<Resource, Vec<(f64, f64)>>,
+}
+
+impl LeakDetector {
+    fn record(&mut self, at: Duration, usage: impl IntoIterator<Item = (Resource, f64)>) {
+        for (resource, value) in usage {
+            self.samples
+                .entry(resource)
+                .or_default()
+                .push((at.as_secs_f64(), value));
+        }
+    }
+
+    /// Resources which kept growing after warming up, and how much they grew.
+    fn leaks(&self) -> Vec<(Resource, f64)> {
+        self.samples
+            .iter()
+            .filter_map(|(resource, samples)| {
+                let (initial, growth) = trend(samples)?;
+                let leaking = growth > resource.min_growth()
+                    && growth > initial.max(0.0) * MAX_RELATIVE_GROWTH;
+                leaking.then_some((*resource, growth))
+            })
+            .collect()
+    }
+}
+
+/// Usage once warmed up and growth from then to the end of the run, from the least squares
+/// fit of the samples.
+fn trend(samples: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let samples = &samples[(samples.len() as f64 * WARMUP) as usize..];
+    if samples.len() < 3 {
+        return None;
+    }
+    let n = samples.len() as f64;
+    let mean_t = samples.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_v = samples.iter().map(|(_, v)| v).sum::<f64>() / n;
+    let (cov, var) = samples.iter().fold((0.0, 0.0), |(cov, var), (t, v)| {
+        (
+            cov + (t - mean_t) * (v - mean_v),
+            var + (t - mean_t).powi(2),
+        )
+    });
+    if var == 0.0 {
+        return None;
+    }
+    let slope = cov / var;
+    let (start, end) = (samples[0].0, samples[samples.len() - 1].0);
+    Some((mean_v - slope * (mean_t - start), slope * (end - start)))
+}
+
+#[test]
+fn detect_growing_resources() {
+    let mut detector = LeakDetector::default();
+    for minute in 0..120 {
+        let at = Duration::from_secs(minute * 60);
+        let minute = minute as f64;
+        detector.record(
+            at,
+            [
+                // grows while warming up and then plateaus
+                (Resource::CachedContracts, (minute * 10.0).min(200.0)),
+                // periodically cleaned up
+                (Resource::PendingOps, 100.0 + (minute % 10.0) * 20.0),
+                // grows slowly but within the noise
+                (Resource::LiveTasks, 100.0 + minute * 0.1),
+                // never released
+                (Resource::CompletedOps, 1_000.0 + minute * 50.0),
+            ],
+        );
+    }
+    let leaks: Vec<_> = detector.leaks().into_iter().map(|(r, _)| r).collect();
+    assert_eq!(leaks, vec![Resource::CompletedOps]);
+}
+
+#[ignore]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn soak_network() -> Result<(), anyhow::Error> {
+    // only the tasks of this network are counted, regardless of other tests running meanwhile
+    let live_tasks = Arc::new(AtomicUsize::new(0));
+    GlobalExecutor::track_tasks(live_tasks.clone(), run_soak(live_tasks)).await
+}
+
+async fn run_soak(live_tasks: Arc<AtomicUsize>) -> Result<(), anyhow::Error> {
+    let duration = std::env::var("LOCUTUS_SOAK_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_DURATION);
+
+    let labels: Vec<_> = (0..NUM_GW)
+        .map(|gw| format!("gateway-{gw}"))
+        .chain((0..NUM_NODES).map(|node| format!("node-{node}")))
+        .collect();
+    // every peer owns a contract and requests the ones owned by the rest
+    let contracts: Vec<_> = labels
+        .iter()
+        .map(|label| {
+            let code = Arc::new(ContractCode::from(label.as_bytes().to_vec()));
+            let contract = WrappedContract::new(code, Parameters::from(vec![]));
+            (
+                ContractContainer::Wasm(WasmAPIVersion::V1(contract)),
+                WrappedState::new(vec![0; 256]),
+            )
+        })
+        .collect();
+    let specs = labels
+        .iter()
+        .zip(&contracts)
+        .map(|(label, (owned, state))| {
+            let spec = NodeSpecification {
+                owned_contracts: vec![(owned.clone(), state.clone())],
+                non_owned_contracts: contracts
+                    .iter()
+                    .map(|(contract, _)| contract.key())
+                    .filter(|key| key != &owned.key())
+                    .collect(),
+                events_to_generate: HashMap::new(),
+                contract_subscribers: HashMap::new(),
+            };
+            (label.clone(), spec)
+        })
+        .collect();
+
+    let mut sim = SimNetwork::new(NUM_GW, NUM_NODES, 5, 3, 8, 2);
+    sim.with_random_events();
+    sim.build_with_specs(specs).await;
+    check_connectivity(&sim, NUM_NODES, Duration::from_secs(30)).await?;
+
+    let mut detector = LeakDetector::default();
+    let mut rng = StdRng::from_entropy();
+    let mut requests = tokio::time::interval(REQUEST_EVERY);
+    let mut samples = tokio::time::interval(SAMPLE_EVERY);
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        tokio::select! {
+            _ = requests.tick() => {
+                let label = labels.choose(&mut rng).expect("at least one peer");
+                sim.trigger_event(label, 0, None).await?;
+            }
+            _ = samples.tick() => {
+                let usage = resources_usage(&sim, &live_tasks);
+                tracing::info!("Resources usage after {:?}: {usage:?}", start.elapsed());
+                detector.record(start.elapsed(), usage);
+            }
+        }
+    }
+
+    let leaks = detector.leaks();
+    for (resource, growth) in &leaks {
+        tracing::error!("The {resource} grew unbounded by {growth:.0} during the run");
+    }
+    if !leaks.is_empty() {
+        anyhow::bail!("found {} leaking resources", leaks.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,281 @@
+//! Sampling of the per-transaction diagnostics: the tracing of the messages handled, the event
+//! log and the dumps of the messages going through the wire.
+//!
+//! Tracing every transaction is too expensive for nodes handling a lot of traffic (like
+//! gateways), so only a fraction of the transactions can be traced. Whether a transaction is
+//! sampled depends only on its id, so all the subsystems (and every node using the same rate)
+//! agree on which transactions are traced, and their traces can be followed across the network.
+//! Transactions which fail at this node are traced from then on regardless of the rate, and a
+//! summary of the most recent messages which were not sampled is kept around so the ones of a
+//! failed transaction can still be traced.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+
+use super::PeerKey;
+use crate::message::{Message, Transaction, TransactionType};
+
+/// Max number of failed transactions which are forced to be sampled at a given time.
+const MAX_FAILED: usize = 1_000;
+/// For how long a failed transaction keeps being sampled.
+const FAILED_TTL: Duration = Duration::from_secs(60 * 5);
+/// Max number of transactions with recent unsampled messages kept in case they fail.
+const MAX_BUFFERED_TXS: usize = 256;
+/// Max number of recent unsampled messages kept per transaction.
+const MAX_BUFFERED_PER_TX: usize = 16;
+
+pub(crate) struct TraceSampler {
+    /// transactions which sample point is below the threshold are sampled
+    threshold: u64,
+    /// transactions which failed at this node, and when they failed
+    failed: DashMap<Transaction, Instant>,
+    /// summaries of the most recent messages handled which were not sampled, by transaction
+    unsampled: DashMap<Transaction, BufferedMessages>,
+}
+
+struct BufferedMessages {
+    /// when the last message was buffered
+    last: Instant,
+    /// oldest first
+    messages: VecDeque<MessageSummary>,
+}
+
+/// Summary of a message which was not sampled, cheap to keep around until its transaction
+/// either fails or gets evicted.
+#[derive(Debug, Clone)]
+pub(crate) struct MessageSummary {
+    pub tx: Transaction,
+    pub tx_type: TransactionType,
+    /// the peer the message was addressed to, if any
+    pub target: Option<PeerKey>,
+    pub msg: String,
+}
+
+impl MessageSummary {
+    fn new(msg: &Message) -> Self {
+        Self {
+            tx: *msg.id(),
+            tx_type: msg.id().tx_type(),
+            target: msg.target().map(|target| target.peer),
+            msg: msg.to_string(),
+        }
+    }
+}
+
+impl Display for MessageSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} tx {}", self.tx_type, self.tx)?;
+        if let Some(target) = &self.target {
+            write!(f, " to {target}")?;
+        }
+        write!(f, ": {}", self.msg)
+    }
+}
+
+impl TraceSampler {
+    /// Samples approximately the given ratio of transactions, between 0 (none, except the ones
+    /// failing) and 1 (all of them).
+    pub fn new(rate: f64) -> Self {
+        let rate = if rate.is_nan() {
+            1.0
+        } else {
+            rate.clamp(0.0, 1.0)
+        };
+        let threshold = if rate >= 1.0 {
+            u64::MAX
+        } else {
+            (rate * u64::MAX as f64) as u64
+        };
+        Self {
+            threshold,
+            failed: DashMap::new(),
+            unsampled: DashMap::new(),
+        }
+    }
+
+    /// Whether the diagnostics for this transaction should be recorded.
+    pub fn is_sampled(&self, tx: &Transaction) -> bool {
+        self.threshold == u64::MAX
+            || sample_point(tx) < self.threshold
+            || self.failed.contains_key(tx)
+    }
+
+    /// Keeps a summary of the message if it is not sampled, in case its transaction fails
+    /// later on.
+    pub fn buffer(&self, msg: &Message) {
+        self.buffer_at(msg, Instant::now())
+    }
+
+    fn buffer_at(&self, msg: &Message, now: Instant) {
+        let tx = *msg.id();
+        if self.is_sampled(&tx) {
+            return;
+        }
+        if !self.unsampled.contains_key(&tx) && self.unsampled.len() >= MAX_BUFFERED_TXS {
+            evict_oldest(&self.unsampled, |buffered| buffered.last);
+        }
+        let summary = MessageSummary::new(msg);
+        let mut buffered = self
+            .unsampled
+            .entry(tx)
+            .or_insert_with(|| BufferedMessages {
+                last: now,
+                messages: VecDeque::new(),
+            });
+        if buffered.messages.len() >= MAX_BUFFERED_PER_TX {
+            buffered.messages.pop_front();
+        }
+        buffered.last = now;
+        buffered.messages.push_back(summary);
+    }
+
+    /// Forces sampling the rest of a transaction after it failed at this node. Returns the
+    /// summaries of the buffered messages of the transaction handled before the failure,
+    /// oldest first.
+    pub fn sample_on_error(&self, tx: Transaction) -> Vec<MessageSummary> {
+        self.sample_on_error_at(tx, Instant::now())
+    }
+
+    fn sample_on_error_at(&self, tx: Transaction, now: Instant) -> Vec<MessageSummary> {
+        if self.threshold == u64::MAX {
+            return vec![];
+        }
+        let handled = self
+            .unsampled
+            .remove(&tx)
+            .map(|(_, buffered)| buffered.messages.into())
+            .unwrap_or_default();
+        if !self.failed.contains_key(&tx) && self.failed.len() >= MAX_FAILED {
+            self.failed
+                .retain(|_, failed_at| now.duration_since(*failed_at) < FAILED_TTL);
+            if self.failed.len() >= MAX_FAILED {
+                evict_oldest(&self.failed, |failed_at| *failed_at);
+            }
+        }
+        self.failed.insert(tx, now);
+        handled
+    }
+}
+
+/// Removes the least recently updated entry.
+fn evict_oldest<V>(map: &DashMap<Transaction, V>, updated_at: impl Fn(&V) -> Instant) {
+    let oldest = map
+        .iter()
+        .min_by_key(|entry| updated_at(entry.value()))
+        .map(|entry| *entry.key());
+    if let Some(tx) = oldest {
+        map.remove(&tx);
+    }
+}
+
+/// Uniformly distributed value derived from the transaction id, the same for every node.
+fn sample_point(tx: &Transaction) -> u64 {
+    // ids of transactions started close in time by the same peer share most of their bits,
+    // so mix them well (splitmix64 finalizer) before comparing against the threshold
+    let id = tx.as_u128();
+    let mut z = ((id >> 64) as u64) ^ (id as u64);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        message::TxType,
+        node::{Capabilities, PeerKey},
+        operations::{join_ring::JoinRingMsg, put::PutMsg},
+        ring::PeerKeyLocation,
+    };
+
+    fn new_tx() -> Transaction {
+        Transaction::new(<PutMsg as TxType>::tx_type_id(), &PeerKey::random())
+    }
+
+    #[test]
+    fn sample_by_rate() {
+        let all = TraceSampler::new(1.0);
+        let none = TraceSampler::new(0.0);
+        let some = TraceSampler::new(0.25);
+        let txs: Vec<_> = (0..1_000).map(|_| new_tx()).collect();
+        assert!(txs.iter().all(|tx| all.is_sampled(tx)));
+        assert!(!txs.iter().any(|tx| none.is_sampled(tx)));
+        let sampled = txs.iter().filter(|tx| some.is_sampled(tx)).count();
+        assert!((150..350).contains(&sampled), "sampled: {sampled}");
+        // the decision is the same for every sampler with the same rate
+        let other = TraceSampler::new(0.25);
+        assert!(txs
+            .iter()
+            .all(|tx| some.is_sampled(tx) == other.is_sampled(tx)));
+    }
+
+    #[test]
+    fn always_sample_on_error() {
+        let sampler = TraceSampler::new(0.0);
+        let tx = new_tx();
+        assert!(!sampler.is_sampled(&tx));
+        sampler.sample_on_error(tx);
+        assert!(sampler.is_sampled(&tx));
+    }
+
+    #[test]
+    fn evict_oldest_failed() {
+        let sampler = TraceSampler::new(0.0);
+        let start = Instant::now();
+        let txs: Vec<_> = (0..MAX_FAILED).map(|_| new_tx()).collect();
+        for (i, tx) in txs.iter().enumerate() {
+            sampler.sample_on_error_at(*tx, start + Duration::from_millis(i as u64));
+        }
+        // none expired yet, so the oldest makes room for the new failure
+        let tx = new_tx();
+        sampler.sample_on_error_at(tx, start + Duration::from_secs(1));
+        assert!(sampler.is_sampled(&tx));
+        assert!(!sampler.is_sampled(&txs[0]));
+        assert!(txs[1..].iter().all(|tx| sampler.is_sampled(tx)));
+    }
+
+    #[test]
+    fn flush_buffered_on_error() {
+        let sampler = TraceSampler::new(0.0);
+        let msg = |id| {
+            Message::from(JoinRingMsg::Connected {
+                id,
+                sender: PeerKeyLocation::random(),
+                target: PeerKeyLocation::random(),
+                domain: None,
+                capabilities: Capabilities::empty(),
+            })
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let failed = new_tx();
+        let other = new_tx();
+        sampler.buffer_at(&msg(failed), at(0));
+        sampler.buffer_at(&msg(other), at(1));
+        sampler.buffer_at(&msg(failed), at(2));
+        let handled = sampler.sample_on_error(failed);
+        assert_eq!(handled.len(), 2);
+        assert!(handled.iter().all(|summary| summary.tx == failed));
+        // already flushed
+        assert!(sampler.sample_on_error(failed).is_empty());
+
+        // only the most recent messages of each transaction are kept
+        let busy = new_tx();
+        for i in 0..MAX_BUFFERED_PER_TX as u64 + 1 {
+            sampler.buffer_at(&msg(busy), at(3 + i));
+        }
+        assert_eq!(sampler.sample_on_error(busy).len(), MAX_BUFFERED_PER_TX);
+
+        // the transactions buffered least recently are evicted first
+        for i in 0..MAX_BUFFERED_TXS as u64 {
+            sampler.buffer_at(&msg(new_tx()), at(100 + i));
+        }
+        assert!(sampler.sample_on_error(other).is_empty());
+    }
+}
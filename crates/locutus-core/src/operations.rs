@@ -3,6 +3,8 @@ use tokio::sync::mpsc::error::SendError;
 use self::op_trait::Operation;
 use crate::operations::get::GetOp;
 use crate::operations::put::PutOp;
+use crate::operations::sample::SampleOp;
+use crate::operations::seed::SeedOp;
 use crate::operations::subscribe::SubscribeOp;
 use crate::{
     contract::ContractError,
@@ -10,12 +12,15 @@ use crate::{
     node::{ConnectionBridge, ConnectionError, OpManager, PeerKey},
     operations::join_ring::JoinRingOp,
     ring::RingError,
+    util::ErrorClass,
 };
 
 pub(crate) mod get;
 pub(crate) mod join_ring;
 pub(crate) mod op_trait;
 pub(crate) mod put;
+pub(crate) mod sample;
+pub(crate) mod seed;
 pub(crate) mod subscribe;
 
 pub(crate) struct OperationResult {
@@ -42,16 +47,37 @@ where
 {
     let sender;
     let tx = *msg.id();
+    // messages of the same transaction are handled one at a time, so whether it completed and
+    // its state are checked and updated atomically
+    let _tx_guard = op_storage.lock_tx(&tx).await;
+    if op_storage.is_completed(&tx) {
+        tracing::debug!("ignoring message for already completed transaction {tx}");
+        return Ok(());
+    }
+    let applies_changes = msg.applies_changes();
+    if applies_changes && op_storage.changes_applied(&tx) {
+        tracing::debug!("ignoring replayed changes of transaction {tx}");
+        return Ok(());
+    }
+    // only operations which kept state at this node (like the ones started here) can finish at
+    // this node, peers which just forward a message may still see later messages of it
+    let resumed = op_storage.contains(&tx);
+    let _processing = op_storage.processing(tx);
     let result: Result<_, Op::Error> = {
         let OpInitialization { sender: s, op } = Op::load_or_init(op_storage, &msg)?;
         sender = s;
         op.process_message(conn_manager, op_storage, msg).await
     };
+    if applies_changes && result.is_ok() {
+        op_storage.mark_changes_applied(tx);
+    }
     handle_op_result(
         op_storage,
         conn_manager,
+        tx,
         result.map_err(|err| (err.into(), tx)),
         sender,
+        resumed,
     )
     .await
 }
@@ -59,8 +85,10 @@ where
 async fn handle_op_result<CB, CErr>(
     op_storage: &OpManager<CErr>,
     conn_manager: &mut CB,
+    tx: Transaction,
     result: Result<OperationResult, (OpError<CErr>, Transaction)>,
     sender: Option<PeerKey>,
+    resumed: bool,
 ) -> Result<(), OpError<CErr>>
 where
     CB: ConnectionBridge,
@@ -102,6 +130,9 @@ where
             state: None,
         }) => {
             // finished the operation at this node, informing back
+            if resumed {
+                op_storage.completed(tx);
+            }
             if let Some(target) = msg.target().cloned() {
                 conn_manager.send(&target.peer, msg).await?;
             }
@@ -111,6 +142,9 @@ where
             state: None,
         }) => {
             // operation finished_completely
+            if resumed {
+                op_storage.completed(tx);
+            }
         }
     }
     Ok(())
@@ -121,6 +155,8 @@ pub(crate) enum OpEnum {
     Put(put::PutOp),
     Get(get::GetOp),
     Subscribe(subscribe::SubscribeOp),
+    Sample(sample::SampleOp),
+    Seed(seed::SeedOp),
 }
 
 impl OpEnum {
@@ -134,6 +170,8 @@ impl OpEnum {
             Put(op) => *<PutOp as Operation<CErr, CB>>::id(op),
             Get(op) => *<GetOp as Operation<CErr, CB>>::id(op),
             Subscribe(op) => *<SubscribeOp as Operation<CErr, CB>>::id(op),
+            Sample(op) => *<SampleOp as Operation<CErr, CB>>::id(op),
+            Seed(op) => *<SeedOp as Operation<CErr, CB>>::id(op),
         }
     }
 }
@@ -159,6 +197,8 @@ pub(crate) enum OpError<S: std::error::Error> {
     OpNotPresent(Transaction),
     #[error("max number of retries for tx {0} of op type {1} reached")]
     MaxRetriesExceeded(Transaction, String),
+    #[error("rate limit exceeded for tx {0}")]
+    RateLimited(Transaction),
 
     // user for control flow
     /// This is used as an early interrumpt of an op update when an op
@@ -167,6 +207,45 @@ pub(crate) enum OpError<S: std::error::Error> {
     StatePushed,
 }
 
+impl<S> OpError<S>
+where
+    S: std::error::Error,
+{
+    /// Whether retrying the operation, possibly with other peers, may succeed.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            OpError::ConnError(err) => connection_error_class(err),
+            OpError::RingError(RingError::ConnError(err)) => connection_error_class(err),
+            OpError::RingError(RingError::EmptyRing) => ErrorClass::Transient,
+            OpError::RingError(RingError::NoCachingPeers(_)) => ErrorClass::Unavailable,
+            OpError::ContractError(ContractError::ContractNotFound(_)) => ErrorClass::Unavailable,
+            OpError::ContractError(ContractError::NoEvHandlerResponse) => ErrorClass::Transient,
+            OpError::ContractError(_) => ErrorClass::Permanent,
+            OpError::RateLimited(_) => ErrorClass::Transient,
+            OpError::UnexpectedOpState
+            | OpError::InvalidStateTransition(_)
+            | OpError::NotificationError(_)
+            | OpError::IncorrectTxType(..)
+            | OpError::OpNotPresent(_)
+            | OpError::MaxRetriesExceeded(..)
+            | OpError::StatePushed => ErrorClass::Permanent,
+        }
+    }
+}
+
+fn connection_error_class(err: &ConnectionError) -> ErrorClass {
+    match err {
+        ConnectionError::LocationUnknown
+        | ConnectionError::SendNotCompleted
+        | ConnectionError::IOError(_)
+        | ConnectionError::NegotiationError(_) => ErrorClass::Transient,
+        ConnectionError::Serialization(_)
+        | ConnectionError::FrameTooLarge { .. }
+        | ConnectionError::UnsupportedEnvelope(_)
+        | ConnectionError::NotAllowed(_) => ErrorClass::Permanent,
+    }
+}
+
 impl<S> From<SendError<Message>> for OpError<S>
 where
     S: std::error::Error,
@@ -175,3 +254,30 @@ where
         OpError::NotificationError(Box::new(err))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use locutus_runtime::prelude::ContractKey;
+
+    use super::*;
+
+    #[test]
+    fn classify_op_errors() {
+        type Error = OpError<std::io::Error>;
+        let key = ContractKey::from_id("DCBi7HNZC3QUZRiZLFZDiEduv5KHgZfgBk8WwTiheGq1".to_string())
+            .unwrap();
+        let retry = crate::util::RetryPolicy::new(Duration::ZERO, Duration::ZERO, 1);
+
+        let unreachable = Error::from(ConnectionError::SendNotCompleted);
+        assert_eq!(unreachable.class(), ErrorClass::Transient);
+        let not_found = Error::from(ContractError::ContractNotFound(key.clone()));
+        assert_eq!(not_found.class(), ErrorClass::Unavailable);
+        let no_peers = Error::from(RingError::NoCachingPeers(key));
+        assert_eq!(no_peers.class(), ErrorClass::Unavailable);
+        let rejected = Error::from(ConnectionError::UnsupportedEnvelope(0));
+        assert!(!retry.should_retry(0, rejected.class()));
+        assert!(retry.should_retry(0, unreachable.class()));
+    }
+}
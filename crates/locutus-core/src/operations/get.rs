@@ -8,7 +8,7 @@ use crate::message::InnerMessage;
 use crate::operations::op_trait::Operation;
 use crate::operations::OpInitialization;
 use crate::{
-    config::PEER_TIMEOUT,
+    config::{CONFIG, PEER_TIMEOUT},
     contract::{ContractError, ContractHandlerEvent, StoreResponse},
     message::{Message, Transaction, TxType},
     node::{ConnectionBridge, OpManager, PeerKey},
@@ -19,13 +19,12 @@ use super::{OpEnum, OpError, OperationResult};
 
 pub(crate) use self::messages::GetMsg;
 
-/// Maximum number of retries to get values.
-const MAX_RETRIES: usize = 10;
 /// Maximum number of hops performed while trying to perform a get (a hop will be performed
 /// when the current node cannot perform a get for whichever reason, eg. being out of the caching
 /// distance for the contract)
 const MAX_GET_RETRY_HOPS: usize = 1;
 
+#[derive(Debug)]
 pub(crate) struct GetOp {
     id: Transaction,
     state: Option<GetState>,
@@ -116,6 +115,7 @@ where
                     target,
                     htl,
                 } => {
+                    op_storage.ring.record_contract_access(&key);
                     let is_cached_contract = op_storage.ring.is_contract_cached(&key);
                     if !is_cached_contract {
                         tracing::warn!(
@@ -143,29 +143,47 @@ where
                                     },
                                     sender: op_storage.ring.own_location(),
                                     target: sender, // return to requester
+                                    hot_spot: op_storage.ring.is_hot_spot(&key),
                                 }),
                                 self._ttl,
                             );
                         }
 
                         let new_htl = htl - 1;
-                        let new_target =
-                            op_storage.ring.closest_caching(&key, 1, &[sender.peer])[0];
-
-                        continue_seeking(
-                            conn_manager,
-                            &new_target,
-                            (GetMsg::SeekNode {
+                        let policy = CONFIG.retry.operation;
+                        let mut skip_list = vec![sender.peer];
+                        loop {
+                            let Some(new_target) = op_storage
+                                .ring
+                                .closest_caching(&key, 1, &skip_list)
+                                .into_iter()
+                                .next()
+                            else {
+                                return Err(RingError::NoCachingPeers(key).into());
+                            };
+                            let seek = GetMsg::SeekNode {
                                 id,
-                                key,
+                                key: key.clone(),
                                 fetch_contract,
                                 sender,
                                 target: new_target,
                                 htl: new_htl,
-                            })
-                            .into(),
-                        )
-                        .await?;
+                            };
+                            match continue_seeking(conn_manager, &new_target, seek.into()).await {
+                                Ok(()) => break,
+                                // try with the next closest peer if this one was unreachable
+                                Err(err)
+                                    if policy.should_retry(skip_list.len() - 1, err.class()) =>
+                                {
+                                    tracing::warn!(
+                                        "Failed forwarding get request to {}: {err}",
+                                        new_target.peer
+                                    );
+                                    skip_list.push(new_target.peer);
+                                }
+                                Err(err) => return Err(err),
+                            }
+                        }
 
                         return_msg = None;
                         new_state = None;
@@ -204,12 +222,14 @@ where
                             Some(GetState::ReceivedRequest) => {
                                 tracing::debug!("Returning contract {} to {}", key, sender.peer);
                                 new_state = None;
+                                let hot_spot = op_storage.ring.is_hot_spot(&key);
                                 return_msg = Some(GetMsg::ReturnGet {
                                     id,
                                     key,
                                     value: value.unwrap(),
                                     sender: target,
                                     target: sender,
+                                    hot_spot,
                                 });
                             }
                             _ => return Err(OpError::InvalidStateTransition(self.id)),
@@ -228,6 +248,7 @@ where
                         },
                     sender,
                     target,
+                    hot_spot,
                     ..
                 } => {
                     let this_loc = target;
@@ -245,7 +266,10 @@ where
                             fetch_contract,
                             ..
                         }) => {
-                            if retries < MAX_RETRIES {
+                            let policy = CONFIG.retry.operation;
+                            let failure =
+                                OpError::from(ContractError::ContractNotFound(key.clone()));
+                            if policy.should_retry(retries, failure.class()) {
                                 // no response received from this peer, so skip it in the next iteration
                                 skip_list.push(target.peer);
                                 if let Some(target) = op_storage
@@ -270,12 +294,14 @@ where
                                     retries: retries + 1,
                                     fetch_contract,
                                 });
-                            } else {
+                            } else if policy.is_retryable(failure.class()) {
                                 tracing::error!(
                                     "Failed getting a value for contract {}, reached max retries",
                                     key
                                 );
                                 return Err(OpError::MaxRetriesExceeded(id, "get".to_owned()));
+                            } else {
+                                return Err(failure);
                             }
                         }
                         Some(GetState::ReceivedRequest) => {
@@ -290,6 +316,7 @@ where
                                 },
                                 sender,
                                 target,
+                                hot_spot,
                             });
                         }
                         _ => return Err(OpError::InvalidStateTransition(self.id)),
@@ -305,7 +332,13 @@ where
                     id,
                     sender,
                     target,
+                    hot_spot,
                 } => {
+                    if hot_spot {
+                        op_storage.ring.add_hot_spot_hint(key.clone());
+                    }
+                    // cache hot spots along the request path more aggressively
+                    let cache_contract = op_storage.ring.is_hot_spot(&key);
                     let require_contract = matches!(
                         self.state,
                         Some(GetState::AwaitingResponse {
@@ -315,7 +348,7 @@ where
                     );
 
                     // received a response with a contract value
-                    if require_contract {
+                    if require_contract || (cache_contract && contract.is_some()) {
                         if let Some(contract) = &contract {
                             // store contract first
                             op_storage
@@ -324,6 +357,9 @@ where
                                 ))
                                 .await?;
                             let key = contract.key();
+                            if cache_contract {
+                                op_storage.ring.contract_cached(&key);
+                            }
                             tracing::debug!("Contract `{}` successfully put", key);
                         } else {
                             // no contract, consider this like an error ignoring the incoming update value
@@ -349,6 +385,7 @@ where
                                         },
                                         sender,
                                         target,
+                                        hot_spot,
                                     }),
                                     OpEnum::Get(op),
                                 )
@@ -361,6 +398,7 @@ where
                         .notify_contract_handler(ContractHandlerEvent::PushQuery {
                             key: key.clone(),
                             state: value.clone(),
+                            causality: None,
                         })
                         .await?;
 
@@ -390,6 +428,7 @@ where
                                 },
                                 sender,
                                 target,
+                                hot_spot,
                             });
                         }
                         _ => return Err(OpError::InvalidStateTransition(self.id)),
@@ -605,6 +644,8 @@ mod messages {
             value: StoreResponse,
             sender: PeerKeyLocation,
             target: PeerKeyLocation,
+            /// Hint that the contract is a hot spot for the peers returning the value.
+            hot_spot: bool,
         },
     }
 
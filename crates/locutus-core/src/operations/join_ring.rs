@@ -1,23 +1,25 @@
 use futures::Future;
 use std::pin::Pin;
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use super::{OpError, OperationResult};
 use crate::operations::op_trait::Operation;
 use crate::operations::OpInitialization;
 use crate::{
-    config::PEER_TIMEOUT,
+    config::{CONFIG, PEER_TIMEOUT},
     message::{InnerMessage, Message, Transaction},
-    node::{ConnectionBridge, ConnectionError, OpManager, PeerKey},
-    operations::OpEnum,
-    ring::{Location, PeerKeyLocation, Ring},
+    node::{Capabilities, ConnectionBridge, ConnectionError, OpManager, PeerKey},
+    operations::{seed, OpEnum},
+    ring::{FailureDomain, Location, PeerKeyLocation, Ring},
     util::ExponentialBackoff,
 };
 
 pub(crate) use self::messages::{JoinRequest, JoinResponse, JoinRingMsg};
 
-const MAX_JOIN_RETRIES: usize = 3;
-
+#[derive(Debug)]
 pub(crate) struct JoinRingOp {
     id: Transaction,
     state: Option<JRState>,
@@ -108,13 +110,14 @@ where
                     );
 
                     let new_location = Location::random();
-                    let accepted_by = if op_storage.ring.should_accept(&new_location) {
+                    let accepted_by = if op_storage.ring.should_accept(&req_peer, &new_location) {
                         tracing::debug!("Accepting connection from {}", req_peer,);
                         HashSet::from_iter([this_node_loc])
                     } else {
                         tracing::debug!("Rejecting connection from peer {}", req_peer);
                         HashSet::new()
                     };
+                    let domains = announced_domains(&op_storage.ring, &accepted_by);
 
                     let new_peer_loc = PeerKeyLocation {
                         location: Some(new_location),
@@ -136,7 +139,7 @@ where
                             this_node_loc.peer,
                             id
                         );
-                        updated_state.add_new_proxy(accepted_by)?;
+                        updated_state.add_new_proxy(accepted_by, domains)?;
                         // awaiting responses from proxies
                         new_state = Some(updated_state);
                         return_msg = None;
@@ -156,6 +159,7 @@ where
                             sender: this_node_loc,
                             msg: JoinResponse::AcceptedBy {
                                 peers: accepted_by,
+                                domains,
                                 your_location: new_location,
                                 your_peer_id: req_peer,
                             },
@@ -183,10 +187,10 @@ where
                         hops_to_live,
                         own_loc.peer
                     );
-                    let mut accepted_by = if op_storage
-                        .ring
-                        .should_accept(&joiner.location.ok_or(ConnectionError::LocationUnknown)?)
-                    {
+                    let mut accepted_by = if op_storage.ring.should_accept(
+                        &joiner.peer,
+                        &joiner.location.ok_or(ConnectionError::LocationUnknown)?,
+                    ) {
                         tracing::debug!("Accepting proxy connection from {}", joiner.peer);
                         HashSet::from_iter([own_loc])
                     } else {
@@ -196,6 +200,7 @@ where
                         );
                         HashSet::new()
                     };
+                    let mut domains = announced_domains(&op_storage.ring, &accepted_by);
 
                     if let Some(mut updated_state) = forward_conn(
                         id,
@@ -208,7 +213,7 @@ where
                     )
                     .await?
                     {
-                        updated_state.add_new_proxy(accepted_by)?;
+                        updated_state.add_new_proxy(accepted_by, domains)?;
                         // awaiting responses from proxies
                         new_state = Some(updated_state);
                         return_msg = None;
@@ -220,12 +225,14 @@ where
                                     &sender,
                                     &own_loc,
                                     accepted_by.clone(),
+                                    domains.clone(),
                                 );
                                 new_state = state;
                                 return_msg = msg;
                             }
                             Some(JRState::AwaitingProxyResponse {
                                 accepted_by: mut previously_accepted,
+                                domains: mut previous_domains,
                                 new_peer_id,
                                 target,
                                 new_location,
@@ -237,6 +244,7 @@ where
 
                                 if is_accepted {
                                     previously_accepted.extend(accepted_by.drain());
+                                    previous_domains.extend(domains.drain());
                                 }
 
                                 if match_target {
@@ -253,6 +261,7 @@ where
                                         sender,
                                         msg: JoinResponse::AcceptedBy {
                                             peers: accepted_by,
+                                            domains,
                                             your_location: new_location,
                                             your_peer_id: new_peer_id,
                                         },
@@ -274,7 +283,10 @@ where
                                         id,
                                         target,
                                         sender,
-                                        msg: JoinResponse::Proxy { accepted_by },
+                                        msg: JoinResponse::Proxy {
+                                            accepted_by,
+                                            domains,
+                                        },
                                     });
                                 }
                             }
@@ -293,6 +305,7 @@ where
                     msg:
                         JoinResponse::AcceptedBy {
                             peers: accepted_by,
+                            domains,
                             your_location,
                             your_peer_id,
                         },
@@ -308,6 +321,7 @@ where
 
                     match self.state {
                         Some(JRState::Connecting(ConnectionInfo { gateway, .. })) => {
+                            op_storage.ring.joined_through(gateway.peer);
                             if !accepted_by.clone().is_empty() {
                                 tracing::debug!(
                                     "OC received and acknowledged at requesting peer {} from gateway {}",
@@ -317,7 +331,11 @@ where
                                 new_state = Some(JRState::OCReceived);
                                 return_msg = Some(JoinRingMsg::Response {
                                     id,
-                                    msg: JoinResponse::ReceivedOC { by_peer: pk_loc },
+                                    msg: JoinResponse::ReceivedOC {
+                                        by_peer: pk_loc,
+                                        domain: op_storage.ring.own_failure_domain(),
+                                        capabilities: op_storage.ring.own_capabilities(),
+                                    },
                                     sender: pk_loc,
                                     target: sender,
                                 });
@@ -334,11 +352,16 @@ where
                             op_storage,
                             sender,
                             &other_peer,
+                            domains.get(&other_peer.peer).copied(),
                             JoinRingMsg::Response {
                                 id,
                                 target: other_peer,
                                 sender: pk_loc,
-                                msg: JoinResponse::ReceivedOC { by_peer: pk_loc },
+                                msg: JoinResponse::ReceivedOC {
+                                    by_peer: pk_loc,
+                                    domain: op_storage.ring.own_failure_domain(),
+                                    capabilities: op_storage.ring.own_capabilities(),
+                                },
                             },
                         )
                         .await;
@@ -349,7 +372,11 @@ where
                     id,
                     sender,
                     target,
-                    msg: JoinResponse::Proxy { mut accepted_by },
+                    msg:
+                        JoinResponse::Proxy {
+                            mut accepted_by,
+                            mut domains,
+                        },
                 } => {
                     tracing::debug!("Received proxy join at @ {}", target.peer);
                     match self.state {
@@ -369,7 +396,10 @@ where
                                 new_state = None;
                             }
                             return_msg = Some(JoinRingMsg::Response {
-                                msg: JoinResponse::Proxy { accepted_by },
+                                msg: JoinResponse::Proxy {
+                                    accepted_by,
+                                    domains,
+                                },
                                 sender,
                                 id,
                                 target,
@@ -377,6 +407,7 @@ where
                         }
                         Some(JRState::AwaitingProxyResponse {
                             accepted_by: mut previously_accepted,
+                            domains: mut previous_domains,
                             new_peer_id,
                             target: state_target,
                             new_location,
@@ -388,6 +419,7 @@ where
 
                             if is_accepted {
                                 previously_accepted.extend(accepted_by.drain());
+                                previous_domains.extend(domains.drain());
                                 if is_target_peer {
                                     new_state = Some(JRState::OCReceived);
                                 } else {
@@ -413,6 +445,7 @@ where
                                     sender: target,
                                     msg: JoinResponse::AcceptedBy {
                                         peers: accepted_by,
+                                        domains,
                                         your_location: new_location,
                                         your_peer_id: new_peer_id,
                                     },
@@ -429,7 +462,10 @@ where
                                     id,
                                     target: state_target,
                                     sender: target,
-                                    msg: JoinResponse::Proxy { accepted_by },
+                                    msg: JoinResponse::Proxy {
+                                        accepted_by,
+                                        domains,
+                                    },
                                 });
                             }
                         }
@@ -444,7 +480,12 @@ where
                 JoinRingMsg::Response {
                     id,
                     sender,
-                    msg: JoinResponse::ReceivedOC { by_peer },
+                    msg:
+                        JoinResponse::ReceivedOC {
+                            by_peer,
+                            domain,
+                            capabilities,
+                        },
                     target,
                 } => {
                     match self.state {
@@ -455,6 +496,8 @@ where
                                 id,
                                 sender: target,
                                 target: sender,
+                                domain: op_storage.ring.own_failure_domain(),
+                                capabilities: op_storage.ring.own_capabilities(),
                             });
                         }
                         _ => return Err(OpError::InvalidStateTransition(self.id)),
@@ -462,18 +505,30 @@ where
                     if let Some(state) = new_state.clone() {
                         if !state.is_connected() {
                             return Err(OpError::InvalidStateTransition(id));
+                        } else if !op_storage.ring.is_allowed(&sender.peer) {
+                            return Err(ConnectionError::NotAllowed(sender.peer).into());
                         } else {
                             conn_manager.add_connection(sender.peer).await?;
                             op_storage.ring.add_connection(
                                 sender.location.ok_or(ConnectionError::LocationUnknown)?,
                                 sender.peer,
+                                domain,
                             );
+                            op_storage
+                                .ring
+                                .record_capabilities(sender.peer, capabilities);
                             tracing::debug!("Opened connection with peer {}", by_peer.peer);
                             new_state = None;
                         }
                     };
                 }
-                JoinRingMsg::Connected { target, sender, id } => {
+                JoinRingMsg::Connected {
+                    target,
+                    sender,
+                    id,
+                    domain,
+                    capabilities,
+                } => {
                     match self.state {
                         Some(JRState::OCReceived) => {
                             tracing::debug!("Acknowledge connected at peer");
@@ -485,6 +540,8 @@ where
                     if let Some(state) = new_state.clone() {
                         if !state.is_connected() {
                             return Err(OpError::InvalidStateTransition(id));
+                        } else if !op_storage.ring.is_allowed(&sender.peer) {
+                            return Err(ConnectionError::NotAllowed(sender.peer).into());
                         } else {
                             tracing::info!(
                                 "Successfully completed connection @ {}, new location = {:?}",
@@ -495,7 +552,23 @@ where
                             op_storage.ring.add_connection(
                                 sender.location.ok_or(ConnectionError::LocationUnknown)?,
                                 sender.peer,
+                                domain,
                             );
+                            op_storage
+                                .ring
+                                .record_capabilities(sender.peer, capabilities);
+                            // snapshots are streamed in chunks, so only seed the cache from
+                            // peers able to stream them
+                            let can_seed = op_storage
+                                .ring
+                                .shared_capabilities(&sender.peer)
+                                .contains(Capabilities::STREAMING);
+                            if can_seed && op_storage.start_cache_seeding() {
+                                let op = seed::start_op(&op_storage.ring.peer_key);
+                                if let Err(err) = seed::request_seed(op_storage, op).await {
+                                    tracing::warn!("Failed seeding the cache: {err}");
+                                }
+                            }
                             new_state = None;
                         }
                     };
@@ -541,6 +614,7 @@ fn try_proxy_connection(
     sender: &PeerKeyLocation,
     own_loc: &PeerKeyLocation,
     accepted_by: HashSet<PeerKeyLocation>,
+    domains: HashMap<PeerKey, FailureDomain>,
 ) -> (Option<JRState>, Option<JoinRingMsg>) {
     let new_state = if accepted_by.contains(own_loc) {
         tracing::debug!(
@@ -555,7 +629,10 @@ fn try_proxy_connection(
         None
     };
     let return_msg = Some(JoinRingMsg::Response {
-        msg: JoinResponse::Proxy { accepted_by },
+        msg: JoinResponse::Proxy {
+            accepted_by,
+            domains,
+        },
         sender: *own_loc,
         id: *id,
         target: *sender,
@@ -568,9 +645,11 @@ async fn propagate_oc_to_accepted_peers<CErr: std::error::Error, CB: ConnectionB
     op_storage: &OpManager<CErr>,
     sender: PeerKeyLocation,
     other_peer: &PeerKeyLocation,
+    other_domain: Option<FailureDomain>,
     msg: JoinRingMsg,
 ) -> Result<(), OpError<CErr>> {
     if op_storage.ring.should_accept(
+        &other_peer.peer,
         &other_peer
             .location
             .ok_or(ConnectionError::LocationUnknown)?,
@@ -582,6 +661,7 @@ async fn propagate_oc_to_accepted_peers<CErr: std::error::Error, CB: ConnectionB
                 .location
                 .ok_or(ConnectionError::LocationUnknown)?,
             other_peer.peer,
+            other_domain,
         );
         if other_peer.peer != sender.peer {
             // notify all the additional peers which accepted a request;
@@ -595,6 +675,18 @@ async fn propagate_oc_to_accepted_peers<CErr: std::error::Error, CB: ConnectionB
     Ok(())
 }
 
+/// Failure domains announced by the peers of `accepted_by` which are this peer, if known.
+fn announced_domains(
+    ring: &Ring,
+    accepted_by: &HashSet<PeerKeyLocation>,
+) -> HashMap<PeerKey, FailureDomain> {
+    accepted_by
+        .iter()
+        .filter(|pkl| pkl.peer == ring.peer_key)
+        .filter_map(|pkl| Some((pkl.peer, ring.own_failure_domain()?)))
+        .collect()
+}
+
 mod states {
     use super::*;
     use std::fmt::Display;
@@ -622,6 +714,8 @@ enum JRState {
         /// Could be either the requester or nodes which have been previously forwarded to
         target: PeerKeyLocation,
         accepted_by: HashSet<PeerKeyLocation>,
+        /// failure domains announced by the accepting peers
+        domains: HashMap<PeerKey, FailureDomain>,
         new_location: Location,
         new_peer_id: PeerKey,
     },
@@ -655,12 +749,19 @@ impl JRState {
     fn add_new_proxy<CErr>(
         &mut self,
         proxies: impl IntoIterator<Item = PeerKeyLocation>,
+        proxy_domains: HashMap<PeerKey, FailureDomain>,
     ) -> Result<(), OpError<CErr>>
     where
         CErr: std::error::Error,
     {
-        if let Self::AwaitingProxyResponse { accepted_by, .. } = self {
+        if let Self::AwaitingProxyResponse {
+            accepted_by,
+            domains,
+            ..
+        } = self
+        {
             accepted_by.extend(proxies.into_iter());
+            domains.extend(proxy_domains);
             Ok(())
         } else {
             Err(OpError::UnexpectedOpState)
@@ -684,11 +785,7 @@ pub(crate) fn initial_request(
         id,
         state: Some(state),
         gateway: Box::new(gateway),
-        backoff: Some(ExponentialBackoff::new(
-            Duration::from_secs(1),
-            Duration::from_secs(120),
-            MAX_JOIN_RETRIES,
-        )),
+        backoff: Some(CONFIG.retry.gateway_connection.backoff()),
         _ttl: PEER_TIMEOUT,
     }
 }
@@ -795,6 +892,7 @@ where
         let new_state = JRState::AwaitingProxyResponse {
             target: req_peer,
             accepted_by: HashSet::new(),
+            domains: HashMap::new(),
             new_location: new_peer_loc.location.unwrap(),
             new_peer_id: new_peer_loc.peer,
         };
@@ -837,6 +935,10 @@ mod messages {
             id: Transaction,
             sender: PeerKeyLocation,
             target: PeerKeyLocation,
+            /// failure domain of the sender, if known
+            domain: Option<FailureDomain>,
+            /// optional features supported by the sender
+            capabilities: Capabilities,
         },
     }
 
@@ -949,14 +1051,22 @@ mod messages {
     pub(crate) enum JoinResponse {
         AcceptedBy {
             peers: HashSet<PeerKeyLocation>,
+            /// failure domains announced by the accepting peers
+            domains: HashMap<PeerKey, FailureDomain>,
             your_location: Location,
             your_peer_id: PeerKey,
         },
         ReceivedOC {
             by_peer: PeerKeyLocation,
+            /// failure domain of the peer which received the OC, if known
+            domain: Option<FailureDomain>,
+            /// optional features supported by the peer which received the OC
+            capabilities: Capabilities,
         },
         Proxy {
             accepted_by: HashSet<PeerKeyLocation>,
+            /// failure domains announced by the accepting peers
+            domains: HashMap<PeerKey, FailureDomain>,
         },
     }
 }
@@ -965,7 +1075,10 @@ mod messages {
 mod test {
     use std::time::Duration;
 
-    use crate::node::test::{check_connectivity, SimNetwork};
+    use crate::node::{
+        test::{check_connectivity, SimNetwork},
+        Capabilities,
+    };
 
     /// Given a network of one node and one gateway test that both are connected.
     #[ignore]
@@ -998,4 +1111,33 @@ mod test {
         sim_nodes.build().await;
         check_connectivity(&sim_nodes, NUM_NODES, Duration::from_secs(10)).await
     }
+
+    /// A node joining through a gateway able to stream snapshots seeds its cache from it.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn seed_cache_from_streaming_peer() -> Result<(), anyhow::Error> {
+        let mut sim_nodes = SimNetwork::new(1, 1, 1, 1, 2, 2);
+        sim_nodes.build().await;
+        let op_storage = |label| {
+            sim_nodes
+                .running_peers()
+                .find(|(l, _)| *l == label)
+                .map(|(_, op_storage)| op_storage.clone())
+                .unwrap()
+        };
+        let (gateway, node) = (op_storage("gateway-0"), op_storage("node-0"));
+        let gw_key = gateway.ring.peer_key;
+        for _ in 0..30 {
+            let streaming = node
+                .ring
+                .shared_capabilities(&gw_key)
+                .contains(Capabilities::STREAMING);
+            if streaming && node.cache_seeding_started() {
+                // only the joining node seeds its cache
+                assert!(!gateway.cache_seeding_started());
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        anyhow::bail!("node-0 didn't seed its cache from gateway-0")
+    }
 }
@@ -8,10 +8,17 @@ use std::pin::Pin;
 use std::time::Duration;
 
 pub(crate) use self::messages::PutMsg;
-use locutus_runtime::{prelude::ContractKey, ContractContainer};
+use locutus_runtime::{
+    prelude::{Causality, ContractKey, UpdateData},
+    ContractContainer,
+};
+use locutus_stdlib::client_api::{ContractResponse, HostResponse};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
 
 use super::{OpEnum, OpError, OperationResult};
 use crate::{
+    client_events::HostResult,
     config::PEER_TIMEOUT,
     contract::ContractHandlerEvent,
     message::{InnerMessage, Message, Transaction, TxType},
@@ -21,6 +28,10 @@ use crate::{
     WrappedState,
 };
 
+/// Max time to wait for a replica to acknowledge a forwarded value before considering it failed.
+const REPLICATION_DEADLINE: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
 pub(crate) struct PutOp {
     id: Transaction,
     state: Option<PutState>,
@@ -121,6 +132,7 @@ where
                     mut skip_list,
                 } => {
                     let key = contract.key();
+                    op_storage.ring.record_contract_access(&key);
                     let is_cached_contract = op_storage.ring.is_contract_cached(&key);
 
                     tracing::debug!(
@@ -149,10 +161,42 @@ where
                         );
                     }
 
+                    // the change originates here, it succeeds all the updates seen so far
+                    let causality = op_storage
+                        .ring
+                        .next_causality(&key, &UpdateData::State(value.as_ref().into()));
+
                     // after the contract has been cached, push the update query
                     tracing::debug!("Attempting contract value update");
-                    let new_value = put_contract(op_storage, key.clone(), value).await?;
+                    let new_value =
+                        put_contract(op_storage, key.clone(), value, Some(causality.clone()))
+                            .await?;
+                    op_storage.ring.record_causality(&key, &causality);
                     tracing::debug!("Contract successfully updated");
+                    skip_list.push(target.peer);
+
+                    let mut replication = ReplicationOutcome {
+                        confirmed: vec![target.peer],
+                        ..Default::default()
+                    };
+                    if let Some(new_htl) = htl.checked_sub(1) {
+                        // forward changes in the contract to nodes closer to the contract location, if possible
+                        replication.merge(
+                            forward_changes(
+                                op_storage,
+                                conn_manager,
+                                &contract,
+                                new_value.clone(),
+                                &causality,
+                                id,
+                                new_htl,
+                                skip_list.as_slice(),
+                                Some(sender),
+                            )
+                            .await,
+                        );
+                    }
+
                     // if the change was successful, communicate this back to the requestor and broadcast the change
                     conn_manager
                         .send(
@@ -160,31 +204,13 @@ where
                             (PutMsg::SuccessfulUpdate {
                                 id,
                                 new_value: new_value.clone(),
+                                replication,
                             })
                             .into(),
                         )
                         .await?;
-                    skip_list.push(target.peer);
-
-                    if let Some(new_htl) = htl.checked_sub(1) {
-                        // forward changes in the contract to nodes closer to the contract location, if possible
-                        forward_changes(
-                            op_storage,
-                            conn_manager,
-                            &contract,
-                            new_value.clone(),
-                            id,
-                            new_htl,
-                            skip_list.as_slice(),
-                        )
-                        .await;
-                    }
 
-                    let broadcast_to = op_storage
-                        .ring
-                        .subscribers_of(&key)
-                        .map(|i| i.value().to_vec())
-                        .unwrap_or_default();
+                    let broadcast_to = op_storage.ring.subscribers_of(&key).unwrap_or_default();
                     tracing::debug!(
                         "Successfully updated a value for contract {} @ {:?}",
                         key,
@@ -198,6 +224,7 @@ where
                         broadcast_to,
                         key.clone(),
                         new_value,
+                        causality,
                         self._ttl,
                     )
                     .await
@@ -213,21 +240,33 @@ where
                     id,
                     key,
                     new_value,
+                    causality,
                     sender,
                     sender_subscribers,
                 } => {
                     let target = op_storage.ring.own_location();
+                    if op_storage.ring.has_seen_causality(&key, &causality) {
+                        // already applied and broadcast by this node
+                        tracing::debug!("Ignoring already seen update {} of {key}", causality.id());
+                        return Ok(OperationResult {
+                            return_msg: None,
+                            state: None,
+                        });
+                    }
 
                     tracing::debug!("Attempting contract value update");
-                    let new_value = put_contract(op_storage, key.clone(), new_value).await?;
+                    let new_value =
+                        put_contract(op_storage, key.clone(), new_value, Some(causality.clone()))
+                            .await?;
+                    // only seen once applied, so retransmissions of failed updates are retried
+                    op_storage.ring.record_causality(&key, &causality);
                     tracing::debug!("Contract successfully updated");
 
                     let broadcast_to = op_storage
                         .ring
                         .subscribers_of(&key)
-                        .map(|i| {
+                        .map(|mut subscribers| {
                             // Avoid already broadcast nodes and sender from broadcasting
-                            let mut subscribers: Vec<PeerKeyLocation> = i.value().to_vec();
                             let mut avoid_list: HashSet<PeerKey> =
                                 sender_subscribers.into_iter().map(|pl| pl.peer).collect();
                             avoid_list.insert(sender.peer);
@@ -248,6 +287,7 @@ where
                         broadcast_to,
                         key,
                         new_value,
+                        causality,
                         self._ttl,
                     )
                     .await
@@ -265,12 +305,14 @@ where
                     mut broadcasted_to,
                     key,
                     new_value,
+                    causality,
                 } => {
                     let sender = op_storage.ring.own_location();
                     let msg = PutMsg::BroadcastTo {
                         id,
                         key: key.clone(),
                         new_value: new_value.clone(),
+                        causality,
                         sender,
                         sender_subscribers: broadcast_to.clone(),
                     };
@@ -315,28 +357,103 @@ where
                     return_msg = None;
                     new_state = None;
                 }
-                PutMsg::SuccessfulUpdate { .. } => {
+                PutMsg::SuccessfulUpdate {
+                    id,
+                    mut replication,
+                    ..
+                } => {
                     match self.state {
-                        Some(PutState::AwaitingResponse { contract, .. }) => {
-                            tracing::debug!("Successfully updated value for {}", contract,);
-                            new_state = None;
+                        Some(PutState::AwaitingResponse { contract, acked }) => {
+                            for replica in &acked {
+                                replication.confirm(replica);
+                            }
+                            if replication.is_settled() {
+                                finish_put(op_storage, id, contract, replication);
+                                new_state = None;
+                            } else {
+                                // the rest of replicas must acknowledge the value before the deadline
+                                op_storage.notify_op_change_after(
+                                    REPLICATION_DEADLINE,
+                                    PutMsg::ReplicationDeadline { id }.into(),
+                                );
+                                new_state = Some(PutState::AwaitingReplicas {
+                                    contract,
+                                    replication,
+                                });
+                            }
                             return_msg = None;
                         }
                         _ => return Err(OpError::InvalidStateTransition(self.id)),
                     };
-                    tracing::debug!(
-                        "Peer {} completed contract value put",
-                        op_storage.ring.peer_key
-                    );
+                }
+                PutMsg::ReplicaAck { id, replica, .. } => {
+                    match self.state {
+                        Some(PutState::AwaitingResponse {
+                            contract,
+                            mut acked,
+                        }) => {
+                            // the replica acknowledged before the update was confirmed
+                            acked.push(replica);
+                            new_state = Some(PutState::AwaitingResponse { contract, acked });
+                        }
+                        Some(PutState::AwaitingReplicas {
+                            contract,
+                            mut replication,
+                        }) => {
+                            replication.confirm(&replica);
+                            if replication.is_settled() {
+                                finish_put(op_storage, id, contract, replication);
+                                new_state = None;
+                            } else {
+                                new_state = Some(PutState::AwaitingReplicas {
+                                    contract,
+                                    replication,
+                                });
+                            }
+                        }
+                        _ => {
+                            tracing::debug!(
+                                "Replica {replica} acknowledged {id} after the deadline"
+                            );
+                            return Ok(OperationResult {
+                                return_msg: None,
+                                state: None,
+                            });
+                        }
+                    }
+                    return_msg = None;
+                }
+                PutMsg::ReplicationDeadline { id } => {
+                    match self.state {
+                        Some(PutState::AwaitingReplicas {
+                            contract,
+                            mut replication,
+                        }) => {
+                            replication.expire();
+                            finish_put(op_storage, id, contract, replication);
+                        }
+                        _ => {
+                            // every replica acknowledged the value before the deadline
+                            return Ok(OperationResult {
+                                return_msg: None,
+                                state: None,
+                            });
+                        }
+                    }
+                    new_state = None;
+                    return_msg = None;
                 }
                 PutMsg::PutForward {
                     id,
                     contract,
                     new_value,
+                    causality,
                     htl,
                     mut skip_list,
+                    requester,
                 } => {
                     let key = contract.key();
+                    op_storage.ring.record_contract_access(&key);
                     let peer_loc = op_storage.ring.own_location();
 
                     tracing::debug!(
@@ -362,21 +479,42 @@ where
                         });
                     }
                     // after the contract has been cached, push the update query
-                    let new_value = put_contract(op_storage, key, new_value).await?;
+                    let new_value =
+                        put_contract(op_storage, key.clone(), new_value, Some(causality.clone()))
+                            .await?;
+                    op_storage.ring.record_causality(&key, &causality);
+                    if let Some(requester) = requester {
+                        // the value is stored at this replica, acknowledge it to the requester;
+                        // it may not be connected to this peer, which must not stop the changes
+                        // from being forwarded, the requester just counts the replica as pending
+                        let ack = PutMsg::ReplicaAck {
+                            id,
+                            replica: peer_loc.peer,
+                            target: requester,
+                        };
+                        if let Err(err) = conn_manager.send(&requester.peer, ack.into()).await {
+                            tracing::warn!(
+                                "Failed acknowledging put {id} to {}: {err}",
+                                requester.peer
+                            );
+                        }
+                    }
 
                     //update skip list
                     skip_list.push(peer_loc.peer);
 
                     // if successful, forward to the next closest peers (if any)
                     if let Some(new_htl) = htl.checked_sub(1) {
-                        forward_changes(
+                        let _ = forward_changes(
                             op_storage,
                             conn_manager,
                             &contract,
                             new_value,
+                            &causality,
                             id,
                             new_htl,
                             skip_list.as_slice(),
+                            None,
                         )
                         .await;
                     }
@@ -408,6 +546,31 @@ fn build_op_result<CErr: std::error::Error>(
     })
 }
 
+/// Sends back the outcome of the put to the client which requested it.
+fn finish_put<CErr: std::error::Error>(
+    op_storage: &OpManager<CErr>,
+    id: Transaction,
+    contract: ContractKey,
+    replication: ReplicationOutcome,
+) {
+    if replication.is_partial() {
+        tracing::warn!(
+            "Value for {} only updated at {} out of {} replicas",
+            contract,
+            replication.confirmed.len(),
+            replication.confirmed.len() + replication.failed.len()
+        );
+    } else {
+        tracing::debug!("Successfully updated value for {}", contract);
+    }
+    tracing::debug!(
+        "Peer {} completed contract value put",
+        op_storage.ring.peer_key
+    );
+    let response = replication.into_response(contract);
+    op_storage.notify_client(&id, Ok(HostResponse::ContractResponse(response)));
+}
+
 async fn try_to_cache_contract<'a, CErr: std::error::Error>(
     op_storage: &'a OpManager<CErr>,
     contract: &ContractContainer,
@@ -429,6 +592,7 @@ async fn try_to_cache_contract<'a, CErr: std::error::Error>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn try_to_broadcast<CErr: std::error::Error>(
     id: Transaction,
     op_storage: &OpManager<CErr>,
@@ -436,6 +600,7 @@ async fn try_to_broadcast<CErr: std::error::Error>(
     broadcast_to: Vec<PeerKeyLocation>,
     key: ContractKey,
     new_value: WrappedState,
+    causality: Causality,
     ttl: Duration,
 ) -> Result<(Option<PutState>, Option<PutMsg>), OpError<CErr>> {
     let new_state;
@@ -451,7 +616,11 @@ async fn try_to_broadcast<CErr: std::error::Error>(
                 );
                 // means the whole tx finished so can return early
                 new_state = None;
-                return_msg = Some(PutMsg::SuccessfulUpdate { id, new_value });
+                return_msg = Some(PutMsg::SuccessfulUpdate {
+                    id,
+                    new_value,
+                    replication: ReplicationOutcome::default(),
+                });
             } else {
                 tracing::debug!("Callback to start broadcasting to other nodes");
                 new_state = Some(PutState::BroadcastOngoing);
@@ -461,6 +630,7 @@ async fn try_to_broadcast<CErr: std::error::Error>(
                     broadcasted_to: 0,
                     broadcast_to,
                     key,
+                    causality,
                 });
 
                 let op = PutOp {
@@ -519,14 +689,23 @@ enum PutState {
     },
     AwaitingResponse {
         contract: ContractKey,
+        /// replicas which acknowledged the value before the update was confirmed
+        acked: Vec<PeerKey>,
+    },
+    /// The value was updated, waiting for the replicas to acknowledge it.
+    AwaitingReplicas {
+        contract: ContractKey,
+        replication: ReplicationOutcome,
     },
     BroadcastOngoing,
 }
 
-/// Request to insert/update a value into a contract.
+/// Request to insert/update a value into a contract, the outcome is sent back to the client
+/// if any.
 pub(crate) async fn request_put<CErr>(
     op_storage: &OpManager<CErr>,
     put_op: PutOp,
+    client: Option<UnboundedSender<HostResult>>,
 ) -> Result<(), OpError<CErr>>
 where
     CErr: std::error::Error,
@@ -559,7 +738,10 @@ where
             ..
         }) => {
             let key = contract.key();
-            let new_state = Some(PutState::AwaitingResponse { contract: key });
+            let new_state = Some(PutState::AwaitingResponse {
+                contract: key,
+                acked: vec![],
+            });
             let msg = Some(PutMsg::RequestPut {
                 id,
                 contract,
@@ -574,6 +756,9 @@ where
                 _ttl: put_op._ttl,
             };
 
+            if let Some(client) = client {
+                op_storage.register_client(id, client);
+            }
             op_storage
                 .notify_op_change(msg.map(Message::from).unwrap(), OpEnum::Put(op))
                 .await?;
@@ -588,13 +773,18 @@ async fn put_contract<CErr>(
     op_storage: &OpManager<CErr>,
     key: ContractKey,
     state: WrappedState,
+    causality: Option<Causality>,
 ) -> Result<WrappedState, OpError<CErr>>
 where
     CErr: std::error::Error,
 {
     // after the contract has been cached, push the update query
     match op_storage
-        .notify_contract_handler(ContractHandlerEvent::PushQuery { key, state })
+        .notify_contract_handler(ContractHandlerEvent::PushQuery {
+            key,
+            state,
+            causality,
+        })
         .await
     {
         Ok(ContractHandlerEvent::PushResponse {
@@ -614,43 +804,118 @@ where
 // TODO: keep track of who is supposed to have the contract, and only send if necessary
 // since sending the contract over and over, will be expensive; this can be done via subscriptions
 /// Communicate changes in the contract to other peers nearby the contract location.
-/// This operation is "fire and forget" and the node does not keep track if is successful or not.
+///
+/// Replicas receiving the changes acknowledge them to the requester, if any. Returns which of
+/// the replicas are pending to acknowledge the changes and which could not be reached, but
+/// the node does not keep track of whether the changes are successfully applied further.
+#[allow(clippy::too_many_arguments)]
 async fn forward_changes<CErr, CB>(
     op_storage: &OpManager<CErr>,
     conn_manager: &CB,
     contract: &ContractContainer,
     new_value: WrappedState,
+    causality: &Causality,
     id: Transaction,
     htl: usize,
     skip_list: &[PeerKey],
-) where
+    requester: Option<PeerKeyLocation>,
+) -> ReplicationOutcome
+where
     CErr: std::error::Error,
     CB: ConnectionBridge,
 {
     let key = contract.key();
     let contract_loc = Location::from(&key);
-    let forward_to = op_storage.ring.closest_caching(&key, 1, skip_list);
+    let replicas = op_storage.ring.replication_factor(&key);
+    let forward_to = op_storage
+        .ring
+        .closest_caching_across_domains(&key, replicas, skip_list);
     let own_loc = op_storage.ring.own_location().location.expect("infallible");
+    let mut outcome = ReplicationOutcome::default();
     for peer in forward_to {
         let other_loc = peer.location.as_ref().expect("infallible");
         let other_distance = contract_loc.distance(other_loc);
         let self_distance = contract_loc.distance(own_loc);
         if other_distance < self_distance {
-            // forward the contract towards this node since it is indeed closer to the contract location
-            // and forget about it, no need to keep track of this op or wait for response
-            let _ = conn_manager
-                .send(
+            // forward the contract towards this node since it is indeed closer to the contract location,
+            // no need to keep track of this op since the replica acknowledges it to the requester
+            let forwarded = tokio::time::timeout(
+                REPLICATION_DEADLINE,
+                conn_manager.send(
                     &peer.peer,
                     (PutMsg::PutForward {
                         id,
                         contract: contract.clone(),
                         new_value: new_value.clone(),
+                        causality: causality.clone(),
                         htl,
                         skip_list: skip_list.to_vec(),
+                        requester,
                     })
                     .into(),
-                )
-                .await;
+                ),
+            )
+            .await;
+            match forwarded {
+                Ok(Ok(())) => outcome.pending.push(peer.peer),
+                Ok(Err(err)) => {
+                    tracing::debug!("Failed forwarding changes to {}: {}", peer.peer, err);
+                    outcome.failed.push(peer.peer);
+                }
+                Err(_) => {
+                    tracing::debug!("Timed out forwarding changes to {}", peer.peer);
+                    outcome.failed.push(peer.peer);
+                }
+            }
+        }
+    }
+    outcome
+}
+
+/// Which replicas confirmed a put before the deadline and which did not.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct ReplicationOutcome {
+    confirmed: Vec<PeerKey>,
+    failed: Vec<PeerKey>,
+    /// replicas which were forwarded the value but did not acknowledge it yet
+    pending: Vec<PeerKey>,
+}
+
+impl ReplicationOutcome {
+    fn merge(&mut self, other: ReplicationOutcome) {
+        self.confirmed.extend(other.confirmed);
+        self.failed.extend(other.failed);
+        self.pending.extend(other.pending);
+    }
+
+    /// The replica acknowledged the value.
+    fn confirm(&mut self, replica: &PeerKey) {
+        if let Some(pos) = self.pending.iter().position(|p| p == replica) {
+            self.confirmed.push(self.pending.swap_remove(pos));
+        }
+    }
+
+    /// The deadline passed, so replicas which did not acknowledge the value failed.
+    fn expire(&mut self) {
+        self.failed.append(&mut self.pending);
+    }
+
+    fn is_settled(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn is_partial(&self) -> bool {
+        !self.failed.is_empty()
+    }
+
+    fn into_response(self, key: ContractKey) -> ContractResponse {
+        if !self.is_partial() {
+            return ContractResponse::PutResponse { key };
+        }
+        ContractResponse::PartialPutResponse {
+            key,
+            confirmed: self.confirmed.iter().map(|p| p.to_string()).collect(),
+            failed: self.failed.iter().map(|p| p.to_string()).collect(),
         }
     }
 }
@@ -661,7 +926,6 @@ mod messages {
     use super::*;
 
     use crate::message::InnerMessage;
-    use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
     pub(crate) enum PutMsg {
@@ -687,14 +951,27 @@ mod messages {
             id: Transaction,
             contract: ContractContainer,
             new_value: WrappedState,
+            causality: Causality,
             /// current htl, reduced by one at each hop
             htl: usize,
             skip_list: Vec<PeerKey>,
+            /// peer awaiting the acknowledgement of this replica, if any
+            requester: Option<PeerKeyLocation>,
+        },
+        /// A replica stored the value forwarded to it.
+        ReplicaAck {
+            id: Transaction,
+            replica: PeerKey,
+            target: PeerKeyLocation,
         },
+        /// Internal node instruction to stop waiting for replicas to acknowledge a value.
+        ReplicationDeadline { id: Transaction },
         /// Value successfully inserted/updated.
         SuccessfulUpdate {
             id: Transaction,
             new_value: WrappedState,
+            /// replicas which (did not) confirm the new value
+            replication: ReplicationOutcome,
         },
         /// Target the node which is closest to the key
         SeekNode {
@@ -716,6 +993,7 @@ mod messages {
             broadcast_to: Vec<PeerKeyLocation>,
             key: ContractKey,
             new_value: WrappedState,
+            causality: Causality,
         },
         /// Broadcasting a change to a peer, which then will relay the changes to other peers.
         BroadcastTo {
@@ -723,6 +1001,8 @@ mod messages {
             sender: PeerKeyLocation,
             key: ContractKey,
             new_value: WrappedState,
+            /// Allows subscribers to order concurrent updates consistently.
+            causality: Causality,
             sender_subscribers: Vec<PeerKeyLocation>,
         },
     }
@@ -738,8 +1018,17 @@ mod messages {
                 Self::PutForward { id, .. } => id,
                 Self::AwaitPut { id } => id,
                 Self::BroadcastTo { id, .. } => id,
+                Self::ReplicaAck { id, .. } => id,
+                Self::ReplicationDeadline { id } => id,
             }
         }
+
+        fn applies_changes(&self) -> bool {
+            matches!(
+                self,
+                Self::RequestPut { .. } | Self::SeekNode { .. } | Self::PutForward { .. }
+            )
+        }
     }
 
     impl PutMsg {
@@ -755,6 +1044,7 @@ mod messages {
             match self {
                 Self::SeekNode { target, .. } => Some(target),
                 Self::RequestPut { target, .. } => Some(target),
+                Self::ReplicaAck { target, .. } => Some(target),
                 _ => None,
             }
         }
@@ -763,7 +1053,11 @@ mod messages {
             use PutMsg::*;
             matches!(
                 self,
-                SuccessfulUpdate { .. } | SeekNode { .. } | PutForward { .. }
+                SuccessfulUpdate { .. }
+                    | SeekNode { .. }
+                    | PutForward { .. }
+                    | ReplicaAck { .. }
+                    | ReplicationDeadline { .. }
             )
         }
     }
@@ -780,6 +1074,8 @@ mod messages {
                 Self::PutForward { .. } => write!(f, "PutForward(id: {id})"),
                 Self::AwaitPut { .. } => write!(f, "AwaitPut(id: {id})"),
                 Self::BroadcastTo { .. } => write!(f, "BroadcastTo(id: {id})"),
+                Self::ReplicaAck { .. } => write!(f, "ReplicaAck(id: {id})"),
+                Self::ReplicationDeadline { .. } => write!(f, "ReplicationDeadline(id: {id})"),
             }
         }
     }
@@ -795,6 +1091,46 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn partial_replication_response() {
+        let bytes = crate::util::test::random_bytes_1024();
+        let mut gen = arbitrary::Unstructured::new(&bytes);
+        let contract: WrappedContract = gen.arbitrary().unwrap();
+        let key = contract.key().clone();
+
+        let mut outcome = ReplicationOutcome {
+            confirmed: vec![PeerKey::random()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            outcome.clone().into_response(key.clone()),
+            ContractResponse::PutResponse { .. }
+        ));
+
+        // only replicas which acknowledge the value are confirmed
+        let (acked, failed) = (PeerKey::random(), PeerKey::random());
+        outcome.merge(ReplicationOutcome {
+            pending: vec![acked, failed],
+            ..Default::default()
+        });
+        outcome.confirm(&acked);
+        outcome.confirm(&PeerKey::random());
+        assert!(!outcome.is_settled());
+        outcome.expire();
+        assert!(outcome.is_settled());
+        match outcome.into_response(key) {
+            ContractResponse::PartialPutResponse {
+                confirmed,
+                failed: failed_replicas,
+                ..
+            } => {
+                assert_eq!(confirmed.len(), 2);
+                assert_eq!(failed_replicas, vec![failed.to_string()]);
+            }
+            other => panic!("unexpected response: {other:?}"),
+        }
+    }
+
     #[ignore]
     #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
     async fn successful_put_op_between_nodes() -> Result<(), anyhow::Error> {
@@ -0,0 +1,558 @@
+//! Random walk based sampling of the peers in the network.
+//!
+//! A sample is obtained by sending a random walk through the ring: every visited peer
+//! appends some public information about itself (location, node version and number of
+//! connections) and proposes one of its connections chosen at random as the next hop, until
+//! the walk length is exhausted and the samples are returned to the requester. From the
+//! samples the requester then estimates global network properties, like the size of the
+//! network, the distribution of node versions or the latency between peers.
+//!
+//! A plain random walk visits peers proportionally to their number of connections, which
+//! would bias the estimates towards well connected peers. Instead the walk follows the
+//! Metropolis-Hastings rule: the proposed peer only accepts the hop with probability
+//! `min(1, proposer degree / own degree)`, otherwise the walk stays at the proposer, which is
+//! sampled again. This way every peer is visited with the same probability in the long run.
+//!
+//! Since walks could be abused as a scanning primitive, their length is capped, peers
+//! don't share any identifying information other than their location and every peer rate
+//! limits the number of hops it takes part in per neighbour. The neighbour which sent each
+//! hop is checked against the peer authenticated by the transport, so it can't be spoofed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use locutus_stdlib::client_api::{HostResponse, NetworkSample};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    client_events::HostResult,
+    config::PEER_TIMEOUT,
+    message::{InnerMessage, Message, Transaction, TxType},
+    node::{ConnectionBridge, ConnectionError, OpManager, PeerKey},
+    operations::{op_trait::Operation, OpInitialization},
+    ring::{Location, PeerKeyLocation, RingError},
+};
+
+use super::{OpEnum, OpError, OperationResult};
+
+pub(crate) use self::messages::SampleMsg;
+
+/// Max number of hops of a single random walk.
+const MAX_WALK_LENGTH: usize = 20;
+
+/// Window over which the hops sent by a given neighbour are rate limited.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Max number of hops sent by the same neighbour a peer takes part in per window.
+const MAX_HOPS_PER_NEIGHBOUR: usize = 20;
+
+/// Number of tracked neighbours above which expired entries are cleaned up.
+const MAX_TRACKED_NEIGHBOURS: usize = 1024;
+
+#[derive(Debug)]
+pub(crate) struct SampleOp {
+    id: Transaction,
+    state: Option<SampleState>,
+    _ttl: Duration,
+}
+
+impl<CErr, CB: ConnectionBridge> Operation<CErr, CB> for SampleOp
+where
+    CErr: std::error::Error + Send,
+{
+    type Message = SampleMsg;
+    type Error = OpError<CErr>;
+
+    fn load_or_init(
+        op_storage: &OpManager<CErr>,
+        msg: &Self::Message,
+    ) -> Result<OpInitialization<Self>, OpError<CErr>> {
+        let sender = msg.sender().map(|peer_key_loc| peer_key_loc.peer);
+        let id = *msg.id();
+
+        let result = match op_storage.pop(msg.id()) {
+            Some(OpEnum::Sample(sample_op)) => {
+                // the walk returned to the requester
+                Ok(OpInitialization {
+                    op: sample_op,
+                    sender,
+                })
+            }
+            Some(_) => return Err(OpError::OpNotPresent(id)),
+            None => {
+                // this peer is part of an other peer random walk
+                Ok(OpInitialization {
+                    op: Self {
+                        state: Some(SampleState::ReceivedRequest),
+                        id,
+                        _ttl: PEER_TIMEOUT,
+                    },
+                    sender,
+                })
+            }
+        };
+        result
+    }
+
+    fn id(&self) -> &Transaction {
+        &self.id
+    }
+
+    fn process_message<'a>(
+        self,
+        _conn_manager: &'a mut CB,
+        op_storage: &'a OpManager<CErr>,
+        input: Self::Message,
+    ) -> Pin<Box<dyn Future<Output = Result<OperationResult, Self::Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let return_msg;
+            let new_state;
+
+            match input {
+                SampleMsg::RequestSample {
+                    id,
+                    target,
+                    walk_length,
+                } => {
+                    // fast tracked from the request_sample func
+                    debug_assert!(matches!(
+                        self.state,
+                        Some(SampleState::AwaitingResponse { .. })
+                    ));
+                    new_state = self.state;
+                    return_msg = Some(SampleMsg::RandomWalk {
+                        id,
+                        requester: op_storage.ring.own_location(),
+                        from: op_storage.ring.own_location(),
+                        from_degree: op_storage.ring.num_connections(),
+                        target,
+                        htl: walk_length,
+                        samples: vec![],
+                    });
+                }
+                SampleMsg::RandomWalk {
+                    id,
+                    requester,
+                    from,
+                    from_degree,
+                    target,
+                    htl,
+                    samples,
+                } => {
+                    // the walk could visit the requester again, in which case keep awaiting
+                    new_state = match self.state {
+                        Some(SampleState::AwaitingResponse { .. }) => self.state,
+                        _ => None,
+                    };
+                    return_msg = if !op_storage.sampling_allowed(&from.peer) {
+                        // cut the walk short and return whatever has been sampled so far
+                        tracing::debug!("Rate limited random walk hop from {}", from.peer);
+                        Some(SampleMsg::ReturnSample {
+                            id,
+                            target: requester,
+                            samples,
+                        })
+                    } else if accept_hop(from_degree, op_storage.ring.num_connections()) {
+                        Some(visit(op_storage, id, requester, target, htl, samples)?)
+                    } else {
+                        Some(SampleMsg::RejectedHop {
+                            id,
+                            requester,
+                            from: target,
+                            target: from,
+                            htl,
+                            samples,
+                        })
+                    };
+                }
+                SampleMsg::RejectedHop {
+                    id,
+                    requester,
+                    from,
+                    target,
+                    htl,
+                    samples,
+                } => {
+                    new_state = match self.state {
+                        Some(SampleState::AwaitingResponse { .. }) => self.state,
+                        _ => None,
+                    };
+                    return_msg = if op_storage.sampling_allowed(&from.peer) {
+                        // the walk stays at this peer for this step
+                        Some(visit(op_storage, id, requester, target, htl, samples)?)
+                    } else {
+                        tracing::debug!("Rate limited random walk hop from {}", from.peer);
+                        Some(SampleMsg::ReturnSample {
+                            id,
+                            target: requester,
+                            samples,
+                        })
+                    };
+                }
+                SampleMsg::ReturnSample { samples, .. } => match self.state {
+                    Some(SampleState::AwaitingResponse { started }) => {
+                        let estimate = estimate(&samples, started.elapsed());
+                        tracing::info!("Network sample completed: {}", estimate);
+                        op_storage
+                            .notify_client(&self.id, Ok(HostResponse::NetworkSample(estimate)));
+                        new_state = None;
+                        return_msg = None;
+                    }
+                    _ => return Err(OpError::InvalidStateTransition(self.id)),
+                },
+            }
+
+            build_op_result(self.id, new_state, return_msg, self._ttl)
+        })
+    }
+}
+
+fn build_op_result<CErr: std::error::Error>(
+    id: Transaction,
+    state: Option<SampleState>,
+    msg: Option<SampleMsg>,
+    ttl: Duration,
+) -> Result<OperationResult, OpError<CErr>> {
+    // peers visited by the walk don't need to keep any state around
+    let output_op = state.map(|state| SampleOp {
+        id,
+        state: Some(state),
+        _ttl: ttl,
+    });
+    Ok(OperationResult {
+        return_msg: msg.map(Message::from),
+        state: output_op.map(OpEnum::Sample),
+    })
+}
+
+/// Metropolis-Hastings acceptance of a hop proposed by a peer with `from_degree` connections
+/// to a peer with `own_degree` connections.
+fn accept_hop(from_degree: usize, own_degree: usize) -> bool {
+    own_degree <= from_degree || rand::random::<f64>() < from_degree as f64 / own_degree as f64
+}
+
+/// Samples this peer and proposes the next hop of the walk, or returns the samples to the
+/// requester if the walk is over.
+fn visit<CErr: std::error::Error>(
+    op_storage: &OpManager<CErr>,
+    id: Transaction,
+    requester: PeerKeyLocation,
+    own: PeerKeyLocation,
+    htl: usize,
+    mut samples: Vec<PeerSample>,
+) -> Result<SampleMsg, OpError<CErr>> {
+    let degree = op_storage.ring.num_connections();
+    samples.push(PeerSample {
+        location: own.location.ok_or(ConnectionError::LocationUnknown)?,
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        connections: degree,
+    });
+    let next_hop = htl
+        .checked_sub(1)
+        .filter(|_| samples.len() < MAX_WALK_LENGTH)
+        .zip(op_storage.ring.random_connection(&[own.peer]));
+    Ok(match next_hop {
+        Some((htl, next_target)) => SampleMsg::RandomWalk {
+            id,
+            requester,
+            from: own,
+            from_degree: degree,
+            target: next_target,
+            htl,
+            samples,
+        },
+        None => SampleMsg::ReturnSample {
+            id,
+            target: requester,
+            samples,
+        },
+    })
+}
+
+pub(crate) fn start_op(walk_length: usize, peer: &PeerKey) -> SampleOp {
+    let id = Transaction::new(<SampleMsg as TxType>::tx_type_id(), peer);
+    let state = Some(SampleState::PrepareRequest {
+        id,
+        walk_length: walk_length.min(MAX_WALK_LENGTH),
+    });
+    SampleOp {
+        id,
+        state,
+        _ttl: PEER_TIMEOUT,
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum SampleState {
+    /// Prepare the request to sample the network.
+    PrepareRequest { id: Transaction, walk_length: usize },
+    /// Visited as part of an other peer random walk.
+    ReceivedRequest,
+    /// Awaiting for the random walk to return.
+    AwaitingResponse { started: Instant },
+}
+
+/// Request a random walk sample of the network, the estimate is sent back to the client
+/// if any.
+pub(crate) async fn request_sample<CErr>(
+    op_storage: &OpManager<CErr>,
+    sample_op: SampleOp,
+    client: Option<UnboundedSender<HostResult>>,
+) -> Result<(), OpError<CErr>>
+where
+    CErr: std::error::Error,
+{
+    let own_peer = op_storage.ring.peer_key;
+    match sample_op.state {
+        Some(SampleState::PrepareRequest { id, walk_length }) => {
+            if !op_storage.sampling_allowed(&own_peer) {
+                return Err(OpError::RateLimited(id));
+            }
+            let target = op_storage
+                .ring
+                .random_connection(&[own_peer])
+                .ok_or(RingError::EmptyRing)?;
+            let msg = SampleMsg::RequestSample {
+                id,
+                target,
+                walk_length,
+            };
+            let op = SampleOp {
+                id,
+                state: Some(SampleState::AwaitingResponse {
+                    started: Instant::now(),
+                }),
+                _ttl: sample_op._ttl,
+            };
+            if let Some(client) = client {
+                op_storage.register_client(id, client);
+            }
+            op_storage
+                .notify_op_change(Message::from(msg), OpEnum::Sample(op))
+                .await?;
+        }
+        _ => return Err(OpError::InvalidStateTransition(sample_op.id)),
+    }
+    Ok(())
+}
+
+/// Public information about a peer visited during a random walk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PeerSample {
+    location: Location,
+    version: String,
+    connections: usize,
+}
+
+/// Estimates global network properties from the samples of a random walk.
+fn estimate(samples: &[PeerSample], elapsed: Duration) -> NetworkSample {
+    let mut visits: HashMap<u64, usize> = HashMap::new();
+    let mut versions = BTreeMap::new();
+    let mut connections = 0;
+    for sample in samples {
+        let location = sample.location.as_f64().to_bits();
+        *visits.entry(location).or_default() += 1;
+        *versions.entry(sample.version.clone()).or_default() += 1;
+        connections += sample.connections;
+    }
+
+    // birthday paradox estimate: the number of peers sampled more than once
+    // is inversely proportional to the size of the network
+    let collisions: usize = visits.values().map(|n| n * (n - 1) / 2).sum();
+    let size = (collisions > 0).then(|| {
+        let estimate = samples.len() * (samples.len() - 1) / (2 * collisions);
+        estimate.max(visits.len())
+    });
+
+    NetworkSample {
+        samples: samples.len(),
+        size,
+        versions,
+        avg_connections: connections as f64 / samples.len().max(1) as f64,
+        // the walk plus the way back to the requester
+        hop_latency: elapsed / (samples.len() as u32 + 1),
+    }
+}
+
+/// Tracks how many walk hops sent by each neighbour this peer took part in.
+#[derive(Debug, Default)]
+pub(crate) struct SamplingRateLimiter {
+    hops: DashMap<PeerKey, (Instant, usize)>,
+}
+
+impl SamplingRateLimiter {
+    /// Returns whether a new hop sent by the neighbour is allowed, accounting for it if so.
+    pub fn allow(&self, neighbour: &PeerKey) -> bool {
+        let now = Instant::now();
+        if self.hops.len() > MAX_TRACKED_NEIGHBOURS {
+            self.hops
+                .retain(|_, (start, _)| now.duration_since(*start) < RATE_LIMIT_WINDOW);
+        }
+        let mut hops = self.hops.entry(*neighbour).or_insert((now, 0));
+        let (start, count) = hops.value_mut();
+        if now.duration_since(*start) >= RATE_LIMIT_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= MAX_HOPS_PER_NEIGHBOUR {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+mod messages {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    pub(crate) enum SampleMsg {
+        /// Internal node instruction to start a random walk.
+        RequestSample {
+            id: Transaction,
+            target: PeerKeyLocation,
+            walk_length: usize,
+        },
+        /// Random walk in progress, proposing the target peer as the next hop.
+        RandomWalk {
+            id: Transaction,
+            requester: PeerKeyLocation,
+            /// peer which proposed the hop, must be the one sending the message
+            from: PeerKeyLocation,
+            /// number of connections of the proposing peer
+            from_degree: usize,
+            target: PeerKeyLocation,
+            /// hops left in this walk
+            htl: usize,
+            samples: Vec<PeerSample>,
+        },
+        /// The proposed hop was rejected, the walk stays at the target peer for this step.
+        RejectedHop {
+            id: Transaction,
+            requester: PeerKeyLocation,
+            /// peer which rejected the hop, must be the one sending the message
+            from: PeerKeyLocation,
+            target: PeerKeyLocation,
+            htl: usize,
+            samples: Vec<PeerSample>,
+        },
+        /// Samples collected during the walk, returned to the requester.
+        ReturnSample {
+            id: Transaction,
+            target: PeerKeyLocation,
+            samples: Vec<PeerSample>,
+        },
+    }
+
+    impl InnerMessage for SampleMsg {
+        fn id(&self) -> &Transaction {
+            match self {
+                Self::RequestSample { id, .. } => id,
+                Self::RandomWalk { id, .. } => id,
+                Self::RejectedHop { id, .. } => id,
+                Self::ReturnSample { id, .. } => id,
+            }
+        }
+    }
+
+    impl SampleMsg {
+        pub fn sender(&self) -> Option<&PeerKeyLocation> {
+            match self {
+                // failures are reported straight back to the requester
+                Self::RandomWalk { requester, .. } => Some(requester),
+                Self::RejectedHop { requester, .. } => Some(requester),
+                _ => None,
+            }
+        }
+
+        /// Neighbour which sent this hop of the walk, as claimed by the message.
+        pub fn hop_sender(&self) -> Option<&PeerKeyLocation> {
+            match self {
+                Self::RandomWalk { from, .. } => Some(from),
+                Self::RejectedHop { from, .. } => Some(from),
+                _ => None,
+            }
+        }
+
+        pub fn target(&self) -> Option<&PeerKeyLocation> {
+            match self {
+                Self::RequestSample { target, .. } => Some(target),
+                Self::RandomWalk { target, .. } => Some(target),
+                Self::RejectedHop { target, .. } => Some(target),
+                Self::ReturnSample { target, .. } => Some(target),
+            }
+        }
+
+        pub fn terminal(&self) -> bool {
+            matches!(self, Self::ReturnSample { .. })
+        }
+    }
+
+    impl Display for SampleMsg {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let id = self.id();
+            match self {
+                Self::RequestSample { .. } => write!(f, "RequestSample(id: {id})"),
+                Self::RandomWalk { .. } => write!(f, "RandomWalk(id: {id})"),
+                Self::RejectedHop { .. } => write!(f, "RejectedHop(id: {id})"),
+                Self::ReturnSample { .. } => write!(f, "ReturnSample(id: {id})"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample(location: f64, version: &str) -> PeerSample {
+        PeerSample {
+            location: Location::new(location),
+            version: version.to_owned(),
+            connections: 4,
+        }
+    }
+
+    #[test]
+    fn estimate_from_samples() {
+        let samples = [
+            sample(0.1, "0.1.0"),
+            sample(0.2, "0.1.0"),
+            sample(0.3, "0.2.0"),
+            sample(0.1, "0.1.0"),
+        ];
+        let network = estimate(&samples, Duration::from_millis(500));
+        assert_eq!(network.size, Some(6));
+        assert_eq!(network.versions.get("0.1.0"), Some(&3));
+        assert_eq!(network.versions.get("0.2.0"), Some(&1));
+        assert_eq!(network.hop_latency, Duration::from_millis(100));
+
+        let network = estimate(&samples[..3], Duration::from_millis(400));
+        assert_eq!(network.size, None);
+    }
+
+    #[test]
+    fn rate_limit_hops() {
+        let limiter = SamplingRateLimiter::default();
+        let (neighbour, other) = (PeerKey::random(), PeerKey::random());
+        for _ in 0..MAX_HOPS_PER_NEIGHBOUR {
+            assert!(limiter.allow(&neighbour));
+        }
+        assert!(!limiter.allow(&neighbour));
+        assert!(limiter.allow(&other));
+    }
+
+    #[test]
+    fn degree_corrected_hops() {
+        // hops towards peers with fewer connections are always accepted
+        assert!(accept_hop(8, 4));
+        assert!(accept_hop(4, 4));
+        let accepted = (0..10_000).filter(|_| accept_hop(2, 8)).count();
+        assert!((2000..3000).contains(&accepted), "accepted {accepted}");
+    }
+}
@@ -0,0 +1,632 @@
+//! Seeding the contract cache of a node which just joined the ring.
+//!
+//! Instead of waiting to passively accumulate contract states as requests are routed
+//! through it, a new node requests a snapshot from its closest neighbour of all the contracts
+//! the neighbour is caching which are now closer to the new node than to the neighbour itself.
+//! The snapshot is streamed in chunks, up to a max size, and every entry is verified by the
+//! requester before being cached: the contract must fall within the requested range and its
+//! state must pass the contract validation, otherwise the entry is discarded.
+//!
+//! Snapshots are only served to connected peers, for the location they joined the ring with,
+//! and at most once per peer every `SNAPSHOT_INTERVAL`. Since they are streamed in chunks,
+//! only neighbours sharing the [`Capabilities::STREAMING`] capability are asked for one.
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use locutus_runtime::{ContractContainer, ContractKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::PEER_TIMEOUT,
+    contract::{ContractHandlerEvent, StoreResponse},
+    message::{InnerMessage, Message, Transaction, TxType},
+    node::{Capabilities, ConnectionBridge, ConnectionError, OpManager, PeerKey},
+    operations::{op_trait::Operation, OpInitialization},
+    ring::{Location, PeerKeyLocation, RingError},
+    WrappedState,
+};
+
+use super::{OpEnum, OpError, OperationResult};
+
+pub(crate) use self::messages::SeedMsg;
+
+/// Approximate max size of the entries transferred in a single chunk; entries bigger
+/// than this are sent in a chunk of their own.
+const SNAPSHOT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Approximate max size of the entries transferred in a single snapshot.
+const MAX_SNAPSHOT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Min time between two snapshots sent to the same peer.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Number of tracked requesters above which expired entries are cleaned up.
+const MAX_TRACKED_REQUESTERS: usize = 1024;
+
+#[derive(Debug)]
+pub(crate) struct SeedOp {
+    id: Transaction,
+    state: Option<SeedState>,
+    _ttl: Duration,
+}
+
+impl<CErr, CB: ConnectionBridge> Operation<CErr, CB> for SeedOp
+where
+    CErr: std::error::Error + Send,
+{
+    type Message = SeedMsg;
+    type Error = OpError<CErr>;
+
+    fn load_or_init(
+        op_storage: &OpManager<CErr>,
+        msg: &Self::Message,
+    ) -> Result<OpInitialization<Self>, OpError<CErr>> {
+        let sender = msg.sender().map(|peer_key_loc| peer_key_loc.peer);
+        let id = *msg.id();
+
+        let result = match op_storage.pop(msg.id()) {
+            Some(OpEnum::Seed(seed_op)) => {
+                // received a chunk of a requested snapshot
+                Ok(OpInitialization {
+                    op: seed_op,
+                    sender,
+                })
+            }
+            Some(_) => return Err(OpError::OpNotPresent(id)),
+            None => {
+                // a new peer requested a snapshot to this peer
+                Ok(OpInitialization {
+                    op: Self {
+                        state: Some(SeedState::ReceivedRequest),
+                        id,
+                        _ttl: PEER_TIMEOUT,
+                    },
+                    sender,
+                })
+            }
+        };
+        result
+    }
+
+    fn id(&self) -> &Transaction {
+        &self.id
+    }
+
+    fn process_message<'a>(
+        self,
+        conn_manager: &'a mut CB,
+        op_storage: &'a OpManager<CErr>,
+        input: Self::Message,
+    ) -> Pin<Box<dyn Future<Output = Result<OperationResult, Self::Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let return_msg;
+            let new_state;
+
+            match input {
+                SeedMsg::RequestSeed { id, target } => {
+                    // fast tracked from the request_seed func
+                    debug_assert!(matches!(
+                        self.state,
+                        Some(SeedState::AwaitingSnapshot { .. })
+                    ));
+                    new_state = self.state;
+                    return_msg = Some(SeedMsg::RequestSnapshot {
+                        id,
+                        requester: op_storage.ring.own_location(),
+                        target,
+                    });
+                }
+                SeedMsg::RequestSnapshot {
+                    id,
+                    requester,
+                    target,
+                } => {
+                    if !matches!(self.state, Some(SeedState::ReceivedRequest)) {
+                        return Err(OpError::InvalidStateTransition(self.id));
+                    }
+                    // the range is computed from the location the requester joined the ring
+                    // with, not from the one claimed in the request
+                    let location = op_storage
+                        .ring
+                        .location_of(&requester.peer)
+                        .ok_or(ConnectionError::LocationUnknown)?;
+                    if !op_storage.snapshot_allowed(&requester.peer) {
+                        tracing::debug!("Rate limited snapshot request from {}", requester.peer);
+                        return Err(OpError::RateLimited(id));
+                    }
+                    let requester = PeerKeyLocation {
+                        peer: requester.peer,
+                        location: Some(location),
+                    };
+                    let keys = op_storage.ring.contracts_closer_to(&location);
+                    tracing::debug!(
+                        "Sending snapshot of up to {} contracts to peer {}",
+                        keys.len(),
+                        requester.peer
+                    );
+                    let mut chunks = SnapshotChunks::default();
+                    let mut chunk = 0;
+                    for key in keys {
+                        if chunks.is_full() {
+                            tracing::debug!(
+                                "Snapshot for peer {} truncated at {MAX_SNAPSHOT_SIZE} bytes",
+                                requester.peer
+                            );
+                            break;
+                        }
+                        let Some(entry) = fetch_entry(op_storage, key).await? else {
+                            continue;
+                        };
+                        if let Some(entries) = chunks.push(entry) {
+                            let msg = SeedMsg::SnapshotChunk {
+                                id,
+                                sender: target,
+                                target: requester,
+                                chunk,
+                                last: false,
+                                entries,
+                            };
+                            conn_manager.send(&requester.peer, msg.into()).await?;
+                            chunk += 1;
+                        }
+                    }
+                    new_state = None;
+                    return_msg = Some(SeedMsg::SnapshotChunk {
+                        id,
+                        sender: target,
+                        target: requester,
+                        chunk,
+                        last: true,
+                        entries: chunks.finish(),
+                    });
+                }
+                SeedMsg::SnapshotChunk {
+                    sender,
+                    chunk,
+                    last,
+                    entries,
+                    ..
+                } => match self.state {
+                    Some(SeedState::AwaitingSnapshot {
+                        mut received,
+                        mut total,
+                        mut cached,
+                        mut rejected,
+                    }) => {
+                        if received.insert(chunk) {
+                            let own_location = op_storage
+                                .ring
+                                .own_location()
+                                .location
+                                .ok_or(ConnectionError::LocationUnknown)?;
+                            let sender_location = op_storage
+                                .ring
+                                .location_of(&sender.peer)
+                                .ok_or(ConnectionError::LocationUnknown)?;
+                            for entry in entries {
+                                let key = entry.contract.key();
+                                let location = Location::from(&key);
+                                if location.distance(own_location)
+                                    >= location.distance(sender_location)
+                                {
+                                    tracing::debug!("Contract {key} out of the requested range");
+                                    rejected += 1;
+                                } else if cache_entry(op_storage, entry).await? {
+                                    op_storage.ring.contract_cached(&key);
+                                    cached += 1;
+                                } else {
+                                    rejected += 1;
+                                }
+                            }
+                        }
+                        if last {
+                            total = Some(chunk + 1);
+                        }
+                        return_msg = None;
+                        if total.is_some_and(|total| received.len() >= total) {
+                            tracing::info!(
+                                "Seeded cache from peer {}: {cached} contracts cached, {rejected} rejected",
+                                sender.peer
+                            );
+                            new_state = None;
+                        } else {
+                            new_state = Some(SeedState::AwaitingSnapshot {
+                                received,
+                                total,
+                                cached,
+                                rejected,
+                            });
+                        }
+                    }
+                    _ => return Err(OpError::InvalidStateTransition(self.id)),
+                },
+            }
+
+            build_op_result(self.id, new_state, return_msg, self._ttl)
+        })
+    }
+}
+
+fn build_op_result<CErr: std::error::Error>(
+    id: Transaction,
+    state: Option<SeedState>,
+    msg: Option<SeedMsg>,
+    ttl: Duration,
+) -> Result<OperationResult, OpError<CErr>> {
+    let output_op = state.map(|state| SeedOp {
+        id,
+        state: Some(state),
+        _ttl: ttl,
+    });
+    Ok(OperationResult {
+        return_msg: msg.map(Message::from),
+        state: output_op.map(OpEnum::Seed),
+    })
+}
+
+/// Fetches a cached contract and its current state from the contract handler.
+async fn fetch_entry<CErr>(
+    op_storage: &OpManager<CErr>,
+    key: ContractKey,
+) -> Result<Option<SnapshotEntry>, OpError<CErr>>
+where
+    CErr: std::error::Error,
+{
+    match op_storage
+        .notify_contract_handler(ContractHandlerEvent::FetchQuery {
+            key: key.clone(),
+            fetch_contract: true,
+        })
+        .await?
+    {
+        ContractHandlerEvent::FetchResponse {
+            response:
+                Ok(StoreResponse {
+                    state: Some(state),
+                    contract: Some(contract),
+                }),
+            ..
+        } => Ok(Some(SnapshotEntry { contract, state })),
+        ContractHandlerEvent::FetchResponse { .. } => {
+            tracing::debug!("Contract {key} not available, skipping from snapshot");
+            Ok(None)
+        }
+        _ => Err(OpError::UnexpectedOpState),
+    }
+}
+
+/// Stores a snapshot entry, returns false if the state was rejected by the contract.
+async fn cache_entry<CErr>(
+    op_storage: &OpManager<CErr>,
+    entry: SnapshotEntry,
+) -> Result<bool, OpError<CErr>>
+where
+    CErr: std::error::Error,
+{
+    let key = entry.contract.key();
+    match op_storage
+        .notify_contract_handler(ContractHandlerEvent::Cache(entry.contract))
+        .await?
+    {
+        ContractHandlerEvent::CacheResult(Ok(())) => {}
+        ContractHandlerEvent::CacheResult(Err(err)) => {
+            tracing::warn!("Failed storing contract {key} from snapshot: {err}");
+            return Ok(false);
+        }
+        _ => return Err(OpError::UnexpectedOpState),
+    }
+    // the state is validated against the contract before being stored
+    match op_storage
+        .notify_contract_handler(ContractHandlerEvent::CacheState {
+            key: key.clone(),
+            params: entry.contract.params(),
+            state: entry.state,
+        })
+        .await?
+    {
+        ContractHandlerEvent::CacheStateResult(Ok(true)) => Ok(true),
+        ContractHandlerEvent::CacheStateResult(Ok(false)) => {
+            tracing::warn!("Invalid state for contract {key} in snapshot");
+            Ok(false)
+        }
+        ContractHandlerEvent::CacheStateResult(Err(err)) => {
+            tracing::warn!("Failed storing state for contract {key} from snapshot: {err}");
+            Ok(false)
+        }
+        _ => Err(OpError::UnexpectedOpState),
+    }
+}
+
+/// Splits a snapshot into chunks as its entries are fetched, up to `MAX_SNAPSHOT_SIZE`.
+#[derive(Default)]
+struct SnapshotChunks {
+    current: Vec<SnapshotEntry>,
+    current_size: usize,
+    total_size: usize,
+}
+
+impl SnapshotChunks {
+    /// Whether the snapshot reached its max size, no more entries should be added.
+    fn is_full(&self) -> bool {
+        self.total_size >= MAX_SNAPSHOT_SIZE
+    }
+
+    /// Adds an entry, returns the previous chunk once it is complete. Entries bigger
+    /// than a chunk are sent in a chunk of their own.
+    fn push(&mut self, entry: SnapshotEntry) -> Option<Vec<SnapshotEntry>> {
+        let size = entry.size();
+        let complete = if !self.current.is_empty() && self.current_size + size > SNAPSHOT_CHUNK_SIZE
+        {
+            self.current_size = 0;
+            Some(std::mem::take(&mut self.current))
+        } else {
+            None
+        };
+        self.current_size += size;
+        self.total_size += size;
+        self.current.push(entry);
+        complete
+    }
+
+    /// The last chunk, sent even if empty so the requester knows the snapshot is done.
+    fn finish(self) -> Vec<SnapshotEntry> {
+        self.current
+    }
+}
+
+/// Tracks when each peer was last sent a snapshot.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotRateLimiter {
+    served: DashMap<PeerKey, Instant>,
+}
+
+impl SnapshotRateLimiter {
+    /// Returns whether a new snapshot can be sent to the peer, accounting for it if so.
+    pub fn allow(&self, peer: &PeerKey) -> bool {
+        let now = Instant::now();
+        if self.served.len() > MAX_TRACKED_REQUESTERS {
+            self.served
+                .retain(|_, served| now.duration_since(*served) < SNAPSHOT_INTERVAL);
+        }
+        let mut allowed = true;
+        self.served
+            .entry(*peer)
+            .and_modify(|served| {
+                if now.duration_since(*served) < SNAPSHOT_INTERVAL {
+                    allowed = false;
+                } else {
+                    *served = now;
+                }
+            })
+            .or_insert(now);
+        allowed
+    }
+}
+
+pub(crate) fn start_op(peer: &PeerKey) -> SeedOp {
+    let id = Transaction::new(<SeedMsg as TxType>::tx_type_id(), peer);
+    SeedOp {
+        id,
+        state: Some(SeedState::PrepareRequest { id }),
+        _ttl: PEER_TIMEOUT,
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum SeedState {
+    /// Prepare the request for a snapshot.
+    PrepareRequest { id: Transaction },
+    /// Received a request for a snapshot from a new peer.
+    ReceivedRequest,
+    /// Awaiting for the chunks of the snapshot.
+    AwaitingSnapshot {
+        received: BTreeSet<usize>,
+        /// known once the last chunk is received
+        total: Option<usize>,
+        cached: usize,
+        rejected: usize,
+    },
+}
+
+/// Request a snapshot of the contracts this node is now responsible for to its closest neighbour
+/// able to stream it.
+pub(crate) async fn request_seed<CErr>(
+    op_storage: &OpManager<CErr>,
+    seed_op: SeedOp,
+) -> Result<(), OpError<CErr>>
+where
+    CErr: std::error::Error,
+{
+    match seed_op.state {
+        Some(SeedState::PrepareRequest { id }) => {
+            let own_location = op_storage
+                .ring
+                .own_location()
+                .location
+                .ok_or(ConnectionError::LocationUnknown)?;
+            let target = op_storage
+                .ring
+                .routing(&own_location, None, op_storage.ring.num_connections(), &[])
+                .into_iter()
+                .find(|peer| {
+                    op_storage
+                        .ring
+                        .shared_capabilities(&peer.peer)
+                        .contains(Capabilities::STREAMING)
+                })
+                .ok_or(RingError::EmptyRing)?;
+            let msg = SeedMsg::RequestSeed { id, target };
+            let op = SeedOp {
+                id,
+                state: Some(SeedState::AwaitingSnapshot {
+                    received: BTreeSet::new(),
+                    total: None,
+                    cached: 0,
+                    rejected: 0,
+                }),
+                _ttl: seed_op._ttl,
+            };
+            op_storage
+                .notify_op_change(Message::from(msg), OpEnum::Seed(op))
+                .await?;
+        }
+        _ => return Err(OpError::InvalidStateTransition(seed_op.id)),
+    }
+    Ok(())
+}
+
+/// A cached contract and its state, as transferred in a snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SnapshotEntry {
+    contract: ContractContainer,
+    state: WrappedState,
+}
+
+impl SnapshotEntry {
+    fn size(&self) -> usize {
+        self.contract.data().len() + self.state.size()
+    }
+}
+
+mod messages {
+    use super::*;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) enum SeedMsg {
+        /// Internal node instruction to request a snapshot.
+        RequestSeed {
+            id: Transaction,
+            target: PeerKeyLocation,
+        },
+        /// Request for the contracts closer to the requester than to the target.
+        RequestSnapshot {
+            id: Transaction,
+            requester: PeerKeyLocation,
+            target: PeerKeyLocation,
+        },
+        /// A chunk of the requested snapshot.
+        SnapshotChunk {
+            id: Transaction,
+            sender: PeerKeyLocation,
+            target: PeerKeyLocation,
+            chunk: usize,
+            last: bool,
+            entries: Vec<SnapshotEntry>,
+        },
+    }
+
+    impl InnerMessage for SeedMsg {
+        fn id(&self) -> &Transaction {
+            match self {
+                Self::RequestSeed { id, .. } => id,
+                Self::RequestSnapshot { id, .. } => id,
+                Self::SnapshotChunk { id, .. } => id,
+            }
+        }
+    }
+
+    impl SeedMsg {
+        pub fn sender(&self) -> Option<&PeerKeyLocation> {
+            match self {
+                Self::RequestSnapshot { requester, .. } => Some(requester),
+                Self::SnapshotChunk { sender, .. } => Some(sender),
+                _ => None,
+            }
+        }
+
+        pub fn target(&self) -> Option<&PeerKeyLocation> {
+            match self {
+                Self::RequestSeed { target, .. } => Some(target),
+                Self::RequestSnapshot { target, .. } => Some(target),
+                Self::SnapshotChunk { target, .. } => Some(target),
+            }
+        }
+
+        pub fn terminal(&self) -> bool {
+            matches!(self, Self::SnapshotChunk { last: true, .. })
+        }
+    }
+
+    impl Display for SeedMsg {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let id = self.id();
+            match self {
+                Self::RequestSeed { .. } => write!(f, "RequestSeed(id: {id})"),
+                Self::RequestSnapshot { .. } => write!(f, "RequestSnapshot(id: {id})"),
+                Self::SnapshotChunk { chunk, last, .. } => {
+                    write!(f, "SnapshotChunk(id: {id}, chunk: {chunk}, last: {last})")
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use locutus_runtime::{ContractCode, Parameters, WasmAPIVersion, WrappedContract};
+
+    use super::*;
+
+    fn entry(size: usize) -> SnapshotEntry {
+        let code = Arc::new(ContractCode::from(vec![0; size]));
+        let contract = WrappedContract::new(code, Parameters::from(vec![]));
+        SnapshotEntry {
+            contract: ContractContainer::Wasm(WasmAPIVersion::V1(contract)),
+            state: WrappedState::new(vec![0; size]),
+        }
+    }
+
+    fn into_chunks(entries: Vec<SnapshotEntry>) -> Vec<usize> {
+        let mut chunks = SnapshotChunks::default();
+        let mut sizes = vec![];
+        for entry in entries {
+            sizes.extend(chunks.push(entry).map(|chunk| chunk.len()));
+        }
+        sizes.push(chunks.finish().len());
+        sizes
+    }
+
+    #[test]
+    fn split_snapshot_in_chunks() {
+        // each entry takes half a chunk
+        let size = SNAPSHOT_CHUNK_SIZE / 4;
+        let chunks = into_chunks(vec![entry(size), entry(size), entry(size)]);
+        assert_eq!(chunks, vec![2, 1]);
+
+        // oversized entries still get transferred in a chunk of their own
+        let chunks = into_chunks(vec![entry(SNAPSHOT_CHUNK_SIZE), entry(1)]);
+        assert_eq!(chunks, vec![1, 1]);
+
+        // an empty snapshot is still sent
+        assert_eq!(into_chunks(vec![]), vec![0]);
+    }
+
+    #[test]
+    fn snapshot_size_limit() {
+        let mut chunks = SnapshotChunks::default();
+        let mut sent = 0;
+        while !chunks.is_full() {
+            sent += chunks
+                .push(entry(SNAPSHOT_CHUNK_SIZE / 2))
+                .map_or(0, |c| c.len());
+        }
+        sent += chunks.finish().len();
+        assert_eq!(sent, MAX_SNAPSHOT_SIZE / SNAPSHOT_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn rate_limit_snapshots() {
+        let limiter = SnapshotRateLimiter::default();
+        let (peer, other) = (PeerKey::random(), PeerKey::random());
+        assert!(limiter.allow(&peer));
+        assert!(!limiter.allow(&peer));
+        assert!(limiter.allow(&other));
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use locutus_runtime::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -8,19 +10,30 @@ use serde::{Deserialize, Serialize};
 use crate::operations::op_trait::Operation;
 use crate::operations::OpInitialization;
 use crate::{
-    config::PEER_TIMEOUT,
+    config::{CONFIG, PEER_TIMEOUT},
     contract::ContractError,
     message::{Message, Transaction, TxType},
     node::{ConnectionBridge, OpManager, PeerKey},
     ring::{PeerKeyLocation, RingError},
+    util::ExponentialBackoff,
 };
 
-use super::{OpEnum, OpError, OperationResult};
+use super::{get, OpEnum, OpError, OperationResult};
 
 pub(crate) use self::messages::SubscribeMsg;
 
-const MAX_RETRIES: usize = 10;
+/// Maximum number of hops performed while seeking a peer caching the contract to subscribe to.
+const MAX_SUBSCRIBE_HOPS: usize = 10;
 
+/// How often this node checks whether any of its subscriptions must be renewed, and
+/// retries renewals which failed.
+const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the state of every subscribed contract is fetched again, to apply any updates
+/// missed while this node was disconnected or the notifications were dropped.
+const ANTI_ENTROPY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
 pub(crate) struct SubscribeOp {
     id: Transaction,
     state: Option<SubscribeState>,
@@ -115,6 +128,7 @@ where
                                 key: key.clone(),
                                 id,
                                 subscribed: false,
+                                lease: Duration::ZERO,
                                 sender,
                                 target: subscriber,
                             })),
@@ -126,32 +140,50 @@ where
                         tracing::info!("Contract {} not found while processing info", key);
                         tracing::info!("Trying to found the contract from another node");
 
-                        let new_target =
-                            op_storage.ring.closest_caching(&key, 1, &[sender.peer])[0];
                         let new_htl = htl + 1;
-
-                        if new_htl > MAX_RETRIES {
+                        if new_htl > MAX_SUBSCRIBE_HOPS {
                             return Ok(return_err());
                         }
 
                         let mut new_skip_list = skip_list.clone();
                         new_skip_list.push(target.peer);
 
-                        // Retry seek node when the contract to subscribe has not been found in this node
-                        conn_manager
-                            .send(
-                                &new_target.peer,
-                                (SubscribeMsg::SeekNode {
-                                    id,
-                                    key: key.clone(),
-                                    subscriber,
-                                    target: new_target,
-                                    skip_list: new_skip_list.clone(),
-                                    htl: new_htl,
-                                })
-                                .into(),
-                            )
-                            .await?;
+                        // Retry seek node when the contract to subscribe has not been found in this node,
+                        // trying with the next closest peer if one is unreachable
+                        let policy = CONFIG.retry.operation;
+                        let mut unreachable = vec![sender.peer];
+                        loop {
+                            let Some(new_target) = op_storage
+                                .ring
+                                .closest_caching(&key, 1, &unreachable)
+                                .into_iter()
+                                .next()
+                            else {
+                                return Ok(return_err());
+                            };
+                            let seek = SubscribeMsg::SeekNode {
+                                id,
+                                key: key.clone(),
+                                subscriber,
+                                target: new_target,
+                                skip_list: new_skip_list.clone(),
+                                htl: new_htl,
+                            };
+                            match conn_manager.send(&new_target.peer, seek.into()).await {
+                                Ok(()) => break,
+                                Err(err) => {
+                                    let err = OpError::<CErr>::from(err);
+                                    if !policy.should_retry(unreachable.len() - 1, err.class()) {
+                                        return Err(err);
+                                    }
+                                    tracing::warn!(
+                                        "Failed forwarding subscribe request to {}: {err}",
+                                        new_target.peer
+                                    );
+                                    unreachable.push(new_target.peer);
+                                }
+                            }
+                        }
                     } else if op_storage.ring.add_subscriber(&key, subscriber).is_err() {
                         // max number of subscribers for this contract reached
                         return Ok(return_err());
@@ -172,6 +204,7 @@ where
                                 id,
                                 key,
                                 subscribed: true,
+                                lease: op_storage.ring.subscription_lease(),
                             });
                         }
                         _ => return Err(OpError::InvalidStateTransition(self.id)),
@@ -183,6 +216,7 @@ where
                     sender,
                     target: _,
                     id,
+                    ..
                 } => {
                     tracing::warn!(
                         "Contract `{}` not found at potential subscription provider {}",
@@ -196,11 +230,17 @@ where
                             retries,
                             ..
                         }) => {
-                            if retries < MAX_RETRIES {
+                            // the provider didn't have the contract or can't take more subscribers
+                            let policy = CONFIG.retry.operation;
+                            let failure =
+                                OpError::from(ContractError::ContractNotFound(key.clone()));
+                            if policy.should_retry(retries, failure.class()) {
                                 skip_list.push(sender.peer);
+                                // the provider may be unreachable due to an outage upstream,
+                                // so try with one in another failure domain first
                                 if let Some(target) = op_storage
                                     .ring
-                                    .closest_caching(&key, 1, skip_list.as_slice())
+                                    .closest_caching_across_domains(&key, 1, skip_list.as_slice())
                                     .into_iter()
                                     .next()
                                 {
@@ -220,8 +260,10 @@ where
                                     skip_list,
                                     retries: retries + 1,
                                 });
-                            } else {
+                            } else if policy.is_retryable(failure.class()) {
                                 return Err(OpError::MaxRetriesExceeded(id, "sub".to_owned()));
+                            } else {
+                                return Err(failure);
                             }
                         }
                         _ => return Err(OpError::InvalidStateTransition(self.id)),
@@ -233,13 +275,14 @@ where
                     sender,
                     target: _,
                     id: _,
+                    lease,
                 } => {
                     tracing::warn!(
                         "Subscribed to `{}` not found at potential subscription provider {}",
                         key,
                         sender.peer
                     );
-                    op_storage.ring.add_subscription(key);
+                    op_storage.ring.add_subscription(key, sender, lease);
 
                     match self.state {
                         Some(SubscribeState::AwaitingResponse { .. }) => {
@@ -276,7 +319,30 @@ fn build_op_result<CErr: std::error::Error>(
 
 pub(crate) fn start_op(key: ContractKey, peer: &PeerKey) -> SubscribeOp {
     let id = Transaction::new(<SubscribeMsg as TxType>::tx_type_id(), peer);
-    let state = Some(SubscribeState::PrepareRequest { id, key });
+    let state = Some(SubscribeState::PrepareRequest {
+        id,
+        key,
+        provider: None,
+    });
+    SubscribeOp {
+        id,
+        state,
+        _ttl: PEER_TIMEOUT,
+    }
+}
+
+/// Renew the subscription to a contract with the peer which granted it.
+pub(crate) fn renewal_op(
+    key: ContractKey,
+    provider: PeerKeyLocation,
+    peer: &PeerKey,
+) -> SubscribeOp {
+    let id = Transaction::new(<SubscribeMsg as TxType>::tx_type_id(), peer);
+    let state = Some(SubscribeState::PrepareRequest {
+        id,
+        key,
+        provider: Some(provider),
+    });
     SubscribeOp {
         id,
         state,
@@ -290,6 +356,8 @@ enum SubscribeState {
     PrepareRequest {
         id: Transaction,
         key: ContractKey,
+        /// peer to subscribe with, otherwise the closest one caching the contract
+        provider: Option<PeerKeyLocation>,
     },
     /// Received a request to subscribe to this network.
     ReceivedRequest,
@@ -310,19 +378,20 @@ where
     CErr: std::error::Error,
 {
     let (target, _id) =
-        if let Some(SubscribeState::PrepareRequest { id, key }) = sub_op.state.clone() {
+        if let Some(SubscribeState::PrepareRequest { id, key, provider }) = sub_op.state.clone() {
             if !op_storage.ring.is_contract_cached(&key) {
                 return Err(OpError::ContractError(ContractError::ContractNotFound(key)));
             }
-            (
-                op_storage
+            let target = match provider {
+                Some(provider) => provider,
+                None => op_storage
                     .ring
                     .closest_caching(&key, 1, &[])
                     .into_iter()
                     .next()
                     .ok_or(RingError::EmptyRing)?,
-                id,
-            )
+            };
+            (target, id)
         } else {
             return Err(OpError::UnexpectedOpState);
         };
@@ -349,6 +418,90 @@ where
     Ok(())
 }
 
+/// Renews the subscriptions of this node before their leases expire, otherwise the
+/// peers caching the contracts would drop this node as a subscriber. Subscribers of this
+/// node which didn't renew their own are dropped meanwhile.
+///
+/// Runs until the node is shut down.
+pub(crate) async fn renew_subscriptions<CErr>(op_storage: Arc<OpManager<CErr>>)
+where
+    CErr: std::error::Error,
+{
+    let mut interval = tokio::time::interval(RENEWAL_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if op_storage.is_shut_down() {
+            break;
+        }
+        op_storage.ring.prune_subscribers();
+        for (key, provider) in op_storage
+            .ring
+            .subscriptions_to_renew(RENEWAL_CHECK_INTERVAL)
+        {
+            tracing::debug!(
+                "Renewing subscription to contract {key} with {}",
+                provider.peer
+            );
+            let op = renewal_op(key.clone(), provider, &op_storage.ring.peer_key);
+            if let Err(err) = request_subscribe(&op_storage, op).await {
+                tracing::warn!("Failed renewing subscription to contract {key}: {err}");
+            }
+        }
+    }
+}
+
+/// Periodically fetches the state of the subscribed contracts, so updates which never
+/// reached this node are eventually applied. Failed syncs are retried following the
+/// anti-entropy retry policy instead of waiting for the next round.
+///
+/// Runs until the node is shut down.
+pub(crate) async fn sync_subscribed_contracts<CErr>(op_storage: Arc<OpManager<CErr>>)
+where
+    CErr: std::error::Error,
+{
+    let policy = CONFIG.retry.anti_entropy;
+    let mut failed: HashMap<ContractKey, (ExponentialBackoff, Instant)> = HashMap::new();
+    let mut last_round: Option<Instant> = None;
+    let mut interval = tokio::time::interval(RENEWAL_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if op_storage.is_shut_down() {
+            break;
+        }
+        let now = Instant::now();
+        let due: Vec<_> = if last_round.map_or(true, |last| now - last >= ANTI_ENTROPY_INTERVAL) {
+            last_round = Some(now);
+            op_storage.ring.subscribed_contracts()
+        } else {
+            failed
+                .iter()
+                .filter(|(_, (_, retry_at))| *retry_at <= now)
+                .map(|(key, _)| key.clone())
+                .collect()
+        };
+        for key in due {
+            let op = get::start_op(key.clone(), false, &op_storage.ring.peer_key);
+            let Err(err) = get::request_get(&op_storage, op).await else {
+                failed.remove(&key);
+                continue;
+            };
+            let (backoff, retry_at) = failed
+                .entry(key.clone())
+                .or_insert_with(|| (policy.backoff(), now));
+            match backoff.next_delay() {
+                Some(delay) if policy.is_retryable(err.class()) => {
+                    tracing::warn!("Failed syncing contract {key}, retrying in {delay:?}: {err}");
+                    *retry_at = now + delay;
+                }
+                _ => {
+                    tracing::warn!("Failed syncing contract {key} until the next round: {err}");
+                    failed.remove(&key);
+                }
+            }
+        }
+    }
+}
+
 mod messages {
     use crate::message::InnerMessage;
     use std::fmt::Display;
@@ -380,6 +533,8 @@ mod messages {
             sender: PeerKeyLocation,
             target: PeerKeyLocation,
             subscribed: bool,
+            /// For how long the subscription lasts unless renewed.
+            lease: Duration,
         },
     }
 
@@ -12,27 +12,38 @@
 
 use std::{
     borrow::Borrow,
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     convert::TryFrom,
     fmt::Display,
     hash::Hasher,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::{
         atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use anyhow::bail;
-use dashmap::{mapref::one::Ref as DmRef, DashMap, DashSet};
-use locutus_runtime::prelude::ContractKey;
+use dashmap::DashMap;
+use locutus_runtime::prelude::{Causality, ContractKey, UpdateData};
+use locutus_stdlib::client_api::{ContractInfo, NodeNotification, NodeQuota, PeerInfo};
 use parking_lot::RwLock;
+use rand::seq::IteratorRandom;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
 use crate::{
-    node::{self, PeerKey},
+    contract::CausalHistories,
+    node::{self, Capabilities, PeerAllowlist, PeerKey},
     NodeConfig,
 };
 
+use self::{contract_cache::ContractCache, hot_spots::HotSpotTracker};
+
+mod contract_cache;
+mod hot_spots;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 /// The location of a peer in the ring. This location allows routing towards the peer.
 pub(crate) struct PeerKeyLocation {
@@ -51,6 +62,35 @@ impl PeerKeyLocation {
     }
 }
 
+/// Part of the network whose outage would disconnect all the peers behind it at once.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum FailureDomain {
+    /// The gateway a peer joined the network through.
+    Gateway(PeerKey),
+    /// The autonomous system of the peer's upstream network.
+    As(u32),
+    /// The network a peer was observed connecting from.
+    Network(IpAddr),
+}
+
+impl FailureDomain {
+    /// The network of the address, roughly the block assigned to a provider: the /16 prefix
+    /// for IPv4 and the /32 one for IPv6.
+    pub fn network_of(addr: IpAddr) -> Self {
+        let network = match addr {
+            IpAddr::V4(ip) => {
+                let [a, b, ..] = ip.octets();
+                IpAddr::V4(Ipv4Addr::new(a, b, 0, 0))
+            }
+            IpAddr::V6(ip) => {
+                let [a, b, ..] = ip.segments();
+                IpAddr::V6(Ipv6Addr::new(a, b, 0, 0, 0, 0, 0, 0))
+            }
+        };
+        FailureDomain::Network(network)
+    }
+}
+
 impl From<PeerKey> for PeerKeyLocation {
     fn from(peer: PeerKey) -> Self {
         PeerKeyLocation {
@@ -71,16 +111,37 @@ pub(crate) struct Ring {
     min_connections: usize,
     connections_by_location: Arc<RwLock<BTreeMap<Location, PeerKeyLocation>>>,
     location_for_peer: Arc<RwLock<BTreeMap<PeerKey, Location>>>,
+    /// since when this node is connected to each peer
+    connected_since: Arc<DashMap<PeerKey, Instant>>,
+    /// failure domain of the connected peers, if it was announced during the handshake
+    failure_domains: Arc<DashMap<PeerKey, FailureDomain>>,
+    /// network the connected peers were observed connecting from
+    observed_domains: Arc<DashMap<PeerKey, FailureDomain>>,
+    own_failure_domain: Arc<RwLock<Option<FailureDomain>>>,
+    /// optional features this node is willing to use with peers supporting them
+    capabilities: Capabilities,
+    /// capabilities shared with each connected peer, negotiated during the handshake
+    peer_capabilities: Arc<DashMap<PeerKey, Capabilities>>,
+    /// if set, only the peers in the allowlist are accepted as connections
+    allowlist: Arc<RwLock<Option<PeerAllowlist>>>,
     /// contracts in the ring cached by this node
-    cached_contracts: DashSet<ContractKey>,
+    cached_contracts: Arc<ContractCache>,
     own_location: Arc<AtomicU64>,
     /// The container for subscriber is a vec instead of something like a hashset
     /// that would allow for blind inserts of duplicate peers subscribing because
     /// of data locality, since we are likely to end up iterating over the whole sequence
     /// of subscribers more often than inserting, and anyways is a relatively short sequence
     /// then is more optimal to just use a vector for it's compact memory layout.
-    subscribers: Arc<DashMap<ContractKey, Vec<PeerKeyLocation>>>,
-    subscriptions: Arc<RwLock<Vec<ContractKey>>>,
+    ///
+    /// Subscriptions are leased, each subscriber is kept alongside the instant its lease
+    /// expires at unless renewed.
+    subscribers: Arc<DashMap<ContractKey, Vec<(PeerKeyLocation, Instant)>>>,
+    /// Contracts this node is subscribed to.
+    subscriptions: Arc<DashMap<ContractKey, Subscription>>,
+    /// For how long subscriptions to contracts cached by this node last unless renewed.
+    subscription_lease: Duration,
+    /// Updates to each contract seen by this node.
+    causal_histories: CausalHistories,
 
     // A peer which has been blacklisted to perform actions regarding a given contract.
     // todo: add blacklist
@@ -88,6 +149,32 @@ pub(crate) struct Ring {
     /// Interim connections ongoing haandshake or successfully open connections
     /// Is important to keep track of this so no more connections are accepted prematurely.
     open_connections: Arc<AtomicUsize>,
+    /// Lifecycle and network events forwarded to the clients subscribed to them.
+    node_events: broadcast::Sender<NodeNotification>,
+    /// When the last warning about reaching each quota was sent to the clients.
+    quota_warnings: Arc<DashMap<NodeQuota, Instant>>,
+    /// Contracts receiving a disproportionate amount of traffic.
+    hot_spots: Arc<HotSpotTracker>,
+}
+
+/// A subscription of this node to a contract cached at an other peer.
+#[derive(Debug, Clone, Copy)]
+struct Subscription {
+    /// Peer which granted the subscription, renewals are sent to it.
+    provider: PeerKeyLocation,
+    renew_at: Instant,
+    expires_at: Instant,
+}
+
+impl Subscription {
+    fn new(provider: PeerKeyLocation, lease: Duration, now: Instant) -> Self {
+        Self {
+            provider,
+            // renew well ahead of the expiration to account for the time the renewal takes
+            renew_at: now + lease / 2,
+            expires_at: now + lease,
+        }
+    }
 }
 
 // /// A data type that represents the fact that a peer has been blacklisted
@@ -106,6 +193,9 @@ impl Ring {
     /// Max number of subscribers for a contract.
     const MAX_SUBSCRIBERS: usize = 10;
 
+    /// Default duration of the subscriptions leased to other peers.
+    const SUBSCRIPTION_LEASE: Duration = Duration::from_secs(10 * 60);
+
     /// Above this number of remaining hops,
     /// randomize which of node a message which be forwarded to.
     const RAND_WALK_ABOVE_HTL: usize = 7;
@@ -114,6 +204,21 @@ impl Ring {
     /// connection of a peer in the network).
     const MAX_HOPS_TO_LIVE: usize = 10;
 
+    /// Max number of node events buffered for lagging subscribers.
+    const NODE_EVENTS_BUFFER: usize = 100;
+
+    /// Min time between warnings about reaching the same quota.
+    const QUOTA_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Max number of contracts cached by this node.
+    const MAX_CACHED_CONTRACTS: usize = 10_000;
+
+    /// Number of peers to which changes are forwarded for regular contracts.
+    const REPLICATION_FACTOR: usize = 1;
+
+    /// Number of peers to which changes are forwarded for hot spot contracts.
+    const HOT_SPOT_REPLICATION_FACTOR: usize = 3;
+
     pub fn new<const CLIENTS: usize>(
         config: &NodeConfig<CLIENTS>,
         gateways: &[PeerKeyLocation],
@@ -147,6 +252,24 @@ impl Ring {
             Self::MAX_CONNECTIONS
         };
 
+        let subscription_lease = config
+            .subscription_lease
+            .unwrap_or(Self::SUBSCRIPTION_LEASE);
+
+        // gateways are the failure domain of the peers joining through them
+        let own_failure_domain = match (config.asn, config.location) {
+            (Some(asn), _) => Some(FailureDomain::As(asn)),
+            (None, Some(_)) => Some(FailureDomain::Gateway(peer_key)),
+            (None, None) => None,
+        };
+
+        // until the allowlist is read from its contract only the gateways are accepted
+        let allowlist = match (&config.peer_allowlist, &config.allowlist_contract) {
+            (Some(allowlist), _) => Some(allowlist.clone()),
+            (None, Some(_)) => Some(PeerAllowlist::new(gateways.iter().map(|gw| gw.peer))),
+            (None, None) => None,
+        };
+
         let ring = Ring {
             rnd_if_htl_above,
             max_hops_to_live,
@@ -154,13 +277,25 @@ impl Ring {
             min_connections,
             connections_by_location: Arc::new(RwLock::new(BTreeMap::new())),
             location_for_peer: Arc::new(RwLock::new(BTreeMap::new())),
-            cached_contracts: DashSet::new(),
+            connected_since: Arc::new(DashMap::new()),
+            failure_domains: Arc::new(DashMap::new()),
+            observed_domains: Arc::new(DashMap::new()),
+            own_failure_domain: Arc::new(RwLock::new(own_failure_domain)),
+            capabilities: config.capabilities,
+            peer_capabilities: Arc::new(DashMap::new()),
+            allowlist: Arc::new(RwLock::new(allowlist)),
+            cached_contracts: Arc::new(ContractCache::new(Self::MAX_CACHED_CONTRACTS)),
             own_location,
             peer_key,
             subscribers: Arc::new(DashMap::new()),
-            subscriptions: Arc::new(RwLock::new(Vec::new())),
+            subscriptions: Arc::new(DashMap::new()),
+            subscription_lease,
+            causal_histories: CausalHistories::default(),
             // contract_blacklist: Arc::new(DashMap::new()),
             open_connections: Arc::new(AtomicUsize::new(0)),
+            node_events: broadcast::channel(Self::NODE_EVENTS_BUFFER).0,
+            quota_warnings: Arc::new(DashMap::new()),
+            hot_spots: Arc::new(HotSpotTracker::default()),
         };
 
         if let Some(loc) = config.location {
@@ -170,7 +305,11 @@ impl Ring {
             ring.update_location(Some(loc));
             for PeerKeyLocation { peer, location } in gateways {
                 // all gateways are aware of each other
-                ring.add_connection((*location).unwrap(), *peer);
+                ring.add_connection(
+                    (*location).unwrap(),
+                    *peer,
+                    Some(FailureDomain::Gateway(*peer)),
+                );
             }
         }
 
@@ -193,16 +332,133 @@ impl Ring {
         self.cached_contracts.contains(key)
     }
 
+    /// Caches the contract at this node, making room by evicting the least recently used one.
     #[inline]
     pub fn contract_cached(&self, key: &ContractKey) {
-        self.cached_contracts.insert(key.clone());
+        if let Some(evicted) = self.cached_contracts.insert(key) {
+            self.contract_evicted(&evicted);
+        }
+    }
+
+    /// Contracts cached by this node which are closer to the given location than to this node.
+    pub fn contracts_closer_to(&self, location: &Location) -> Vec<ContractKey> {
+        let own_location = match self.own_location().location {
+            Some(loc) => loc,
+            None => return vec![],
+        };
+        self.cached_contracts
+            .entries()
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| {
+                let contract_loc = Location::from(key);
+                contract_loc.distance(location) < contract_loc.distance(own_location)
+            })
+            .collect()
+    }
+
+    /// Contracts cached by this node.
+    pub fn cached_contracts(&self) -> Vec<ContractInfo> {
+        self.cached_contracts
+            .entries()
+            .into_iter()
+            .map(|(key, cached_since)| ContractInfo {
+                location: Location::from(&key).as_f64(),
+                key,
+                cached_for: cached_since.elapsed(),
+            })
+            .collect()
+    }
+
+    /// Record a request involving the given contract, used to detect hot spots and to keep
+    /// the contracts in use cached.
+    #[inline]
+    pub fn record_contract_access(&self, key: &ContractKey) {
+        self.hot_spots.record_access(key);
+        self.cached_contracts.touch(key);
+    }
+
+    /// Whether the given contract is receiving a disproportionate amount of traffic,
+    /// either at this node or at any peer which hinted so.
+    #[inline]
+    pub fn is_hot_spot(&self, key: &ContractKey) -> bool {
+        self.hot_spots.is_hot(key)
+    }
+
+    /// Record a hint from an other peer that the given contract is a hot spot.
+    #[inline]
+    pub fn add_hot_spot_hint(&self, key: ContractKey) {
+        self.hot_spots.add_hint(key);
+    }
+
+    /// Number of peers to which changes in the given contract should be forwarded.
+    /// Hot spots are replicated to more peers to smooth their load.
+    pub fn replication_factor(&self, key: &ContractKey) -> usize {
+        if self.is_hot_spot(key) {
+            Self::HOT_SPOT_REPLICATION_FACTOR
+        } else {
+            Self::REPLICATION_FACTOR
+        }
+    }
+
+    /// Causality of a new update to the contract, succeeding all the updates seen so far.
+    pub fn next_causality(&self, key: &ContractKey, update: &UpdateData<'_>) -> Causality {
+        self.causal_histories.next(key, update)
+    }
+
+    /// Records an update to the contract, returns `false` if it had already been seen.
+    pub fn record_causality(&self, key: &ContractKey, causality: &Causality) -> bool {
+        self.causal_histories.record(key, causality)
+    }
+
+    /// Whether the update to the contract has already been applied at this node.
+    pub fn has_seen_causality(&self, key: &ContractKey, causality: &Causality) -> bool {
+        self.causal_histories.has_seen(key, causality)
+    }
+
+    /// Stop caching the given contract at this node.
+    pub fn evict_contract(&self, key: &ContractKey) {
+        if self.cached_contracts.remove(key) {
+            self.contract_evicted(key);
+        }
+    }
+
+    fn contract_evicted(&self, key: &ContractKey) {
+        self.causal_histories.forget(key);
+        self.notify_node_event(NodeNotification::CacheEviction { key: key.clone() });
+    }
+
+    /// Subscribe to the lifecycle and network events of this node.
+    pub fn subscribe_node_events(&self) -> broadcast::Receiver<NodeNotification> {
+        self.node_events.subscribe()
+    }
+
+    pub(crate) fn notify_node_event(&self, event: NodeNotification) {
+        // an error only means that there are no subscribers at the moment
+        let _ = self.node_events.send(event);
+    }
+
+    /// Warns the clients that a quota was reached, at most once per quota and interval.
+    fn warn_quota(&self, quota: NodeQuota, used: usize, limit: usize) {
+        let now = Instant::now();
+        if let Some(warned_at) = self.quota_warnings.get(&quota) {
+            if now.duration_since(*warned_at) < Self::QUOTA_WARNING_INTERVAL {
+                return;
+            }
+        }
+        self.quota_warnings.insert(quota, now);
+        self.notify_node_event(NodeNotification::QuotaWarning { quota, used, limit });
     }
 
     /// Update this node location.
     pub fn update_location(&self, loc: Option<Location>) {
         if let Some(loc) = loc {
-            self.own_location
-                .store(u64::from_le_bytes(loc.0.to_le_bytes()), SeqCst)
+            let previous = self
+                .own_location
+                .swap(u64::from_le_bytes(loc.0.to_le_bytes()), SeqCst);
+            if previous == u64::from_le_bytes((-1f64).to_le_bytes()) {
+                self.notify_node_event(NodeNotification::RingJoined { location: loc.0 });
+            }
         } else {
             self.own_location
                 .store(u64::from_le_bytes((-1f64).to_le_bytes()), SeqCst)
@@ -229,7 +485,11 @@ impl Ring {
     ///
     /// # Panic
     /// Will panic if the node checking for this condition has no location assigned.
-    pub fn should_accept(&self, location: &Location) -> bool {
+    pub fn should_accept(&self, peer: &PeerKey, location: &Location) -> bool {
+        if !self.is_allowed(peer) {
+            tracing::debug!("Peer {peer} is not in the allowlist, not accepting connection");
+            return false;
+        }
         let open_conn = self.open_connections.fetch_add(1, SeqCst) + 1;
         let my_location = &self
             .own_location()
@@ -241,6 +501,7 @@ impl Ring {
         } else if open_conn < self.min_connections {
             true
         } else if open_conn >= self.max_connections {
+            self.warn_quota(NodeQuota::Connections, open_conn - 1, self.max_connections);
             false
         } else {
             my_location.distance(location)
@@ -254,9 +515,17 @@ impl Ring {
         accepted
     }
 
-    pub fn add_connection(&self, loc: Location, peer: PeerKey) {
+    pub fn add_connection(&self, loc: Location, peer: PeerKey, domain: Option<FailureDomain>) {
+        if !self.is_allowed(&peer) {
+            tracing::warn!("Peer {peer} is not in the allowlist, not adding connection");
+            return;
+        }
         let mut cbl = self.connections_by_location.write();
         self.location_for_peer.write().insert(peer, loc);
+        self.connected_since.insert(peer, Instant::now());
+        if let Some(domain) = domain {
+            self.failure_domains.insert(peer, domain);
+        }
         cbl.insert(
             loc,
             PeerKeyLocation {
@@ -264,6 +533,9 @@ impl Ring {
                 location: Some(loc),
             },
         );
+        self.notify_node_event(NodeNotification::PeerConnected {
+            peer: peer.to_string(),
+        });
     }
 
     /// Returns the median distance to other peers for the node. None if there are
@@ -296,6 +568,117 @@ impl Ring {
         self.routing(&Location::from(contract_key), None, n, skip_list)
     }
 
+    /// Like [`Self::closest_caching`], but spreading the peers over as many failure domains
+    /// as possible, so a single gateway outage doesn't take out all of them. The failure
+    /// domains of the peers in the skip list are avoided too.
+    ///
+    /// Peers whose failure domain is unknown are considered to be in a domain of their own,
+    /// see [`Self::failure_domain_of`].
+    /// Only if there are not enough peers in distinct domains the closest remaining ones
+    /// are returned, after the others.
+    pub fn closest_caching_across_domains(
+        &self,
+        contract_key: &ContractKey,
+        n: usize,
+        skip_list: &[PeerKey],
+    ) -> Vec<PeerKeyLocation> {
+        let candidates = self.routing(&Location::from(contract_key), None, usize::MAX, skip_list);
+        let mut used_domains: HashSet<_> = skip_list
+            .iter()
+            .filter_map(|peer| self.failure_domain_of(peer))
+            .collect();
+        let mut picked = Vec::with_capacity(n);
+        let mut same_domain = vec![];
+        for peer in candidates {
+            if picked.len() == n {
+                break;
+            }
+            match self.failure_domain_of(&peer.peer) {
+                Some(domain) if !used_domains.insert(domain) => same_domain.push(peer),
+                _ => picked.push(peer),
+            }
+        }
+        let missing = n - picked.len();
+        picked.extend(same_domain.into_iter().take(missing));
+        picked
+    }
+
+    /// Location of a peer this node is connected to.
+    pub fn location_of(&self, peer: &PeerKey) -> Option<Location> {
+        self.location_for_peer.read().get(peer).copied()
+    }
+
+    /// The network the peer was observed connecting from, which unlike the domain it announced
+    /// can't be forged, so a peer can't push others away by claiming to share their domain.
+    /// The announced domain is only used if the peer address is unknown, e.g. with the
+    /// in-memory transport.
+    pub fn failure_domain_of(&self, peer: &PeerKey) -> Option<FailureDomain> {
+        self.observed_domains
+            .get(peer)
+            .or_else(|| self.failure_domains.get(peer))
+            .map(|domain| *domain)
+    }
+
+    /// Records the address the peer connected from, see [`Self::failure_domain_of`].
+    pub fn record_peer_addr(&self, peer: PeerKey, addr: IpAddr) {
+        self.observed_domains
+            .insert(peer, FailureDomain::network_of(addr));
+    }
+
+    /// Whether connections with the peer are accepted, always unless restricted by an allowlist.
+    pub fn is_allowed(&self, peer: &PeerKey) -> bool {
+        self.allowlist
+            .read()
+            .as_ref()
+            .map_or(true, |allowlist| allowlist.allows(peer))
+    }
+
+    /// Replaces the allowlist, returns the connected peers which are not allowed anymore.
+    pub fn update_allowlist(&self, allowlist: PeerAllowlist) -> Vec<PeerKey> {
+        let disallowed = self
+            .location_for_peer
+            .read()
+            .keys()
+            .filter(|peer| !allowlist.allows(peer))
+            .copied()
+            .collect();
+        *self.allowlist.write() = Some(allowlist);
+        disallowed
+    }
+
+    /// Capabilities of this node, announced to the peers it connects to.
+    pub fn own_capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Keeps the capabilities announced by a connected peer which this node supports too.
+    pub fn record_capabilities(&self, peer: PeerKey, announced: Capabilities) {
+        let shared = self.capabilities.intersection(announced);
+        tracing::debug!("Capabilities shared with peer {}: {}", peer, shared);
+        self.peer_capabilities.insert(peer, shared);
+    }
+
+    /// Capabilities supported by both this node and the given peer.
+    pub fn shared_capabilities(&self, peer: &PeerKey) -> Capabilities {
+        self.peer_capabilities
+            .get(peer)
+            .map(|caps| *caps)
+            .unwrap_or_default()
+    }
+
+    /// The failure domain of this node, announced to the peers it connects to.
+    pub fn own_failure_domain(&self) -> Option<FailureDomain> {
+        *self.own_failure_domain.read()
+    }
+
+    /// Sets the gateway this node joined the network through as its failure domain,
+    /// unless already known.
+    pub fn joined_through(&self, gateway: PeerKey) {
+        self.own_failure_domain
+            .write()
+            .get_or_insert(FailureDomain::Gateway(gateway));
+    }
+
     /// Find the closest number of peers to a given location. Result is returned sorted by proximity.
     pub fn routing(
         &self,
@@ -334,40 +717,173 @@ impl Ring {
             .copied()
     }
 
+    /// Get a peer chosen uniformly at random from the known ring connections,
+    /// excluding the ones in the skip list.
+    pub fn random_connection(&self, skip_list: &[PeerKey]) -> Option<PeerKeyLocation> {
+        self.connections_by_location
+            .read()
+            .values()
+            .filter(|conn| !skip_list.contains(&conn.peer))
+            .choose(&mut rand::thread_rng())
+            .copied()
+    }
+
     /// Will return an error in case the max number of subscribers has been added.
+    ///
+    /// If the peer was already subscribed its lease is renewed instead.
     pub fn add_subscriber(
         &self,
         contract: &ContractKey,
         subscriber: PeerKeyLocation,
     ) -> Result<(), ()> {
+        self.add_subscriber_at(contract, subscriber, Instant::now())
+    }
+
+    fn add_subscriber_at(
+        &self,
+        contract: &ContractKey,
+        subscriber: PeerKeyLocation,
+        now: Instant,
+    ) -> Result<(), ()> {
+        let expires_at = now + self.subscription_lease;
         let mut subs = self
             .subscribers
             .entry(contract.clone())
             .or_insert(Vec::with_capacity(Self::MAX_SUBSCRIBERS));
-        if subs.len() >= Self::MAX_SUBSCRIBERS {
-            return Err(());
-        }
-        if let Err(next_idx) = subs.value_mut().binary_search(&subscriber) {
-            let subs = subs.value_mut();
-            if subs.len() == Self::MAX_SUBSCRIBERS {
+        let subs = subs.value_mut();
+        Self::drop_expired_subscribers(subs, now);
+        match subs.binary_search_by_key(&&subscriber, |(peer, _)| peer) {
+            Ok(idx) => {
+                subs[idx].1 = expires_at;
+            }
+            Err(_) if subs.len() >= Self::MAX_SUBSCRIBERS => {
+                self.warn_quota(NodeQuota::Subscribers, subs.len(), Self::MAX_SUBSCRIBERS);
                 return Err(());
-            } else {
-                subs.insert(next_idx, subscriber);
             }
+            Err(next_idx) => subs.insert(next_idx, (subscriber, expires_at)),
         }
         Ok(())
     }
 
-    /// Add a new subscription for this peer.
-    pub fn add_subscription(&self, contract: ContractKey) {
-        self.subscriptions.write().push(contract);
+    fn drop_expired_subscribers(subs: &mut Vec<(PeerKeyLocation, Instant)>, now: Instant) {
+        subs.retain(|(_, expires_at)| *expires_at > now);
+    }
+
+    /// Drops the subscribers whose lease expired, and the contracts left without subscribers.
+    pub fn prune_subscribers(&self) {
+        self.prune_subscribers_at(Instant::now())
+    }
+
+    fn prune_subscribers_at(&self, now: Instant) {
+        self.subscribers.retain(|_, subs| {
+            Self::drop_expired_subscribers(subs, now);
+            !subs.is_empty()
+        });
+    }
+
+    /// Contracts this peer is subscribed to.
+    pub fn subscribed_contracts(&self) -> Vec<ContractKey> {
+        self.subscriptions.iter().map(|e| e.key().clone()).collect()
+    }
+
+    /// Whether this peer is subscribed to the contract.
+    pub fn is_subscribed(&self, contract: &ContractKey) -> bool {
+        self.subscriptions.contains_key(contract)
+    }
+
+    /// For how long subscriptions granted by this node last unless renewed.
+    pub fn subscription_lease(&self) -> Duration {
+        self.subscription_lease
     }
 
-    pub fn subscribers_of(
+    /// Add a new subscription for this peer to a contract cached at the provider, leased for
+    /// the given duration.
+    pub fn add_subscription(
+        &self,
+        contract: ContractKey,
+        provider: PeerKeyLocation,
+        lease: Duration,
+    ) {
+        self.subscriptions
+            .insert(contract, Subscription::new(provider, lease, Instant::now()));
+    }
+
+    /// Subscriptions of this peer which must be renewed now, with the peer providing each.
+    ///
+    /// The renewal of the returned subscriptions is postponed, so if it fails it is
+    /// retried later on as long as the lease didn't expire; expired subscriptions are dropped.
+    pub fn subscriptions_to_renew(
+        &self,
+        retry_after: Duration,
+    ) -> Vec<(ContractKey, PeerKeyLocation)> {
+        self.subscriptions_to_renew_at(retry_after, Instant::now())
+    }
+
+    fn subscriptions_to_renew_at(
+        &self,
+        retry_after: Duration,
+        now: Instant,
+    ) -> Vec<(ContractKey, PeerKeyLocation)> {
+        let mut to_renew = Vec::new();
+        self.subscriptions.retain(|key, subscription| {
+            if subscription.expires_at <= now {
+                tracing::warn!("Subscription to contract {key} expired before being renewed");
+                return false;
+            }
+            if subscription.renew_at <= now {
+                subscription.renew_at = now + retry_after;
+                to_renew.push((key.clone(), subscription.provider));
+            }
+            true
+        });
+        to_renew
+    }
+
+    /// Peers currently subscribed to the given contract at this node.
+    pub fn subscribers_of(&self, contract: &ContractKey) -> Option<Vec<PeerKeyLocation>> {
+        self.subscribers_of_at(contract, Instant::now())
+    }
+
+    fn subscribers_of_at(
         &self,
         contract: &ContractKey,
-    ) -> Option<DmRef<ContractKey, Vec<PeerKeyLocation>>> {
-        self.subscribers.get(contract)
+        now: Instant,
+    ) -> Option<Vec<PeerKeyLocation>> {
+        let subscribers: Vec<_> = {
+            let mut subs = self.subscribers.get_mut(contract)?;
+            Self::drop_expired_subscribers(subs.value_mut(), now);
+            subs.iter().map(|(peer, _)| *peer).collect()
+        };
+        if subscribers.is_empty() {
+            self.subscribers
+                .remove_if(contract, |_, subs| subs.is_empty());
+            return None;
+        }
+        Some(subscribers)
+    }
+
+    /// Number of subscriptions to contracts kept at this node, including the expired ones
+    /// which were not dropped yet.
+    #[cfg(test)]
+    pub fn num_subscriptions(&self) -> usize {
+        self.subscribers.iter().map(|subs| subs.len()).sum()
+    }
+
+    /// Peers this node is connected to.
+    pub fn connected_peers(&self) -> Vec<PeerInfo> {
+        self.connections_by_location
+            .read()
+            .iter()
+            .map(|(location, peer)| PeerInfo {
+                peer: peer.peer.to_string(),
+                location: location.as_f64(),
+                connected_for: self
+                    .connected_since
+                    .get(&peer.peer)
+                    .map(|since| since.elapsed())
+                    .unwrap_or_default(),
+            })
+            .collect()
     }
 
     pub fn num_connections(&self) -> usize {
@@ -375,20 +891,30 @@ impl Ring {
     }
 
     pub fn prune_connection(&self, peer: PeerKey) {
-        let loc = self.location_for_peer.write().remove(&peer).unwrap();
+        self.observed_domains.remove(&peer);
+        // connections closed before the peer was added to the ring have nothing else to prune
+        let Some(loc) = self.location_for_peer.write().remove(&peer) else {
+            return;
+        };
+        self.connected_since.remove(&peer);
+        self.failure_domains.remove(&peer);
+        self.peer_capabilities.remove(&peer);
         {
             let conns = &mut *self.connections_by_location.write();
             conns.remove(&loc);
         }
         {
             self.subscribers.alter_all(|_, mut subs| {
-                if let Some(pos) = subs.iter().position(|l| l.location == Some(loc)) {
+                if let Some(pos) = subs.iter().position(|(l, _)| l.location == Some(loc)) {
                     subs.swap_remove(pos);
                 }
                 subs
             });
         }
         self.open_connections.fetch_sub(1, SeqCst);
+        self.notify_node_event(NodeNotification::PeerDisconnected {
+            peer: peer.to_string(),
+        });
     }
 }
 
@@ -558,6 +1084,241 @@ mod test {
         assert!(l0.distance(l1) == Distance(0.25));
     }
 
+    #[test]
+    fn node_events_on_connection_changes() {
+        let peer_key: PeerKey = PeerKey::random();
+        let (_, receiver) = channel((0, peer_key));
+        let user_events = MemoryEventsGen::new(receiver, peer_key);
+        let config = NodeConfig::new([Box::new(user_events)]);
+        let ring = Ring::new(&config, &[]).unwrap();
+        let mut events = ring.subscribe_node_events();
+
+        ring.update_location(Some(Location(0.5)));
+        let other = PeerKey::random();
+        ring.add_connection(Location(0.3), other, None);
+        ring.prune_connection(other);
+        // peers which never joined the ring are ignored
+        ring.prune_connection(PeerKey::random());
+
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeNotification::RingJoined { location: 0.5 }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeNotification::PeerConnected {
+                peer: other.to_string()
+            }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeNotification::PeerDisconnected {
+                peer: other.to_string()
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn cache_evictions_and_quota_warnings() {
+        use locutus_runtime::{ContractCode, Parameters};
+
+        let peer_key: PeerKey = PeerKey::random();
+        let (_, receiver) = channel((0, peer_key));
+        let user_events = MemoryEventsGen::new(receiver, peer_key);
+        let config = NodeConfig::new([Box::new(user_events)]);
+        let mut ring = Ring::new(&config, &[]).unwrap();
+        ring.cached_contracts = Arc::new(ContractCache::new(1));
+        let mut events = ring.subscribe_node_events();
+
+        let key = |code: u8| {
+            ContractKey::from((&Parameters::from(vec![]), &ContractCode::from(vec![code])))
+        };
+        let (evicted, cached) = (key(0), key(1));
+        ring.contract_cached(&evicted);
+        ring.contract_cached(&cached);
+        assert!(!ring.is_contract_cached(&evicted));
+        assert!(ring.is_contract_cached(&cached));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeNotification::CacheEviction { key: evicted }
+        );
+
+        // repeated warnings about the same quota are dropped
+        ring.warn_quota(NodeQuota::Connections, 20, 20);
+        ring.warn_quota(NodeQuota::Connections, 20, 20);
+        ring.warn_quota(NodeQuota::Subscribers, 10, 10);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeNotification::QuotaWarning {
+                quota: NodeQuota::Connections,
+                used: 20,
+                limit: 20
+            }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeNotification::QuotaWarning {
+                quota: NodeQuota::Subscribers,
+                used: 10,
+                limit: 10
+            }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn subscriber_leases() {
+        use locutus_runtime::{ContractCode, Parameters};
+
+        let peer_key: PeerKey = PeerKey::random();
+        let (_, receiver) = channel((0, peer_key));
+        let user_events = MemoryEventsGen::new(receiver, peer_key);
+        let mut config = NodeConfig::new([Box::new(user_events)]);
+        config.with_subscription_lease(Duration::from_millis(50));
+        let ring = Ring::new(&config, &[]).unwrap();
+        let key = ContractKey::from((&Parameters::from(vec![]), &ContractCode::from(vec![0])));
+
+        let start = Instant::now();
+        let (subscriber, renewing) = (PeerKeyLocation::random(), PeerKeyLocation::random());
+        ring.add_subscriber_at(&key, subscriber, start).unwrap();
+        ring.add_subscriber_at(&key, renewing, start).unwrap();
+        let renewed_at = start + Duration::from_millis(30);
+        ring.add_subscriber_at(&key, renewing, renewed_at).unwrap();
+        assert_eq!(ring.subscribers_of_at(&key, renewed_at).unwrap().len(), 2);
+
+        let expired_first = start + Duration::from_millis(60);
+        assert_eq!(
+            ring.subscribers_of_at(&key, expired_first).unwrap(),
+            vec![renewing]
+        );
+
+        // contracts left without subscribers are dropped
+        ring.prune_subscribers_at(start + Duration::from_millis(100));
+        assert_eq!(ring.num_subscriptions(), 0);
+        assert!(ring.subscribers.is_empty());
+    }
+
+    #[test]
+    fn renew_subscriptions_with_provider() {
+        use locutus_runtime::{ContractCode, Parameters};
+
+        let peer_key: PeerKey = PeerKey::random();
+        let (_, receiver) = channel((0, peer_key));
+        let user_events = MemoryEventsGen::new(receiver, peer_key);
+        let config = NodeConfig::new([Box::new(user_events)]);
+        let ring = Ring::new(&config, &[]).unwrap();
+        let key = ContractKey::from((&Parameters::from(vec![]), &ContractCode::from(vec![0])));
+        let provider = PeerKeyLocation::random();
+        let (lease, retry_after) = (Duration::from_secs(60), Duration::from_secs(10));
+
+        let start = Instant::now();
+        ring.subscriptions
+            .insert(key.clone(), Subscription::new(provider, lease, start));
+        assert!(ring
+            .subscriptions_to_renew_at(retry_after, start)
+            .is_empty());
+
+        // renewed with the same provider halfway through the lease
+        let halfway = start + lease / 2;
+        assert_eq!(
+            ring.subscriptions_to_renew_at(retry_after, halfway),
+            vec![(key.clone(), provider)]
+        );
+        // the failed renewal is retried later on, while the lease is valid
+        assert!(ring
+            .subscriptions_to_renew_at(retry_after, halfway)
+            .is_empty());
+        assert_eq!(
+            ring.subscriptions_to_renew_at(retry_after, halfway + retry_after)
+                .len(),
+            1
+        );
+        // but dropped once expired
+        assert!(ring
+            .subscriptions_to_renew_at(retry_after, start + lease)
+            .is_empty());
+        assert!(ring.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn spread_replicas_across_domains() {
+        use locutus_runtime::{ContractCode, Parameters};
+
+        let peer_key: PeerKey = PeerKey::random();
+        let (_, receiver) = channel((0, peer_key));
+        let user_events = MemoryEventsGen::new(receiver, peer_key);
+        let config = NodeConfig::new([Box::new(user_events)]);
+        let ring = Ring::new(&config, &[]).unwrap();
+        let key = ContractKey::from((&Parameters::from(vec![]), &ContractCode::from(vec![0])));
+
+        let target = Location::from(&key).as_f64();
+        let (gw_a, gw_b) = (PeerKey::random(), PeerKey::random());
+        let connect = |dist: f64, gateway: PeerKey| {
+            let peer = PeerKey::random();
+            let loc = Location((target + dist) % 1.0);
+            ring.add_connection(loc, peer, Some(FailureDomain::Gateway(gateway)));
+            PeerKeyLocation {
+                peer,
+                location: Some(loc),
+            }
+        };
+        let closest = connect(0.01, gw_a);
+        let second = connect(0.02, gw_a);
+        let third = connect(0.03, gw_b);
+
+        assert_eq!(ring.closest_caching(&key, 2, &[]), vec![closest, second]);
+        assert_eq!(
+            ring.closest_caching_across_domains(&key, 2, &[]),
+            vec![closest, third]
+        );
+        assert_eq!(
+            ring.closest_caching_across_domains(&key, 3, &[]),
+            vec![closest, third, second]
+        );
+        assert_eq!(
+            ring.closest_caching_across_domains(&key, 1, &[closest.peer]),
+            vec![third]
+        );
+
+        // the observed network prevails over the announced domain
+        let fourth = connect(0.005, gw_b);
+        ring.record_peer_addr(closest.peer, Ipv4Addr::new(203, 0, 113, 7).into());
+        ring.record_peer_addr(fourth.peer, Ipv4Addr::new(203, 0, 1, 9).into());
+        ring.record_peer_addr(third.peer, Ipv4Addr::new(198, 51, 100, 1).into());
+        assert_eq!(
+            ring.closest_caching_across_domains(&key, 2, &[]),
+            vec![fourth, second]
+        );
+    }
+
+    #[test]
+    fn only_accept_allowed_peers() {
+        let peer_key: PeerKey = PeerKey::random();
+        let (_, receiver) = channel((0, peer_key));
+        let user_events = MemoryEventsGen::new(receiver, peer_key);
+        let (allowed, other) = (PeerKey::random(), PeerKey::random());
+        let mut config = NodeConfig::new([Box::new(user_events)]);
+        config.with_peer_allowlist(PeerAllowlist::new([allowed]));
+        let ring = Ring::new(&config, &[]).unwrap();
+        ring.update_location(Some(Location(0.5)));
+
+        assert!(!ring.should_accept(&other, &Location(0.4)));
+        ring.add_connection(Location(0.4), other, None);
+        assert_eq!(ring.num_connections(), 0);
+        assert!(ring.should_accept(&allowed, &Location(0.4)));
+        ring.add_connection(Location(0.4), allowed, None);
+        assert_eq!(ring.num_connections(), 1);
+
+        // peers removed from the allowlist are returned so they can be disconnected
+        assert_eq!(
+            ring.update_allowlist(PeerAllowlist::new([other])),
+            vec![allowed]
+        );
+        assert!(ring.is_allowed(&other));
+        assert!(!ring.is_allowed(&allowed));
+    }
+
     #[ignore]
     #[test]
     fn find_closest() {
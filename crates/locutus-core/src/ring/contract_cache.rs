@@ -0,0 +1,141 @@
+//! Contracts cached by this node, evicting the least recently used one once full.
+//!
+//! Every access moves the contract to the back of the recency order, so both accesses and
+//! evictions take logarithmic time regardless of the number of contracts cached.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+
+use locutus_runtime::prelude::ContractKey;
+use parking_lot::Mutex;
+
+#[derive(Debug)]
+struct CachedContract {
+    cached_since: Instant,
+    /// position in the recency order, increasing with every access
+    last_access: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    contracts: HashMap<ContractKey, CachedContract>,
+    /// cached contracts by last access, least recently used first
+    recency: BTreeMap<u64, ContractKey>,
+    next_access: u64,
+}
+
+impl Inner {
+    fn touch(&mut self, key: &ContractKey) -> bool {
+        let Some(cached) = self.contracts.get_mut(key) else {
+            return false;
+        };
+        self.recency.remove(&cached.last_access);
+        cached.last_access = self.next_access;
+        self.recency.insert(self.next_access, key.clone());
+        self.next_access += 1;
+        true
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct ContractCache {
+    inner: Mutex<Inner>,
+    capacity: usize,
+}
+
+impl ContractCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner::default()),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, key: &ContractKey) -> bool {
+        self.inner.lock().contracts.contains_key(key)
+    }
+
+    /// Records an access to the contract, if cached.
+    pub fn touch(&self, key: &ContractKey) {
+        self.inner.lock().touch(key);
+    }
+
+    /// Caches the contract, or records an access to it if it already was. Returns the least
+    /// recently used contract if it was evicted to make room.
+    pub fn insert(&self, key: &ContractKey) -> Option<ContractKey> {
+        let inner = &mut *self.inner.lock();
+        if inner.touch(key) {
+            return None;
+        }
+        let access = inner.next_access;
+        inner.next_access += 1;
+        inner.contracts.insert(
+            key.clone(),
+            CachedContract {
+                cached_since: Instant::now(),
+                last_access: access,
+            },
+        );
+        inner.recency.insert(access, key.clone());
+        if inner.contracts.len() <= self.capacity {
+            return None;
+        }
+        let (_, evicted) = inner.recency.pop_first()?;
+        inner.contracts.remove(&evicted);
+        Some(evicted)
+    }
+
+    /// Stops caching the contract, returns whether it was cached.
+    pub fn remove(&self, key: &ContractKey) -> bool {
+        let inner = &mut *self.inner.lock();
+        match inner.contracts.remove(key) {
+            Some(cached) => {
+                inner.recency.remove(&cached.last_access);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Contracts cached and since when.
+    pub fn entries(&self) -> Vec<(ContractKey, Instant)> {
+        self.inner
+            .lock()
+            .contracts
+            .iter()
+            .map(|(key, cached)| (key.clone(), cached.cached_since))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use locutus_runtime::{ContractCode, Parameters};
+
+    use super::*;
+
+    fn key(code: u8) -> ContractKey {
+        ContractKey::from((&Parameters::from(vec![]), &ContractCode::from(vec![code])))
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let cache = ContractCache::new(2);
+        assert_eq!(cache.insert(&key(0)), None);
+        assert_eq!(cache.insert(&key(1)), None);
+        // accessing the oldest contract keeps it cached
+        cache.touch(&key(0));
+        assert_eq!(cache.insert(&key(2)), Some(key(1)));
+        assert!(cache.contains(&key(0)));
+        // caching again counts as an access
+        assert_eq!(cache.insert(&key(2)), None);
+        assert_eq!(cache.insert(&key(3)), Some(key(0)));
+
+        assert!(cache.remove(&key(2)));
+        assert!(!cache.remove(&key(2)));
+        assert_eq!(cache.insert(&key(4)), None);
+        assert_eq!(cache.entries().len(), 2);
+    }
+}
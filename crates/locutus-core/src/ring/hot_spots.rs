@@ -0,0 +1,239 @@
+//! Detection of contracts receiving a disproportionate amount of traffic at this node.
+//!
+//! Accesses to every contract are tracked over a sliding time window. A contract is
+//! considered a hot spot if its estimated access rate is above a minimum threshold and
+//! above a multiple of the mean access rate of the contracts accessed recently at the node.
+//! The aggregated rate is tracked alongside the per contract ones, so checking for a hot spot
+//! is constant time, while counters of contracts not accessed anymore are evicted periodically.
+//!
+//! Peers also gossip hot spot hints to each other while returning values, so a contract
+//! which is hot somewhere else in the network is treated as hot locally for a while, even
+//! if the local counters have not caught up yet.
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use locutus_runtime::prelude::ContractKey;
+use parking_lot::Mutex;
+
+/// Length of the time window over which accesses are counted.
+const ACCESS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Minimum number of accesses per window for a contract to be considered hot.
+const MIN_HOT_ACCESSES: f64 = 50.0;
+
+/// How many times above the mean access rate a contract has to be to be considered hot.
+const HOT_SPOT_FACTOR: f64 = 4.0;
+
+/// Time during which a hint received from an other peer is honored.
+const HINT_TTL: Duration = Duration::from_secs(120);
+
+/// How often idle counters and expired hints are evicted.
+const EVICTION_INTERVAL: Duration = ACCESS_WINDOW;
+
+#[derive(Debug)]
+struct AccessCounter {
+    window_start: Instant,
+    current: u64,
+    previous: u64,
+}
+
+impl AccessCounter {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= ACCESS_WINDOW * 2 {
+            self.previous = 0;
+            self.current = 0;
+            self.window_start = now;
+        } else if elapsed >= ACCESS_WINDOW {
+            self.previous = self.current;
+            self.current = 0;
+            self.window_start += ACCESS_WINDOW;
+        }
+    }
+
+    /// Estimated number of accesses over the last window, weighting the accesses
+    /// of the previous window by how much it still overlaps with the sliding window.
+    fn rate(&self, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= ACCESS_WINDOW * 2 {
+            return 0.0;
+        } else if elapsed >= ACCESS_WINDOW {
+            let overlap =
+                1.0 - (elapsed - ACCESS_WINDOW).as_secs_f64() / ACCESS_WINDOW.as_secs_f64();
+            return self.current as f64 * overlap;
+        }
+        let overlap = 1.0 - elapsed.as_secs_f64() / ACCESS_WINDOW.as_secs_f64();
+        self.previous as f64 * overlap + self.current as f64
+    }
+
+    /// No accesses were recorded during the last window.
+    fn is_idle(&self, now: Instant) -> bool {
+        now.duration_since(self.window_start) >= ACCESS_WINDOW * 2
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct HotSpotTracker {
+    accesses: DashMap<ContractKey, AccessCounter>,
+    /// Accesses to all the tracked contracts.
+    total: Mutex<AccessCounter>,
+    /// Hot spots gossiped by other peers and when they were received.
+    hints: DashMap<ContractKey, Instant>,
+    /// When idle counters and expired hints must be evicted next.
+    next_eviction: Mutex<Instant>,
+}
+
+impl Default for HotSpotTracker {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            accesses: DashMap::new(),
+            total: Mutex::new(AccessCounter::new(now)),
+            hints: DashMap::new(),
+            next_eviction: Mutex::new(now + EVICTION_INTERVAL),
+        }
+    }
+}
+
+impl HotSpotTracker {
+    /// Record a request involving the given contract at this node.
+    pub fn record_access(&self, key: &ContractKey) {
+        self.record_access_at(key, Instant::now())
+    }
+
+    fn record_access_at(&self, key: &ContractKey, now: Instant) {
+        {
+            let mut counter = self
+                .accesses
+                .entry(key.clone())
+                .or_insert_with(|| AccessCounter::new(now));
+            counter.roll(now);
+            counter.current += 1;
+        }
+        {
+            let mut total = self.total.lock();
+            total.roll(now);
+            total.current += 1;
+        }
+        self.evict_idle(now);
+    }
+
+    /// Record a hint from an other peer that the given contract is a hot spot.
+    pub fn add_hint(&self, key: ContractKey) {
+        let now = Instant::now();
+        self.hints.insert(key, now);
+        self.evict_idle(now);
+    }
+
+    pub fn is_hot(&self, key: &ContractKey) -> bool {
+        self.is_hot_at(key, Instant::now())
+    }
+
+    fn is_hot_at(&self, key: &ContractKey, now: Instant) -> bool {
+        let hint = self.hints.get(key).map(|r| *r.value());
+        if let Some(received) = hint {
+            if now.duration_since(received) < HINT_TTL {
+                return true;
+            }
+        }
+
+        let rate = match self.accesses.get(key) {
+            Some(counter) => counter.rate(now),
+            None => return false,
+        };
+        if rate < MIN_HOT_ACCESSES {
+            return false;
+        }
+        let tracked = self.accesses.len().max(1);
+        let mean = self.total.lock().rate(now) / tracked as f64;
+        rate >= mean * HOT_SPOT_FACTOR
+    }
+
+    /// Drops the counters of contracts not accessed during the last window and the expired
+    /// hints, at most once every `EVICTION_INTERVAL`.
+    fn evict_idle(&self, now: Instant) {
+        {
+            let mut next_eviction = self.next_eviction.lock();
+            if now < *next_eviction {
+                return;
+            }
+            *next_eviction = now + EVICTION_INTERVAL;
+        }
+        self.accesses.retain(|_, counter| !counter.is_idle(now));
+        self.hints
+            .retain(|_, received| now.duration_since(*received) < HINT_TTL);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use locutus_runtime::{ContractCode, Parameters};
+
+    use super::*;
+
+    fn key(byte: u8) -> ContractKey {
+        let params = Parameters::from(vec![byte]);
+        let code = ContractCode::from(vec![byte]);
+        ContractKey::from((&params, &code))
+    }
+
+    #[test]
+    fn detect_hot_spot() {
+        let tracker = HotSpotTracker::default();
+        let (hot, cold) = (key(0), key(1));
+        for n in 0..10u8 {
+            tracker.record_access(&key(n + 2));
+        }
+        for _ in 0..100 {
+            tracker.record_access(&hot);
+        }
+        tracker.record_access(&cold);
+
+        assert!(tracker.is_hot(&hot));
+        assert!(!tracker.is_hot(&cold));
+    }
+
+    #[test]
+    fn honor_hints() {
+        let tracker = HotSpotTracker::default();
+        let hinted = key(0);
+        assert!(!tracker.is_hot(&hinted));
+        tracker.add_hint(hinted.clone());
+        assert!(tracker.is_hot(&hinted));
+    }
+
+    #[test]
+    fn evict_idle_counters() {
+        let tracker = HotSpotTracker::default();
+        let start = Instant::now();
+        for n in 0..10u8 {
+            tracker.record_access_at(&key(n), start);
+        }
+        tracker.add_hint(key(20));
+        assert_eq!(tracker.accesses.len(), 10);
+
+        // once idle for a whole window the counters and expired hints are evicted
+        let later = start + ACCESS_WINDOW * 3;
+        let hot = key(10);
+        for _ in 0..100 {
+            tracker.record_access_at(&hot, later);
+        }
+        assert_eq!(tracker.accesses.len(), 1);
+        assert!(tracker.hints.is_empty());
+        // the rate of the evicted counters does not count towards the mean anymore
+        assert!(!tracker.is_hot_at(&hot, later));
+        for n in 0..10u8 {
+            tracker.record_access_at(&key(n), later);
+        }
+        assert!(tracker.is_hot_at(&hot, later));
+    }
+}
@@ -32,11 +32,101 @@ pub fn set_cleanup_on_exit() -> Result<(), ctrlc::Error> {
     })
 }
 
+/// Broad classes of errors, used to decide whether a failed attempt is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Temporary failures, like connection errors or timeouts.
+    Transient,
+    /// What was requested was not available at the peers contacted so far,
+    /// retrying with other peers may succeed.
+    Unavailable,
+    /// Failures which won't be solved by retrying.
+    Permanent,
+}
+
+/// How failed attempts of a given kind are retried: exponential backoff with random jitter,
+/// capped to a max number of attempts and only for retryable classes of errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    base: Duration,
+    ceiling: Duration,
+    max_attempts: usize,
+    /// Fraction of each delay, in the [0, 1] range, which is randomized.
+    jitter: f64,
+    retry_unavailable: bool,
+}
+
+impl RetryPolicy {
+    const DEFAULT_JITTER: f64 = 0.2;
+
+    pub const fn new(base: Duration, ceiling: Duration, max_attempts: usize) -> Self {
+        RetryPolicy {
+            base,
+            ceiling,
+            max_attempts,
+            jitter: Self::DEFAULT_JITTER,
+            retry_unavailable: true,
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_base_delay(mut self, base: Duration) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn with_max_delay(mut self, ceiling: Duration) -> Self {
+        self.ceiling = ceiling;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Whether failures of the [`ErrorClass::Unavailable`] class are retried.
+    pub fn retry_unavailable(mut self, retry: bool) -> Self {
+        self.retry_unavailable = retry;
+        self
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    pub fn is_retryable(&self, class: ErrorClass) -> bool {
+        match class {
+            ErrorClass::Transient => true,
+            ErrorClass::Unavailable => self.retry_unavailable,
+            ErrorClass::Permanent => false,
+        }
+    }
+
+    /// Whether a new attempt should be made after `attempts` failed attempts.
+    pub fn should_retry(&self, attempts: usize, class: ErrorClass) -> bool {
+        attempts < self.max_attempts && self.is_retryable(class)
+    }
+
+    pub fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff {
+            jitter: self.jitter,
+            ..ExponentialBackoff::new(self.base, self.ceiling, self.max_attempts)
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct ExponentialBackoff {
     attempt: usize,
     max_attempts: usize,
     base: Duration,
     ceiling: Duration,
+    jitter: f64,
 }
 
 impl ExponentialBackoff {
@@ -46,6 +136,7 @@ impl ExponentialBackoff {
             max_attempts,
             base,
             ceiling,
+            jitter: 0.0,
         }
     }
 
@@ -60,15 +151,30 @@ impl ExponentialBackoff {
         }
     }
 
+    /// Record that we made an attempt and return how long to wait before the next one, without
+    /// sleeping. If the max number of attempts was reached returns none.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        (self.attempt < self.max_attempts).then(|| self.next_attempt())
+    }
+
+    /// Start over after a successful attempt.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
     pub fn retries(&self) -> usize {
         self.attempt
     }
 
     fn delay(&self) -> Duration {
-        let mut delay = self.base.saturating_mul(1 << self.attempt);
+        let mut delay = self.base.saturating_mul(1 << self.attempt.min(31));
         if delay > self.ceiling {
             delay = self.ceiling;
         }
+        if self.jitter > 0.0 {
+            let factor = 1.0 - self.jitter * rand::thread_rng().gen::<f64>();
+            delay = delay.mul_f64(factor);
+        }
         delay
     }
 
@@ -189,4 +295,27 @@ pub(crate) mod test {
     }
 
     rnd_bytes!(1024 -> random_bytes_1024);
+
+    #[test]
+    fn retry_policy_backoff() {
+        let policy =
+            RetryPolicy::new(Duration::from_secs(1), Duration::from_secs(5), 4).with_jitter(0.5);
+        let mut backoff = policy.backoff();
+        for attempt in 0..4 {
+            let max = Duration::from_secs(1 << attempt).min(Duration::from_secs(5));
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay <= max && delay >= max / 2, "{delay:?}");
+        }
+        assert_eq!(backoff.next_delay(), None);
+        backoff.reset();
+        assert!(backoff.next_delay().unwrap() <= Duration::from_secs(1));
+
+        assert!(policy.should_retry(3, ErrorClass::Transient));
+        assert!(!policy.should_retry(4, ErrorClass::Transient));
+        assert!(!policy.should_retry(0, ErrorClass::Permanent));
+        assert!(policy.should_retry(0, ErrorClass::Unavailable));
+        assert!(!policy
+            .retry_unavailable(false)
+            .should_retry(0, ErrorClass::Unavailable));
+    }
 }
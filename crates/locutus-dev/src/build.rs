@@ -14,16 +14,24 @@ use tar::Builder;
 use locutus_runtime::{locutus_stdlib::web::WebApp, ContractCode};
 use locutus_stdlib::prelude::WrappedContract;
 
-use crate::{config::BuildToolCliConfig, util::pipe_std_streams, DynError, Error};
+use crate::{
+    config::BuildToolCliConfig,
+    output::{progress, ArtifactsOutput},
+    util::pipe_std_streams,
+    DynError, Error,
+};
 
 const DEFAULT_OUTPUT_NAME: &str = "contract-state";
 const WASI_TARGET: &str = "wasm32-wasi";
 const WASM_TARGET: &str = "wasm32-unknown-unknown";
 
-pub fn build_package(cli_config: BuildToolCliConfig, cwd: &Path) -> Result<(), DynError> {
+pub fn build_package(
+    cli_config: BuildToolCliConfig,
+    cwd: &Path,
+) -> Result<ArtifactsOutput, DynError> {
     let mut config = get_config(cwd)?;
-    compile_contract(&config, &cli_config, cwd)?;
-    match config.contract.c_type.unwrap_or(ContractType::Standard) {
+    let contract = compile_contract(&config, &cli_config, cwd)?;
+    let state = match config.contract.c_type.unwrap_or(ContractType::Standard) {
         ContractType::WebApp => {
             let embedded =
                 if let Some(d) = config.webapp.as_ref().and_then(|a| a.dependencies.as_ref()) {
@@ -35,8 +43,10 @@ pub fn build_package(cli_config: BuildToolCliConfig, cwd: &Path) -> Result<(), D
             build_web_state(&config, embedded, cwd)?
         }
         ContractType::Standard => build_generic_state(&mut config, cwd)?,
-    }
-    Ok(())
+    };
+    Ok(ArtifactsOutput {
+        artifacts: contract.into_iter().chain(state).collect(),
+    })
 }
 
 #[derive(Serialize, Deserialize)]
@@ -100,7 +110,7 @@ fn build_web_state(
     config: &BuildToolConfig,
     embedded_deps: EmbeddedDeps,
     cwd: &Path,
-) -> Result<(), DynError> {
+) -> Result<Option<PathBuf>, DynError> {
     let metadata = if let Some(md) = config.webapp.as_ref().and_then(|a| a.metadata.as_ref()) {
         let mut buf = vec![];
         File::open(md)?.read_to_end(&mut buf)?;
@@ -111,7 +121,7 @@ fn build_web_state(
 
     let mut archive: Builder<Cursor<Vec<u8>>> = Builder::new(Cursor::new(Vec::new()));
     if let Some(web_config) = &config.webapp {
-        println!("Bundling webapp contract state");
+        progress!("Bundling webapp contract state");
         match &web_config.lang {
             SupportedWebLangs::Typescript => {
                 let child = Command::new("npm")
@@ -148,7 +158,7 @@ fn build_web_state(
                             Error::CommandFailed("tsc")
                         })?;
                     pipe_std_streams(child)?;
-                    println!("Compiled input using webpack");
+                    progress!("Compiled input using webpack");
                 } else {
                     let cmd_args: &[&str] =
                         if atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr) {
@@ -167,17 +177,17 @@ fn build_web_state(
                             Error::CommandFailed("tsc")
                         })?;
                     pipe_std_streams(child)?;
-                    println!("Compiled input using tsc");
+                    progress!("Compiled input using tsc");
                 }
             }
             SupportedWebLangs::Javascript => todo!(),
         }
     } else {
-        println!("No webapp config found.");
-        return Ok(());
+        progress!("No webapp config found.");
+        return Ok(None);
     }
 
-    let build_state = |sources: &Sources| -> Result<(), DynError> {
+    let build_state = |sources: &Sources| -> Result<Option<PathBuf>, DynError> {
         let mut found_entry = false;
         if let Some(sources) = &sources.files {
             for src in sources {
@@ -235,14 +245,12 @@ fn build_web_state(
         }
         if !found_entry {
             return Err("didn't find entry point `index.html` in package".into());
-        } else {
-            let state = WebApp::from_data(metadata, archive)?;
-            let packed = state.pack()?;
-            output_artifact(&config.contract.output_dir, &packed, cwd)?;
-            println!("Finished bundling webapp contract state");
         }
-
-        Ok(())
+        let state = WebApp::from_data(metadata, archive)?;
+        let packed = state.pack()?;
+        let output = output_artifact(&config.contract.output_dir, &packed, cwd)?;
+        progress!("Finished bundling webapp contract state");
+        Ok(Some(output))
     };
 
     if let Some(sources) = config
@@ -256,14 +264,17 @@ fn build_web_state(
     }
 }
 
-fn build_generic_state(config: &mut BuildToolConfig, cwd: &Path) -> Result<(), DynError> {
+fn build_generic_state(
+    config: &mut BuildToolConfig,
+    cwd: &Path,
+) -> Result<Option<PathBuf>, DynError> {
     const REQ_ONE_FILE_ERR: &str = "Requires exactly one source file specified for the state.";
 
     let sources = config.state.as_mut().and_then(|s| s.files.as_mut());
     let sources = if let Some(s) = sources {
         s
     } else {
-        return Ok(());
+        return Ok(None);
     };
 
     let output_path = config
@@ -273,14 +284,14 @@ fn build_generic_state(config: &mut BuildToolConfig, cwd: &Path) -> Result<(), D
         .map(Ok)
         .unwrap_or_else(|| get_default_ouput_dir(cwd).map(|p| p.join(DEFAULT_OUTPUT_NAME)))?;
 
-    println!("Bundling contract state");
+    progress!("Bundling contract state");
     let state: PathBuf = (sources.len() == 1)
         .then(|| sources.pop().unwrap())
         .ok_or_else(|| Error::MissConfiguration(REQ_ONE_FILE_ERR.into()))?
         .into();
-    std::fs::copy(cwd.join(state), output_path)?;
-    println!("Finished bundling state");
-    Ok(())
+    std::fs::copy(cwd.join(state), &output_path)?;
+    progress!("Finished bundling state");
+    Ok(Some(output_path))
 }
 
 #[inline]
@@ -290,16 +301,21 @@ fn get_default_ouput_dir(cwd: &Path) -> std::io::Result<PathBuf> {
     Ok(output)
 }
 
-fn output_artifact(output: &Option<PathBuf>, packed: &[u8], cwd: &Path) -> Result<(), DynError> {
+fn output_artifact(
+    output: &Option<PathBuf>,
+    packed: &[u8],
+    cwd: &Path,
+) -> Result<PathBuf, DynError> {
     if let Some(path) = output {
         File::create(path)?.write_all(packed)?;
+        Ok(path.clone())
     } else {
         let default_out_dir = get_default_ouput_dir(cwd)?;
         fs::create_dir_all(&default_out_dir)?;
-        let mut f = File::create(default_out_dir.join(DEFAULT_OUTPUT_NAME))?;
-        f.write_all(packed)?;
+        let path = default_out_dir.join(DEFAULT_OUTPUT_NAME);
+        File::create(&path)?.write_all(packed)?;
+        Ok(path)
     }
-    Ok(())
 }
 
 fn get_config(cwd: &Path) -> Result<BuildToolConfig, DynError> {
@@ -317,17 +333,17 @@ fn compile_contract(
     config: &BuildToolConfig,
     cli_config: &BuildToolCliConfig,
     cwd: &Path,
-) -> Result<(), DynError> {
+) -> Result<Option<PathBuf>, DynError> {
     let work_dir = match config.contract.c_type.unwrap_or(ContractType::Standard) {
         ContractType::WebApp => cwd.join("container"),
         ContractType::Standard => cwd.to_path_buf(),
     };
-    match config.contract.lang {
+    let out_file = match config.contract.lang {
         Some(SupportedContractLangs::Rust) => {
             const RUST_TARGET_ARGS: &[&str] = &["build", "--release", "--target"];
             let target = cli_config.wasi.then(|| WASI_TARGET).unwrap_or(WASM_TARGET);
             if target == WASI_TARGET {
-                println!("Enabling WASI extension");
+                progress!("Enabling WASI extension");
             }
             let cmd_args = if atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr) {
                 RUST_TARGET_ARGS
@@ -343,7 +359,7 @@ fn compile_contract(
                     .collect::<Vec<_>>()
             };
 
-            println!("Compiling contract with rust");
+            progress!("Compiling contract with rust");
             let child = Command::new("cargo")
                 .args(&cmd_args)
                 .current_dir(&work_dir)
@@ -369,13 +385,17 @@ fn compile_contract(
                 get_default_ouput_dir(cwd)?.join(package_name)
             };
             let output = get_contract_with_version(&output_lib, cli_config)?;
-            let mut file = File::create(out_file)?;
+            let mut file = File::create(&out_file)?;
             file.write_all(output.as_slice())?;
+            Some(out_file)
         }
-        None => println!("no lang specified, skipping contract compilation"),
-    }
-    println!("Contract compiled");
-    Ok(())
+        None => {
+            progress!("no lang specified, skipping contract compilation");
+            None
+        }
+    };
+    progress!("Contract compiled");
+    Ok(out_file)
 }
 
 fn get_out_lib(
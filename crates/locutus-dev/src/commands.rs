@@ -1,26 +1,25 @@
-use std::{fs::File, io::Read};
+use std::{fs::File, io::Read, path::PathBuf};
 
 use locutus_core::{
     locutus_runtime::StateDelta, ClientId, Config, Executor, OperationMode, Storage,
 };
 use locutus_runtime::{
-    ContractContainer, ContractInstanceId, ContractStore, Parameters, StateStore,
+    ContractContainer, ContractInstanceId, ContractKey, ContractStore, DelegateStore, Parameters,
+    RelatedContracts, Runtime, SecretsStore, StateStore, ValidateResult, WrappedState,
 };
 use locutus_stdlib::client_api::{ClientRequest, ContractRequest};
 
 use crate::{
     config::{BaseConfig, PutConfig, UpdateConfig},
-    DynError,
+    output::{progress, ContractOutput},
+    DynError, Error,
 };
 
 const MAX_MEM_CACHE: u32 = 10_000_000;
 const DEFAULT_MAX_CONTRACT_SIZE: i64 = 50 * 1024 * 1024;
 
 // #[track_caller]
-pub async fn put(config: PutConfig, other: BaseConfig) -> Result<(), DynError> {
-    if config.release {
-        return Err("Cannot publish contracts in the network yet".into());
-    }
+pub async fn put(config: PutConfig, other: BaseConfig) -> Result<ContractOutput, DynError> {
     let params = if let Some(params) = config.parameters {
         let mut buf = vec![];
         File::open(params)?.read_to_end(&mut buf)?;
@@ -40,39 +39,86 @@ pub async fn put(config: PutConfig, other: BaseConfig) -> Result<(), DynError> {
         Default::default()
     };
 
-    println!("Putting contract {}", contract.key());
+    let key = contract.key();
+    if config.validate {
+        progress!("Validating the state of contract {key}");
+        validate_state(&contract, &state, related_contracts.clone(), &other)?;
+    }
+    if config.release {
+        return Err(Error::Unsupported("publishing contracts in the network").into());
+    }
+    progress!("Putting contract {key}");
     let request = ContractRequest::Put {
         contract,
         state,
         related_contracts,
     }
     .into();
-    execute_command(request, other).await
+    execute_command(request, other).await?;
+    Ok(ContractOutput {
+        contract_key: key.to_string(),
+    })
 }
 
-pub async fn update(config: UpdateConfig, other: BaseConfig) -> Result<(), DynError> {
+pub async fn update(config: UpdateConfig, other: BaseConfig) -> Result<ContractOutput, DynError> {
     if config.release {
-        return Err("Cannot publish contracts in the network yet".into());
+        return Err(Error::Unsupported("updating contracts in the network").into());
     }
-    let key = ContractInstanceId::try_from(config.key)?.into();
-    println!("Updating contract {key}");
+    let key: ContractKey = ContractInstanceId::try_from(config.key)?.into();
+    progress!("Updating contract {key}");
+    let output = ContractOutput {
+        contract_key: key.to_string(),
+    };
     let data = {
         let mut buf = vec![];
         File::open(&config.delta)?.read_to_end(&mut buf)?;
         StateDelta::from(buf).into()
     };
     let request = ContractRequest::Update { key, data }.into();
-    execute_command(request, other).await
+    execute_command(request, other).await?;
+    Ok(output)
+}
+
+/// Runs the validation of the contract against the state locally, without storing anything,
+/// so an invalid put fails before reaching any node.
+fn validate_state(
+    contract: &ContractContainer,
+    state: &WrappedState,
+    related_contracts: RelatedContracts<'static>,
+    other: &BaseConfig,
+) -> Result<(), DynError> {
+    let contract_store = ContractStore::new(contract_data_dir(other), DEFAULT_MAX_CONTRACT_SIZE)?;
+    let mut runtime = Runtime::build(
+        contract_store,
+        DelegateStore::default(),
+        SecretsStore::default(),
+        false,
+    )?;
+    match runtime.prevalidate_put(contract, state, related_contracts) {
+        Ok(ValidateResult::Valid) => Ok(()),
+        Ok(ValidateResult::Invalid) => {
+            Err(Error::Execution("the state is not valid for the contract".to_owned()).into())
+        }
+        Ok(ValidateResult::RequestRelated(_)) => Err(Error::Execution(
+            "validating the state requires related contracts".to_owned(),
+        )
+        .into()),
+        Err(err) => Err(Error::Execution(err.to_string()).into()),
+    }
+}
+
+fn contract_data_dir(other: &BaseConfig) -> PathBuf {
+    other
+        .contract_data_dir
+        .clone()
+        .unwrap_or_else(|| Config::get_conf().config_paths.local_contracts_dir())
 }
 
 async fn execute_command(
     request: ClientRequest<'static>,
     other: BaseConfig,
 ) -> Result<(), DynError> {
-    let data_path = other
-        .contract_data_dir
-        .unwrap_or_else(|| Config::get_conf().config_paths.local_contracts_dir());
-    let contract_store = ContractStore::new(data_path, DEFAULT_MAX_CONTRACT_SIZE)?;
+    let contract_store = ContractStore::new(contract_data_dir(&other), DEFAULT_MAX_CONTRACT_SIZE)?;
     let state_store = StateStore::new(Storage::new().await?, MAX_MEM_CACHE).unwrap();
     let mut executor =
         Executor::new(contract_store, state_store, || {}, OperationMode::Local).await?;
@@ -81,8 +127,8 @@ async fn execute_command(
         .handle_request(ClientId::new(0), request, None)
         .await
         .map_err(|e| match e {
-            either::Either::Right(e) => e,
-            either::Either::Left(_) => unreachable!(),
+            either::Either::Left(e) => Error::Execution(e.to_string()),
+            either::Either::Right(e) => Error::Execution(e.to_string()),
         })?;
 
     Ok(())
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use crate::local_node::LocalNodeCliConfig;
+use crate::{local_node::LocalNodeCliConfig, output::OutputFormat};
 use locutus_core::OperationMode;
 use locutus_stdlib::prelude::Version;
 
@@ -22,6 +22,12 @@ pub struct BaseConfig {
     /// Node operation mode.
     #[clap(value_enum, default_value_t=OperationMode::Local)]
     pub(crate) mode: OperationMode,
+    /// Format of the output of the command.
+    #[clap(long = "output", value_enum, default_value_t = OutputFormat::Text, global = true)]
+    pub output_format: OutputFormat,
+    /// Only print the machine readable output of the command, implies `--output json`.
+    #[clap(long, short, global = true)]
+    pub quiet: bool,
 }
 
 #[derive(clap::Subcommand, Clone)]
@@ -33,6 +39,24 @@ pub enum SubCommand {
     Execute(RunCliConfig),
 }
 
+impl SubCommand {
+    /// Name of the command in the machine readable output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SubCommand::RunLocal(_) => "run-local",
+            SubCommand::Build(_) => "build",
+            SubCommand::New(_) => "new",
+            SubCommand::Publish(_) => "publish",
+            SubCommand::Execute(RunCliConfig {
+                command: NodeCommand::Put(_),
+            }) => "execute put",
+            SubCommand::Execute(RunCliConfig {
+                command: NodeCommand::Update(_),
+            }) => "execute update",
+        }
+    }
+}
+
 /// Node CLI
 ///
 /// This tool allows the execution of commands against the local node
@@ -82,6 +106,10 @@ pub struct PutConfig {
     /// A path to a JSON file listing the related contracts.
     #[clap(long)]
     pub(crate) related_contracts: Option<PathBuf>,
+    /// Validate the initial state against the contract locally before submitting it,
+    /// failing right away if it is not valid.
+    #[clap(long)]
+    pub(crate) validate: bool,
 }
 
 /// Builds and packages a contract.
@@ -7,6 +7,7 @@ pub mod commands;
 pub mod config;
 pub mod local_node;
 pub mod new_pckg;
+pub mod output;
 pub mod util;
 
 type CommandReceiver = tokio::sync::mpsc::Receiver<ClientRequest<'static>>;
@@ -19,4 +20,10 @@ enum Error {
     MissConfiguration(Cow<'static, str>),
     #[error("Command failed: {0}")]
     CommandFailed(&'static str),
+    #[error("Command exited with status: {0}")]
+    CommandExit(std::process::ExitStatus),
+    #[error("Execution failed: {0}")]
+    Execution(String),
+    #[error("Not supported: {0}")]
+    Unsupported(&'static str),
 }
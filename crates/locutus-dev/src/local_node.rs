@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use clap::ArgGroup;
 
+use crate::output::progress;
+
 mod commands;
 mod state;
 mod user_events;
@@ -31,7 +33,7 @@ pub async fn run_local_node_client(
         res = runtime => { res?? }
         res = user_fn => { res? }
     };
-    println!("Shutdown...");
+    progress!("Shutdown...");
     Ok(())
 }
 
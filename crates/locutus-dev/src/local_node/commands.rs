@@ -1,7 +1,10 @@
 use locutus_core::ClientId;
 use locutus_stdlib::client_api::{ClientRequest, ContractRequest, ContractResponse, HostResponse};
 
-use crate::{CommandReceiver, DynError};
+use crate::{
+    output::{progress, report_error},
+    CommandReceiver, DynError,
+};
 
 use super::{state::AppState, LocalNodeCliConfig};
 
@@ -30,10 +33,10 @@ async fn execute_command(
             req @ ContractRequest::Put { .. } => {
                 match node.handle_request(ClientId::FIRST, req.into(), None).await {
                     Ok(HostResponse::ContractResponse(ContractResponse::PutResponse { key })) => {
-                        println!("valid put for {key}");
+                        progress!("valid put for {key}");
                     }
                     Err(err) => {
-                        println!("error: {err}");
+                        report_error!("{err}");
                     }
                     _ => unreachable!(),
                 }
@@ -44,11 +47,11 @@ async fn execute_command(
                         key,
                         summary,
                     })) => {
-                        println!("valid update for {key}, state summary:");
+                        progress!("valid update for {key}, state summary:");
                         app.printout_deser(summary.as_ref())?;
                     }
                     Err(err) => {
-                        println!("error: {err}");
+                        report_error!("{err}");
                     }
                     _ => unreachable!(),
                 }
@@ -69,16 +72,16 @@ async fn execute_command(
                     )
                     .await
                 {
-                    Ok(HostResponse::ContractResponse(ContractResponse::GetResponse {
+                    Ok(HostResponse::ContractResponse(ContractResponse::KeyedGetResponse {
                         state,
                         ..
                     })) => {
-                        println!("current state for {key}:");
+                        progress!("current state for {key}:");
                         app.printout_deser(state.as_ref())?;
                         todo!()
                     }
                     Err(err) => {
-                        println!("error: {err}");
+                        report_error!("{err}");
                     }
                     _ => unreachable!(),
                 }
@@ -89,15 +92,21 @@ async fn execute_command(
             match node.handle_request(ClientId::FIRST, op.into(), None).await {
                 Ok(_res) => todo!(),
                 Err(either::Either::Left(err)) => {
-                    println!("error: {err}");
+                    report_error!("{err}");
                 }
                 Err(either::Either::Right(err)) => {
-                    println!("error: {err}");
+                    report_error!("{err}");
                 }
             }
         }
         ClientRequest::Disconnect { .. } => return Ok(true),
         ClientRequest::GenerateRandData { .. } => todo!("FIXME"),
+        req @ (ClientRequest::SubscribeNodeEvents
+        | ClientRequest::SampleNetwork { .. }
+        | ClientRequest::NodeQueries(_)
+        | ClientRequest::NodeAdmin { .. }) => {
+            report_error!("{req} is only available when connected to the network");
+        }
     }
     Ok(false)
 }
@@ -9,20 +9,24 @@ use std::{
 use crate::{
     build::*,
     config::{ContractKind, NewPackageCliConfig},
+    output::ArtifactsOutput,
     util::pipe_std_streams,
     DynError, Error,
 };
 
-pub fn create_new_package(config: NewPackageCliConfig) -> Result<(), DynError> {
+pub fn create_new_package(config: NewPackageCliConfig) -> Result<ArtifactsOutput, DynError> {
     let cwd = env::current_dir()?;
-    match config.kind {
+    let package_config = match config.kind {
         ContractKind::WebApp => create_view_package(&cwd)?,
         ContractKind::Contract => create_regular_contract(&cwd)?,
-    }
-    Ok(())
+    };
+    Ok(ArtifactsOutput {
+        artifacts: vec![package_config],
+    })
 }
 
-fn create_view_package(cwd: &Path) -> Result<(), DynError> {
+/// Returns the path to the package `locutus.toml` config file.
+fn create_view_package(cwd: &Path) -> Result<PathBuf, DynError> {
     create_rust_crate(cwd, ContractKind::WebApp)?;
     create_web_init_files(cwd)?;
     let locutus_file_config = BuildToolConfig {
@@ -45,12 +49,13 @@ fn create_view_package(cwd: &Path) -> Result<(), DynError> {
     };
     let serialized = toml::to_vec(&locutus_file_config)?;
     let path = cwd.join("locutus").with_extension("toml");
-    let mut file = File::create(path)?;
+    let mut file = File::create(&path)?;
     file.write_all(&serialized)?;
-    Ok(())
+    Ok(path)
 }
 
-fn create_regular_contract(cwd: &Path) -> Result<(), DynError> {
+/// Returns the path to the package `locutus.toml` config file.
+fn create_regular_contract(cwd: &Path) -> Result<PathBuf, DynError> {
     create_rust_crate(cwd, ContractKind::Contract)?;
     let locutus_file_config = BuildToolConfig {
         contract: Contract {
@@ -63,9 +68,9 @@ fn create_regular_contract(cwd: &Path) -> Result<(), DynError> {
     };
     let serialized = toml::to_vec(&locutus_file_config)?;
     let path = cwd.join("locutus").with_extension("toml");
-    let mut file = File::create(path)?;
+    let mut file = File::create(&path)?;
     file.write_all(&serialized)?;
-    Ok(())
+    Ok(path)
 }
 
 fn create_rust_crate(cwd: &Path, kind: ContractKind) -> Result<(), DynError> {
@@ -0,0 +1,321 @@
+//! Output of the commands, either for humans or for scripts.
+//!
+//! With `--output json` every command prints exactly one JSON object to stdout once finished,
+//! progress messages are written to stderr instead. With `--quiet` progress messages are
+//! suppressed altogether and only the JSON object is printed. The object has the form:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "command": "publish",
+//!   "status": "ok",
+//!   "exit_code": 0,
+//!   "data": { "contract_key": "..." }
+//! }
+//! ```
+//!
+//! On failure `status` is `"error"` and an `error` object with the `kind` of the failure and a
+//! `message` is present; `data` may be present too if the command produced partial results.
+//! Fields may be added in the future without bumping the schema version, but never removed.
+//!
+//! The exit code of the process is the same regardless of the output format, see [`ExitStatus`].
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use serde::Serialize;
+
+use crate::{DynError, Error};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable output.
+    Text,
+    /// A single JSON object, see the output schema.
+    Json,
+}
+
+const TEXT: u8 = 0;
+const JSON: u8 = 1;
+const QUIET: u8 = 2;
+
+static MODE: AtomicU8 = AtomicU8::new(TEXT);
+
+/// Sets how the output of the commands is printed; `quiet` implies JSON output.
+pub fn init(format: OutputFormat, quiet: bool) {
+    let mode = match (format, quiet) {
+        (_, true) => QUIET,
+        (OutputFormat::Json, false) => JSON,
+        (OutputFormat::Text, false) => TEXT,
+    };
+    MODE.store(mode, Ordering::SeqCst);
+}
+
+/// Whether stdout is reserved for the machine readable output.
+pub fn is_machine_readable() -> bool {
+    MODE.load(Ordering::SeqCst) != TEXT
+}
+
+/// Parses the command line arguments. Arguments rejected while parsing are reported as any
+/// other failure, in the output format requested by the arguments, and the process exits with
+/// [`ExitStatus::Usage`]; help and version requests are printed as usual.
+pub fn parse_args<P: clap::Parser>(command: &str) -> P {
+    match P::try_parse() {
+        Ok(args) => args,
+        Err(err) if !err.use_stderr() => err.exit(),
+        Err(err) => {
+            let args: Vec<String> = std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            let (format, quiet) = requested_output(&args);
+            init(format, quiet);
+            if !is_machine_readable() {
+                err.exit();
+            }
+            let error: DynError = Error::MissConfiguration(err.to_string().into()).into();
+            std::process::exit(emit::<()>(command, None, Some(&error)))
+        }
+    }
+}
+
+/// Output format requested in arguments which could not be parsed.
+fn requested_output(args: &[String]) -> (OutputFormat, bool) {
+    let mut format = OutputFormat::Text;
+    let mut quiet = false;
+    for (i, arg) in args.iter().enumerate() {
+        match arg.as_str() {
+            "--quiet" | "-q" => quiet = true,
+            "--output" if args.get(i + 1).map(String::as_str) == Some("json") => {
+                format = OutputFormat::Json
+            }
+            "--output=json" => format = OutputFormat::Json,
+            _ => {}
+        }
+    }
+    (format, quiet)
+}
+
+pub(crate) fn is_quiet() -> bool {
+    MODE.load(Ordering::SeqCst) == QUIET
+}
+
+pub(crate) fn print_progress(args: std::fmt::Arguments) {
+    match MODE.load(Ordering::SeqCst) {
+        TEXT => println!("{args}"),
+        JSON => eprintln!("{args}"),
+        _ => {}
+    }
+}
+
+/// Prints a progress message meant for humans, which never goes to stdout in JSON mode.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        $crate::output::print_progress(format_args!($($arg)*))
+    };
+}
+
+pub(crate) use progress;
+
+pub(crate) fn print_error(args: std::fmt::Arguments) {
+    eprintln!("error: {args}");
+}
+
+/// Prints an error which doesn't end the command, e.g. a failed request in the interactive
+/// shell, to stderr in every output mode, so it's reported even with `--quiet`.
+macro_rules! report_error {
+    ($($arg:tt)*) => {
+        $crate::output::print_error(format_args!($($arg)*))
+    };
+}
+
+pub(crate) use report_error;
+
+/// Exit codes of the commands.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    Success = 0,
+    /// Any failure not covered by the other codes.
+    Failure = 1,
+    /// Invalid arguments or configuration, same code used for arguments rejected while parsing.
+    Usage = 2,
+    /// Reading or writing some file failed.
+    Io = 3,
+    /// The node rejected the request or failed executing it.
+    Execution = 4,
+    /// An external tool (cargo, npm...) failed.
+    ToolFailed = 5,
+    /// The command is not supported yet in the requested mode.
+    Unsupported = 6,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    fn of(err: &DynError) -> Self {
+        if let Some(err) = err.downcast_ref::<Error>() {
+            return match err {
+                Error::MissConfiguration(_) => ExitStatus::Usage,
+                Error::CommandFailed(_) | Error::CommandExit(_) => ExitStatus::ToolFailed,
+                Error::Execution(_) => ExitStatus::Execution,
+                Error::Unsupported(_) => ExitStatus::Unsupported,
+            };
+        }
+        if err.is::<std::io::Error>() {
+            ExitStatus::Io
+        } else {
+            ExitStatus::Failure
+        }
+    }
+}
+
+/// Data returned by the commands operating on a contract.
+#[derive(Serialize, Debug)]
+pub struct ContractOutput {
+    pub contract_key: String,
+}
+
+/// Data returned by the commands writing files.
+#[derive(Serialize, Debug, Default)]
+pub struct ArtifactsOutput {
+    /// Files written by the command.
+    pub artifacts: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct ErrorOutput {
+    kind: ExitStatus,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct CommandOutput<'a, T> {
+    schema_version: u32,
+    command: &'a str,
+    status: &'static str,
+    exit_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<&'a T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorOutput>,
+}
+
+/// Reports the result of a command, returning the exit code for the process.
+pub fn finish<T: Serialize>(command: &str, result: Result<T, DynError>) -> i32 {
+    match result {
+        Ok(data) => emit(command, Some(&data), None),
+        Err(err) => emit::<()>(command, None, Some(&err)),
+    }
+}
+
+/// Reports the outcome of a command which may have produced data even if it failed,
+/// returning the exit code for the process.
+pub fn emit<T: Serialize>(command: &str, data: Option<&T>, error: Option<&DynError>) -> i32 {
+    let status = error.map(ExitStatus::of).unwrap_or(ExitStatus::Success);
+    if !is_machine_readable() {
+        if let Some(err) = error {
+            eprintln!("error: {err}");
+        }
+        return status.code();
+    }
+    let output = CommandOutput {
+        schema_version: SCHEMA_VERSION,
+        command,
+        status: if error.is_some() { "error" } else { "ok" },
+        exit_code: status.code(),
+        data,
+        error: error.map(|err| ErrorOutput {
+            kind: status,
+            message: err.to_string(),
+        }),
+    };
+    let mut stdout = std::io::stdout().lock();
+    let printed = serde_json::to_writer(&mut stdout, &output)
+        .map_err(std::io::Error::from)
+        .and_then(|_| writeln!(stdout));
+    match printed {
+        Ok(()) => status.code(),
+        Err(_) => ExitStatus::Io.code(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exit_status_of_errors() {
+        let err: DynError = Error::MissConfiguration("missing".into()).into();
+        assert_eq!(ExitStatus::of(&err), ExitStatus::Usage);
+        let err: DynError = std::io::Error::from(std::io::ErrorKind::NotFound).into();
+        assert_eq!(ExitStatus::of(&err).code(), 3);
+        let err: DynError = "unknown".into();
+        assert_eq!(ExitStatus::of(&err), ExitStatus::Failure);
+    }
+
+    #[test]
+    fn output_of_unparsed_args() {
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+        assert_eq!(
+            requested_output(&args(&["ldt", "--output", "json", "--bogus"])),
+            (OutputFormat::Json, false)
+        );
+        assert_eq!(
+            requested_output(&args(&["ldt", "--bogus", "--output=json", "-q"])),
+            (OutputFormat::Json, true)
+        );
+        assert_eq!(
+            requested_output(&args(&["ldt", "--output", "text", "--bogus"])),
+            (OutputFormat::Text, false)
+        );
+    }
+
+    #[test]
+    fn output_schema() -> Result<(), serde_json::Error> {
+        let data = ContractOutput {
+            contract_key: "key".to_owned(),
+        };
+        let output = CommandOutput {
+            schema_version: SCHEMA_VERSION,
+            command: "publish",
+            status: "ok",
+            exit_code: 0,
+            data: Some(&data),
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&output)?,
+            serde_json::json!({
+                "schema_version": 1,
+                "command": "publish",
+                "status": "ok",
+                "exit_code": 0,
+                "data": { "contract_key": "key" },
+            })
+        );
+
+        let output = CommandOutput::<()> {
+            schema_version: SCHEMA_VERSION,
+            command: "build",
+            status: "error",
+            exit_code: 5,
+            data: None,
+            error: Some(ErrorOutput {
+                kind: ExitStatus::ToolFailed,
+                message: "cargo".to_owned(),
+            }),
+        };
+        assert_eq!(
+            serde_json::to_value(&output)?["error"],
+            serde_json::json!({ "kind": "tool_failed", "message": "cargo" })
+        );
+        Ok(())
+    }
+}
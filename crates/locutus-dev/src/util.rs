@@ -6,7 +6,7 @@ use std::{
 
 use serde::de::DeserializeOwned;
 
-use crate::{local_node::DeserializationFmt, DynError};
+use crate::{local_node::DeserializationFmt, output, DynError, Error};
 
 pub fn deserialize<T, R>(deser_format: Option<DeserializationFmt>, data: &R) -> Result<T, DynError>
 where
@@ -27,13 +27,25 @@ where
     }
 }
 
+/// Forwards the output of the child process, which is discarded in quiet mode and written to
+/// stderr when stdout is reserved for the machine readable output.
 pub(crate) fn pipe_std_streams(mut child: Child) -> Result<(), DynError> {
     let mut c_stdout = child.stdout.take().expect("Failed to open command stdout");
-    let mut stdout = io::stdout();
+    let mut stdout: Box<dyn Write> = if output::is_quiet() {
+        Box::new(io::sink())
+    } else if output::is_machine_readable() {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
     let mut stdout_buf = vec![];
 
     let mut c_stderr = child.stderr.take().expect("Failed to open command stderr");
-    let mut stderr = io::stderr();
+    let mut stderr: Box<dyn Write> = if output::is_quiet() {
+        Box::new(io::sink())
+    } else {
+        Box::new(io::stderr())
+    };
     let mut stderr_buf = vec![];
 
     let mut write_child_output = || -> Result<(), DynError> {
@@ -52,7 +64,7 @@ pub(crate) fn pipe_std_streams(mut child: Child) -> Result<(), DynError> {
             Ok(Some(status)) => {
                 write_child_output()?;
                 if !status.success() {
-                    return Err(format!("exit with status: {status}").into());
+                    return Err(Error::CommandExit(status).into());
                 }
                 break;
             }
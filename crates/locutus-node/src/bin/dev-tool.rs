@@ -1,32 +1,48 @@
-use clap::Parser;
 use locutus_dev::{
     build::build_package,
     commands::{put, update},
     config::{Config, SubCommand},
     local_node::run_local_node_client,
     new_pckg::create_new_package,
+    output,
 };
 use tracing_subscriber::EnvFilter;
 
+type DynError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+async fn main() {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
         .init();
-    let cwd = std::env::current_dir()?;
-    let config = Config::parse();
-    match config.sub_command {
-        SubCommand::RunLocal(local_node_config) => run_local_node_client(local_node_config).await,
-        SubCommand::Build(build_tool_config) => build_package(build_tool_config, &cwd),
-        SubCommand::New(new_pckg_config) => create_new_package(new_pckg_config),
-        SubCommand::Publish(publish_config) => put(publish_config, config.additional).await,
+    let config: Config = output::parse_args("ldt");
+    output::init(config.additional.output_format, config.additional.quiet);
+    let command = config.sub_command.name();
+    let code = match config.sub_command {
+        SubCommand::RunLocal(local_node_config) => {
+            output::finish(command, run_local_node_client(local_node_config).await)
+        }
+        SubCommand::Build(build_tool_config) => {
+            let result = std::env::current_dir()
+                .map_err(DynError::from)
+                .and_then(|cwd| build_package(build_tool_config, &cwd));
+            output::finish(command, result)
+        }
+        SubCommand::New(new_pckg_config) => {
+            output::finish(command, create_new_package(new_pckg_config))
+        }
+        SubCommand::Publish(publish_config) => {
+            output::finish(command, put(publish_config, config.additional).await)
+        }
         SubCommand::Execute(cmd_config) => match cmd_config.command {
             locutus_dev::config::NodeCommand::Put(put_config) => {
-                put(put_config, config.additional).await
+                output::finish(command, put(put_config, config.additional).await)
             }
             locutus_dev::config::NodeCommand::Update(update_config) => {
-                update(update_config, config.additional).await
+                output::finish(command, update(update_config, config.additional).await)
             }
         },
-    }
+    };
+    std::process::exit(code);
 }
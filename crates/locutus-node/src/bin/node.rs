@@ -1,8 +1,16 @@
-use clap::Parser;
+//! Runs a Locutus node, or checks the environment for it with the `doctor` subcommand.
+//!
+//! With `--output json` (or `--quiet`) every subcommand, running the node included, prints a
+//! single JSON object with its outcome to stdout once finished, see
+//! [`locutus_dev::output`]. The exit codes are those of [`output::ExitStatus`] regardless
+//! of the output format: arguments which could not be parsed exit with `2`.
+
+use locutus::doctor::{self, DoctorConfig};
 use locutus_core::{
     locutus_runtime::{ContractStore, StateStore},
     Config, Executor, OperationMode, Storage,
 };
+use locutus_dev::output::{self, OutputFormat};
 use std::net::SocketAddr;
 use std::net::{IpAddr, Ipv4Addr};
 use std::path::PathBuf;
@@ -41,27 +49,65 @@ async fn run_local(config: NodeConfig) -> Result<(), DynError> {
     locutus::local_node::run_local_node(executor, socket).await
 }
 
-fn main() -> Result<(), DynError> {
+/// Returns the exit code of the process.
+async fn run_doctor(config: NodeConfig) -> i32 {
+    let conf = Config::get_conf();
+    let data_dir = config
+        .contract_data_dir
+        .unwrap_or_else(|| conf.config_paths.local_contracts_dir());
+    let doctor_config = DoctorConfig {
+        bind: config.bind,
+        port: config.port,
+        p2p_port: config.p2p_port.unwrap_or(conf.bootstrap_port),
+        data_dir,
+        gateway: SocketAddr::new(conf.bootstrap_ip, conf.bootstrap_port),
+    };
+    let reports = doctor::run_checks(&doctor_config).await;
+    if !output::is_machine_readable() {
+        for report in &reports {
+            println!("{report}");
+        }
+    }
+    let failed = reports
+        .iter()
+        .filter(|r| r.status == doctor::Status::Failure)
+        .count();
+    let error: Option<DynError> = (failed > 0).then(|| format!("{failed} check(s) failed").into());
+    output::emit("doctor", Some(&reports), error.as_ref())
+}
+
+fn main() {
     tracing_subscriber::fmt()
         .with_level(true)
+        .with_writer(std::io::stderr)
         .with_env_filter(
             EnvFilter::builder()
                 .with_default_directive(LevelFilter::INFO.into())
                 .from_env_lossy(),
         )
         .init();
-    let config = NodeConfig::parse();
+    let config: NodeConfig = output::parse_args("locutus-node");
+    output::init(config.output_format, config.quiet);
     let rt = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(4)
         .enable_all()
         .build()
         .unwrap();
-    rt.block_on(run(config))?;
-    Ok(())
+    let code = match config.command {
+        Some(Command::Doctor) => rt.block_on(run_doctor(config)),
+        None => {
+            let result = rt.block_on(run(config));
+            output::emit::<()>("run", None, result.err().as_ref())
+        }
+    };
+    std::process::exit(code);
 }
 
 #[derive(clap::Parser, Clone, Debug)]
 struct NodeConfig {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Node operation mode.
     #[clap(value_enum, default_value_t=OperationMode::Local)]
     mode: OperationMode,
@@ -75,4 +121,22 @@ struct NodeConfig {
     /// Port to expose api on
     #[arg(long, short, default_value_t = 50509)]
     port: u16,
+
+    /// Port to listen at for other peers, defaults to the one gateways listen at
+    #[arg(long)]
+    p2p_port: Option<u16>,
+
+    /// Format of the output of the command.
+    #[arg(long = "output", value_enum, default_value_t = OutputFormat::Text, global = true)]
+    output_format: OutputFormat,
+
+    /// Only print the machine readable output of the command, implies `--output json`.
+    #[arg(long, short, global = true)]
+    quiet: bool,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum Command {
+    /// Check the local environment for common problems which would prevent the node from working.
+    Doctor,
 }
@@ -0,0 +1,447 @@
+//! Self-test of the local environment, helps diagnosing why a node may not work properly.
+
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use locutus_core::{
+    locutus_runtime::{ContractStore, DelegateStore, Runtime, SecretsStore},
+    PortMappingError, PortMappingProtocol,
+};
+
+/// 2023-01-01T00:00:00Z, any clock set before this date is surely wrong.
+const MIN_VALID_UNIX_TIME: u64 = 1_672_531_200;
+const MAX_CLOCK_DRIFT: Duration = Duration::from_secs(1);
+const MIN_FREE_SPACE: u64 = 100 * 1024 * 1024;
+const LOW_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warning,
+    Failure,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CheckReport {
+    pub name: &'static str,
+    pub status: Status,
+    pub details: String,
+    /// What the user can do to solve the problem, if any.
+    pub remediation: Option<String>,
+}
+
+impl CheckReport {
+    fn ok(name: &'static str, details: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            details: details.into(),
+            remediation: None,
+        }
+    }
+
+    fn warning(
+        name: &'static str,
+        details: impl Into<String>,
+        remediation: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status: Status::Warning,
+            details: details.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+
+    fn failure(
+        name: &'static str,
+        details: impl Into<String>,
+        remediation: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status: Status::Failure,
+            details: details.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+}
+
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self.status {
+            Status::Ok => "[ok]  ",
+            Status::Warning => "[warn]",
+            Status::Failure => "[fail]",
+        };
+        write!(f, "{status} {}: {}", self.name, self.details)?;
+        if let Some(remediation) = &self.remediation {
+            write!(f, "\n       -> {remediation}")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DoctorConfig {
+    /// Address the node API will be bound to.
+    pub bind: IpAddr,
+    /// Port the node API will be exposed on.
+    pub port: u16,
+    /// Port the node will listen at for other peers.
+    pub p2p_port: u16,
+    /// Directory where the node data is stored.
+    pub data_dir: PathBuf,
+    /// Gateway the node joins the network through.
+    pub gateway: SocketAddr,
+}
+
+/// Run all the checks over the local environment.
+pub async fn run_checks(config: &DoctorConfig) -> Vec<CheckReport> {
+    let gateway = config.gateway;
+    vec![
+        check_port("API port", config.bind, config.port, "--port"),
+        check_port(
+            "p2p port",
+            Ipv4Addr::UNSPECIFIED.into(),
+            config.p2p_port,
+            "--p2p-port",
+        ),
+        check_nat(gateway),
+        check_port_mapping().await,
+        check_disk_space(&config.data_dir),
+        check_clock().await,
+        check_wasm_runtime(),
+        check_gateway(gateway).await,
+    ]
+}
+
+/// Checks the port can be bound, `flag` being the argument through which it is set.
+fn check_port(name: &'static str, bind: IpAddr, port: u16, flag: &str) -> CheckReport {
+    match TcpListener::bind((bind, port)) {
+        Ok(_) => CheckReport::ok(name, format!("{bind}:{port} is available")),
+        Err(err) if err.kind() == ErrorKind::AddrInUse => CheckReport::failure(
+            name,
+            format!("{bind}:{port} is already in use"),
+            format!("stop the process using the port or choose a different one with `{flag}`"),
+        ),
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => CheckReport::failure(
+            name,
+            format!("not allowed to bind to {bind}:{port}"),
+            format!(
+                "ports below 1024 require elevated privileges, choose a higher one with `{flag}`"
+            ),
+        ),
+        Err(err) if err.kind() == ErrorKind::AddrNotAvailable => CheckReport::failure(
+            name,
+            format!("{bind} is not assigned to any local interface"),
+            "choose one of the addresses of this machine with `--bind`",
+        ),
+        Err(err) => CheckReport::failure(
+            name,
+            format!("unable to bind to {bind}:{port}: {err}"),
+            format!("choose a different port with `{flag}`"),
+        ),
+    }
+}
+
+fn check_nat(gateway: SocketAddr) -> CheckReport {
+    // connecting an UDP socket does not send anything, but picks the interface
+    // which would be used to reach the remote address
+    let remote = if gateway.ip().is_loopback() {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)), 53)
+    } else {
+        gateway
+    };
+    let local_addr = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| socket.connect(remote).map(|_| socket))
+        .and_then(|socket| socket.local_addr());
+    nat_report(local_addr.map(|addr| addr.ip()))
+}
+
+fn nat_report(local_addr: io::Result<IpAddr>) -> CheckReport {
+    const NAME: &str = "NAT status";
+    match local_addr {
+        Ok(IpAddr::V4(ip)) if ip.is_private() || ip.is_link_local() => CheckReport::warning(
+            NAME,
+            format!("behind NAT, local address is {ip}"),
+            "other peers won't be able to connect to this node unless the p2p port is \
+            forwarded in the router, see the port mapping check",
+        ),
+        Ok(ip) if ip.is_loopback() => CheckReport::warning(
+            NAME,
+            "no network interface other than loopback available",
+            "connect this machine to a network",
+        ),
+        Ok(ip) => CheckReport::ok(NAME, format!("publicly reachable address {ip}")),
+        Err(err) => CheckReport::failure(
+            NAME,
+            format!("unable to determine the local address: {err}"),
+            "check the network configuration of this machine",
+        ),
+    }
+}
+
+async fn check_port_mapping() -> CheckReport {
+    port_mapping_report(locutus_core::discover_gateway().await)
+}
+
+fn port_mapping_report(protocol: Result<PortMappingProtocol, PortMappingError>) -> CheckReport {
+    const NAME: &str = "port mapping";
+    const REMEDIATION: &str = "unless this machine has a public address, enable UPnP or \
+        NAT-PMP in the router or forward the p2p port in it manually";
+    match protocol {
+        Ok(protocol) => {
+            CheckReport::ok(NAME, format!("the router can map ports through {protocol}"))
+        }
+        Err(PortMappingError::GatewayNotFound) => CheckReport::warning(
+            NAME,
+            "no router supporting UPnP or NAT-PMP found",
+            REMEDIATION,
+        ),
+        Err(err) => CheckReport::warning(
+            NAME,
+            format!("unable to query the router: {err}"),
+            REMEDIATION,
+        ),
+    }
+}
+
+fn check_disk_space(data_dir: &Path) -> CheckReport {
+    // the data directory is created by the node if missing
+    let available = data_dir
+        .ancestors()
+        .find(|dir| dir.exists())
+        .and_then(|dir| available_space(dir).ok());
+    disk_space_report(data_dir, available)
+}
+
+/// Space available to unprivileged users in the file system containing the path.
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: the path is a valid C string and stat is only read if the call succeeded
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    // the width of the fields depends on the platform
+    #[allow(clippy::unnecessary_cast)]
+    let available = stat.f_bavail as u64 * stat.f_frsize as u64;
+    Ok(available)
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "not supported on this platform",
+    ))
+}
+
+fn disk_space_report(data_dir: &Path, available: Option<u64>) -> CheckReport {
+    const NAME: &str = "disk space";
+    let dir = data_dir.display();
+    match available {
+        Some(bytes) if bytes < MIN_FREE_SPACE => CheckReport::failure(
+            NAME,
+            format!("only {} MiB available at {dir}", bytes / 1024 / 1024),
+            "free some disk space or set a different data directory",
+        ),
+        Some(bytes) if bytes < LOW_FREE_SPACE => CheckReport::warning(
+            NAME,
+            format!("only {} MiB available at {dir}", bytes / 1024 / 1024),
+            "the node may run out of space soon, consider freeing some disk space",
+        ),
+        Some(bytes) => CheckReport::ok(
+            NAME,
+            format!("{} MiB available at {dir}", bytes / 1024 / 1024),
+        ),
+        None => CheckReport::warning(
+            NAME,
+            format!("unable to determine the available space at {dir}"),
+            "make sure the data directory exists and is in a writable device",
+        ),
+    }
+}
+
+async fn check_clock() -> CheckReport {
+    const NAME: &str = "clock";
+    const REMEDIATION: &str = "synchronize the system clock, e.g. enabling NTP";
+    let since_epoch = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch,
+        Err(_) => {
+            return CheckReport::failure(NAME, "system time is before the unix epoch", REMEDIATION)
+        }
+    };
+    if since_epoch.as_secs() < MIN_VALID_UNIX_TIME {
+        return CheckReport::failure(NAME, "system time is set in the past", REMEDIATION);
+    }
+
+    // compare the wall clock against the monotonic clock to detect jumps
+    let (wall_start, mono_start) = (SystemTime::now(), Instant::now());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mono_elapsed = mono_start.elapsed();
+    let drift = match wall_start.elapsed() {
+        Ok(wall_elapsed) if wall_elapsed > mono_elapsed => wall_elapsed - mono_elapsed,
+        Ok(wall_elapsed) => mono_elapsed - wall_elapsed,
+        Err(_) => Duration::MAX,
+    };
+    if drift > MAX_CLOCK_DRIFT {
+        CheckReport::warning(NAME, "system time is jumping", REMEDIATION)
+    } else {
+        CheckReport::ok(NAME, "system time looks sane")
+    }
+}
+
+fn check_wasm_runtime() -> CheckReport {
+    const NAME: &str = "WASM runtime";
+    let runtime = tempfile::tempdir()
+        .map_err(|err| err.to_string())
+        .and_then(|dir| {
+            let dir = dir.path();
+            ContractStore::new(dir.join("contracts"), 1024)
+                .and_then(|contracts| {
+                    let delegates = DelegateStore::new(dir.join("delegates"), 1024)?;
+                    let secrets = SecretsStore::new(dir.join("secrets"))?;
+                    Runtime::build(contracts, delegates, secrets, true)
+                })
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        });
+    match runtime {
+        Ok(()) => CheckReport::ok(NAME, "runtime initialized successfully"),
+        Err(err) => CheckReport::failure(
+            NAME,
+            format!("failed to initialize the runtime: {err}"),
+            "make sure this platform is supported by the WASM engine and the temporary \
+            directory is writable",
+        ),
+    }
+}
+
+async fn check_gateway(gateway: SocketAddr) -> CheckReport {
+    let connect = tokio::net::TcpStream::connect(gateway);
+    let connected = tokio::time::timeout(GATEWAY_TIMEOUT, connect)
+        .await
+        .ok()
+        .map(|res| res.map(|_| ()));
+    gateway_report(gateway, connected)
+}
+
+/// `connected` is none if connecting to the gateway timed out.
+fn gateway_report(gateway: SocketAddr, connected: Option<io::Result<()>>) -> CheckReport {
+    const NAME: &str = "gateway connectivity";
+    match connected {
+        Some(Ok(())) => CheckReport::ok(NAME, format!("reached gateway at {gateway}")),
+        Some(Err(err)) => CheckReport::failure(
+            NAME,
+            format!("unable to connect to gateway at {gateway}: {err}"),
+            "check the network connection and the gateway set through the \
+            `LOCUTUS_BOOTSTRAP_HOST` and `LOCUTUS_BOOTSTRAP_PORT` environment variables",
+        ),
+        None => CheckReport::failure(
+            NAME,
+            format!("timed out connecting to gateway at {gateway}"),
+            "check that no firewall is blocking outbound connections and that the gateway \
+            is up",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn port_in_use() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let report = check_port("API port", Ipv4Addr::LOCALHOST.into(), port, "--port");
+        assert_eq!(report.status, Status::Failure);
+        assert!(report.remediation.unwrap().contains("`--port`"));
+
+        drop(listener);
+        let report = check_port("p2p port", Ipv4Addr::LOCALHOST.into(), port, "--p2p-port");
+        assert_eq!(report.status, Status::Ok);
+    }
+
+    #[test]
+    fn nat_status() {
+        let status = |addr: [u8; 4]| nat_report(Ok(Ipv4Addr::from(addr).into())).status;
+        assert_eq!(status([192, 168, 1, 10]), Status::Warning);
+        assert_eq!(status([169, 254, 0, 1]), Status::Warning);
+        assert_eq!(status([127, 0, 0, 1]), Status::Warning);
+        assert_eq!(status([203, 0, 113, 7]), Status::Ok);
+        let unreachable = io::Error::new(ErrorKind::Other, "network is unreachable");
+        assert_eq!(nat_report(Err(unreachable)).status, Status::Failure);
+    }
+
+    #[test]
+    fn port_mapping_status() {
+        let report = port_mapping_report(Ok(PortMappingProtocol::Upnp));
+        assert_eq!(report.status, Status::Ok);
+        let report = port_mapping_report(Err(PortMappingError::GatewayNotFound));
+        assert_eq!(report.status, Status::Warning);
+        assert!(report.remediation.is_some());
+    }
+
+    #[tokio::test]
+    async fn gateway_connectivity() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let gateway = listener.local_addr().unwrap();
+        assert_eq!(check_gateway(gateway).await.status, Status::Ok);
+
+        drop(listener);
+        let report = check_gateway(gateway).await;
+        assert_eq!(report.status, Status::Failure, "{report}");
+        assert!(report.details.starts_with("unable to connect"));
+
+        let report = gateway_report(gateway, None);
+        assert!(report.details.starts_with("timed out"));
+    }
+
+    #[test]
+    fn disk_space_thresholds() {
+        let dir = Path::new("/data");
+        let status = |available| disk_space_report(dir, available).status;
+        assert_eq!(status(Some(MIN_FREE_SPACE - 1)), Status::Failure);
+        assert_eq!(status(Some(LOW_FREE_SPACE - 1)), Status::Warning);
+        assert_eq!(status(Some(LOW_FREE_SPACE)), Status::Ok);
+        assert_eq!(status(None), Status::Warning);
+    }
+
+    #[test]
+    fn disk_space_of_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = check_disk_space(&dir.path().join("not").join("created"));
+        assert!(!report.details.starts_with("unable"), "{report}");
+        assert!(available_space(dir.path()).unwrap() > 0);
+    }
+
+    #[test]
+    fn concurrent_wasm_runtime_checks() {
+        let checks: Vec<_> = (0..2)
+            .map(|_| std::thread::spawn(check_wasm_runtime))
+            .collect();
+        for check in checks {
+            let report = check.join().unwrap();
+            assert_eq!(report.status, Status::Ok, "{report}");
+        }
+    }
+
+    #[tokio::test]
+    async fn sane_clock() {
+        assert_eq!(check_clock().await.status, Status::Ok);
+    }
+}
@@ -20,7 +20,7 @@ use std::{
     time::Duration,
 };
 use tokio::sync::{
-    mpsc::{self, error::TryRecvError, UnboundedReceiver},
+    mpsc::{self, error::TryRecvError, UnboundedReceiver, UnboundedSender},
     Mutex,
 };
 
@@ -41,6 +41,8 @@ static REQUEST_ID: AtomicUsize = AtomicUsize::new(0);
 pub struct HttpGateway {
     server_request: mpsc::Receiver<ClientConnection>,
     response_channels: HashMap<ClientId, mpsc::UnboundedSender<HostCallbackResult>>,
+    /// channels for the node to send back results to the clients while handling their requests
+    notification_channels: HashMap<ClientId, UnboundedSender<HostResult>>,
 }
 
 impl HttpGateway {
@@ -53,11 +55,13 @@ impl HttpGateway {
         let gateway = Self {
             server_request,
             response_channels: HashMap::new(),
+            notification_channels: HashMap::new(),
         };
 
         let router = Router::new()
             .route("/", get(home))
             .route("/contract/command/", get(websocket_commands))
+            .route("/v1/contract/command/", get(websocket_commands_v1))
             .route("/contract/web/:key/", get(web_home))
             .route("/contract/web/:key/*path", get(web_subpages))
             .layer(Extension(request_sender));
@@ -107,12 +111,36 @@ async fn web_subpages(
     web_handling::variable_content(key, full_path).await
 }
 
+/// Version of the websocket API a client connected through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApiVersion {
+    /// `/contract/command/`, get responses are not tagged with the contract key.
+    Unversioned,
+    /// `/v1/contract/command/`
+    V1,
+}
+
 async fn websocket_commands(
     ws: WebSocketUpgrade,
     Extension(rs): Extension<mpsc::Sender<ClientConnection>>,
+) -> Response {
+    websocket_upgrade(ws, rs, ApiVersion::Unversioned)
+}
+
+async fn websocket_commands_v1(
+    ws: WebSocketUpgrade,
+    Extension(rs): Extension<mpsc::Sender<ClientConnection>>,
+) -> Response {
+    websocket_upgrade(ws, rs, ApiVersion::V1)
+}
+
+fn websocket_upgrade(
+    ws: WebSocketUpgrade,
+    rs: mpsc::Sender<ClientConnection>,
+    version: ApiVersion,
 ) -> Response {
     let on_upgrade = move |ws: WebSocket| async move {
-        if let Err(e) = websocket_interface(rs.clone(), ws).await {
+        if let Err(e) = websocket_interface(rs.clone(), ws, version).await {
             tracing::error!("{e}");
         }
     };
@@ -122,6 +150,7 @@ async fn websocket_commands(
 async fn websocket_interface(
     request_sender: mpsc::Sender<ClientConnection>,
     ws: WebSocket,
+    version: ApiVersion,
 ) -> Result<(), DynError> {
     let (mut response_rx, client_id) = new_client_connection(&request_sender).await?;
     let (mut tx, mut rx) = ws.split();
@@ -168,7 +197,7 @@ async fn websocket_interface(
 
         let active_listeners = listeners.clone();
         tokio::select! { biased;
-            msg = async { process_host_response(response_rx.recv().await, client_id, &mut tx).await } => {
+            msg = async { process_host_response(response_rx.recv().await, client_id, version, &mut tx).await } => {
                 if let Some(NewSubscription { key, callback }) = msg? {
                     let active_listeners = &mut *active_listeners.lock().await;
                     active_listeners.push((key, callback));
@@ -220,8 +249,8 @@ async fn process_client_request(
     };
 
     let req: ClientRequest = {
-        match ContractRequest::try_decode(&msg) {
-            Ok(r) => r.into(),
+        match ClientRequest::try_decode(&msg) {
+            Ok(r) => r,
             Err(e) => {
                 let result_error = rmp_serde::to_vec(&Err::<HostResponse, ClientError>(
                     ErrorKind::DeserializationError {
@@ -245,6 +274,7 @@ async fn process_client_request(
 async fn process_host_response(
     msg: Option<HostCallbackResult>,
     client_id: ClientId,
+    version: ApiVersion,
     tx: &mut SplitSink<WebSocket, Message>,
 ) -> Result<Option<NewSubscription>, DynError> {
     match msg {
@@ -254,10 +284,14 @@ async fn process_host_response(
                 Ok(res) => {
                     tracing::debug!(response = %res, cli_id = %id, "sending response");
                     match res {
-                        HostResponse::ContractResponse(ContractResponse::GetResponse {
+                        // keep the format of get responses known to clients of the unversioned API
+                        HostResponse::ContractResponse(ContractResponse::KeyedGetResponse {
                             contract,
                             state,
-                        }) => Ok(ContractResponse::GetResponse { contract, state }.into()),
+                            ..
+                        }) if version == ApiVersion::Unversioned => {
+                            Ok(ContractResponse::GetResponse { contract, state }.into())
+                        }
                         other => Ok(other),
                     }
                 }
@@ -298,7 +332,19 @@ impl HttpGateway {
                 new_client_ch
                     .send(HostCallbackResult::NewId(cli_id))
                     .map_err(|_e| ErrorKind::NodeUnavailable)?;
+                // results sent back by the node are delivered as any other response
+                let (notifications, mut notifications_rx) = mpsc::unbounded_channel();
+                let client_ch = new_client_ch.clone();
+                tokio::spawn(async move {
+                    while let Some(result) = notifications_rx.recv().await {
+                        let result = HostCallbackResult::Result { id: cli_id, result };
+                        if client_ch.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
                 self.response_channels.insert(cli_id, new_client_ch);
+                self.notification_channels.insert(cli_id, notifications);
                 Ok(None)
             }
             ClientConnection::Request {
@@ -325,7 +371,13 @@ impl HttpGateway {
             }
             ClientConnection::Request { client_id, req } => {
                 // just forward the request to the node
-                Ok(Some(OpenRequest::new(client_id, req)))
+                let request = OpenRequest::new(client_id, req);
+                match self.notification_channels.get(&client_id) {
+                    Some(notifications) => {
+                        Ok(Some(request.with_notification(notifications.clone())))
+                    }
+                    None => Ok(Some(request)),
+                }
             }
         }
     }
@@ -363,6 +415,8 @@ impl ClientEventsProxy for HttpGateway {
                 if ch.send(HostCallbackResult::Result { id, result }).is_ok() && !should_rm {
                     // still alive connection, keep it
                     self.response_channels.insert(id, ch);
+                } else {
+                    self.notification_channels.remove(&id);
                 }
             } else {
                 tracing::warn!("client: {id} not found");
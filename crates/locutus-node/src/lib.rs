@@ -1,3 +1,4 @@
+pub mod doctor;
 pub(crate) mod errors;
 mod http_gateway;
 pub(crate) mod web_handling;
@@ -34,9 +35,8 @@ enum HostCallbackResult {
 pub mod local_node {
     use std::net::SocketAddr;
 
-    use locutus_core::{
-        either, ClientEventsProxy, Executor, OpenRequest, RequestError, WebSocketProxy,
-    };
+    use futures::FutureExt;
+    use locutus_core::{either, ClientEventsProxy, Executor, RequestError, WebSocketProxy};
     use locutus_stdlib::client_api::{ClientError, ErrorKind};
 
     use crate::{DynError, HttpGateway};
@@ -51,17 +51,22 @@ pub mod local_node {
         // let mut all_clients =
         //    ClientEventsCombinator::new([Box::new(ws_handle), Box::new(http_handle)]);
         loop {
-            let OpenRequest {
-                id,
-                request,
-                notification_channel,
-                ..
-            } = http_handle.recv().await?;
-            tracing::debug!("client {id}, req -> {request}");
-            match executor
-                .handle_request(id, request, notification_channel)
-                .await
-            {
+            if !executor.has_pending_requests() {
+                let req = http_handle.recv().await?;
+                tracing::debug!("client {}, req -> {}", req.id, req.request);
+                executor.schedule_request(req);
+            }
+            // pick up any other request already waiting so all of them are scheduled fairly
+            while let Some(req) = http_handle.recv().now_or_never() {
+                let req = req?;
+                tracing::debug!("client {}, req -> {}", req.id, req.request);
+                executor.schedule_request(req);
+            }
+            let (id, res) = match executor.handle_next_request().await {
+                Some(handled) => handled,
+                None => continue,
+            };
+            match res {
                 Ok(res) => {
                     http_handle.send(id, Ok(res)).await?;
                 }
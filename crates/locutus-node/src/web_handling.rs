@@ -61,7 +61,7 @@ pub(crate) async fn contract_home(
     let response = match response_recv.recv().await {
         Some(HostCallbackResult::Result {
             result:
-                Ok(HostResponse::ContractResponse(ContractResponse::GetResponse {
+                Ok(HostResponse::ContractResponse(ContractResponse::KeyedGetResponse {
                     contract,
                     state,
                     ..
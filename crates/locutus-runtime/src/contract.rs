@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use locutus_stdlib::prelude::{
     ContractInterfaceResult, ContractKey, Parameters, RelatedContracts, StateDelta, StateSummary,
     UpdateData, UpdateModification, ValidateResult, WrappedState,
@@ -100,18 +102,18 @@ impl ContractRuntimeInterface for crate::Runtime {
                 .instance
                 .exports
                 .get_typed_function(&self.wasm_store, "validate_state")?;
+        let started = Instant::now();
+        let res = validate_func.call(
+            &mut self.wasm_store,
+            param_buf_ptr as i64,
+            state_buf_ptr as i64,
+            related_buf_ptr as i64,
+        );
+        self.record_execution(key, started.elapsed());
         let is_valid = unsafe {
-            ContractInterfaceResult::from_raw(
-                validate_func.call(
-                    &mut self.wasm_store,
-                    param_buf_ptr as i64,
-                    state_buf_ptr as i64,
-                    related_buf_ptr as i64,
-                )?,
-                &linear_mem,
-            )
-            .unwrap_validate_state_res(linear_mem)
-            .map_err(Into::<ContractExecError>::into)?
+            ContractInterfaceResult::from_raw(res?, &linear_mem)
+                .unwrap_validate_state_res(linear_mem)
+                .map_err(Into::<ContractExecError>::into)?
         };
         Ok(is_valid)
     }
@@ -142,17 +144,17 @@ impl ContractRuntimeInterface for crate::Runtime {
             .instance
             .exports
             .get_typed_function(&self.wasm_store, "validate_delta")?;
+        let started = Instant::now();
+        let res = validate_func.call(
+            &mut self.wasm_store,
+            param_buf_ptr as i64,
+            delta_buf_ptr as i64,
+        );
+        self.record_execution(key, started.elapsed());
         let is_valid = unsafe {
-            ContractInterfaceResult::from_raw(
-                validate_func.call(
-                    &mut self.wasm_store,
-                    param_buf_ptr as i64,
-                    delta_buf_ptr as i64,
-                )?,
-                &linear_mem,
-            )
-            .unwrap_validate_delta_res(linear_mem)
-            .map_err(Into::<ContractExecError>::into)?
+            ContractInterfaceResult::from_raw(res?, &linear_mem)
+                .unwrap_validate_delta_res(linear_mem)
+                .map_err(Into::<ContractExecError>::into)?
         };
         Ok(is_valid)
     }
@@ -194,18 +196,18 @@ impl ContractRuntimeInterface for crate::Runtime {
                 .instance
                 .exports
                 .get_typed_function(&self.wasm_store, "update_state")?;
+        let started = Instant::now();
+        let res = validate_func.call(
+            &mut self.wasm_store,
+            param_buf_ptr as i64,
+            state_buf_ptr as i64,
+            update_data_buf_ptr as i64,
+        );
+        self.record_execution(key, started.elapsed());
         let update_res = unsafe {
-            ContractInterfaceResult::from_raw(
-                validate_func.call(
-                    &mut self.wasm_store,
-                    param_buf_ptr as i64,
-                    state_buf_ptr as i64,
-                    update_data_buf_ptr as i64,
-                )?,
-                &linear_mem,
-            )
-            .unwrap_update_state(linear_mem)
-            .map_err(Into::<ContractExecError>::into)?
+            ContractInterfaceResult::from_raw(res?, &linear_mem)
+                .unwrap_update_state(linear_mem)
+                .map_err(Into::<ContractExecError>::into)?
         };
         Ok(update_res)
     }
@@ -236,15 +238,15 @@ impl ContractRuntimeInterface for crate::Runtime {
             .exports
             .get_typed_function(&self.wasm_store, "summarize_state")?;
 
+        let started = Instant::now();
+        let res = summary_func.call(
+            &mut self.wasm_store,
+            param_buf_ptr as i64,
+            state_buf_ptr as i64,
+        );
+        self.record_execution(key, started.elapsed());
         let result = unsafe {
-            let int_res = ContractInterfaceResult::from_raw(
-                summary_func.call(
-                    &mut self.wasm_store,
-                    param_buf_ptr as i64,
-                    state_buf_ptr as i64,
-                )?,
-                &linear_mem,
-            );
+            let int_res = ContractInterfaceResult::from_raw(res?, &linear_mem);
             int_res
                 .unwrap_summarize_state(linear_mem)
                 .map_err(Into::<ContractExecError>::into)?
@@ -284,18 +286,16 @@ impl ContractRuntimeInterface for crate::Runtime {
             .exports
             .get_typed_function(&self.wasm_store, "get_state_delta")?;
 
+        let started = Instant::now();
+        let res = get_state_delta_func.call(
+            &mut self.wasm_store,
+            param_buf_ptr as i64,
+            state_buf_ptr as i64,
+            summary_buf_ptr as i64,
+        );
+        self.record_execution(key, started.elapsed());
         let result = unsafe {
-            let int_res = {
-                ContractInterfaceResult::from_raw(
-                    get_state_delta_func.call(
-                        &mut self.wasm_store,
-                        param_buf_ptr as i64,
-                        state_buf_ptr as i64,
-                        summary_buf_ptr as i64,
-                    )?,
-                    &linear_mem,
-                )
-            };
+            let int_res = { ContractInterfaceResult::from_raw(res?, &linear_mem) };
             int_res
                 .unwrap_get_state_delta(linear_mem)
                 .map_err(Into::<ContractExecError>::into)?
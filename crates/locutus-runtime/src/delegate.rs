@@ -317,7 +317,7 @@ mod test {
             Runtime::build(contract_store, component_store, secret_store, false).unwrap();
 
         let component = {
-            let bytes = crate::tests::get_test_module(name)?;
+            let bytes = crate::tests::get_test_delegate(name)?;
             Delegate::from(bytes)
         };
         let _ = runtime.component_store.store_component(component.clone());
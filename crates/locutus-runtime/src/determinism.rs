@@ -0,0 +1,86 @@
+//! Restrictions enforced so contract execution is deterministic.
+//!
+//! Every peer holding a contract must reach the same state from the same inputs, otherwise
+//! replicas diverge and valid updates may be rejected by part of the network. Contracts are
+//! thus compiled with NaN canonicalization (the bit pattern of NaNs produced by float
+//! operations is otherwise platform dependent) and without support for threads, and modules
+//! importing functions which depend on the environment they run on (WASI, the clock) are
+//! rejected before being instantiated.
+
+use wasmer::{CompilerConfig, Cranelift, EngineBuilder, Features, Module, Store};
+
+use crate::runtime::ContractExecError;
+
+/// Modules whose functions contracts can't import.
+const FORBIDDEN_CONTRACT_IMPORTS: &[&str] = &[
+    "wasi_snapshot_preview1",
+    "wasi_unstable",
+    // the time may differ between peers
+    "locutus_time",
+];
+
+/// Store compiling modules with the deterministic configuration.
+pub(crate) fn deterministic_store() -> Store {
+    let mut compiler = Cranelift::new();
+    compiler.canonicalize_nans(true);
+    let mut features = Features::new();
+    features.threads(false);
+    let engine = EngineBuilder::new(compiler)
+        .set_features(Some(features))
+        .engine();
+    Store::new(engine)
+}
+
+/// Checks that the contract module doesn't import any forbidden function.
+pub(crate) fn validate_contract(module: &Module) -> Result<(), ContractExecError> {
+    let forbidden = module
+        .imports()
+        .find(|import| FORBIDDEN_CONTRACT_IMPORTS.contains(&import.module()));
+    match forbidden {
+        Some(import) => Err(ContractExecError::ForbiddenImport {
+            module: import.module().to_owned(),
+            name: import.name().to_owned(),
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasmer::{imports, Instance, TypedFunction};
+
+    use super::*;
+
+    #[test]
+    fn reject_forbidden_imports() -> Result<(), Box<dyn std::error::Error>> {
+        let store = deterministic_store();
+        let allowed = Module::new(&store, r#"(module (import "env" "memory" (memory 1)))"#)?;
+        assert!(validate_contract(&allowed).is_ok());
+
+        let forbidden = Module::new(
+            &store,
+            r#"(module (import "locutus_time" "utc_now" (func (param i64 i64))))"#,
+        )?;
+        assert!(matches!(
+            validate_contract(&forbidden),
+            Err(ContractExecError::ForbiddenImport { name, .. }) if name == "utc_now"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn canonicalize_nans() -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = deterministic_store();
+        let module = Module::new(
+            &store,
+            r#"(module
+                (func (export "nan") (param f32) (result i32)
+                    (i32.reinterpret_f32 (f32.div (local.get 0) (local.get 0)))))"#,
+        )?;
+        let instance = Instance::new(&mut store, &module, &imports! {})?;
+        let nan: TypedFunction<f32, i32> = instance.exports.get_typed_function(&store, "nan")?;
+        let bits = nan.call(&mut store, 0.0)?;
+        assert_eq!(bits as u32, 0x7fc0_0000);
+        Ok(())
+    }
+}
@@ -4,9 +4,12 @@ mod contract;
 mod contract_store;
 mod delegate;
 mod delegate_store;
+mod determinism;
 pub(crate) mod error;
+mod messaging;
 mod native_api;
 mod runtime;
+mod scheduler;
 mod secrets_store;
 mod state_store;
 mod store;
@@ -26,7 +29,9 @@ pub mod prelude {
     pub use super::delegate_store::DelegateStore;
     pub use super::error::ContractError;
     pub use super::error::RuntimeResult;
+    pub use super::messaging::{ContractMessageQueue, InboundContractMessage, MessagingError};
     pub use super::runtime::{ContractExecError, Runtime};
+    pub use super::scheduler::{ExecutionStats, ExecutionTracker, FairScheduler};
     pub use super::secrets_store::SecretsStore;
     pub use super::state_store::{StateStorage, StateStore, StateStoreError};
     pub use locutus_stdlib::prelude::*;
@@ -0,0 +1,164 @@
+//! Delivery of messages exchanged between contracts.
+//!
+//! Contracts can emit messages for other contracts while updating their state (see
+//! [`UpdateModification::send_message`](locutus_stdlib::prelude::UpdateModification::send_message)),
+//! which in turn can emit further messages. To prevent contracts from looping forever or
+//! amplifying a single update into an unbounded amount of work, messages are processed
+//! breadth-first through a [`ContractMessageQueue`] which enforces both a maximum chain
+//! depth and a total budget of messages per originating update.
+
+use std::collections::VecDeque;
+
+use locutus_stdlib::prelude::{ContractInstanceId, ContractMessage, StateDelta, UpdateData};
+
+/// Maximum length of a chain of messages triggered by a single update.
+pub const DEFAULT_MAX_MESSAGE_DEPTH: usize = 8;
+/// Maximum number of messages delivered as a result of a single update.
+pub const DEFAULT_MAX_MESSAGES: usize = 64;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum MessagingError {
+    #[error("contract {0} attempted to send a message to itself")]
+    SelfMessage(ContractInstanceId),
+    #[error("message chain exceeded the maximum depth of {0}")]
+    MaxDepthExceeded(usize),
+    #[error("exhausted the budget of {0} messages")]
+    BudgetExhausted(usize),
+}
+
+/// A message pending to be delivered to a contract.
+#[derive(Debug, Clone)]
+pub struct InboundContractMessage {
+    pub from: ContractInstanceId,
+    pub target: ContractInstanceId,
+    pub payload: Vec<u8>,
+    /// Number of contract updates which preceded this message in the chain.
+    pub depth: usize,
+}
+
+impl InboundContractMessage {
+    /// Converts the message into the update data passed to the target contract.
+    pub fn into_update_data(self) -> UpdateData<'static> {
+        UpdateData::RelatedDelta {
+            related_to: self.from,
+            delta: StateDelta::from(self.payload),
+        }
+    }
+}
+
+pub struct ContractMessageQueue {
+    pending: VecDeque<InboundContractMessage>,
+    max_depth: usize,
+    budget: usize,
+    accepted: usize,
+}
+
+impl Default for ContractMessageQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_MESSAGE_DEPTH, DEFAULT_MAX_MESSAGES)
+    }
+}
+
+impl ContractMessageQueue {
+    pub fn new(max_depth: usize, budget: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            max_depth,
+            budget,
+            accepted: 0,
+        }
+    }
+
+    /// Enqueues the messages emitted by a contract after being updated at the given depth.
+    ///
+    /// Messages within the limits are queued even if an error is returned for the rest, and
+    /// messages a contract addresses to itself are rejected without affecting the others.
+    pub fn enqueue(
+        &mut self,
+        from: ContractInstanceId,
+        messages: Vec<ContractMessage>,
+        depth: usize,
+    ) -> Result<(), MessagingError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        if depth >= self.max_depth {
+            return Err(MessagingError::MaxDepthExceeded(self.max_depth));
+        }
+        let mut result = Ok(());
+        for ContractMessage { target, payload } in messages {
+            if target == from {
+                result = Err(MessagingError::SelfMessage(from));
+                continue;
+            }
+            if self.accepted >= self.budget {
+                return Err(MessagingError::BudgetExhausted(self.budget));
+            }
+            self.accepted += 1;
+            self.pending.push_back(InboundContractMessage {
+                from,
+                target,
+                payload,
+                depth: depth + 1,
+            });
+        }
+        result
+    }
+
+    /// Next message pending to be delivered.
+    pub fn next_message(&mut self) -> Option<InboundContractMessage> {
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use locutus_stdlib::prelude::{ContractCode, Parameters};
+
+    use super::*;
+
+    fn instance_id(seed: u8) -> ContractInstanceId {
+        let code = ContractCode::from(vec![seed]);
+        ContractInstanceId::from((Parameters::from(vec![]), code))
+    }
+
+    fn message(target: ContractInstanceId) -> ContractMessage {
+        ContractMessage {
+            target,
+            payload: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn message_guards() {
+        let (a, b) = (instance_id(1), instance_id(2));
+
+        let mut queue = ContractMessageQueue::new(2, 4);
+        // only the message to itself is rejected
+        assert_eq!(
+            queue.enqueue(a, vec![message(a), message(b)], 0),
+            Err(MessagingError::SelfMessage(a))
+        );
+        let msg = queue.next_message().unwrap();
+        assert_eq!((msg.from, msg.target), (a, b));
+
+        // ping-pong between two contracts is cut at the max depth
+        queue.enqueue(a, vec![message(b)], 0).unwrap();
+        let msg = queue.next_message().unwrap();
+        assert_eq!((msg.from, msg.target, msg.depth), (a, b, 1));
+        queue.enqueue(b, vec![message(a)], msg.depth).unwrap();
+        let msg = queue.next_message().unwrap();
+        assert_eq!(
+            queue.enqueue(a, vec![message(b)], msg.depth),
+            Err(MessagingError::MaxDepthExceeded(2))
+        );
+
+        // fan out is limited by the budget
+        assert_eq!(
+            queue.enqueue(a, vec![message(b), message(b)], 0),
+            Err(MessagingError::BudgetExhausted(4))
+        );
+        assert!(queue.next_message().is_some());
+        assert!(queue.next_message().is_none());
+    }
+}
@@ -43,3 +43,115 @@ pub(crate) mod time {
         };
     }
 }
+
+pub(crate) mod causality {
+    use super::*;
+    use wasmer::{Function, FunctionEnv, FunctionEnvMut, Imports, Memory};
+
+    /// State shared with the causality imports of the instances running in a store.
+    #[derive(Default)]
+    pub(crate) struct CausalityEnv {
+        /// id and memory of the instance currently running, the only one allowed to read
+        pub instance: Option<(InstanceId, Memory)>,
+        /// serialized causality of the update being applied
+        pub causality: Option<Vec<u8>>,
+    }
+
+    pub(crate) fn prepare_export(
+        store: &mut wasmer::Store,
+        imports: &mut Imports,
+    ) -> FunctionEnv<CausalityEnv> {
+        let env = FunctionEnv::new(store, CausalityEnv::default());
+        let causality_len = Function::new_typed_with_env(store, &env, causality_len);
+        let causality_read = Function::new_typed_with_env(store, &env, causality_read);
+        imports.register_namespace(
+            "locutus_causality",
+            [
+                ("causality_len".to_owned(), causality_len.into()),
+                ("causality_read".to_owned(), causality_read.into()),
+            ],
+        );
+        env
+    }
+
+    fn causality_len(env: FunctionEnvMut<CausalityEnv>, id: i64) -> i64 {
+        match env.data() {
+            CausalityEnv {
+                instance: Some((current, _)),
+                causality: Some(causality),
+            } if *current == id => causality.len() as i64,
+            _ => -1,
+        }
+    }
+
+    /// Writes the causality at `ptr` in the memory of the calling instance, returns the number
+    /// of bytes written or -1 if there is no causality or the range is out of bounds.
+    fn causality_read(mut env: FunctionEnvMut<CausalityEnv>, id: i64, ptr: i64) -> i64 {
+        let (data, store) = env.data_and_store_mut();
+        let (memory, causality) = match data {
+            CausalityEnv {
+                instance: Some((current, memory)),
+                causality: Some(causality),
+            } if *current == id => (memory, causality),
+            _ => return -1,
+        };
+        let view = memory.view(&store);
+        let offset = match u64::try_from(ptr) {
+            Ok(offset) => offset,
+            Err(_) => return -1,
+        };
+        match offset.checked_add(causality.len() as u64) {
+            Some(end) if end <= view.data_size() => {}
+            _ => return -1,
+        }
+        match view.write(offset, causality.as_slice()) {
+            Ok(()) => causality.len() as i64,
+            Err(_) => -1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::causality::*;
+    use wasmer::{Extern, Imports, Memory, MemoryType, Store, Value, WASM_PAGE_SIZE};
+
+    fn read(store: &mut Store, imports: &Imports, id: i64, ptr: i64) -> i64 {
+        let Some(Extern::Function(read)) =
+            imports.get_export("locutus_causality", "causality_read")
+        else {
+            unreachable!()
+        };
+        read.call(store, &[Value::I64(id), Value::I64(ptr)])
+            .unwrap()[0]
+            .unwrap_i64()
+    }
+
+    #[test]
+    fn causality_read_within_instance_memory() {
+        let mut store = Store::default();
+        let mut imports = Imports::new();
+        let env = prepare_export(&mut store, &mut imports);
+        let memory = Memory::new(&mut store, MemoryType::new(1, None, false)).unwrap();
+        let causality = vec![1u8; 16];
+        {
+            let env = env.as_mut(&mut store);
+            env.instance = Some((7, memory.clone()));
+            env.causality = Some(causality.clone());
+        }
+
+        assert_eq!(read(&mut store, &imports, 7, 64), 16);
+        let mut written = vec![0u8; 16];
+        memory.view(&store).read(64, &mut written).unwrap();
+        assert_eq!(written, causality);
+
+        // other instances can't read it
+        assert_eq!(read(&mut store, &imports, 8, 64), -1);
+        assert_eq!(read(&mut store, &imports, -1, 64), -1);
+        // nor write outside the memory of the instance
+        let end = WASM_PAGE_SIZE as i64;
+        assert_eq!(read(&mut store, &imports, 7, end - 8), -1);
+        assert_eq!(read(&mut store, &imports, 7, -1), -1);
+        assert_eq!(read(&mut store, &imports, 7, i64::MAX), -1);
+    }
+}
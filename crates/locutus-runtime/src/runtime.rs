@@ -1,14 +1,24 @@
-use std::{collections::HashMap, sync::atomic::AtomicI64};
+use std::{collections::HashMap, sync::atomic::AtomicI64, time::Duration};
 
 use locutus_stdlib::{
     buf::{BufferBuilder, BufferMut},
     prelude::*,
 };
-use wasmer::{imports, Bytes, Imports, Instance, Memory, MemoryType, Module, Store, TypedFunction};
+use wasmer::{
+    imports, Bytes, FunctionEnv, Imports, Instance, Memory, MemoryType, Module, Store,
+    TypedFunction,
+};
 
 use crate::{
-    contract_store::ContractStore, delegate_store::DelegateStore, error::RuntimeInnerError,
-    native_api, secrets_store::SecretsStore, RuntimeResult,
+    contract::ContractRuntimeInterface,
+    contract_store::ContractStore,
+    delegate_store::DelegateStore,
+    determinism,
+    error::RuntimeInnerError,
+    native_api,
+    scheduler::{ExecutionStats, ExecutionTracker},
+    secrets_store::SecretsStore,
+    RuntimeResult,
 };
 
 static INSTANCE_ID: AtomicI64 = AtomicI64::new(0);
@@ -39,6 +49,7 @@ impl RunningInstance {
         set_id.call(&mut rt.wasm_store, id).unwrap();
         let ptr = memory.view(&rt.wasm_store).data_ptr() as i64;
         native_api::MEM_ADDR.insert(id, ptr);
+        rt.causality_env.as_mut(&mut rt.wasm_store).instance = Some((id, memory.clone()));
         Ok(Self { instance, id })
     }
 }
@@ -59,6 +70,9 @@ pub enum ContractExecError {
 
     #[error("unexpected result from contract interface")]
     UnexpectedResult,
+
+    #[error("contract imports forbidden function `{module}::{name}`")]
+    ForbiddenImport { module: String, name: String },
 }
 
 pub struct Runtime {
@@ -66,6 +80,9 @@ pub struct Runtime {
     pub(crate) wasm_store: Store,
     /// includes all the necessary imports to interact with the native runtime environment
     pub(crate) top_level_imports: Imports,
+    /// imports available to contracts, which exclude the ones depending on the environment
+    /// the contract runs on (e.g. the clock)
+    pub(crate) contract_imports: Imports,
     /// assigned growable host memory
     pub(crate) host_memory: Option<Memory>,
     #[cfg(test)]
@@ -80,6 +97,10 @@ pub struct Runtime {
     pub contract_store: ContractStore,
     /// loaded contract modules
     pub(crate) contract_modules: HashMap<ContractKey, Module>,
+    /// time spent executing each contract
+    pub(crate) execution_stats: ExecutionTracker<ContractKey>,
+    /// causality of the update being applied and the instance allowed to read it
+    pub(crate) causality_env: FunctionEnv<native_api::causality::CausalityEnv>,
 }
 
 impl Runtime {
@@ -101,11 +122,15 @@ impl Runtime {
         } else {
             (None, imports! {})
         };
+        let causality_env =
+            native_api::causality::prepare_export(&mut store, &mut top_level_imports);
+        let contract_imports = top_level_imports.clone();
         native_api::time::prepare_export(&mut store, &mut top_level_imports);
 
         Ok(Self {
             wasm_store: store,
             top_level_imports,
+            contract_imports,
             host_memory,
             #[cfg(test)]
             enable_wasi: false,
@@ -116,9 +141,37 @@ impl Runtime {
 
             contract_store,
             component_modules: HashMap::new(),
+            execution_stats: ExecutionTracker::default(),
+            causality_env,
         })
     }
 
+    /// Sets the causality of the update being applied, available to the contracts executed
+    /// until it is cleared.
+    pub fn set_causality(&mut self, causality: Option<&Causality>) {
+        self.causality_env.as_mut(&mut self.wasm_store).causality =
+            causality.and_then(|causality| bincode::serialize(causality).ok());
+    }
+
+    /// Accumulated execution time of the given contract in this runtime.
+    pub fn execution_stats(&self, key: &ContractKey) -> Option<&ExecutionStats> {
+        self.execution_stats.get(key)
+    }
+
+    /// Accumulated execution time of all the contracts executed recently in this runtime.
+    pub fn all_execution_stats(&self) -> impl Iterator<Item = (&ContractKey, &ExecutionStats)> {
+        self.execution_stats.iter()
+    }
+
+    /// Time spent executing contracts in this runtime.
+    pub fn execution_time(&self) -> Duration {
+        self.execution_stats.total_time()
+    }
+
+    pub(crate) fn record_execution(&mut self, key: &ContractKey, elapsed: Duration) {
+        self.execution_stats.record(key, elapsed);
+    }
+
     pub(crate) fn init_buf<T>(&mut self, instance: &Instance, data: T) -> RuntimeResult<BufferMut>
     where
         T: AsRef<[u8]>,
@@ -163,20 +216,51 @@ impl Runtime {
                 .contract_store
                 .fetch_contract(key, parameters)
                 .ok_or_else(|| RuntimeInnerError::ContractNotFound(key.clone()))?;
-            let module = match contract {
-                ContractContainer::Wasm(WasmAPIVersion::V1(contract_v1)) => {
-                    Module::new(&self.wasm_store, contract_v1.code().data())?
-                }
-            };
+            let module = self.compile_contract(&contract)?;
             self.contract_modules.insert(key.clone(), module);
             self.contract_modules.get(key).unwrap()
         }
         .clone();
-        let instance = self.prepare_instance(&module)?;
+        let instance = self.prepare_instance(&module, true)?;
         self.set_instance_mem(req_bytes, &instance)?;
         RunningInstance::new(self, instance)
     }
 
+    fn compile_contract(&self, contract: &ContractContainer) -> RuntimeResult<Module> {
+        let module = match contract {
+            ContractContainer::Wasm(WasmAPIVersion::V1(contract_v1)) => {
+                Module::new(&self.wasm_store, contract_v1.code().data())?
+            }
+        };
+        determinism::validate_contract(&module)?;
+        Ok(module)
+    }
+
+    /// Validates the initial state of a contract which doesn't need to be in the contract
+    /// store yet, so puts with an invalid state can be rejected before storing or sending
+    /// anything to the network.
+    ///
+    /// The compiled contract is only kept loaded if the state is valid, so storing it
+    /// afterwards doesn't compile it twice while rejected puts can't fill up the modules cache.
+    pub fn prevalidate_put(
+        &mut self,
+        contract: &ContractContainer,
+        state: &WrappedState,
+        related: RelatedContracts,
+    ) -> RuntimeResult<ValidateResult> {
+        let key = contract.key();
+        let loaded = self.contract_modules.contains_key(&key);
+        if !loaded {
+            let module = self.compile_contract(contract)?;
+            self.contract_modules.insert(key.clone(), module);
+        }
+        let result = self.validate_state(&key, &contract.params(), state, related);
+        if !loaded && !matches!(result, Ok(ValidateResult::Valid)) {
+            self.contract_modules.remove(&key);
+        }
+        result
+    }
+
     pub(crate) fn prepare_component_call(
         &mut self,
         key: &DelegateKey,
@@ -194,7 +278,7 @@ impl Runtime {
             self.component_modules.get(key).unwrap()
         }
         .clone();
-        let instance = self.prepare_instance(&module)?;
+        let instance = self.prepare_instance(&module, false)?;
         self.set_instance_mem(req_bytes, &instance)?;
         RunningInstance::new(self, instance)
     }
@@ -226,26 +310,28 @@ impl Runtime {
     }
 
     #[cfg(not(test))]
-    fn prepare_instance(&mut self, module: &Module) -> RuntimeResult<Instance> {
-        Ok(Instance::new(
-            &mut self.wasm_store,
-            module,
-            &self.top_level_imports,
-        )?)
+    fn prepare_instance(&mut self, module: &Module, is_contract: bool) -> RuntimeResult<Instance> {
+        let imports = if is_contract {
+            &self.contract_imports
+        } else {
+            &self.top_level_imports
+        };
+        Ok(Instance::new(&mut self.wasm_store, module, imports)?)
     }
 
     #[cfg(test)]
-    // this fn enables WASI env for debuggability
-    fn prepare_instance(&mut self, module: &Module) -> RuntimeResult<Instance> {
+    // this fn enables WASI env for debuggability, contracts are never instantiated with it
+    fn prepare_instance(&mut self, module: &Module, is_contract: bool) -> RuntimeResult<Instance> {
         use wasmer::namespace;
         use wasmer_wasi::WasiState;
 
-        if !self.enable_wasi {
-            return Ok(Instance::new(
-                &mut self.wasm_store,
-                module,
-                &self.top_level_imports,
-            )?);
+        let imports = if is_contract {
+            &self.contract_imports
+        } else {
+            &self.top_level_imports
+        };
+        if is_contract || !self.enable_wasi {
+            return Ok(Instance::new(&mut self.wasm_store, module, imports)?);
         }
         let mut wasi_env = WasiState::new("locutus").finalize(&mut self.wasm_store)?;
         let mut imports = wasi_env.import_object(&mut self.wasm_store, module)?;
@@ -269,8 +355,7 @@ impl Runtime {
     }
 
     fn instance_store() -> Store {
-        use wasmer::Cranelift;
-        Store::new(Cranelift::new())
+        determinism::deterministic_store()
     }
 
     // #[cfg(not(test))]
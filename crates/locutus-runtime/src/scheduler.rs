@@ -0,0 +1,293 @@
+//! Fair scheduling of contract executions.
+//!
+//! When many contracts have pending invocations, serving them in arrival order lets a single
+//! chatty contract starve everyone else. The [`FairScheduler`] keeps a queue per contract and
+//! serves them round-robin, granting each contract a time slice per turn (deficit round-robin).
+//! Since WASM invocations can't be preempted, contracts which run past their slice accumulate
+//! a debt and skip turns until it has been paid off.
+//!
+//! The execution time of every contract is tracked by the [`ExecutionTracker`] of the runtime,
+//! which only measures the time spent in WASM calls; the scheduler is charged with it.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Execution time a contract is granted on each turn.
+pub const DEFAULT_TIME_SLICE: Duration = Duration::from_millis(10);
+/// Stats of contracts not executed for this long are dropped.
+const STATS_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const STATS_EVICTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Target of the tracing events recording each execution, for metrics collectors.
+pub const METRICS_TARGET: &str = "locutus_runtime::metrics";
+
+/// Accumulated execution time of a contract.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutionStats {
+    pub invocations: u64,
+    pub total_time: Duration,
+    pub max_time: Duration,
+}
+
+impl ExecutionStats {
+    pub fn record(&mut self, elapsed: Duration) {
+        self.invocations += 1;
+        self.total_time += elapsed;
+        self.max_time = self.max_time.max(elapsed);
+    }
+
+    /// Average execution time per invocation.
+    pub fn mean_time(&self) -> Duration {
+        if self.invocations == 0 {
+            return Duration::ZERO;
+        }
+        self.total_time / self.invocations as u32
+    }
+}
+
+/// Execution stats of each contract, forgetting the contracts not executed for a while.
+pub struct ExecutionTracker<K> {
+    stats: HashMap<K, (ExecutionStats, Instant)>,
+    /// Time spent executing any contract, never reset.
+    total_time: Duration,
+    next_eviction: Instant,
+}
+
+impl<K> Default for ExecutionTracker<K> {
+    fn default() -> Self {
+        Self {
+            stats: HashMap::new(),
+            total_time: Duration::ZERO,
+            next_eviction: Instant::now() + STATS_EVICTION_INTERVAL,
+        }
+    }
+}
+
+impl<K> ExecutionTracker<K>
+where
+    K: Hash + Eq + Clone + Display,
+{
+    pub fn record(&mut self, key: &K, elapsed: Duration) {
+        self.record_at(key, elapsed, Instant::now())
+    }
+
+    fn record_at(&mut self, key: &K, elapsed: Duration, now: Instant) {
+        if now >= self.next_eviction {
+            self.stats.retain(|_, (_, last_execution)| {
+                now.duration_since(*last_execution) < STATS_IDLE_TIMEOUT
+            });
+            self.next_eviction = now + STATS_EVICTION_INTERVAL;
+        }
+        let (stats, last_execution) = self
+            .stats
+            .entry(key.clone())
+            .or_insert_with(|| (ExecutionStats::default(), now));
+        stats.record(elapsed);
+        *last_execution = now;
+        self.total_time += elapsed;
+        tracing::trace!(
+            target: METRICS_TARGET,
+            contract = %key,
+            elapsed_us = elapsed.as_micros() as u64,
+            "contract executed"
+        );
+    }
+
+    pub fn get(&self, key: &K) -> Option<&ExecutionStats> {
+        self.stats.get(key).map(|(stats, _)| stats)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &ExecutionStats)> {
+        self.stats.iter().map(|(key, (stats, _))| (key, stats))
+    }
+
+    /// Time spent executing any contract; the difference between two readings is the
+    /// execution time in between.
+    pub fn total_time(&self) -> Duration {
+        self.total_time
+    }
+}
+
+pub struct FairScheduler<K, T> {
+    time_slice: Duration,
+    queues: HashMap<K, VecDeque<T>>,
+    /// Contracts with pending work, in the order they will be served.
+    active: VecDeque<K>,
+    /// Execution time spent beyond the granted time slices.
+    debt: HashMap<K, Duration>,
+}
+
+impl<K, T> Default for FairScheduler<K, T>
+where
+    K: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        Self::new(DEFAULT_TIME_SLICE)
+    }
+}
+
+impl<K, T> FairScheduler<K, T>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new(time_slice: Duration) -> Self {
+        Self {
+            time_slice,
+            queues: HashMap::new(),
+            active: VecDeque::new(),
+            debt: HashMap::new(),
+        }
+    }
+
+    /// Queues a pending invocation for the given contract.
+    pub fn push(&mut self, key: K, task: T) {
+        let queue = self.queues.entry(key.clone()).or_default();
+        if queue.is_empty() {
+            self.active.push_back(key);
+        }
+        queue.push_back(task);
+    }
+
+    /// Picks the next invocation to execute.
+    ///
+    /// Once executed, the time it took must be reported back through [`Self::charge`].
+    pub fn next_task(&mut self) -> Option<(K, T)> {
+        loop {
+            let key = self.active.pop_front()?;
+            if let Some(debt) = self.debt.get_mut(&key) {
+                if *debt >= self.time_slice {
+                    // skip this turn, paying off part of the debt
+                    *debt -= self.time_slice;
+                    self.active.push_back(key);
+                    continue;
+                }
+            }
+            let queue = self.queues.get_mut(&key)?;
+            let task = queue.pop_front()?;
+            if queue.is_empty() {
+                self.queues.remove(&key);
+            } else {
+                self.active.push_back(key.clone());
+            }
+            break Some((key, task));
+        }
+    }
+
+    /// Accounts the execution time of an invocation previously returned by [`Self::next_task`].
+    pub fn charge(&mut self, key: &K, elapsed: Duration) {
+        // contracts without pending invocations can't skip turns to pay off their debt,
+        // the time used by the rest meanwhile does instead
+        let queues = &self.queues;
+        self.debt.retain(|other, debt| {
+            if other != key && !queues.contains_key(other) {
+                *debt = debt.saturating_sub(elapsed);
+            }
+            !debt.is_zero()
+        });
+        let overrun = elapsed.saturating_sub(self.time_slice);
+        if !overrun.is_zero() {
+            *self.debt.entry(key.clone()).or_default() += overrun;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// Number of pending invocations.
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_robin_between_contracts() {
+        let mut scheduler = FairScheduler::new(Duration::from_millis(10));
+        for i in 0..3 {
+            scheduler.push("chatty", i);
+        }
+        scheduler.push("quiet", 0);
+        scheduler.push("other", 0);
+
+        let mut order = vec![];
+        while let Some((key, _)) = scheduler.next_task() {
+            scheduler.charge(&key, Duration::from_millis(1));
+            order.push(key);
+        }
+        assert_eq!(order, ["chatty", "quiet", "other", "chatty", "chatty"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn overrunning_contract_skips_turns() {
+        let mut scheduler = FairScheduler::new(Duration::from_millis(10));
+        for i in 0..3 {
+            scheduler.push("slow", i);
+            scheduler.push("fast", i);
+        }
+
+        let (key, _) = scheduler.next_task().unwrap();
+        assert_eq!(key, "slow");
+        // 2 time slices beyond the granted one
+        scheduler.charge(&key, Duration::from_millis(30));
+
+        let mut order = vec![];
+        while let Some((key, _)) = scheduler.next_task() {
+            scheduler.charge(&key, Duration::from_millis(1));
+            order.push(key);
+        }
+        assert_eq!(order, ["fast", "fast", "fast", "slow", "slow"]);
+    }
+
+    #[test]
+    fn idle_contracts_pay_off_debt() {
+        let mut scheduler = FairScheduler::new(Duration::from_millis(10));
+        scheduler.push("slow", 0);
+        let (key, _) = scheduler.next_task().unwrap();
+        scheduler.charge(&key, Duration::from_millis(30));
+        assert_eq!(scheduler.debt[&"slow"], Duration::from_millis(20));
+
+        scheduler.push("fast", 0);
+        let (key, _) = scheduler.next_task().unwrap();
+        scheduler.charge(&key, Duration::from_millis(15));
+        assert_eq!(scheduler.debt[&"slow"], Duration::from_millis(5));
+        assert_eq!(scheduler.debt[&"fast"], Duration::from_millis(5));
+
+        scheduler.push("fast", 1);
+        let (key, _) = scheduler.next_task().unwrap();
+        scheduler.charge(&key, Duration::from_millis(5));
+        assert!(!scheduler.debt.contains_key(&"slow"));
+    }
+
+    #[test]
+    fn track_execution_stats() {
+        let mut tracker = ExecutionTracker::default();
+        let now = Instant::now();
+        tracker.record_at(&"idle", Duration::from_millis(30), now);
+        tracker.record_at(&"busy", Duration::from_millis(2), now);
+        tracker.record_at(&"busy", Duration::from_millis(4), now);
+        let stats = tracker.get(&"busy").unwrap();
+        assert_eq!(stats.invocations, 2);
+        assert_eq!(stats.max_time, Duration::from_millis(4));
+        assert_eq!(stats.mean_time(), Duration::from_millis(3));
+        assert_eq!(tracker.total_time(), Duration::from_millis(36));
+
+        tracker.record_at(
+            &"busy",
+            Duration::from_millis(1),
+            now + STATS_IDLE_TIMEOUT / 2,
+        );
+        assert!(tracker.get(&"idle").is_some());
+        tracker.record_at(&"busy", Duration::from_millis(1), now + STATS_IDLE_TIMEOUT);
+        assert!(tracker.get(&"idle").is_none());
+        assert_eq!(tracker.get(&"busy").unwrap().invocations, 4);
+        assert_eq!(tracker.total_time(), Duration::from_millis(38));
+    }
+}
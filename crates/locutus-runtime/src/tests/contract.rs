@@ -1,7 +1,13 @@
+use std::sync::Arc;
+
 use locutus_stdlib::prelude::*;
 
 use crate::contract::*;
-use crate::{secrets_store::SecretsStore, tests::setup_test_contract, DelegateStore, Runtime};
+use crate::{
+    secrets_store::SecretsStore,
+    tests::{get_test_module, setup_test_contract},
+    ContractStore, DelegateStore, Runtime,
+};
 
 const TEST_CONTRACT_1: &str = "test_contract_1";
 
@@ -15,7 +21,6 @@ fn validate_state() -> Result<(), Box<dyn std::error::Error>> {
         false,
     )
     .unwrap();
-    runtime.enable_wasi = true; // ENABLE FOR DEBUGGING; requires building for wasi
 
     let is_valid = runtime.validate_state(
         &key,
@@ -36,6 +41,50 @@ fn validate_state() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn prevalidate_put() -> Result<(), Box<dyn std::error::Error>> {
+    let store = ContractStore::new(crate::tests::test_dir("contract"), 10_000)?;
+    let mut runtime = Runtime::build(
+        store,
+        DelegateStore::default(),
+        SecretsStore::default(),
+        false,
+    )
+    .unwrap();
+
+    let contract = ContractContainer::Wasm(WasmAPIVersion::V1(WrappedContract::new(
+        Arc::new(ContractCode::from(get_test_module(TEST_CONTRACT_1)?)),
+        vec![].into(),
+    )));
+    let is_valid = runtime.prevalidate_put(
+        &contract,
+        &WrappedState::new(vec![1, 2, 3, 4]),
+        Default::default(),
+    )?;
+    assert!(is_valid == ValidateResult::Valid);
+    // nothing is stored while validating
+    assert!(runtime
+        .contract_store
+        .fetch_contract(&contract.key(), &contract.params())
+        .is_none());
+    assert!(runtime.contract_modules.contains_key(&contract.key()));
+
+    // the contracts of rejected puts are not kept loaded
+    let contract = ContractContainer::Wasm(WasmAPIVersion::V1(WrappedContract::new(
+        Arc::new(ContractCode::from(get_test_module(TEST_CONTRACT_1)?)),
+        vec![1].into(),
+    )));
+    let not_valid = runtime.prevalidate_put(
+        &contract,
+        &WrappedState::new(vec![1, 0, 0, 1]),
+        Default::default(),
+    )?;
+    assert!(matches!(not_valid, ValidateResult::RequestRelated(_)));
+    assert!(!runtime.contract_modules.contains_key(&contract.key()));
+
+    Ok(())
+}
+
 #[test]
 fn validate_delta() -> Result<(), Box<dyn std::error::Error>> {
     let (store, key) = setup_test_contract(TEST_CONTRACT_1)?;
@@ -46,7 +95,6 @@ fn validate_delta() -> Result<(), Box<dyn std::error::Error>> {
         false,
     )
     .unwrap();
-    runtime.enable_wasi = true; // ENABLE FOR DEBUGGING; requires building for wasi
 
     let is_valid = runtime.validate_delta(
         &key,
@@ -75,7 +123,6 @@ fn update_state() -> Result<(), Box<dyn std::error::Error>> {
         false,
     )
     .unwrap();
-    runtime.enable_wasi = true; // ENABLE FOR DEBUGGING; requires building for wasi
 
     let new_state = runtime
         .update_state(
@@ -100,7 +147,6 @@ fn summarize_state() -> Result<(), Box<dyn std::error::Error>> {
         false,
     )
     .unwrap();
-    runtime.enable_wasi = true; // ENABLE FOR DEBUGGING; requires building for wasi
 
     let summary = runtime.summarize_state(
         &key,
@@ -121,7 +167,6 @@ fn get_state_delta() -> Result<(), Box<dyn std::error::Error>> {
         false,
     )
     .unwrap();
-    runtime.enable_wasi = true; // ENABLE FOR DEBUGGING; requires building for wasi
 
     let delta = runtime.get_state_delta(
         &key,
@@ -0,0 +1,96 @@
+//! The key-value store contract in `modules/key-value`, executed through the runtime.
+
+use std::sync::Arc;
+
+use locutus_kv_interface::{KvDelta, KvStore};
+use locutus_stdlib::prelude::*;
+
+use crate::{
+    contract::ContractRuntimeInterface,
+    tests::{compile_module, repo_dir, CONTRACT_TARGET},
+    ContractStore, DelegateStore, Runtime, SecretsStore,
+};
+
+fn setup_kv_store() -> Result<(Runtime, ContractKey), Box<dyn std::error::Error>> {
+    let path = repo_dir()
+        .join("modules")
+        .join("key-value")
+        .join("contracts")
+        .join("kv-store");
+    let code = compile_module(&path, "locutus_kv_store", CONTRACT_TARGET)?;
+    let mut store = ContractStore::new(crate::tests::test_dir("kv-store"), 10_000_000)?;
+    let contract = ContractContainer::Wasm(WasmAPIVersion::V1(WrappedContract::new(
+        Arc::new(ContractCode::from(code)),
+        Parameters::from(vec![]),
+    )));
+    let key = contract.key();
+    store.store_contract(contract)?;
+    let runtime = Runtime::build(
+        store,
+        DelegateStore::default(),
+        SecretsStore::default(),
+        false,
+    )?;
+    Ok((runtime, key))
+}
+
+fn wrapped(store: KvStore) -> Result<WrappedState, ContractError> {
+    let state: State = store.try_into()?;
+    Ok(WrappedState::new(state.into_bytes()))
+}
+
+fn update(
+    runtime: &mut Runtime,
+    key: &ContractKey,
+    state: &WrappedState,
+    update: UpdateData<'_>,
+) -> Result<WrappedState, Box<dyn std::error::Error>> {
+    let new_state = runtime
+        .update_state(key, &Parameters::from(vec![]), state, &[update])?
+        .unwrap_valid();
+    Ok(WrappedState::new(new_state.into_bytes()))
+}
+
+#[test]
+fn update_and_sync_stores() -> Result<(), Box<dyn std::error::Error>> {
+    let (mut runtime, key) = setup_kv_store()?;
+    let params = Parameters::from(vec![]);
+    let empty = wrapped(KvStore::default())?;
+    assert_eq!(
+        runtime.validate_state(&key, &params, &empty, Default::default())?,
+        ValidateResult::Valid
+    );
+
+    let delta = KvDelta::default().put("a", *b"1", 1).put("b", *b"1", 1);
+    let delta = StateDelta::try_from(delta)?;
+    assert!(runtime.validate_delta(&key, &params, &delta)?);
+    let state = update(&mut runtime, &key, &empty, delta.into())?;
+    let store = KvStore::try_from(State::from(state.as_ref()))?;
+    assert_eq!(store.get("a"), Some(b"1".as_slice()));
+
+    let removal = StateDelta::try_from(KvDelta::default().remove("a", 2))?;
+    let state = update(&mut runtime, &key, &state, removal.into())?;
+    let store = KvStore::try_from(State::from(state.as_ref()))?;
+    assert_eq!(store.get("a"), None);
+    assert_eq!(store.len(), 1);
+
+    // a replica which only has the initial state catches up through its summary
+    let summary = runtime.summarize_state(&key, &params, &empty)?;
+    let missing = runtime.get_state_delta(&key, &params, &state, &summary)?;
+    let replica = update(&mut runtime, &key, &empty, missing.into())?;
+    assert_eq!(replica.as_ref(), state.as_ref());
+    Ok(())
+}
+
+#[test]
+fn reject_invalid_updates() -> Result<(), Box<dyn std::error::Error>> {
+    let (mut runtime, key) = setup_kv_store()?;
+    let params = Parameters::from(vec![]);
+    let delta = StateDelta::try_from(KvDelta::default().put("", *b"1", 1))?;
+    assert!(!runtime.validate_delta(&key, &params, &delta)?);
+    let empty = wrapped(KvStore::default())?;
+    assert!(runtime
+        .update_state(&key, &params, &empty, &[delta.into()])
+        .is_err());
+    Ok(())
+}
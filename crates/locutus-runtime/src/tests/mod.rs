@@ -11,6 +11,7 @@ use locutus_stdlib::prelude::{
 use crate::ContractStore;
 
 mod contract;
+mod kv_store;
 mod time;
 
 static TEST_NO: AtomicUsize = AtomicUsize::new(0);
@@ -26,32 +27,58 @@ pub(crate) fn test_dir(prefix: &str) -> PathBuf {
     test_dir
 }
 
+/// Target contracts are built for, they can't import WASI functions.
+const CONTRACT_TARGET: &str = "wasm32-unknown-unknown";
+/// Target delegates are built for, so they can use WASI while debugging.
+const WASI_TARGET: &str = "wasm32-wasi";
+
 pub(crate) fn get_test_module(name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let module_path = {
-        const CONTRACTS_DIR: &str = env!("CARGO_MANIFEST_DIR");
-        let contracts = PathBuf::from(CONTRACTS_DIR);
-        let mut dirs = contracts.ancestors();
-        let path = dirs.nth(2).unwrap();
-        path.join("tests").join(name.replace('_', "-"))
-    };
+    compile_test_module(name, CONTRACT_TARGET)
+}
+
+pub(crate) fn get_test_delegate(name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    compile_test_module(name, WASI_TARGET)
+}
+
+/// Root of the repository.
+fn repo_dir() -> PathBuf {
+    const CONTRACTS_DIR: &str = env!("CARGO_MANIFEST_DIR");
+    let contracts = PathBuf::from(CONTRACTS_DIR);
+    let mut dirs = contracts.ancestors();
+    dirs.nth(2).unwrap().to_path_buf()
+}
+
+fn compile_test_module(
+    name: &str,
+    target_triple: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let module_path = repo_dir().join("tests").join(name.replace('_', "-"));
+    compile_module(&module_path, name, target_triple)
+}
+
+/// Compiles the crate at the given path, `name` being the name of its library.
+pub(crate) fn compile_module(
+    module_path: &Path,
+    name: &str,
+    target_triple: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     const TARGET_DIR_VAR: &str = "CARGO_TARGET_DIR";
     let target = std::env::var(TARGET_DIR_VAR).map_err(|_| "CARGO_TARGET_DIR should be set")?;
     println!("trying to compile the test contract, target: {target}");
     // attempt to compile it
     const RUST_TARGET_ARGS: &[&str] = &["build", "--target"];
-    const WASI_TARGET: &str = "wasm32-wasi";
     let cmd_args = RUST_TARGET_ARGS
         .iter()
         .copied()
-        .chain([WASI_TARGET])
+        .chain([target_triple])
         .collect::<Vec<_>>();
     let mut child = Command::new("cargo")
         .args(&cmd_args)
-        .current_dir(&module_path)
+        .current_dir(module_path)
         .spawn()?;
     child.wait()?;
     let output_file = Path::new(&target)
-        .join("wasm32-wasi")
+        .join(target_triple)
         .join("debug")
         .join(name)
         .with_extension("wasm");
@@ -1,11 +1,10 @@
-//! A test WASM module that checkes that the `time` module in the std lib works correctly.
-
-use wasmer::TypedFunction;
+//! A test WASM module importing the `time` module of the std lib, which contracts can't use
+//! since the time differs between peers.
 
 use crate::{DelegateStore, Runtime, SecretsStore};
 
 #[test]
-fn now() -> Result<(), Box<dyn std::error::Error>> {
+fn reject_contract_importing_time() -> Result<(), Box<dyn std::error::Error>> {
     let (store, key) = super::setup_test_contract("test_contract_2")?;
     let mut runtime = Runtime::build(
         store,
@@ -14,14 +13,13 @@ fn now() -> Result<(), Box<dyn std::error::Error>> {
         false,
     )
     .unwrap();
-    runtime.enable_wasi = true;
 
-    let module = runtime.prepare_contract_call(&key, &vec![].into(), 1_000)?;
-    let f: TypedFunction<(), ()> = module
-        .instance
-        .exports
-        .get_function("time_func")?
-        .typed(&runtime.wasm_store)?;
-    f.call(&mut runtime.wasm_store)?;
+    let err = runtime
+        .prepare_contract_call(&key, &vec![].into(), 1_000)
+        .err()
+        .expect("the contract imports the clock");
+    assert!(err.is_contract_exec_error(), "unexpected error: {err}");
+    assert!(err.to_string().contains("`locutus_time::utc_now`"));
+    assert!(runtime.contract_modules.is_empty());
     Ok(())
 }
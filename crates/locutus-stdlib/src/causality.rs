@@ -0,0 +1,241 @@
+//! Clock-free ordering of contract updates.
+//!
+//! Peers can't rely on their clocks to order the updates to a contract, so every update is
+//! tagged with a [`Causality`]: an id derived from the update content and the ids of the
+//! updates its originator had already applied (happened-before references), plus its depth in
+//! the resulting causal graph. Ordering updates by depth first and id second gives all
+//! subscribers the same total order, consistent with the happened-before relation, no matter
+//! in which order they received them.
+//!
+//! While applying an update, contracts can access its causality through `current`.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+};
+
+use blake2::{Blake2s256, Digest};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of updates remembered by a [`CausalHistory`].
+const MAX_HISTORY: usize = 1024;
+
+/// Identifies an update to a contract.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, Debug)]
+pub struct UpdateId([u8; 32]);
+
+impl Display for UpdateId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoded = bs58::encode(self.0)
+            .with_alphabet(bs58::Alphabet::BITCOIN)
+            .into_string();
+        write!(f, "{encoded}")
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct Causality {
+    id: UpdateId,
+    /// Updates which happened before this one.
+    parents: Vec<UpdateId>,
+    /// Length of the longest chain of updates leading to this one.
+    depth: u64,
+}
+
+impl Causality {
+    fn new(mut parents: Vec<UpdateId>, depth: u64, update: &[u8]) -> Self {
+        parents.sort_unstable();
+        parents.dedup();
+        let mut hasher = Blake2s256::new();
+        for parent in &parents {
+            hasher.update(parent.0);
+        }
+        hasher.update(depth.to_le_bytes());
+        hasher.update(update);
+        Self {
+            id: UpdateId(hasher.finalize().into()),
+            parents,
+            depth,
+        }
+    }
+
+    pub fn id(&self) -> UpdateId {
+        self.id
+    }
+
+    pub fn parents(&self) -> &[UpdateId] {
+        &self.parents
+    }
+
+    pub fn depth(&self) -> u64 {
+        self.depth
+    }
+}
+
+impl PartialOrd for Causality {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Total order consistent with the happened-before relation; concurrent updates are ordered
+/// by id.
+impl Ord for Causality {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.depth
+            .cmp(&other.depth)
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+/// Updates to a contract observed by a peer.
+#[derive(Clone, Default, Debug)]
+pub struct CausalHistory {
+    /// Known updates which no other known update succeeds, with their depth.
+    heads: Vec<(UpdateId, u64)>,
+    parents: HashMap<UpdateId, Vec<UpdateId>>,
+    /// Number of known updates referencing each update as a parent.
+    references: HashMap<UpdateId, usize>,
+    /// Known updates, oldest first.
+    order: VecDeque<UpdateId>,
+}
+
+impl CausalHistory {
+    /// Causality of a new update which succeeds all the updates known.
+    pub fn next(&self, update: &[u8]) -> Causality {
+        let parents = self.heads.iter().map(|(id, _)| *id).collect();
+        let depth = self.heads.iter().map(|(_, depth)| depth + 1).max();
+        Causality::new(parents, depth.unwrap_or_default(), update)
+    }
+
+    /// Records an update, returning `false` if it was already known.
+    pub fn record(&mut self, causality: &Causality) -> bool {
+        if self.parents.contains_key(&causality.id) {
+            return false;
+        }
+        self.heads.retain(|(id, _)| !causality.parents.contains(id));
+        if !self.references.contains_key(&causality.id) {
+            // otherwise an already known update succeeds it
+            self.heads.push((causality.id, causality.depth));
+            self.heads.sort_unstable();
+        }
+        for parent in &causality.parents {
+            *self.references.entry(*parent).or_default() += 1;
+        }
+        self.parents.insert(causality.id, causality.parents.clone());
+        self.order.push_back(causality.id);
+        if self.order.len() > MAX_HISTORY {
+            self.forget_oldest();
+        }
+        true
+    }
+
+    pub fn contains(&self, id: &UpdateId) -> bool {
+        self.parents.contains_key(id)
+    }
+
+    /// Whether `a` happened before `b`, as far as the remembered history tells.
+    pub fn happened_before(&self, a: &UpdateId, b: &UpdateId) -> bool {
+        let mut pending: Vec<_> = self.parents.get(b).into_iter().flatten().collect();
+        let mut visited = HashSet::new();
+        while let Some(id) = pending.pop() {
+            if id == a {
+                return true;
+            }
+            if visited.insert(id) {
+                pending.extend(self.parents.get(id).into_iter().flatten());
+            }
+        }
+        false
+    }
+
+    fn forget_oldest(&mut self) {
+        let oldest = match self.order.pop_front() {
+            Some(oldest) => oldest,
+            None => return,
+        };
+        for parent in self.parents.remove(&oldest).into_iter().flatten() {
+            if let Some(count) = self.references.get_mut(&parent) {
+                *count -= 1;
+                if *count == 0 {
+                    self.references.remove(&parent);
+                }
+            }
+        }
+    }
+}
+
+/// Causality of the update being applied by the contract, if any.
+#[cfg(target_family = "wasm")]
+pub fn current() -> Option<Causality> {
+    let id = unsafe { crate::global::INSTANCE_ID };
+    let len = unsafe { causality_len(id) };
+    if len < 0 {
+        return None;
+    }
+    let mut buf = vec![0u8; len as usize];
+    if unsafe { causality_read(id, buf.as_mut_ptr() as usize as i64) } != len {
+        return None;
+    }
+    bincode::deserialize(&buf).ok()
+}
+
+#[cfg(target_family = "wasm")]
+#[link(wasm_import_module = "locutus_causality")]
+extern "C" {
+    #[doc(hidden)]
+    fn causality_len(id: i64) -> i64;
+    #[doc(hidden)]
+    fn causality_read(id: i64, ptr: i64) -> i64;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn order_concurrent_updates() {
+        let mut history = CausalHistory::default();
+        let first = history.next(b"first");
+        history.record(&first);
+
+        // two peers produce updates concurrently after the first one
+        let (a, b) = (history.next(b"a"), history.next(b"b"));
+        assert_eq!(a.parents(), [first.id()]);
+
+        let mut other = history.clone();
+        history.record(&a);
+        history.record(&b);
+        other.record(&b);
+        other.record(&a);
+        assert_eq!(history.heads, other.heads);
+
+        let merge = history.next(b"merge");
+        assert_eq!(merge.depth(), 2);
+        assert!(history.happened_before(&first.id(), &a.id()));
+        history.record(&merge);
+        assert!(history.happened_before(&first.id(), &merge.id()));
+        assert!(!history.happened_before(&a.id(), &b.id()));
+
+        let mut updates = vec![merge.clone(), b.clone(), a.clone(), first.clone()];
+        updates.sort();
+        assert_eq!(updates[0], first);
+        assert_eq!(updates[3], merge);
+        assert_eq!(a.cmp(&b), a.id().cmp(&b.id()));
+    }
+
+    #[test]
+    fn late_updates_do_not_become_heads() {
+        let mut history = CausalHistory::default();
+        let first = history.next(b"first");
+        let mut origin = CausalHistory::default();
+        origin.record(&first);
+        let second = origin.next(b"second");
+
+        assert!(history.record(&second));
+        assert!(history.record(&first));
+        assert!(!history.record(&first));
+        assert_eq!(history.heads, [(second.id(), 1)]);
+    }
+}
@@ -11,6 +11,8 @@
 //!   - `browser` (wasm): Via wasm-bindgen (and by extension web-sys).
 //!               (In order to use this client from JS/Typescript refer to the Typescript std lib).
 mod client_events;
+mod node_admin;
+mod node_query;
 
 #[cfg(any(unix, windows))]
 mod regular;
@@ -23,6 +25,8 @@ mod browser;
 pub use browser::*;
 
 pub use client_events::*;
+pub use node_admin::*;
+pub use node_query::*;
 
 type HostResult = Result<HostResponse, ClientError>;
 
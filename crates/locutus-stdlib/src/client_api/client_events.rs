@@ -1,12 +1,21 @@
-use std::{collections::HashMap, fmt::Display, io::Cursor};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::Cursor,
+    time::Duration,
+};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use super::{
+    node_admin::{AdminResponse, AdminToken, NodeAdmin},
+    node_query::{NodeQuery, PeerInfo, QueryResponse},
+};
 use crate::{
     delegate_interface::{Delegate, DelegateKey, InboundDelegateMsg, OutboundDelegateMsg},
     prelude::{
-        ContractKey, RelatedContracts, StateSummary, TryFromTsStd, UpdateData, WrappedState,
-        WsApiError,
+        Causality, ContractError, ContractInterface, ContractKey, RelatedContracts, State,
+        StateSummary, TryFromTsStd, UpdateData, ValidateResult, WrappedState, WsApiError,
     },
     versioning::ContractContainer,
 };
@@ -72,8 +81,23 @@ impl std::error::Error for ClientError {}
 pub enum ClientRequest<'a> {
     DelegateOp(#[serde(borrow)] DelegateRequest<'a>),
     ContractOp(#[serde(borrow)] ContractRequest<'a>),
-    GenerateRandData { bytes: usize },
-    Disconnect { cause: Option<String> },
+    GenerateRandData {
+        bytes: usize,
+    },
+    SubscribeNodeEvents,
+    SampleNetwork {
+        walk_length: usize,
+    },
+    NodeQueries(NodeQuery),
+    /// Administration of the node, only served if the token matches the one configured in
+    /// the node.
+    NodeAdmin {
+        request: NodeAdmin,
+        token: AdminToken,
+    },
+    Disconnect {
+        cause: Option<String>,
+    },
 }
 
 impl ClientRequest<'_> {
@@ -113,6 +137,14 @@ impl ClientRequest<'_> {
                 ClientRequest::DelegateOp(op)
             }
             ClientRequest::GenerateRandData { bytes } => ClientRequest::GenerateRandData { bytes },
+            ClientRequest::SubscribeNodeEvents => ClientRequest::SubscribeNodeEvents,
+            ClientRequest::SampleNetwork { walk_length } => {
+                ClientRequest::SampleNetwork { walk_length }
+            }
+            ClientRequest::NodeQueries(query) => ClientRequest::NodeQueries(query),
+            ClientRequest::NodeAdmin { request, token } => {
+                ClientRequest::NodeAdmin { request, token }
+            }
             ClientRequest::Disconnect { cause } => ClientRequest::Disconnect { cause },
         }
     }
@@ -157,6 +189,26 @@ impl<'a> From<ContractRequest<'a>> for ClientRequest<'a> {
     }
 }
 
+impl ContractRequest<'_> {
+    /// Runs the validation of contract `C` against the state of a put locally, so applications
+    /// linking the contract can reject an invalid put right away instead of after submitting
+    /// it to the network. Other requests are always valid.
+    pub fn prevalidate<C: ContractInterface>(&self) -> Result<ValidateResult, ContractError> {
+        match self {
+            ContractRequest::Put {
+                contract,
+                state,
+                related_contracts,
+            } => C::validate_state(
+                contract.params(),
+                State::from(state.as_ref().to_vec()),
+                related_contracts.clone().into_owned(),
+            ),
+            _ => Ok(ValidateResult::Valid),
+        }
+    }
+}
+
 /// Deserializes a `ContractRequest` from a MessagePack encoded request.
 impl<'a> TryFromTsStd<&[u8]> for ContractRequest<'a> {
     fn try_decode(msg: &[u8]) -> Result<Self, WsApiError> {
@@ -209,7 +261,11 @@ impl<'a> TryFromTsStd<&[u8]> for ContractRequest<'a> {
                         key: ContractKey::try_decode(*value_map.get("key").unwrap())
                             .map_err(|err| WsApiError::deserialization(err.to_string()))?,
                     },
-                    _ => unreachable!(),
+                    _ => {
+                        return Err(WsApiError::MsgpackDecodeError {
+                            cause: format!("unknown contract request: {map_keys:?}"),
+                        })
+                    }
                 }
             } else {
                 return Err(WsApiError::MsgpackDecodeError {
@@ -222,6 +278,17 @@ impl<'a> TryFromTsStd<&[u8]> for ContractRequest<'a> {
     }
 }
 
+/// Deserializes a `ClientRequest` from a MessagePack encoded request, either serialized as the
+/// rust client does or a contract request as the typescript stdlib encodes it.
+impl<'a> TryFromTsStd<&[u8]> for ClientRequest<'a> {
+    fn try_decode(msg: &[u8]) -> Result<Self, WsApiError> {
+        match rmp_serde::from_slice::<ClientRequest>(msg) {
+            Ok(req) => Ok(req.into_owned()),
+            Err(_) => ContractRequest::try_decode(msg).map(Into::into),
+        }
+    }
+}
+
 impl<'a> From<DelegateRequest<'a>> for ClientRequest<'a> {
     fn from(op: DelegateRequest<'a>) -> Self {
         ClientRequest::DelegateOp(op)
@@ -291,6 +358,12 @@ impl Display for ClientRequest<'_> {
             ClientRequest::DelegateOp(_op) => write!(f, "component request"),
             ClientRequest::Disconnect { .. } => write!(f, "client disconnected"),
             ClientRequest::GenerateRandData { bytes } => write!(f, "generate {bytes} random bytes"),
+            ClientRequest::SubscribeNodeEvents => write!(f, "subscribe to node events"),
+            ClientRequest::SampleNetwork { walk_length } => {
+                write!(f, "sample the network ({walk_length} hops)")
+            }
+            ClientRequest::NodeQueries(query) => write!(f, "{query}"),
+            ClientRequest::NodeAdmin { request, .. } => write!(f, "{request}"),
         }
     }
 }
@@ -304,6 +377,14 @@ pub enum HostResponse<T = WrappedState, U = Vec<u8>> {
         values: Vec<OutboundDelegateMsg>,
     },
     GenerateRandData(U),
+    /// Message sent to clients subscribed to the node events.
+    NodeEvent(NodeNotification),
+    /// Result of a [`ClientRequest::SampleNetwork`] request.
+    NetworkSample(NetworkSample),
+    /// Result of a [`ClientRequest::NodeQueries`] request.
+    QueryResponse(QueryResponse),
+    /// Result of a [`ClientRequest::NodeAdmin`] request.
+    AdminResponse(AdminResponse),
     /// A requested action which doesn't require an answer was performed successfully.
     Ok,
 }
@@ -318,9 +399,12 @@ impl HostResponse {
     }
 
     pub fn unwrap_get(self) -> (WrappedState, Option<ContractContainer>) {
-        if let Self::ContractResponse(ContractResponse::GetResponse {
-            contract, state, ..
-        }) = self
+        if let Self::ContractResponse(
+            ContractResponse::GetResponse { contract, state }
+            | ContractResponse::KeyedGetResponse {
+                contract, state, ..
+            },
+        ) = self
         {
             (state, contract)
         } else {
@@ -336,12 +420,24 @@ impl std::fmt::Display for HostResponse {
                 ContractResponse::PutResponse { key } => {
                     f.write_fmt(format_args!("put response: {key}"))
                 }
+                ContractResponse::PartialPutResponse {
+                    key,
+                    confirmed,
+                    failed,
+                } => f.write_fmt(format_args!(
+                    "partial put response: {key} ({} confirmed, {} failed)",
+                    confirmed.len(),
+                    failed.len()
+                )),
                 ContractResponse::UpdateResponse { key, .. } => {
                     f.write_fmt(format_args!("update response ({key})"))
                 }
                 ContractResponse::GetResponse { state, .. } => {
                     f.write_fmt(format_args!("get response: {state}"))
                 }
+                ContractResponse::KeyedGetResponse { key, state, .. } => {
+                    f.write_fmt(format_args!("get response ({key}): {state}"))
+                }
                 ContractResponse::UpdateNotification { key, .. } => {
                     f.write_fmt(format_args!("update notification (key: {key})"))
                 }
@@ -349,6 +445,10 @@ impl std::fmt::Display for HostResponse {
             HostResponse::DelegateResponse { .. } => write!(f, "component responses"),
             HostResponse::Ok => write!(f, "ok response"),
             HostResponse::GenerateRandData(_) => write!(f, "random bytes"),
+            HostResponse::NodeEvent(ev) => write!(f, "node event: {ev}"),
+            HostResponse::NetworkSample(sample) => write!(f, "network sample: {sample}"),
+            HostResponse::QueryResponse(res) => write!(f, "query response: {res}"),
+            HostResponse::AdminResponse(res) => write!(f, "admin response: {res}"),
         }
     }
 }
@@ -356,6 +456,8 @@ impl std::fmt::Display for HostResponse {
 // todo: add a `AsBytes` trait for state representations
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum ContractResponse<T = WrappedState> {
+    /// State of a contract, as sent to the clients of the unversioned websocket API
+    /// (`/contract/command/`), which can't tell the contract it belongs to.
     GetResponse {
         contract: Option<ContractContainer>,
         #[serde(bound(deserialize = "T: DeserializeOwned"))]
@@ -364,11 +466,22 @@ pub enum ContractResponse<T = WrappedState> {
     PutResponse {
         key: ContractKey,
     },
+    /// A put which was only confirmed by some of the targeted replicas before the deadline,
+    /// the client can decide whether to retry it or not.
+    PartialPutResponse {
+        key: ContractKey,
+        /// Replicas which confirmed the new value.
+        confirmed: Vec<String>,
+        /// Replicas which failed or did not confirm in time.
+        failed: Vec<String>,
+    },
     /// Message sent when there is an update to a subscribed contract.
     UpdateNotification {
         key: ContractKey,
         #[serde(deserialize_with = "ContractResponse::<T>::deser_update_data")]
         update: UpdateData<'static>,
+        /// Allows ordering concurrent updates consistently across subscribers.
+        causality: Causality,
     },
     /// Successful update
     UpdateResponse {
@@ -376,6 +489,15 @@ pub enum ContractResponse<T = WrappedState> {
         #[serde(deserialize_with = "ContractResponse::<T>::deser_state")]
         summary: StateSummary<'static>,
     },
+    /// State of a contract, as sent to the clients of the versioned websocket API
+    /// (`/v1/contract/command/`), tagged with the key of the contract so responses to
+    /// concurrent gets can be told apart.
+    KeyedGetResponse {
+        key: ContractKey,
+        contract: Option<ContractContainer>,
+        #[serde(bound(deserialize = "T: DeserializeOwned"))]
+        state: T,
+    },
 }
 
 impl<T> ContractResponse<T> {
@@ -401,3 +523,237 @@ impl<T> From<ContractResponse<T>> for HostResponse<T> {
         HostResponse::ContractResponse(value)
     }
 }
+
+/// Global network properties estimated from a random walk through the ring, sent as
+/// [`HostResponse::NetworkSample`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct NetworkSample {
+    /// Number of peers visited by the walk, counting repeated visits.
+    pub samples: usize,
+    /// Estimated number of peers in the network, unknown if no peer was sampled twice.
+    pub size: Option<usize>,
+    /// Number of visited peers running each node version.
+    pub versions: BTreeMap<String, usize>,
+    pub avg_connections: f64,
+    /// Average latency of a single hop of the walk.
+    pub hop_latency: Duration,
+}
+
+impl Display for NetworkSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.size {
+            Some(size) => write!(f, "~{size} peers")?,
+            None => write!(f, "unknown size")?,
+        }
+        write!(
+            f,
+            " ({} samples, {:.1} avg. connections, {}ms per hop), versions: {:?}",
+            self.samples,
+            self.avg_connections,
+            self.hop_latency.as_millis(),
+            self.versions
+        )
+    }
+}
+
+/// Lifecycle and network events of the node, sent to clients subscribed through
+/// [`ClientRequest::SubscribeNodeEvents`] as [`HostResponse::NodeEvent`] via their
+/// notification channel.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum NodeNotification {
+    /// A connection with a peer in the ring was established.
+    PeerConnected { peer: String },
+    /// A connection with a peer in the ring was dropped.
+    PeerDisconnected { peer: String },
+    /// The node joined the ring and has been assigned a location.
+    RingJoined { location: f64 },
+    /// The node stopped caching the given contract.
+    CacheEviction { key: ContractKey },
+    /// A resource of the node reached its configured limit.
+    QuotaWarning {
+        quota: NodeQuota,
+        used: usize,
+        limit: usize,
+    },
+    /// An operation has been pending for several times its expected duration.
+    StuckOperation(Box<OpDiagnostics>),
+    /// The node started accepting connections at the given address.
+    ListenerStarted { address: String },
+    /// The node stopped accepting new connections at the given addresses.
+    ListenerClosed { addresses: Vec<String> },
+    /// A listener couldn't be started or failed.
+    ListenerFailed { address: String, cause: String },
+}
+
+impl Display for NodeNotification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeNotification::PeerConnected { peer } => write!(f, "connected to peer {peer}"),
+            NodeNotification::PeerDisconnected { peer } => {
+                write!(f, "disconnected from peer {peer}")
+            }
+            NodeNotification::RingJoined { location } => {
+                write!(f, "joined the ring at location {location}")
+            }
+            NodeNotification::CacheEviction { key } => write!(f, "evicted contract {key}"),
+            NodeNotification::QuotaWarning { quota, used, limit } => {
+                write!(f, "{quota} quota reached ({used}/{limit})")
+            }
+            NodeNotification::StuckOperation(diagnostics) => write!(
+                f,
+                "{} operation {} stuck for {:?}",
+                diagnostics.operation, diagnostics.transaction, diagnostics.elapsed
+            ),
+            NodeNotification::ListenerStarted { address } => write!(f, "listening at {address}"),
+            NodeNotification::ListenerClosed { addresses } => {
+                write!(f, "stopped listening at {}", addresses.join(", "))
+            }
+            NodeNotification::ListenerFailed { address, cause } => {
+                write!(f, "listener at {address} failed: {cause}")
+            }
+        }
+    }
+}
+
+/// Diagnostics captured by the node about an operation which seems to be stuck, sent as
+/// [`NodeNotification::StuckOperation`].
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct OpDiagnostics {
+    pub transaction: String,
+    /// Kind of operation, like `Get` or `Put`.
+    pub operation: String,
+    /// Time since the operation was started.
+    pub elapsed: Duration,
+    /// Usual duration of this kind of operation.
+    pub expected: Duration,
+    /// State of the operation at this node.
+    pub state: String,
+    /// Messages of the operation recently handled by this node, oldest first.
+    pub recent_messages: Vec<String>,
+    /// Peers this node was connected to at the time.
+    pub neighbours: Vec<PeerInfo>,
+}
+
+/// Resources of the node which are limited and can trigger a [`NodeNotification::QuotaWarning`].
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NodeQuota {
+    /// Open connections with other peers.
+    Connections,
+    /// Subscribers for a given contract.
+    Subscribers,
+}
+
+impl Display for NodeQuota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeQuota::Connections => write!(f, "connections"),
+            NodeQuota::Subscribers => write!(f, "subscribers"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::prelude::{
+        ContractCode, Parameters, StateDelta, UpdateModification, WasmAPIVersion, WrappedContract,
+    };
+
+    /// Append-only log which must not be empty.
+    struct NonEmpty;
+
+    impl ContractInterface for NonEmpty {
+        fn validate_state(
+            _parameters: Parameters<'static>,
+            state: State<'static>,
+            _related: RelatedContracts<'static>,
+        ) -> Result<ValidateResult, ContractError> {
+            if state.is_empty() {
+                Ok(ValidateResult::Invalid)
+            } else {
+                Ok(ValidateResult::Valid)
+            }
+        }
+
+        fn validate_delta(
+            _parameters: Parameters<'static>,
+            delta: StateDelta<'static>,
+        ) -> Result<bool, ContractError> {
+            Ok(!delta.is_empty())
+        }
+
+        fn update_state(
+            _parameters: Parameters<'static>,
+            state: State<'static>,
+            data: Vec<UpdateData<'static>>,
+        ) -> Result<UpdateModification<'static>, ContractError> {
+            let mut state = state.as_ref().to_vec();
+            for update in data {
+                match update {
+                    UpdateData::State(new_state) => state = new_state.as_ref().to_vec(),
+                    UpdateData::Delta(delta) | UpdateData::StateAndDelta { delta, .. } => {
+                        state.extend_from_slice(delta.as_ref())
+                    }
+                    _ => return Err(ContractError::InvalidUpdate),
+                }
+            }
+            Ok(UpdateModification::valid(State::from(state)))
+        }
+
+        /// The length of the log.
+        fn summarize_state(
+            _parameters: Parameters<'static>,
+            state: State<'static>,
+        ) -> Result<StateSummary<'static>, ContractError> {
+            let len = state.len() as u64;
+            Ok(StateSummary::from(len.to_le_bytes().to_vec()))
+        }
+
+        /// The entries appended since the log had the summarized length.
+        fn get_state_delta(
+            _parameters: Parameters<'static>,
+            state: State<'static>,
+            summary: StateSummary<'static>,
+        ) -> Result<StateDelta<'static>, ContractError> {
+            let len = <[u8; 8]>::try_from(summary.as_ref())
+                .map(u64::from_le_bytes)
+                .map_err(|_| ContractError::InvalidState)?;
+            let appended = state
+                .get(len as usize..)
+                .ok_or(ContractError::InvalidState)?;
+            Ok(StateDelta::from(appended.to_vec()))
+        }
+    }
+
+    #[test]
+    fn prevalidate_put() -> Result<(), ContractError> {
+        let contract = ContractContainer::Wasm(WasmAPIVersion::V1(WrappedContract::new(
+            Arc::new(ContractCode::from(vec![0, 1, 2])),
+            Parameters::from(vec![]),
+        )));
+        let put = |state: Vec<u8>| ContractRequest::Put {
+            contract: contract.clone(),
+            state: WrappedState::new(state),
+            related_contracts: Default::default(),
+        };
+        assert_eq!(
+            put(vec![1]).prevalidate::<NonEmpty>()?,
+            ValidateResult::Valid
+        );
+        assert_eq!(
+            put(vec![]).prevalidate::<NonEmpty>()?,
+            ValidateResult::Invalid
+        );
+
+        let get = ContractRequest::Get {
+            key: contract.key(),
+            fetch_contract: false,
+        };
+        assert_eq!(get.prevalidate::<NonEmpty>()?, ValidateResult::Valid);
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Administration of a running node.
+//!
+//! Changes are applied asynchronously by the node, which confirms them right away with an
+//! [`AdminResponse`] and reports their outcome as node events to the clients subscribed through
+//! [`ClientRequest::SubscribeNodeEvents`].
+//!
+//! The client API is shared with the web applications run by the node, so administration
+//! requests are only served when they carry the [`AdminToken`] configured in the node.
+//!
+//! [`ClientRequest::SubscribeNodeEvents`]: super::ClientRequest::SubscribeNodeEvents
+
+use std::fmt::{Debug, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// Administration request sent through a [`ClientRequest::NodeAdmin`], answered with a
+/// [`HostResponse::AdminResponse`].
+///
+/// [`ClientRequest::NodeAdmin`]: super::ClientRequest::NodeAdmin
+/// [`HostResponse::AdminResponse`]: super::HostResponse::AdminResponse
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeAdmin {
+    /// Start accepting connections at the given address (in multiaddr format, e.g.
+    /// `/ip4/0.0.0.0/tcp/31337`), reported with a [`NodeNotification::ListenerStarted`].
+    ///
+    /// [`NodeNotification::ListenerStarted`]: super::NodeNotification::ListenerStarted
+    AddListener { address: String },
+    /// Stop accepting new connections at the given address, either the one used when the
+    /// listener was added or any of the addresses it is listening at. Connections already
+    /// established through the listener are kept until closed. Reported with a
+    /// [`NodeNotification::ListenerClosed`].
+    ///
+    /// [`NodeNotification::ListenerClosed`]: super::NodeNotification::ListenerClosed
+    RemoveListener { address: String },
+}
+
+impl Display for NodeAdmin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeAdmin::AddListener { address } => write!(f, "add listener at {address}"),
+            NodeAdmin::RemoveListener { address } => write!(f, "remove listener at {address}"),
+        }
+    }
+}
+
+/// Credential which authorizes the [`NodeAdmin`] requests sent to a node.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdminToken(String);
+
+impl AdminToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    /// Compares both tokens in constant time, so the configured one can't be guessed by
+    /// timing the responses.
+    pub fn matches(&self, other: &AdminToken) -> bool {
+        let (a, b) = (self.0.as_bytes(), other.0.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+impl Debug for AdminToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AdminToken(..)")
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AdminResponse {
+    /// The change was accepted and is being applied.
+    Accepted,
+    /// The change was rejected.
+    Rejected { cause: String },
+}
+
+impl Display for AdminResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminResponse::Accepted => write!(f, "accepted"),
+            AdminResponse::Rejected { cause } => write!(f, "rejected: {cause}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn match_tokens() {
+        let token = AdminToken::new("secret");
+        assert!(token.matches(&AdminToken::new("secret")));
+        assert!(!token.matches(&AdminToken::new("secreT")));
+        assert!(!token.matches(&AdminToken::new("secret2")));
+        assert_eq!(format!("{token:?}"), "AdminToken(..)");
+    }
+}
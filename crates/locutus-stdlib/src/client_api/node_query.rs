@@ -0,0 +1,357 @@
+//! Introspection of the node state.
+//!
+//! A node may be connected to many peers and cache thousands of contracts, so results are
+//! never returned as a whole: they are filtered and sorted following the [`QueryFilter`]
+//! and returned one [`Page`] at a time. Pages are requested with the cursor returned along
+//! the previous page, so items added or removed meanwhile don't shift the following pages.
+
+use std::{cmp::Ordering, fmt::Display, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::ContractKey;
+
+/// Upper bound to the number of items returned in a single page.
+pub const MAX_PAGE_SIZE: usize = 1000;
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Introspection request sent through a [`ClientRequest::NodeQueries`], answered with a
+/// [`HostResponse::QueryResponse`].
+///
+/// [`ClientRequest::NodeQueries`]: super::ClientRequest::NodeQueries
+/// [`HostResponse::QueryResponse`]: super::HostResponse::QueryResponse
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum NodeQuery {
+    /// Peers this node is connected to.
+    ConnectedPeers {
+        filter: QueryFilter,
+        page: PageRequest,
+    },
+    /// Contracts cached by this node.
+    CachedContracts {
+        filter: QueryFilter,
+        page: PageRequest,
+    },
+}
+
+impl Display for NodeQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (target, page) = match self {
+            NodeQuery::ConnectedPeers { page, .. } => ("connected peers", page),
+            NodeQuery::CachedContracts { page, .. } => ("cached contracts", page),
+        };
+        write!(f, "query {target} (limit: {}", page.limit)?;
+        if let Some(cursor) = &page.after {
+            write!(f, ", after: {}", cursor.key)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Criteria the items returned by a [`NodeQuery`] must match.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct QueryFilter {
+    /// Prefix of the encoded key (or peer id) of the items.
+    pub key_prefix: Option<String>,
+    /// Range of locations in the ring, both ends included. If the start is greater than the
+    /// end the range wraps around the ring.
+    pub location_range: Option<(f64, f64)>,
+    /// Items which have been around for less time than this are excluded.
+    pub min_age: Option<Duration>,
+    /// Items which have been around for longer than this are excluded.
+    pub max_age: Option<Duration>,
+}
+
+impl QueryFilter {
+    pub fn with_key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.key_prefix = Some(prefix.into());
+        self
+    }
+
+    pub fn with_location_range(mut self, start: f64, end: f64) -> Self {
+        self.location_range = Some((start, end));
+        self
+    }
+
+    pub fn with_age_range(mut self, min: Option<Duration>, max: Option<Duration>) -> Self {
+        self.min_age = min;
+        self.max_age = max;
+        self
+    }
+
+    fn matches(&self, item: &impl QueryItem) -> bool {
+        if let Some(prefix) = &self.key_prefix {
+            if !item.encoded_key().starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some((start, end)) = self.location_range {
+            let location = item.location();
+            let in_range = if start <= end {
+                (start..=end).contains(&location)
+            } else {
+                location >= start || location <= end
+            };
+            if !in_range {
+                return false;
+            }
+        }
+        let age = item.age();
+        self.min_age.map(|min| age >= min).unwrap_or(true)
+            && self.max_age.map(|max| age <= max).unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Key,
+    Location,
+    Age,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PageRequest {
+    /// Only return the (filtered and sorted) items following this cursor, as returned in
+    /// [`Page::next`].
+    pub after: Option<PageCursor>,
+    /// Maximum number of items to return, capped at [`MAX_PAGE_SIZE`].
+    pub limit: usize,
+    pub sort_by: SortBy,
+    pub descending: bool,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            after: None,
+            limit: DEFAULT_PAGE_SIZE,
+            sort_by: SortBy::default(),
+            descending: false,
+        }
+    }
+}
+
+impl PageRequest {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn sorted_by(mut self, sort_by: SortBy, descending: bool) -> Self {
+        self.sort_by = sort_by;
+        self.descending = descending;
+        self
+    }
+
+    /// Requests the page following the one the cursor was returned with.
+    pub fn after(mut self, cursor: PageCursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+
+    /// Filters and sorts the items, returning only the requested page.
+    pub fn paginate<T: QueryItem>(&self, filter: &QueryFilter, items: Vec<T>) -> Page<T> {
+        let mut items: Vec<_> = items
+            .into_iter()
+            .filter(|item| filter.matches(item))
+            .map(|item| (PageCursor::new(&item), item))
+            .collect();
+        items.sort_by(|(a, _), (b, _)| self.cmp(a, b));
+        let total = items.len();
+        let start = match &self.after {
+            // the item the cursor points to may be gone, in which case resume from the
+            // position it would have
+            Some(cursor) => items.partition_point(|(item, _)| self.cmp(item, cursor).is_le()),
+            None => 0,
+        };
+        let limit = self.limit.min(MAX_PAGE_SIZE);
+        let mut page: Vec<_> = items.into_iter().skip(start).take(limit + 1).collect();
+        let next = (page.len() > limit).then(|| {
+            page.truncate(limit);
+            page.last().map(|(cursor, _)| cursor.clone())
+        });
+        Page {
+            items: page.into_iter().map(|(_, item)| item).collect(),
+            total,
+            next: next.flatten(),
+        }
+    }
+
+    /// Ordering of the items in the pages, ties are broken by key so it is total.
+    fn cmp(&self, a: &PageCursor, b: &PageCursor) -> Ordering {
+        let ordering = match self.sort_by {
+            SortBy::Key => Ordering::Equal,
+            SortBy::Location => a.location.total_cmp(&b.location),
+            SortBy::Age => a.age.cmp(&b.age),
+        }
+        .then_with(|| a.key.cmp(&b.key));
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// Position of the last item of a [`Page`], from which the following page starts.
+///
+/// Items keep aging between requests, so when sorting by age and the item the cursor points
+/// to is gone, the following page may repeat some items.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PageCursor {
+    key: String,
+    location: f64,
+    age: Duration,
+}
+
+impl PageCursor {
+    fn new(item: &impl QueryItem) -> Self {
+        Self {
+            key: item.encoded_key(),
+            location: item.location(),
+            age: item.age(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items matching the filter, across all pages.
+    pub total: usize,
+    /// Cursor to request the following page, if there are more items left.
+    pub next: Option<PageCursor>,
+}
+
+/// Items which can be returned from a [`NodeQuery`].
+pub trait QueryItem {
+    fn encoded_key(&self) -> String;
+    fn location(&self) -> f64;
+    fn age(&self) -> Duration;
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PeerInfo {
+    pub peer: String,
+    pub location: f64,
+    /// For how long this node has been connected to the peer.
+    pub connected_for: Duration,
+}
+
+impl QueryItem for PeerInfo {
+    fn encoded_key(&self) -> String {
+        self.peer.clone()
+    }
+
+    fn location(&self) -> f64 {
+        self.location
+    }
+
+    fn age(&self) -> Duration {
+        self.connected_for
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ContractInfo {
+    pub key: ContractKey,
+    pub location: f64,
+    /// For how long this node has been caching the contract.
+    pub cached_for: Duration,
+}
+
+impl QueryItem for ContractInfo {
+    fn encoded_key(&self) -> String {
+        self.key.encoded_contract_id()
+    }
+
+    fn location(&self) -> f64 {
+        self.location
+    }
+
+    fn age(&self) -> Duration {
+        self.cached_for
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum QueryResponse {
+    ConnectedPeers(Page<PeerInfo>),
+    CachedContracts(Page<ContractInfo>),
+}
+
+impl Display for QueryResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryResponse::ConnectedPeers(page) => {
+                write!(f, "{} of {} connected peers", page.items.len(), page.total)
+            }
+            QueryResponse::CachedContracts(page) => {
+                write!(f, "{} of {} cached contracts", page.items.len(), page.total)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peers() -> Vec<PeerInfo> {
+        (0..10)
+            .map(|i| PeerInfo {
+                peer: format!("peer-{i}"),
+                location: i as f64 / 10.0,
+                connected_for: Duration::from_secs(100 - i * 10),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paginate_items() {
+        let filter = QueryFilter::default();
+        let page = PageRequest::new(4).paginate(&filter, peers());
+        assert_eq!(page.items.len(), 4);
+        assert_eq!(page.total, 10);
+        let cursor = page.next.expect("more pages");
+
+        // removing already returned items doesn't shift the following pages
+        let mut remaining = peers();
+        remaining.retain(|p| p.peer != "peer-1" && p.peer != "peer-3");
+        let page = PageRequest::new(4)
+            .after(cursor)
+            .paginate(&filter, remaining);
+        let found: Vec<_> = page.items.iter().map(|p| p.peer.as_str()).collect();
+        assert_eq!(found, ["peer-4", "peer-5", "peer-6", "peer-7"]);
+
+        let page = PageRequest::new(4)
+            .after(page.next.expect("more pages"))
+            .paginate(&filter, peers());
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.next, None);
+
+        let page = PageRequest::new(1)
+            .sorted_by(SortBy::Age, true)
+            .paginate(&filter, peers());
+        assert_eq!(page.items[0].peer, "peer-0");
+    }
+
+    #[test]
+    fn filter_items() {
+        let page = PageRequest::default();
+        let filter = QueryFilter::default().with_location_range(0.85, 0.15);
+        let found = page.paginate(&filter, peers());
+        let found: Vec<_> = found.items.iter().map(|p| p.peer.as_str()).collect();
+        assert_eq!(found, ["peer-0", "peer-1", "peer-9"]);
+
+        let filter = QueryFilter::default()
+            .with_key_prefix("peer-")
+            .with_age_range(Some(Duration::from_secs(30)), Some(Duration::from_secs(50)));
+        let found = page.paginate(&filter, peers());
+        let found: Vec<_> = found.items.iter().map(|p| p.peer.as_str()).collect();
+        assert_eq!(found, ["peer-5", "peer-6", "peer-7"]);
+    }
+}
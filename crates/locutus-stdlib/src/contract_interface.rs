@@ -67,6 +67,9 @@ pub struct UpdateModification<'a> {
     pub new_state: Option<State<'a>>,
     /// Request an other contract so updates can be resolved.
     pub related: Vec<RelatedContract>,
+    /// Messages for other contracts, emitted as a result of this update.
+    #[serde(default)]
+    pub messages: Vec<ContractMessage>,
 }
 
 impl<'a> UpdateModification<'a> {
@@ -75,6 +78,7 @@ impl<'a> UpdateModification<'a> {
         Self {
             new_state: Some(new_state),
             related: vec![],
+            messages: vec![],
         }
     }
 
@@ -84,9 +88,19 @@ impl<'a> UpdateModification<'a> {
         Self {
             new_state: None,
             related,
+            messages: vec![],
         }
     }
 
+    /// Send a message to an other contract after applying this modification.
+    ///
+    /// The message will be delivered to the target contract as an [`UpdateData::RelatedDelta`]
+    /// originating from the contract emitting it.
+    pub fn send_message(mut self, target: ContractInstanceId, payload: Vec<u8>) -> Self {
+        self.messages.push(ContractMessage { target, payload });
+        self
+    }
+
     /// Unwraps self returning a [`State`].
     ///
     /// Panics if self does not contain a state.
@@ -102,12 +116,22 @@ impl<'a> UpdateModification<'a> {
         &self.related
     }
 
+    /// Gets the messages emitted for other contracts.
+    pub fn get_messages(&self) -> &[ContractMessage] {
+        &self.messages
+    }
+
     /// Copies the data if not owned and returns an owned version of self.
     pub fn into_owned(self) -> UpdateModification<'static> {
-        let Self { new_state, related } = self;
+        let Self {
+            new_state,
+            related,
+            messages,
+        } = self;
         UpdateModification {
             new_state: new_state.map(|s| State::from(s.into_bytes())),
             related,
+            messages,
         }
     }
 }
@@ -195,6 +219,16 @@ pub struct RelatedContract {
     // todo: add a timeout so we stop listening/subscribing eventually
 }
 
+/// A message addressed to an other contract, emitted while updating the state of a contract.
+///
+/// If the target contract is available in the local node the message is processed right away,
+/// otherwise it is routed to the peers holding the target contract.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ContractMessage {
+    pub target: ContractInstanceId,
+    pub payload: Vec<u8>,
+}
+
 /// Specification of the notifications of interest from a related contract.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum RelatedMode {
@@ -1088,6 +1122,11 @@ impl ContractKey {
         })
     }
 
+    /// Gets the [`ContractInstanceId`](ContractInstanceId) part of the key.
+    pub fn id(&self) -> &ContractInstanceId {
+        &self.instance
+    }
+
     /// Gets the whole spec key hash.
     pub fn bytes(&self) -> &[u8] {
         self.instance.0.as_ref()
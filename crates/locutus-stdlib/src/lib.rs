@@ -1,6 +1,7 @@
 //! Standard library provided by the Freenet project to be able to write Locutus-compatible contracts.
 #[doc(hidden)]
 pub mod buf;
+pub mod causality;
 #[cfg(all(feature = "net", any(unix, windows, target_family = "wasm")))]
 pub mod client_api;
 mod contract_interface;
@@ -15,6 +16,7 @@ pub mod web;
 /// Locutus stdlib prelude.
 pub mod prelude {
     pub use super::WasmLinearMem;
+    pub use crate::causality::{CausalHistory, Causality, UpdateId};
     pub use crate::contract_interface::wasm_interface::*;
     pub use crate::contract_interface::*;
     pub use crate::delegate_interface::wasm_interface::*;
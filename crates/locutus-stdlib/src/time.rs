@@ -1,4 +1,7 @@
 //! Temporal quantification.
+//!
+//! Only available to delegates: the time differs between peers, so the runtime rejects
+//! contracts importing it.
 
 use std::mem::MaybeUninit;
 
@@ -0,0 +1,74 @@
+use locutus_kv_interface::{KvDelta, KvStore, KvSummary};
+use locutus_stdlib::prelude::*;
+
+struct KvStoreContract;
+
+#[contract]
+impl ContractInterface for KvStoreContract {
+    fn validate_state(
+        _parameters: Parameters<'static>,
+        state: State<'static>,
+        _related: RelatedContracts<'static>,
+    ) -> Result<ValidateResult, ContractError> {
+        let store = KvStore::try_from(state)?;
+        match store.check() {
+            Ok(()) => Ok(ValidateResult::Valid),
+            Err(err) => {
+                tracing::debug!("{err}");
+                Ok(ValidateResult::Invalid)
+            }
+        }
+    }
+
+    fn validate_delta(
+        _parameters: Parameters<'static>,
+        delta: StateDelta<'static>,
+    ) -> Result<bool, ContractError> {
+        let delta = KvDelta::try_from(delta)?;
+        Ok(delta.check().is_ok())
+    }
+
+    fn update_state(
+        _parameters: Parameters<'static>,
+        state: State<'static>,
+        data: Vec<UpdateData<'static>>,
+    ) -> Result<UpdateModification<'static>, ContractError> {
+        let mut store = KvStore::try_from(state)?;
+        for update in data {
+            let result = match update {
+                UpdateData::State(s) => store.merge(KvStore::try_from(s)?),
+                UpdateData::Delta(d) => store.apply(KvDelta::try_from(d)?),
+                UpdateData::StateAndDelta { state, delta } => {
+                    let delta = KvDelta::try_from(delta)?;
+                    store
+                        .merge(KvStore::try_from(state)?)
+                        .and_then(|_| store.apply(delta))
+                }
+                _ => return Err(ContractError::InvalidUpdate),
+            };
+            result.map_err(|err| {
+                tracing::error!("{err}");
+                ContractError::InvalidUpdate
+            })?;
+        }
+        Ok(UpdateModification::valid(store.try_into()?))
+    }
+
+    fn summarize_state(
+        _parameters: Parameters<'static>,
+        state: State<'static>,
+    ) -> Result<StateSummary<'static>, ContractError> {
+        let store = KvStore::try_from(state)?;
+        store.summarize().try_into()
+    }
+
+    fn get_state_delta(
+        _parameters: Parameters<'static>,
+        state: State<'static>,
+        summary: StateSummary<'static>,
+    ) -> Result<StateDelta<'static>, ContractError> {
+        let store = KvStore::try_from(state)?;
+        let summary = KvSummary::try_from(summary)?;
+        store.delta(&summary).try_into()
+    }
+}
@@ -0,0 +1,241 @@
+//! Helpers to use a key-value store from native applications through the websocket client.
+//!
+//! The helpers wait for the response of each request, discarding anything else received in
+//! the meantime, so a connection used with them should not have other requests in flight.
+//! Watching a store takes over its own connection for the same reason.
+//!
+//! The connections must be open through the versioned websocket API of the node
+//! (`/v1/contract/command/`), which tags get responses with the key of the contract.
+
+use std::{sync::Arc, time::SystemTime};
+
+use locutus_stdlib::{
+    client_api::{self, ClientError, ContractRequest, ContractResponse, HostResponse, WebApi},
+    prelude::*,
+};
+
+use crate::{KvDelta, KvStore};
+
+#[derive(Debug, thiserror::Error)]
+pub enum KvClientError {
+    #[error(transparent)]
+    Api(#[from] client_api::Error),
+    #[error(transparent)]
+    Host(#[from] ClientError),
+    #[error(transparent)]
+    Contract(#[from] ContractError),
+}
+
+/// Request to create a new, empty, store from the key-value contract code.
+pub fn kv_store(
+    code: ContractCode<'static>,
+    params: Parameters<'static>,
+) -> Result<ContractRequest<'static>, ContractError> {
+    let contract = WrappedContract::new(Arc::new(code), params);
+    let state: State = KvStore::default().try_into()?;
+    Ok(ContractRequest::Put {
+        contract: ContractContainer::Wasm(WasmAPIVersion::V1(contract)),
+        state: WrappedState::new(state.into_bytes()),
+        related_contracts: RelatedContracts::new(),
+    })
+}
+
+pub async fn kv_put(
+    api: &mut WebApi,
+    store: ContractKey,
+    key: impl Into<String>,
+    value: impl Into<Vec<u8>>,
+) -> Result<(), KvClientError> {
+    let delta = KvDelta::default().put(key, value, unix_millis());
+    update(api, store, delta).await
+}
+
+pub async fn kv_remove(
+    api: &mut WebApi,
+    store: ContractKey,
+    key: impl Into<String>,
+) -> Result<(), KvClientError> {
+    let delta = KvDelta::default().remove(key, unix_millis());
+    update(api, store, delta).await
+}
+
+pub async fn kv_get(
+    api: &mut WebApi,
+    store: ContractKey,
+    key: &str,
+) -> Result<Option<Vec<u8>>, KvClientError> {
+    let request = ContractRequest::Get {
+        key: store.clone(),
+        fetch_contract: false,
+    };
+    api.send(request.into()).await?;
+    loop {
+        if let Some(value) = read_value(&store, key, api.recv().await?)? {
+            return Ok(value);
+        }
+    }
+}
+
+/// Value of the key if the response is the state of the given store, `None` if the response
+/// is any other.
+fn read_value(
+    store: &ContractKey,
+    key: &str,
+    response: HostResponse,
+) -> Result<Option<Option<Vec<u8>>>, KvClientError> {
+    match response {
+        HostResponse::ContractResponse(ContractResponse::KeyedGetResponse {
+            key: response_key,
+            state,
+            ..
+        }) if response_key == *store => {
+            let store = KvStore::try_from(State::from(state.as_ref()))?;
+            Ok(Some(store.get(key).map(ToOwned::to_owned)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Subscribes to the changes to a store.
+pub async fn kv_watch(mut api: WebApi, store: ContractKey) -> Result<KvWatch, KvClientError> {
+    let request = ContractRequest::Subscribe { key: store.clone() };
+    api.send(request.into()).await?;
+    Ok(KvWatch { api, store })
+}
+
+pub struct KvWatch {
+    api: WebApi,
+    store: ContractKey,
+}
+
+impl KvWatch {
+    /// Waits for the next change to the store.
+    pub async fn next(&mut self) -> Result<KvDelta, KvClientError> {
+        loop {
+            if let Some(delta) = read_changes(&self.store, self.api.recv().await?)? {
+                return Ok(delta);
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> WebApi {
+        self.api
+    }
+}
+
+/// Changes notified for the given store, `None` if the response is any other or notifies no
+/// changes.
+fn read_changes(
+    store: &ContractKey,
+    response: HostResponse,
+) -> Result<Option<KvDelta>, KvClientError> {
+    let update = match response {
+        HostResponse::ContractResponse(ContractResponse::UpdateNotification {
+            key,
+            update,
+            ..
+        }) if key == *store => update,
+        _ => return Ok(None),
+    };
+    let delta = match update {
+        UpdateData::Delta(delta) | UpdateData::StateAndDelta { delta, .. } => {
+            KvDelta::try_from(delta)?
+        }
+        UpdateData::State(state) => KvStore::try_from(state)?.into(),
+        _ => return Ok(None),
+    };
+    Ok((!delta.is_empty()).then_some(delta))
+}
+
+async fn update(api: &mut WebApi, store: ContractKey, delta: KvDelta) -> Result<(), KvClientError> {
+    let request = ContractRequest::Update {
+        key: store.clone(),
+        data: UpdateData::Delta(delta.try_into()?),
+    };
+    api.send(request.into()).await?;
+    while !is_update_response(&store, &api.recv().await?) {}
+    Ok(())
+}
+
+fn is_update_response(store: &ContractKey, response: &HostResponse) -> bool {
+    matches!(
+        response,
+        HostResponse::ContractResponse(ContractResponse::UpdateResponse { key, .. })
+            if key == store
+    )
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn store_key(params: &[u8]) -> ContractKey {
+        let code = ContractCode::from(vec![0]);
+        ContractKey::from((&Parameters::from(params.to_vec()), &code))
+    }
+
+    fn get_response(key: ContractKey, store: KvStore) -> HostResponse {
+        let state: State = store.try_into().unwrap();
+        ContractResponse::KeyedGetResponse {
+            key,
+            contract: None,
+            state: WrappedState::new(state.into_bytes()),
+        }
+        .into()
+    }
+
+    #[test]
+    fn read_value_of_requested_store() -> Result<(), KvClientError> {
+        let (requested, other) = (store_key(b"requested"), store_key(b"other"));
+        let mut store = KvStore::default();
+        store
+            .apply(KvDelta::default().put("a", *b"1", 1))
+            .map_err(|_| ContractError::InvalidUpdate)?;
+
+        let response = get_response(other, store.clone());
+        assert!(read_value(&requested, "a", response)?.is_none());
+        let response = get_response(requested.clone(), store);
+        assert_eq!(
+            read_value(&requested, "a", response)?,
+            Some(Some(b"1".to_vec()))
+        );
+        let response = get_response(requested.clone(), KvStore::default());
+        assert_eq!(read_value(&requested, "a", response)?, Some(None));
+        Ok(())
+    }
+
+    #[test]
+    fn read_changes_of_watched_store() -> Result<(), KvClientError> {
+        let (watched, other) = (store_key(b"watched"), store_key(b"other"));
+        let notification = |key: &ContractKey, delta: KvDelta| -> HostResponse {
+            ContractResponse::UpdateNotification {
+                key: key.clone(),
+                update: UpdateData::Delta(delta.try_into().unwrap()),
+                causality: CausalHistory::default().next(&[]),
+            }
+            .into()
+        };
+        let delta = KvDelta::default().put("a", *b"1", 1);
+        assert!(read_changes(&watched, notification(&other, delta.clone()))?.is_none());
+        assert!(read_changes(&watched, notification(&watched, KvDelta::default()))?.is_none());
+        assert_eq!(
+            read_changes(&watched, notification(&watched, delta.clone()))?,
+            Some(delta)
+        );
+
+        let response = ContractResponse::UpdateResponse {
+            key: other,
+            summary: StateSummary::from(vec![]),
+        }
+        .into();
+        assert!(!is_update_response(&watched, &response));
+        Ok(())
+    }
+}
@@ -0,0 +1,437 @@
+//! Types shared by the key-value store contract and its clients.
+//!
+//! A store maps string keys to arbitrary byte values. Every entry carries a version (by
+//! convention the time it was written, in milliseconds since the unix epoch) and concurrent
+//! writes are resolved last-writer-wins, with ties broken by comparing the values, so replicas
+//! converge no matter in which order they receive the updates. Removed entries are kept as
+//! tombstones, otherwise a stale replica would bring them back. Tombstones are compacted once
+//! they are older than [`TOMBSTONE_HORIZON`] relative to the newest entry of the store, so a
+//! replica out of sync for longer than that may still bring back removed entries.
+//!
+//! The contract ignores its parameters, so independent stores can be created from the same
+//! contract code just by using different parameters.
+
+use std::collections::BTreeMap;
+
+use locutus_stdlib::prelude::{
+    blake2::{Blake2s256, Digest},
+    *,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[cfg(all(feature = "client", any(unix, windows)))]
+mod client;
+#[cfg(all(feature = "client", any(unix, windows)))]
+pub use client::*;
+
+pub const MAX_KEY_LEN: usize = 256;
+pub const MAX_VALUE_LEN: usize = 64 * 1024;
+/// Maximum number of entries in a store, tombstones aside.
+pub const MAX_ENTRIES: usize = 10_000;
+/// Maximum number of tombstones in a store, the oldest ones are compacted beyond it.
+pub const MAX_TOMBSTONES: usize = MAX_ENTRIES;
+/// Age, in milliseconds, after which tombstones are compacted: 30 days.
+pub const TOMBSTONE_HORIZON: u64 = 30 * 24 * 60 * 60 * 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KvError {
+    #[error("invalid key `{0}`")]
+    InvalidKey(String),
+    #[error("value of `{0}` exceeds the maximum size")]
+    ValueTooLarge(String),
+    #[error("the store is full")]
+    TooManyEntries,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub version: u64,
+    /// Removed entries don't have a value.
+    pub value: Option<Vec<u8>>,
+}
+
+impl Entry {
+    fn is_tombstone(&self) -> bool {
+        self.value.is_none()
+    }
+
+    fn supersedes(&self, other: &Entry) -> bool {
+        (self.version, &self.value) > (other.version, &other.value)
+    }
+
+    fn digest(&self) -> [u8; 32] {
+        let mut hasher = Blake2s256::new();
+        if let Some(value) = &self.value {
+            hasher.update(value);
+        }
+        hasher.finalize().into()
+    }
+}
+
+fn check_entry(key: &str, entry: &Entry) -> Result<(), KvError> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(KvError::InvalidKey(key.to_owned()));
+    }
+    match &entry.value {
+        Some(value) if value.len() > MAX_VALUE_LEN => Err(KvError::ValueTooLarge(key.to_owned())),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvStore {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl KvStore {
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key)?.value.as_deref()
+    }
+
+    /// Entries currently in the store, sorted by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries
+            .iter()
+            .filter_map(|(key, entry)| Some((key.as_str(), entry.value.as_deref()?)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    fn tombstones(&self) -> usize {
+        self.entries.values().filter(|e| e.is_tombstone()).count()
+    }
+
+    pub fn check(&self) -> Result<(), KvError> {
+        if self.len() > MAX_ENTRIES || self.tombstones() > MAX_TOMBSTONES {
+            return Err(KvError::TooManyEntries);
+        }
+        self.entries
+            .iter()
+            .try_for_each(|(key, entry)| check_entry(key, entry))
+    }
+
+    /// Applies the changes in the delta which are newer than the current entries.
+    pub fn apply(&mut self, delta: KvDelta) -> Result<(), KvError> {
+        delta.check()?;
+        let mut live = self.len();
+        for (key, entry) in delta.entries {
+            match self.entries.get_mut(&key) {
+                Some(current) => {
+                    if entry.supersedes(current) {
+                        if current.is_tombstone() && !entry.is_tombstone() {
+                            if live >= MAX_ENTRIES {
+                                return Err(KvError::TooManyEntries);
+                            }
+                            live += 1;
+                        } else if !current.is_tombstone() && entry.is_tombstone() {
+                            live -= 1;
+                        }
+                        *current = entry;
+                    }
+                }
+                None => {
+                    if !entry.is_tombstone() {
+                        if live >= MAX_ENTRIES {
+                            return Err(KvError::TooManyEntries);
+                        }
+                        live += 1;
+                    }
+                    self.entries.insert(key, entry);
+                }
+            }
+        }
+        self.compact();
+        Ok(())
+    }
+
+    /// Drops the tombstones older than [`TOMBSTONE_HORIZON`] relative to the newest entry,
+    /// and the oldest ones beyond [`MAX_TOMBSTONES`].
+    fn compact(&mut self) {
+        let newest = self.entries.values().map(|e| e.version).max();
+        let horizon = newest.unwrap_or_default().saturating_sub(TOMBSTONE_HORIZON);
+        self.entries
+            .retain(|_, entry| !entry.is_tombstone() || entry.version >= horizon);
+
+        let mut tombstones: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_tombstone())
+            .map(|(key, entry)| (entry.version, key.clone()))
+            .collect();
+        if tombstones.len() > MAX_TOMBSTONES {
+            tombstones.sort_unstable();
+            let excess = tombstones.len() - MAX_TOMBSTONES;
+            for (_, key) in tombstones.into_iter().take(excess) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: KvStore) -> Result<(), KvError> {
+        self.apply(KvDelta {
+            entries: other.entries,
+        })
+    }
+
+    pub fn summarize(&self) -> KvSummary {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (key.clone(), (entry.version, entry.digest())))
+            .collect();
+        KvSummary { entries }
+    }
+
+    /// Entries which would change the state of a replica with the given summary.
+    pub fn delta(&self, summary: &KvSummary) -> KvDelta {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| match summary.entries.get(*key) {
+                Some((version, digest)) => {
+                    entry.version > *version
+                        || (entry.version == *version && entry.digest() != *digest)
+                }
+                None => true,
+            })
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        KvDelta { entries }
+    }
+}
+
+impl From<KvStore> for KvDelta {
+    fn from(store: KvStore) -> Self {
+        KvDelta {
+            entries: store.entries,
+        }
+    }
+}
+
+/// Set of changes to a store.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvDelta {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl KvDelta {
+    pub fn put(mut self, key: impl Into<String>, value: impl Into<Vec<u8>>, version: u64) -> Self {
+        let entry = Entry {
+            version,
+            value: Some(value.into()),
+        };
+        self.entries.insert(key.into(), entry);
+        self
+    }
+
+    pub fn remove(mut self, key: impl Into<String>, version: u64) -> Self {
+        let entry = Entry {
+            version,
+            value: None,
+        };
+        self.entries.insert(key.into(), entry);
+        self
+    }
+
+    /// Changed keys with their new value, or `None` if they were removed.
+    pub fn changes(&self) -> impl Iterator<Item = (&str, Option<&[u8]>)> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| (key.as_str(), entry.value.as_deref()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn check(&self) -> Result<(), KvError> {
+        if self.entries.len() > MAX_ENTRIES + MAX_TOMBSTONES {
+            return Err(KvError::TooManyEntries);
+        }
+        self.entries
+            .iter()
+            .try_for_each(|(key, entry)| check_entry(key, entry))
+    }
+}
+
+/// Versions of the entries in a store, used to compute the changes a replica is missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvSummary {
+    entries: BTreeMap<String, (u64, [u8; 32])>,
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ContractError> {
+    bincode::deserialize(bytes).map_err(|err| ContractError::Deser(format!("{err}")))
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, ContractError> {
+    bincode::serialize(value).map_err(|err| ContractError::Deser(format!("{err}")))
+}
+
+impl TryFrom<State<'_>> for KvStore {
+    type Error = ContractError;
+
+    fn try_from(state: State<'_>) -> Result<Self, Self::Error> {
+        decode(state.as_ref())
+    }
+}
+
+impl TryFrom<KvStore> for State<'static> {
+    type Error = ContractError;
+
+    fn try_from(store: KvStore) -> Result<Self, Self::Error> {
+        Ok(State::from(encode(&store)?))
+    }
+}
+
+impl TryFrom<StateDelta<'_>> for KvDelta {
+    type Error = ContractError;
+
+    fn try_from(delta: StateDelta<'_>) -> Result<Self, Self::Error> {
+        decode(delta.as_ref())
+    }
+}
+
+impl TryFrom<KvDelta> for StateDelta<'static> {
+    type Error = ContractError;
+
+    fn try_from(delta: KvDelta) -> Result<Self, Self::Error> {
+        Ok(StateDelta::from(encode(&delta)?))
+    }
+}
+
+impl TryFrom<StateSummary<'_>> for KvSummary {
+    type Error = ContractError;
+
+    fn try_from(summary: StateSummary<'_>) -> Result<Self, Self::Error> {
+        decode(summary.as_ref())
+    }
+}
+
+impl TryFrom<KvSummary> for StateSummary<'static> {
+    type Error = ContractError;
+
+    fn try_from(summary: KvSummary) -> Result<Self, Self::Error> {
+        Ok(StateSummary::from(encode(&summary)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converge_regardless_of_order() -> Result<(), KvError> {
+        let first = KvDelta::default().put("a", *b"1", 1).put("b", *b"1", 1);
+        let second = KvDelta::default().put("a", *b"2", 2).remove("b", 2);
+        let tie = KvDelta::default().put("a", *b"3", 2);
+
+        let mut store = KvStore::default();
+        store.apply(first.clone())?;
+        store.apply(second.clone())?;
+        store.apply(tie.clone())?;
+
+        let mut other = KvStore::default();
+        other.apply(tie)?;
+        other.apply(second)?;
+        other.apply(first)?;
+
+        assert_eq!(store, other);
+        assert_eq!(store.get("a"), Some(b"3".as_slice()));
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn sync_through_summaries() -> Result<(), KvError> {
+        let mut store = KvStore::default();
+        store.apply(KvDelta::default().put("a", *b"1", 1).put("b", *b"1", 1))?;
+        let mut replica = store.clone();
+        store.apply(KvDelta::default().put("b", *b"2", 2).put("c", *b"1", 1))?;
+        replica.apply(KvDelta::default().put("a", *b"0", 2))?;
+
+        let delta = store.delta(&replica.summarize());
+        let changed: Vec<_> = delta.changes().map(|(key, _)| key).collect();
+        assert_eq!(changed, ["b", "c"]);
+
+        replica.apply(delta)?;
+        store.apply(replica.delta(&store.summarize()))?;
+        assert_eq!(store, replica);
+        assert!(store.delta(&replica.summarize()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_entries() {
+        let mut store = KvStore::default();
+        let long_key = "k".repeat(MAX_KEY_LEN + 1);
+        assert!(matches!(
+            store.apply(KvDelta::default().put(long_key, *b"1", 1)),
+            Err(KvError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            store.apply(KvDelta::default().put("a", vec![0; MAX_VALUE_LEN + 1], 1)),
+            Err(KvError::ValueTooLarge(_))
+        ));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn removed_entries_free_space() -> Result<(), KvError> {
+        let mut store = KvStore::default();
+        let puts = (0..MAX_ENTRIES).fold(KvDelta::default(), |delta, i| {
+            delta.put(format!("key-{i}"), *b"1", 1)
+        });
+        store.apply(puts)?;
+        assert!(matches!(
+            store.apply(KvDelta::default().put("other", *b"1", 1)),
+            Err(KvError::TooManyEntries)
+        ));
+
+        let removes = (0..MAX_ENTRIES).fold(KvDelta::default(), |delta, i| {
+            delta.remove(format!("key-{i}"), 2)
+        });
+        store.apply(removes)?;
+        assert!(store.is_empty());
+        store.apply(KvDelta::default().put("other", *b"1", 3))?;
+        assert_eq!(store.len(), 1);
+        store.check()?;
+
+        // tombstones beyond the limit are compacted oldest first
+        store.apply(KvDelta::default().remove("other", 4))?;
+        assert_eq!(store.tombstones(), MAX_TOMBSTONES);
+        assert!(store.summarize().entries.contains_key("other"));
+        Ok(())
+    }
+
+    #[test]
+    fn compact_old_tombstones() -> Result<(), KvError> {
+        let mut store = KvStore::default();
+        store.apply(KvDelta::default().put("a", *b"1", 1).remove("b", 1))?;
+        store.apply(KvDelta::default().put("c", *b"1", TOMBSTONE_HORIZON))?;
+        assert!(store.summarize().entries.contains_key("b"));
+        store.apply(KvDelta::default().put("c", *b"2", TOMBSTONE_HORIZON + 2))?;
+        assert!(!store.summarize().entries.contains_key("b"));
+        assert_eq!(store.get("a"), Some(b"1".as_slice()));
+        store.check()?;
+        Ok(())
+    }
+
+    #[test]
+    fn encode_state() -> Result<(), ContractError> {
+        let mut store = KvStore::default();
+        store
+            .apply(KvDelta::default().put("a", *b"1", 1))
+            .map_err(|_| ContractError::InvalidUpdate)?;
+        let state = State::try_from(store.clone())?;
+        assert_eq!(KvStore::try_from(state)?, store);
+        Ok(())
+    }
+}